@@ -0,0 +1,53 @@
+// Items compiled by the compile time benchmarks, wrapped in an `items!` macro defined by each benchmark
+
+macro_rules! bench_modules {
+    ($($module:ident),*) => {
+        $(
+            pub mod $module {
+                items! {
+                    pub fn add(a: u32, b: u32) -> u32 {
+                        a + b
+                    }
+
+                    pub fn greet(name: &str) -> String {
+                        format!("Hello, {}", name)
+                    }
+
+                    pub fn generic<T: Clone>(value: &T) -> T {
+                        value.clone()
+                    }
+
+                    pub struct Struct(u32);
+
+                    impl Struct {
+                        pub fn get(&self) -> u32 {
+                            self.0
+                        }
+
+                        pub fn set(&mut self, value: u32) {
+                            self.0 = value;
+                        }
+
+                        pub fn into_string(self, suffix: String) -> String {
+                            format!("{}{}", self.0, suffix)
+                        }
+                    }
+
+                    pub trait Trait {
+                        fn trait_default(&self) -> u32 {
+                            1
+                        }
+                    }
+
+                    impl Trait for Struct {}
+                }
+            }
+        )*
+    };
+}
+
+bench_modules!(
+    m00, m01, m02, m03, m04, m05, m06, m07, m08, m09, m10, m11, m12, m13, m14, m15, m16, m17, m18,
+    m19, m20, m21, m22, m23, m24, m25, m26, m27, m28, m29, m30, m31, m32, m33, m34, m35, m36, m37,
+    m38, m39, m40, m41, m42, m43, m44, m45, m46, m47, m48, m49
+);
@@ -0,0 +1,20 @@
+//! Measures compile time of mockable items, it's the difference between build times of this
+//! benchmark and `compile_time_plain`:
+//!
+//! ```text
+//! cargo bench --bench compile_time_plain --bench compile_time_mockable --no-run --timings
+//! ```
+
+#![allow(dead_code)]
+
+macro_rules! items {
+    ($($items:tt)*) => {
+        mocktopus::macros::mockable_items! {
+            $($items)*
+        }
+    };
+}
+
+include!("compile_time/functions.rs");
+
+fn main() {}
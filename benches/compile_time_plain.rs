@@ -0,0 +1,13 @@
+//! Baseline for `compile_time_mockable`, compiles the same items without making them mockable
+
+#![allow(dead_code)]
+
+macro_rules! items {
+    ($($items:tt)*) => {
+        $($items)*
+    };
+}
+
+include!("compile_time/functions.rs");
+
+fn main() {}
@@ -0,0 +1,67 @@
+use proc_macro::Span;
+use syn::parse::{ParseStream, Parser};
+use syn::token::{Comma, Paren};
+use syn::{parenthesized, parse_quote, Attribute, Meta, Path, Token};
+
+/// Gets the path of the invoked attribute as written, e.g. `mockable` or `mt::macros::mockable`,
+/// so the attribute can be emitted again even when it's imported or the crate is renamed.
+/// It's known only when the invocation has source code, e.g. it isn't generated by a macro.
+pub fn get_invoked_attr_path() -> Option<Path> {
+    let source = Span::call_site().source_text()?;
+    parse_attr_path.parse_str(&source).ok()
+}
+
+/// Gets the path of mocktopus used by the invoked attribute, e.g. `mt` for `#[mt::test]`.
+/// If the attribute is imported, the path can't be known and `::mocktopus` is assumed.
+pub fn get_mocktopus_path() -> Path {
+    let mut path = match get_invoked_attr_path() {
+        Some(path) if path.segments.len() > 1 => path,
+        _ => return parse_quote!(::mocktopus),
+    };
+    let mut segments: Vec<_> = path.segments.into_iter().collect();
+    segments.pop();
+    // The attributes are also available in the `macros` module
+    if segments.len() > 1
+        && segments
+            .last()
+            .is_some_and(|segment| segment.ident == "macros")
+    {
+        segments.pop();
+    }
+    path.segments = segments.into_iter().collect();
+    path
+}
+
+// The source may be the whole attribute, only its contents or an attribute of `cfg_attr`
+fn parse_attr_path(input: ParseStream) -> syn::Result<Path> {
+    if !input.peek(Token![#]) {
+        let path = input.call(Path::parse_mod_style)?;
+        input.parse::<proc_macro2::TokenStream>()?;
+        return Ok(path);
+    }
+    let mut attrs = match input.peek2(Token![!]) {
+        true => input.call(Attribute::parse_inner)?,
+        false => input.call(Attribute::parse_outer)?,
+    };
+    let attr = match attrs.pop() {
+        Some(attr) if attrs.is_empty() => attr,
+        _ => return Err(input.error("expected a single attribute")),
+    };
+    if !attr.path.is_ident("cfg_attr") {
+        return Ok(attr.path);
+    }
+    attr.parse_args_with(|input: ParseStream| {
+        input.parse::<Meta>()?;
+        input.parse::<Comma>()?;
+        let path = input.call(Path::parse_mod_style)?;
+        if input.peek(Paren) {
+            let _args;
+            parenthesized!(_args in input);
+        }
+        input.parse::<Option<Comma>>()?;
+        match input.is_empty() {
+            true => Ok(path),
+            false => Err(input.error("expected a single attribute")),
+        }
+    })
+}
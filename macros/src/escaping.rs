@@ -0,0 +1,75 @@
+use proc_macro2::{Delimiter, Group, Ident, TokenStream, TokenTree};
+
+/// Placeholder for `unsafe` in attributes like `#[unsafe(no_mangle)]`, which syn can't parse
+pub const UNSAFE_ATTR_NAME: &str = "__mocktopus_unsafe__";
+
+/// Placeholder for `use` in precise capturing bounds like `impl Trait + use<'a, T>`,
+/// which syn can't parse, it makes the bounds parsable as trait bounds
+pub const USE_BOUND_NAME: &str = "__mocktopus_use__";
+
+/// Replaces syntax, which syn can't parse, with placeholders parsable as paths:
+/// `unsafe` in `#[unsafe(...)]` attributes and `use` in `use<...>` bounds
+pub fn escape(token_stream: TokenStream) -> TokenStream {
+    let mut is_after_pound = false;
+    let mut tokens = token_stream.into_iter().peekable();
+    let mut escaped = Vec::new();
+    while let Some(token_tree) = tokens.next() {
+        let token_tree = match token_tree {
+            TokenTree::Group(ref group)
+                if is_after_pound && group.delimiter() == Delimiter::Bracket =>
+            {
+                let mut tokens = group.stream().into_iter().peekable();
+                let escaped = match tokens.peek() {
+                    Some(TokenTree::Ident(ident)) if ident == "unsafe" => {
+                        let placeholder = Ident::new(UNSAFE_ATTR_NAME, ident.span());
+                        tokens.next();
+                        Some(TokenTree::Ident(placeholder))
+                    }
+                    _ => None,
+                };
+                let stream = escaped.into_iter().chain(tokens).collect();
+                with_stream(group, stream)
+            }
+            TokenTree::Group(ref group) => with_stream(group, escape(group.stream())),
+            // Paths in `use` declarations never start with `<`
+            TokenTree::Ident(ref ident)
+                if ident == "use"
+                    && matches!(tokens.peek(), Some(TokenTree::Punct(punct)) if punct.as_char() == '<') =>
+            {
+                TokenTree::Ident(Ident::new(USE_BOUND_NAME, ident.span()))
+            }
+            token_tree => token_tree,
+        };
+        // Inner attributes have `!` between `#` and brackets
+        is_after_pound = match token_tree {
+            TokenTree::Punct(ref punct) if punct.as_char() == '#' => true,
+            TokenTree::Punct(ref punct) if punct.as_char() == '!' => is_after_pound,
+            _ => false,
+        };
+        escaped.push(token_tree);
+    }
+    escaped.into_iter().collect()
+}
+
+/// Reverts [escape](fn.escape.html)
+pub fn unescape(token_stream: TokenStream) -> TokenStream {
+    token_stream
+        .into_iter()
+        .map(|token_tree| match token_tree {
+            TokenTree::Ident(ref ident) if ident == UNSAFE_ATTR_NAME => {
+                TokenTree::Ident(Ident::new("unsafe", ident.span()))
+            }
+            TokenTree::Ident(ref ident) if ident == USE_BOUND_NAME => {
+                TokenTree::Ident(Ident::new("use", ident.span()))
+            }
+            TokenTree::Group(ref group) => with_stream(group, unescape(group.stream())),
+            token_tree => token_tree,
+        })
+        .collect()
+}
+
+fn with_stream(group: &Group, stream: TokenStream) -> TokenTree {
+    let mut new_group = Group::new(group.delimiter(), stream);
+    new_group.set_span(group.span());
+    TokenTree::Group(new_group)
+}
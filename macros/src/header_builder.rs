@@ -5,15 +5,15 @@ use std::fmt::{Error, Formatter};
 use syn::punctuated::Punctuated;
 use syn::token::{Colon2, Semi};
 use syn::{
-    self, Expr, ExprUnsafe, FnArg, GenericParam, Pat, PatIdent, PatType, PathSegment, Signature,
-    Stmt,
+    self, Abi, Block, Expr, ExprUnsafe, FnArg, GenericParam, Item, ItemExternCrate, Pat, PatIdent,
+    PatType, PathSegment, Receiver, ReturnType, Signature, Stmt, Type,
 };
 
 const MOCKTOPUS_CRATE_NAME: &str = "__mocktopus_crate__";
 const STD_CRATE_NAME: &str = "__mocktopus_std__";
 const ARGS_TO_CONTINUE_NAME: &str = "__mocktopus_args_to_continue__";
 const ARGS_TO_RETURN_NAME: &str = "__mocktopus_args_to_return__";
-const UNWIND_DATA_NAME: &str = "__mocktopus_unwind_data__";
+const STOP_NAME: &str = "__mocktopus_stop__";
 
 macro_rules! error_msg {
     ($msg:expr) => {
@@ -29,40 +29,57 @@ pub enum FnHeaderBuilder<'a> {
 }
 
 impl<'a> FnHeaderBuilder<'a> {
-    pub fn build(&self, fn_decl: &Signature, fn_block_span: Span) -> Stmt {
-        let fn_args = &fn_decl.inputs;
+    /// Fails if the function has arguments, which aren't plain identifiers
+    pub fn build(&self, fn_decl: &Signature, fn_block_span: Span) -> syn::Result<Stmt> {
+        let fn_arg_names = get_fn_arg_names(&fn_decl.inputs)?;
+        let fn_arg_names = &fn_arg_names;
         let header_str = format!(
             r#"unsafe {{
                 extern crate mocktopus as {mocktopus};
                 extern crate std as {std_crate};
-                match {std_crate}::panic::catch_unwind({std_crate}::panic::AssertUnwindSafe (
-                        || {mocktopus}::mocking::Mockable::call_mock(&{full_fn_name}, {extract_args}))) {{
-                    Ok({mocktopus}::mocking::MockResult::Continue(mut {args_to_continue})) => {restore_args},
-                    Ok({mocktopus}::mocking::MockResult::Return({args_to_return})) => {{
+                match {mocktopus}::mocking::run_mock_header(
+                        || {mocktopus}::mocking::{call_mock}(&{full_fn_name}, {extract_args})) {{
+                    {mocktopus}::mocking::MockHeader::Continue({args_to_continue}) => {restore_args},
+                    {mocktopus}::mocking::MockHeader::Return({args_to_return}) => {{
                         {forget_args}
-                        let returned = {std_crate}::mem::transmute_copy(&{args_to_return});
-                        {std_crate}::mem::forget({args_to_return});
-                        return returned;
+                        return {args_to_return};
                     }},
-                    Err({unwind}) => {{
+                    {mocktopus}::mocking::MockHeader::Stop({stop}) => {{
                         {forget_args}
-                        {std_crate}::panic::resume_unwind({unwind});
+                        {stop}.raise(&{full_fn_name});
                     }},
                 }}
             }}"#,
             mocktopus = MOCKTOPUS_CRATE_NAME,
             std_crate = STD_CRATE_NAME,
+            call_mock = display(|f| write_call_mock(f, fn_decl)),
             full_fn_name = display(|f| write_full_fn_name(f, self, fn_decl)),
-            extract_args = display(|f| write_extract_args(f, fn_args)),
+            extract_args = display(|f| write_extract_args(f, fn_arg_names)),
             args_to_continue = ARGS_TO_CONTINUE_NAME,
             args_to_return = ARGS_TO_RETURN_NAME,
-            restore_args = display(|f| write_restore_args(f, fn_args)),
-            forget_args = display(|f| write_forget_args(f, fn_args)),
-            unwind = UNWIND_DATA_NAME
+            restore_args = display(|f| write_restore_args(f, fn_arg_names)),
+            forget_args = display(|f| write_forget_args(f, fn_arg_names)),
+            stop = STOP_NAME
         );
         let header_block = syn::parse_str::<ExprUnsafe>(&header_str)
             .expect(error_msg!("generated header unparsable"));
-        create_call_site_spanned_stmt(header_block, fn_block_span)
+        Ok(create_call_site_spanned_stmt(header_block, fn_block_span))
+    }
+}
+
+/// Checks if the block starts with a header, e.g. when the function was already made mockable
+/// by an attribute, which isn't recognized as `#[mockable]`, because it was imported under another name
+pub fn is_header_injected(block: &Block) -> bool {
+    let header_block = match block.stmts.first() {
+        Some(Stmt::Semi(Expr::Unsafe(ExprUnsafe { block, .. }), _)) => block,
+        _ => return false,
+    };
+    match header_block.stmts.first() {
+        Some(Stmt::Item(Item::ExternCrate(ItemExternCrate {
+            rename: Some((_, ident)),
+            ..
+        }))) => ident == MOCKTOPUS_CRATE_NAME,
+        _ => false,
     }
 }
 
@@ -88,6 +105,50 @@ fn make_token_tree_span_call_site(mut token_tree: TokenTree, span: Span) -> Toke
     token_tree
 }
 
+// Unsafe and non-Rust ABI functions don't implement Fn traits,
+// which would provide their argument and return types
+fn write_call_mock(f: &mut Formatter, fn_decl: &Signature) -> Result<(), Error> {
+    if fn_decl.unsafety.is_none() && !has_foreign_abi(fn_decl) {
+        return write!(f, "Mockable::call_mock");
+    }
+    write!(f, "call_unsafe_fn_mock::<_, (")?;
+    for fn_arg in &fn_decl.inputs {
+        match *fn_arg {
+            FnArg::Receiver(Receiver {
+                reference: Some((_, ref lifetime)),
+                ref mutability,
+                ..
+            }) => write!(
+                f,
+                "&{} {} Self, ",
+                lifetime.into_token_stream(),
+                mutability.into_token_stream()
+            )?,
+            FnArg::Receiver(_) => write!(f, "Self, ")?,
+            FnArg::Typed(PatType { ref ty, .. }) => write!(f, "{}, ", ty.into_token_stream())?,
+        }
+    }
+    match fn_decl.output {
+        ReturnType::Default => write!(f, "), ()>"),
+        ReturnType::Type(_, ref ty) => match **ty {
+            Type::ImplTrait(_) => write!(f, "), _>"),
+            Type::Never(_) => write!(f, "), {}::mocking::Never>", MOCKTOPUS_CRATE_NAME),
+            _ => write!(f, "), {}>", ty.into_token_stream()),
+        },
+    }
+}
+
+fn has_foreign_abi(fn_decl: &Signature) -> bool {
+    match fn_decl.abi {
+        Some(Abi {
+            name: Some(ref name),
+            ..
+        }) => name.value() != "Rust",
+        Some(_) => true,
+        None => false,
+    }
+}
+
 fn write_full_fn_name(
     f: &mut Formatter,
     builder: &FnHeaderBuilder,
@@ -128,16 +189,17 @@ fn write_fn_generics(f: &mut Formatter, fn_decl: &Signature) -> Result<(), Error
 fn get_generic_param_name(param: &GenericParam) -> Option<String> {
     match *param {
         GenericParam::Type(ref type_param) => Some(type_param.ident.to_string()),
-        _ => None,
+        GenericParam::Const(ref const_param) => Some(const_param.ident.to_string()),
+        GenericParam::Lifetime(_) => None,
     }
 }
 
-fn write_extract_args<T>(f: &mut Formatter, fn_args: &Punctuated<FnArg, T>) -> Result<(), Error> {
-    if fn_args.is_empty() {
+fn write_extract_args(f: &mut Formatter, fn_arg_names: &[String]) -> Result<(), Error> {
+    if fn_arg_names.is_empty() {
         return write!(f, "()");
     }
     write!(f, "(")?;
-    for fn_arg_name in iter_fn_arg_names(fn_args) {
+    for fn_arg_name in fn_arg_names {
         write!(
             f,
             "{}::mem::transmute_copy(&{}), ",
@@ -147,49 +209,44 @@ fn write_extract_args<T>(f: &mut Formatter, fn_args: &Punctuated<FnArg, T>) -> R
     write!(f, ")")
 }
 
-fn write_restore_args<T>(f: &mut Formatter, fn_args: &Punctuated<FnArg, T>) -> Result<(), Error> {
-    if fn_args.is_empty() {
+fn write_restore_args(f: &mut Formatter, fn_arg_names: &[String]) -> Result<(), Error> {
+    if fn_arg_names.is_empty() {
         return writeln!(f, "()");
     }
     writeln!(f, "{{")?;
-    for (fn_arg_index, fn_arg_name) in iter_fn_arg_names(fn_args).enumerate() {
+    for (fn_arg_index, fn_arg_name) in fn_arg_names.iter().enumerate() {
         writeln!(
             f,
-            "{}::mem::swap(&mut *(&{} as *const _ as *mut _), &mut {}.{});",
-            STD_CRATE_NAME, fn_arg_name, ARGS_TO_CONTINUE_NAME, fn_arg_index
+            "{}::mocking::restore_arg(&{}, {}.{});",
+            MOCKTOPUS_CRATE_NAME, fn_arg_name, ARGS_TO_CONTINUE_NAME, fn_arg_index
         )?;
     }
-    writeln!(
-        f,
-        "{}::mem::forget({});",
-        STD_CRATE_NAME, ARGS_TO_CONTINUE_NAME
-    )?;
     writeln!(f, "}}")
 }
 
-fn write_forget_args<T>(f: &mut Formatter, fn_args: &Punctuated<FnArg, T>) -> Result<(), Error> {
-    for fn_arg_name in iter_fn_arg_names(fn_args) {
-        writeln!(f, "{}::mem::forget({});", STD_CRATE_NAME, fn_arg_name)?;
+fn write_forget_args(f: &mut Formatter, fn_arg_names: &[String]) -> Result<(), Error> {
+    for fn_arg_name in fn_arg_names {
+        writeln!(
+            f,
+            "#[allow(forgetting_references, forgetting_copy_types)] {}::mem::forget({});",
+            STD_CRATE_NAME, fn_arg_name
+        )?;
     }
     Ok(())
 }
 
-fn iter_fn_arg_names<'a, T>(
-    input_args: &'a Punctuated<FnArg, T>,
-) -> impl Iterator<Item = String> + 'a {
-    input_args.iter().map(|fn_arg| {
-        match fn_arg {
-            FnArg::Receiver(_) => return "self".to_string(),
-            FnArg::Typed(PatType { pat, .. }) => {
-                if let Pat::Ident(PatIdent { ident, .. }) = &**pat {
-                    return ident.to_string();
-                }
-            }
-        };
-        panic!(
-            "{}: '{}'",
-            error_msg!("invalid fn arg type"),
-            fn_arg.clone().into_token_stream()
-        )
-    })
+fn get_fn_arg_names<T>(fn_args: &Punctuated<FnArg, T>) -> syn::Result<Vec<String>> {
+    fn_args
+        .iter()
+        .map(|fn_arg| match fn_arg {
+            FnArg::Receiver(_) => Ok("self".to_string()),
+            FnArg::Typed(PatType { pat, .. }) => match &**pat {
+                Pat::Ident(PatIdent { ident, .. }) => Ok(ident.to_string()),
+                _ => Err(syn::Error::new_spanned(
+                    fn_arg,
+                    "Mockable functions must take arguments bound to plain identifiers",
+                )),
+            },
+        })
+        .collect()
 }
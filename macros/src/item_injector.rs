@@ -1,13 +1,22 @@
-use crate::header_builder::FnHeaderBuilder;
-use proc_macro2::{TokenStream, TokenTree};
+use crate::attr_path;
+use crate::escaping;
+use crate::header_builder::{self, FnHeaderBuilder};
+use crate::mockable_args::MockableArgs;
+use proc_macro2::{Span, TokenStream, TokenTree};
 use quote::{format_ident, quote, quote_spanned, ToTokens};
 use std::iter::FromIterator;
+use syn::parse::{ParseStream, Parser};
 use syn::punctuated::Punctuated;
-use syn::token::Comma;
+use syn::spanned::Spanned;
+use syn::token::{Add, Comma};
+use syn::visit::Visit;
+use syn::visit_mut::{self, VisitMut};
 use syn::{
-    parse_quote, Attribute, Block, FnArg, GenericParam, Generics, Ident, ImplItem, ImplItemMethod,
-    Item, ItemFn, ItemImpl, ItemMod, ItemTrait, Pat, PatIdent, PatType, Receiver, ReturnType,
-    Signature, TraitItem, TraitItemMethod, Type, Visibility, WhereClause,
+    parse_quote, Attribute, Block, Expr, ExprAsync, ExprCall, ExprClosure, ExprPath, ExprReturn,
+    File, FnArg, ForeignItem, ForeignItemFn, GenericArgument, GenericParam, Generics, Ident,
+    ImplItem, ImplItemMethod, Item, ItemFn, ItemForeignMod, ItemImpl, ItemMacro, ItemMod,
+    ItemStatic, ItemTrait, Pat, PatIdent, PatType, Path, PathArguments, Receiver, ReturnType,
+    Signature, TraitItem, TraitItemMethod, Type, TypeParamBound, TypePath, Visibility, WhereClause,
 };
 
 #[derive(Clone, Copy)]
@@ -20,27 +29,339 @@ enum Context<'a> {
     Fn,
 }
 
-pub fn inject_item(item: &mut Item) {
+pub fn inject_item(item: &mut Item, args: &MockableArgs) {
     match *item {
-        Item::Fn(ref mut item_fn) => inject_fn(item_fn),
-        Item::Mod(ref mut item_mod) => inject_mod(item_mod),
-        Item::Trait(ref mut item_trait) => inject_trait(item_trait),
-        Item::Impl(ref mut item_impl) => inject_impl(item_impl),
+        Item::Fn(ref item_fn) if is_exported_fn_mockable(item_fn) => {
+            *item = inject_exported_fn(item_fn, args)
+        }
+        Item::Fn(ref item_fn) if is_const_fn_mockable(args, &item_fn.attrs, &item_fn.sig) => {
+            *item = inject_const_fn(item_fn, args)
+        }
+        Item::Fn(ref mut item_fn) => {
+            let original = item_fn.clone();
+            inject_fn(item_fn, args);
+            if let Some(token_stream) = keep_doc_signature(original, item_fn.clone()) {
+                *item = Item::Verbatim(token_stream);
+            }
+        }
+        Item::Mod(ref mut item_mod) => inject_mod(item_mod, args),
+        Item::Trait(ref mut item_trait) => inject_trait(item_trait, args),
+        Item::Impl(ref mut item_impl) => inject_impl(item_impl, args),
         _ => (),
     }
 }
 
-fn inject_fn(item_fn: &mut ItemFn) {
+/// Makes all items of a crate annotated with an inner attribute mockable
+pub fn inject_crate(file: &mut File, args: &MockableArgs) {
+    for item in &mut file.items {
+        match *item {
+            // The prelude import injected by the compiler is unused after being re-emitted
+            Item::Use(ref mut item_use)
+                if item_use
+                    .attrs
+                    .iter()
+                    .any(|attr| attr.path.is_ident("prelude_import")) =>
+            {
+                item_use.attrs.push(parse_quote!(#[allow(unused_imports)]))
+            }
+            _ => inject_mod_item(item, args),
+        }
+    }
+}
+
+/// Makes the item mockable only in test builds, in other builds it's left untouched
+pub fn gate_test_only(item: impl ToTokens, args: &MockableArgs) -> TokenStream {
+    let path = get_mockable_path();
+    quote! {
+        #[cfg_attr(test, #path(#args))]
+        #item
+    }
+}
+
+/// `#[async_trait]` must transform async fns before they are made mockable, so if it's applied
+/// after `#[mockable]`, `#[mockable]` is moved after it to be expanded again on its output
+pub fn move_after_async_trait(item: &mut Item, args: TokenStream) -> bool {
+    let attrs = match *item {
+        Item::Impl(ItemImpl { ref mut attrs, .. })
+        | Item::Trait(ItemTrait { ref mut attrs, .. }) => attrs,
+        _ => return false,
+    };
+    let async_trait_idx = attrs.iter().position(|attr| {
+        attr.path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "async_trait")
+    });
+    match async_trait_idx {
+        Some(idx) => {
+            let path = get_mockable_path();
+            attrs.insert(idx + 1, parse_quote!(#[#path(#args)]));
+            true
+        }
+        None => false,
+    }
+}
+
+fn get_mockable_path() -> Path {
+    attr_path::get_invoked_attr_path()
+        .unwrap_or_else(|| parse_quote!(::mocktopus::macros::mockable))
+}
+
+fn inject_fn(item_fn: &mut ItemFn, args: &MockableArgs) {
     inject_any_fn(
+        args,
         Context::Fn,
         &FnHeaderBuilder::StaticFn,
-        &item_fn.attrs,
+        &mut item_fn.attrs,
         &mut item_fn.sig,
         &mut *item_fn.block,
     );
 }
 
-fn inject_mod(item_mod: &mut ItemMod) {
+/// In test builds turns the static into a `MockableStatic`, values of `LazyLock` statics
+/// are mocked directly. Other builds keep the static as declared.
+pub fn inject_static(item_static: &ItemStatic) -> syn::Result<TokenStream> {
+    if let Some(mutability) = item_static.mutability {
+        return Err(syn::Error::new_spanned(
+            mutability,
+            "Mutable statics can't be made mockable",
+        ));
+    }
+    let mut declared_static = item_static.clone();
+    declared_static.attrs.push(parse_quote!(#[cfg(not(test))]));
+    let mut test_static = item_static.clone();
+    test_static.attrs.push(parse_quote!(#[cfg(test)]));
+    let declared_ty = &item_static.ty;
+    let expr = &item_static.expr;
+    // The declared type is kept in the mockable type, so it's still visible and used
+    let (ty, init): (Type, Expr) = match get_lazy_init(declared_ty, expr) {
+        Some((ty, init)) => (
+            parse_quote!(::mocktopus::mocking::MockableStatic<#ty, #declared_ty>),
+            init.clone(),
+        ),
+        None => (
+            parse_quote!(::mocktopus::mocking::MockableStatic<#declared_ty>),
+            parse_quote!(|| #expr),
+        ),
+    };
+    *test_static.ty = ty;
+    *test_static.expr = parse_quote!(::mocktopus::mocking::MockableStatic::new(#init));
+    Ok(quote!(#declared_static #test_static))
+}
+
+/// Wraps functions of the extern block in mockable functions named like them, which are unsafe
+/// unless `safe` is passed.
+/// In test builds the block isn't compiled, so native libraries aren't linked,
+/// and the wrappers panic unless they are mocked.
+pub fn inject_foreign_mod(item_foreign_mod: &ItemForeignMod, args: &MockableArgs) -> TokenStream {
+    let mut item_foreign_mod = item_foreign_mod.clone();
+    let mut wrappers = Vec::new();
+    let mut is_wrapped_entirely = true;
+    for foreign_item in &mut item_foreign_mod.items {
+        match *foreign_item {
+            ForeignItem::Fn(ref mut foreign_fn) => match foreign_fn.sig.variadic {
+                Some(ref variadic) => {
+                    warn_not_mockable(
+                        variadic,
+                        "Variadic functions can't be made mockable",
+                        "declare the function with a fixed number of arguments",
+                    );
+                    is_wrapped_entirely = false;
+                }
+                None => wrappers.push(wrap_foreign_fn(foreign_fn, args.safe)),
+            },
+            _ => is_wrapped_entirely = false,
+        }
+    }
+    // Items, which aren't wrapped, must be available in test builds too
+    if is_wrapped_entirely {
+        item_foreign_mod.attrs.push(parse_quote!(#[cfg(not(test))]));
+    }
+    quote! {
+        #item_foreign_mod
+        #(#wrappers)*
+    }
+}
+
+const FOREIGN_LINKAGE_ATTRS: [&str; 2] = ["link_name", "link_ordinal"];
+
+// The foreign function is renamed, but it keeps linking to the same symbol.
+// Native functions may have any preconditions, so wrappers are unsafe unless opted out.
+fn wrap_foreign_fn(foreign_fn: &mut ForeignItemFn, is_safe: bool) -> Item {
+    let ident = foreign_fn.sig.ident.clone();
+    let (linkage_attrs, attrs): (Vec<_>, Vec<_>) = foreign_fn
+        .attrs
+        .drain(..)
+        .partition(|attr| FOREIGN_LINKAGE_ATTRS.contains(&get_attr_name(attr).as_str()));
+    foreign_fn.attrs = get_cfg_attrs(&attrs).into_iter().cloned().collect();
+    foreign_fn.attrs.extend(linkage_attrs);
+    if !foreign_fn
+        .attrs
+        .iter()
+        .any(|attr| get_attr_name(attr) == "link_name")
+    {
+        let link_name = ident.to_string();
+        foreign_fn
+            .attrs
+            .push(parse_quote!(#[link_name = #link_name]));
+    }
+    let extern_ident = format_ident!("__mocktopus_extern_{}", ident);
+    foreign_fn.sig.ident = extern_ident.clone();
+    let vis = std::mem::replace(&mut foreign_fn.vis, Visibility::Inherited);
+
+    let mut sig = foreign_fn.sig.clone();
+    sig.ident = ident;
+    if !is_safe {
+        sig.unsafety = Some(Default::default());
+    }
+    let mut arg_idents = Vec::new();
+    for (i, input) in sig.inputs.iter_mut().enumerate() {
+        if let FnArg::Typed(PatType { ref mut pat, .. }) = *input {
+            let arg_ident = match **pat {
+                Pat::Ident(PatIdent { ref ident, .. }) => ident.clone(),
+                _ => positional_arg(i),
+            };
+            *pat = parse_quote!(#arg_ident);
+            arg_idents.push(arg_ident);
+        }
+    }
+    let message = format!(
+        "`{}` is a native function, in test builds it must be mocked",
+        sig.ident
+    );
+    let mut item = Item::Fn(parse_quote! {
+        #(#attrs)*
+        #vis #sig {
+            #[cfg(test)]
+            panic!(#message);
+            #[cfg(not(test))]
+            unsafe {
+                #extern_ident(#(#arg_idents),*)
+            }
+        }
+    });
+    inject_item(&mut item, &MockableArgs::default());
+    item
+}
+
+const LAZY_TYPES: [&str; 2] = ["LazyLock", "Lazy"];
+
+// Recognizes `LazyLock<T>` initialized with `LazyLock::new(init)`, also `once_cell`'s `Lazy`
+fn get_lazy_init<'a>(ty: &'a Type, expr: &'a Expr) -> Option<(&'a Type, &'a Expr)> {
+    let segment = match *ty {
+        Type::Path(TypePath {
+            qself: None,
+            ref path,
+        }) => path.segments.last()?,
+        _ => return None,
+    };
+    if !LAZY_TYPES.contains(&segment.ident.to_string().as_str()) {
+        return None;
+    }
+    // Lazy values with custom initializer types can't be unwrapped
+    let value_ty = match segment.arguments {
+        PathArguments::AngleBracketed(ref generic_args) if generic_args.args.len() == 1 => {
+            match generic_args.args[0] {
+                GenericArgument::Type(ref value_ty) => value_ty,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    let (func, args) = match *expr {
+        Expr::Call(ExprCall {
+            ref func, ref args, ..
+        }) if args.len() == 1 => (func, args),
+        _ => return None,
+    };
+    match **func {
+        Expr::Path(ExprPath { ref path, .. }) => {
+            let mut segments = path.segments.iter().rev();
+            match (segments.next(), segments.next()) {
+                (Some(new), Some(lazy)) if new.ident == "new" && lazy.ident == segment.ident => {
+                    Some((value_ty, &args[0]))
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// Const fns can't call mocks, so with `const_fn` in test builds they are replaced with
+// non-const versions
+fn inject_const_fn(item_fn: &ItemFn, args: &MockableArgs) -> Item {
+    let mut const_fn = item_fn.clone();
+    const_fn.attrs.push(parse_quote!(#[cfg(not(test))]));
+    let mut test_fn = item_fn.clone();
+    test_fn.attrs.push(parse_quote!(#[cfg(test)]));
+    test_fn.sig.constness = None;
+    inject_fn(&mut test_fn, args);
+    Item::Verbatim(quote!(#const_fn #test_fn))
+}
+
+// Calls through exported symbols can't be mocked, so they are routed to a separate copy of
+// the function, which keeps the linkage attributes and isn't mockable
+fn inject_exported_fn(item_fn: &ItemFn, args: &MockableArgs) -> Item {
+    let mut exported_fn = item_fn.clone();
+    exported_fn.sig.ident = format_ident!("__mocktopus_exported_{}", item_fn.sig.ident);
+    exported_fn.vis = Visibility::Inherited;
+    exported_fn.attrs = item_fn
+        .attrs
+        .iter()
+        .map(|attr| match get_linkage_attr_name(attr) {
+            Some(ref name) if name == "no_mangle" => export_name_attr(attr, &item_fn.sig.ident),
+            _ => attr.clone(),
+        })
+        .collect();
+    exported_fn.attrs.push(parse_quote!(#[doc(hidden)]));
+    let mut mockable_fn = item_fn.clone();
+    mockable_fn
+        .attrs
+        .retain(|attr| get_linkage_attr_name(attr).is_none());
+    let mut mockable_item = Item::Fn(mockable_fn);
+    inject_item(&mut mockable_item, args);
+    Item::Verbatim(quote!(#exported_fn #mockable_item))
+}
+
+fn is_exported_fn_mockable(item_fn: &ItemFn) -> bool {
+    item_fn.sig.variadic.is_none()
+        && !is_not_mockable(&item_fn.attrs)
+        && item_fn
+            .attrs
+            .iter()
+            .any(|attr| get_linkage_attr_name(attr).is_some())
+}
+
+const LINKAGE_ATTRS: [&str; 3] = ["no_mangle", "export_name", "link_section"];
+
+// Recognizes both `#[no_mangle]` and `#[unsafe(no_mangle)]`
+fn get_linkage_attr_name(attr: &Attribute) -> Option<String> {
+    let name = if attr.path.is_ident(escaping::UNSAFE_ATTR_NAME) {
+        match attr.tokens.clone().into_iter().next() {
+            Some(TokenTree::Group(group)) => match group.stream().into_iter().next() {
+                Some(TokenTree::Ident(ident)) => ident.to_string(),
+                _ => return None,
+            },
+            _ => return None,
+        }
+    } else {
+        attr.path.get_ident()?.to_string()
+    };
+    Some(name).filter(|name| LINKAGE_ATTRS.contains(&name.as_str()))
+}
+
+fn export_name_attr(no_mangle: &Attribute, ident: &Ident) -> Attribute {
+    let export_name = ident.to_string();
+    if no_mangle.path.is_ident(escaping::UNSAFE_ATTR_NAME) {
+        let unsafe_attr = Ident::new(escaping::UNSAFE_ATTR_NAME, ident.span());
+        parse_quote!(#[#unsafe_attr(export_name = #export_name)])
+    } else {
+        parse_quote!(#[export_name = #export_name])
+    }
+}
+
+fn inject_mod(item_mod: &mut ItemMod, args: &MockableArgs) {
     if is_not_mockable(&item_mod.attrs) {
         return;
     }
@@ -48,29 +369,622 @@ fn inject_mod(item_mod: &mut ItemMod) {
         .content
         .iter_mut()
         .flat_map(|c| &mut c.1)
-        .for_each(inject_item)
+        .for_each(|item| inject_mod_item(item, args))
+}
+
+// Selection of items applies only to the annotated module, selected items are injected entirely
+fn inject_mod_item(item: &mut Item, args: &MockableArgs) {
+    if let Item::Mod(_) = *item {
+        if !args.recurse {
+            return;
+        }
+    }
+    if let Some(ref only) = args.only {
+        match get_item_name(item) {
+            Some(name) if only.contains(name) => (),
+            _ => return,
+        }
+    }
+    let nested_args = MockableArgs {
+        only: None,
+        test_only: false,
+        ..args.clone()
+    };
+    let stopped = split_by_stopper_cfg(item);
+    if args.test_only {
+        *item = Item::Verbatim(gate_test_only(&*item, &nested_args));
+    } else {
+        inject_item(item, &nested_args);
+    }
+    if let Some(stopped) = stopped {
+        *item = Item::Verbatim(quote!(#stopped #item));
+    }
+}
+
+fn get_item_name(item: &Item) -> Option<&Ident> {
+    match *item {
+        Item::Fn(ref item_fn) => Some(&item_fn.sig.ident),
+        Item::Mod(ref item_mod) => Some(&item_mod.ident),
+        Item::Trait(ref item_trait) => Some(&item_trait.ident),
+        Item::Impl(ItemImpl { ref self_ty, .. }) => match **self_ty {
+            Type::Path(TypePath { ref path, .. }) => path.segments.last().map(|s| &s.ident),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Warns about public functions of the item, which won't be mockable, and explains why
+pub fn audit_item(item: &Item, args: &MockableArgs) {
+    audit_any_item(item, args, None)
+}
+
+// Reasons of enclosing items, e.g. `#[not_mockable]` impl blocks, apply to all their functions
+fn audit_any_item(item: &Item, args: &MockableArgs, enclosing_reason: Option<&'static str>) {
+    match *item {
+        Item::Fn(ref item_fn) if is_public(&item_fn.vis) => {
+            let reason = enclosing_reason.or_else(|| {
+                get_unmockable_reason(
+                    args,
+                    &item_fn.attrs,
+                    &item_fn.sig,
+                    &FnHeaderBuilder::StaticFn,
+                )
+            });
+            warn_unmockable(&item_fn.sig.ident, reason);
+        }
+        Item::Impl(ref item_impl) => {
+            let impl_reason =
+                enclosing_reason.or_else(|| get_not_mockable_reason(&item_impl.attrs));
+            let is_trait_impl = item_impl.trait_.is_some();
+            let builder = match item_impl.trait_ {
+                Some((_, ref path, _)) => FnHeaderBuilder::TraitImpl(&path.segments),
+                None => FnHeaderBuilder::StructImpl,
+            };
+            let is_drop_impl = item_impl
+                .trait_
+                .as_ref()
+                .and_then(|(_, path, _)| path.segments.last())
+                .is_some_and(|segment| segment.arguments.is_empty() && segment.ident == "Drop");
+            for impl_item in &item_impl.items {
+                let item_method = match *impl_item {
+                    ImplItem::Method(ref item_method)
+                        if is_trait_impl || is_public(&item_method.vis) =>
+                    {
+                        item_method
+                    }
+                    _ => continue,
+                };
+                let reason =
+                    impl_reason.or_else(|| match is_drop_impl && item_method.sig.ident == "drop" {
+                        true => Some("`Drop::drop` isn't made mockable"),
+                        false => get_unmockable_reason(
+                            args,
+                            &item_method.attrs,
+                            &item_method.sig,
+                            &builder,
+                        ),
+                    });
+                warn_unmockable(&item_method.sig.ident, reason);
+            }
+        }
+        Item::Trait(ref item_trait) if is_public(&item_trait.vis) => {
+            let trait_reason =
+                enclosing_reason.or_else(|| get_not_mockable_reason(&item_trait.attrs));
+            for trait_item in &item_trait.items {
+                if let TraitItem::Method(ref item_method) = *trait_item {
+                    if item_method.default.is_some() {
+                        let reason = trait_reason.or_else(|| {
+                            get_unmockable_reason(
+                                args,
+                                &item_method.attrs,
+                                &item_method.sig,
+                                &FnHeaderBuilder::TraitDefault,
+                            )
+                        });
+                        warn_unmockable(&item_method.sig.ident, reason);
+                    }
+                }
+            }
+        }
+        Item::Mod(ref item_mod) => {
+            let mod_reason = enclosing_reason.or_else(|| get_not_mockable_reason(&item_mod.attrs));
+            if let Some((_, ref items)) = item_mod.content {
+                audit_mod_items(items, args, mod_reason);
+            }
+        }
+        // Definitions of `macro_rules!` macros have names
+        Item::Macro(ItemMacro {
+            ident: None,
+            ref mac,
+            ..
+        }) if enclosing_reason.is_none() && !mac.path.is_ident("mockable_items") => {
+            mac.path
+                .span()
+                .unwrap()
+                .warning("Functions generated by the macro invocation won't be mockable")
+                .help("make the items mockable inside of the macro with `mockable_items!`")
+                .emit();
+        }
+        _ => (),
+    }
+}
+
+/// Warns about public functions of the crate annotated with an inner attribute like `audit_item`
+pub fn audit_crate(file: &File, args: &MockableArgs) {
+    audit_mod_items(&file.items, args, None)
 }
 
-fn inject_trait(item_trait: &mut ItemTrait) {
+fn audit_mod_items(items: &[Item], args: &MockableArgs, mod_reason: Option<&'static str>) {
+    let nested_args = MockableArgs {
+        only: None,
+        ..args.clone()
+    };
+    for item in items {
+        let is_selected = match args.only {
+            Some(ref only) => get_item_name(item).is_some_and(|name| only.contains(name)),
+            None => true,
+        };
+        let reason = match *item {
+            _ if mod_reason.is_some() => mod_reason,
+            Item::Mod(_) if !args.recurse => {
+                Some("nested modules aren't made mockable with `recurse = false`")
+            }
+            _ if !is_selected => Some("it isn't selected with `only`"),
+            _ => None,
+        };
+        audit_any_item(item, &nested_args, reason);
+    }
+}
+
+fn is_public(vis: &Visibility) -> bool {
+    !matches!(*vis, Visibility::Inherited)
+}
+
+fn get_not_mockable_reason(attrs: &[Attribute]) -> Option<&'static str> {
+    attrs
+        .iter()
+        .filter_map(|attr| attr.path.segments.last())
+        .any(|segment| segment.ident == "not_mockable")
+        .then_some("it's annotated with `#[not_mockable]`")
+}
+
+fn get_unmockable_reason(
+    args: &MockableArgs,
+    attrs: &[Attribute],
+    sig: &Signature,
+    builder: &FnHeaderBuilder,
+) -> Option<&'static str> {
+    get_not_mockable_reason(attrs)
+        .or_else(|| {
+            (sig.constness.is_some() && !args.const_fn).then_some(
+                "const functions are made mockable only with `#[mockable(const_fn)]`, \
+                which makes them non-const in test builds",
+            )
+        })
+        .or_else(|| {
+            check_sig_mockable(sig, builder)
+                .err()
+                .map(|unmockable| unmockable.message)
+        })
+}
+
+fn warn_unmockable(ident: &Ident, reason: Option<&str>) {
+    if let Some(reason) = reason {
+        ident
+            .span()
+            .unwrap()
+            .warning(format!("Public function `{}` won't be mockable", ident))
+            .note(reason)
+            .emit();
+    }
+}
+
+fn inject_trait(item_trait: &mut ItemTrait, args: &MockableArgs) {
     if is_not_mockable(&item_trait.attrs) {
         return;
     }
 
     let context = Context::Trait;
     for item in &mut item_trait.items {
-        if let TraitItem::Method(TraitItemMethod {
-            ref attrs,
-            ref mut sig,
-            default: Some(ref mut block),
-            ..
-        }) = *item
+        let stopped = split_by_stopper_cfg(item);
+        if let TraitItem::Method(ref mut item_method) = *item {
+            let original = item_method.clone();
+            if let TraitItemMethod {
+                ref mut attrs,
+                ref mut sig,
+                default: Some(ref mut block),
+                ..
+            } = *item_method
+            {
+                inject_any_fn(
+                    args,
+                    context,
+                    &FnHeaderBuilder::TraitDefault,
+                    attrs,
+                    sig,
+                    block,
+                );
+            }
+            if let Some(token_stream) = keep_doc_signature(original, item_method.clone()) {
+                *item = TraitItem::Verbatim(token_stream);
+            }
+        }
+        if let Some(stopped) = stopped {
+            *item = TraitItem::Verbatim(quote!(#stopped #item));
+        }
+    }
+}
+
+pub fn build_mock_struct(item_trait: &ItemTrait) -> TokenStream {
+    let mut methods = Vec::new();
+    for item in &item_trait.items {
+        match *item {
+            TraitItem::Method(TraitItemMethod {
+                ref attrs,
+                ref sig,
+                default: None,
+                ..
+            }) => methods.push((get_cfg_attrs(attrs), sig.clone())),
+            TraitItem::Type(ref item_type) if item_type.default.is_none() => {
+                return syn::Error::new_spanned(
+                    item_type,
+                    "Mock struct can't be generated for traits with associated types",
+                )
+                .to_compile_error()
+            }
+            TraitItem::Const(ref item_const) if item_const.default.is_none() => {
+                return syn::Error::new_spanned(
+                    item_const,
+                    "Mock struct can't be generated for traits with associated consts",
+                )
+                .to_compile_error()
+            }
+            _ => (),
+        }
+    }
+
+    let trait_ident = &item_trait.ident;
+    let struct_ident = format_ident!("Mock{}", trait_ident);
+    let (impl_generics, ty_generics, where_clause) = item_trait.generics.split_for_impl();
+    let trait_path: syn::Path = parse_quote!(#trait_ident #ty_generics);
+    let self_ty: Type = parse_quote!(#struct_ident);
+    let builder = FnHeaderBuilder::TraitImpl(&trait_path.segments);
+    let context = Context::Impl {
+        receiver: &self_ty,
+        impl_generics: &item_trait.generics,
+    };
+    let methods = methods.into_iter().map(|(cfg_attrs, mut sig)| {
+        let message = format!("{}::{} called without a mock", struct_ident, sig.ident);
+        let mut block: Block = parse_quote!({ panic!(#message) });
+        let mut attrs = Vec::new();
+        inject_any_fn(
+            &MockableArgs::default(),
+            context,
+            &builder,
+            &mut attrs,
+            &mut sig,
+            &mut block,
+        );
+        quote! {
+            #(#cfg_attrs)*
+            #(#attrs)*
+            #[allow(unused_variables)]
+            #sig #block
+        }
+    });
+    // The mock exists only when the trait does
+    let cfg_attrs = get_cfg_attrs(&item_trait.attrs);
+    let item_impl = quote! {
+        #(#cfg_attrs)*
+        impl #impl_generics #trait_path for #self_ty #where_clause {
+            #(#methods)*
+        }
+    };
+
+    let vis = &item_trait.vis;
+    let doc = format!(
+        "Mock implementation of [{}], its functions panic unless they are mocked",
+        trait_ident
+    );
+    quote! {
+        #(#cfg_attrs)*
+        #[doc = #doc]
+        #[derive(Clone, Copy, Debug, Default)]
+        #vis struct #struct_ident;
+
+        #item_impl
+    }
+}
+
+/// Builds a builder of a double of the struct, which mocks functions of the impl block in bulk
+pub fn build_double(item_impl: &ItemImpl, vis: &Visibility) -> TokenStream {
+    if !item_impl.generics.params.is_empty() {
+        return syn::Error::new_spanned(
+            &item_impl.generics,
+            "Doubles can't be generated for generic impl blocks",
+        )
+        .to_compile_error();
+    }
+    let struct_ident = match *item_impl.self_ty {
+        Type::Path(TypePath {
+            qself: None,
+            ref path,
+        }) => &path.segments.last().expect("path has segments").ident,
+        _ => {
+            return syn::Error::new_spanned(
+                &item_impl.self_ty,
+                "Doubles can be generated only for impl blocks of named types",
+            )
+            .to_compile_error()
+        }
+    };
+    let double_ident = format_ident!("{}Double", struct_ident);
+    let methods = item_impl
+        .items
+        .iter()
+        .filter_map(|impl_item| match *impl_item {
+            ImplItem::Method(ref item_method) => {
+                build_double_method(&item_impl.self_ty, struct_ident, item_method)
+            }
+            _ => None,
+        });
+    // The double exists only when the impl block does
+    let cfg_attrs = get_cfg_attrs(&item_impl.attrs);
+    let doc = format!(
+        "Builder of a double of [{}], it mocks functions of the struct in bulk",
+        struct_ident
+    );
+    quote! {
+        #(#cfg_attrs)*
+        #[doc = #doc]
+        #[derive(Default)]
+        #vis struct #double_ident<'mocktopus> {
+            context: ::mocktopus::mocking::MockContext<'mocktopus>,
+        }
+
+        #(#cfg_attrs)*
+        #[allow(dead_code)]
+        impl<'mocktopus> #double_ident<'mocktopus> {
+            /// Create a builder without any mocks
+            pub fn new() -> Self {
+                Self::default()
+            }
+
+            #(#methods)*
+
+            /// Make functions, which aren't mocked, panic when they are called, see `MockContext::strict`
+            pub fn strict(self) -> Self {
+                #double_ident {
+                    context: self.context.strict(),
+                }
+            }
+
+            /// Convert into a context with the configured mocks, which can be extended with more mocks
+            pub fn into_context(self) -> ::mocktopus::mocking::MockContext<'mocktopus> {
+                self.context
+            }
+
+            /// Run the function while mocking the configured functions, see `MockContext::run`
+            pub fn run<T, F: FnOnce() -> T>(self, f: F) -> T {
+                self.context.run(f)
+            }
+        }
+    }
+}
+
+// Functions, which can't be mocked with `mock_safe`, are left to be mocked with the context
+fn build_double_method(
+    self_ty: &Type,
+    struct_ident: &Ident,
+    item_method: &ImplItemMethod,
+) -> Option<TokenStream> {
+    let sig = &item_method.sig;
+    let is_returning_impl_trait = match sig.output {
+        ReturnType::Type(_, ref ty) => matches!(**ty, Type::ImplTrait(_)),
+        ReturnType::Default => false,
+    };
+    if is_not_mockable(&item_method.attrs)
+        || sig.asyncness.is_some()
+        || sig.unsafety.is_some()
+        || sig.abi.is_some()
+        || sig.variadic.is_some()
+        || find_destructured_arg(sig).is_some()
+        || is_returning_impl_trait
+    {
+        return None;
+    }
+    let mut sig = sig.clone();
+    desugar_impl_trait_args(&mut sig);
+    let mut namer = FreshLifetimeNamer::default();
+    let mut self_lifetime = None;
+    let mut args = Vec::new();
+    for input in &sig.inputs {
+        let arg = match *input {
+            FnArg::Receiver(Receiver {
+                reference: Some((_, ref lifetime)),
+                ref mutability,
+                ..
+            }) => {
+                let lifetime = lifetime.clone().unwrap_or_else(|| namer.fresh());
+                self_lifetime = Some(lifetime.clone());
+                parse_quote!(&#lifetime #mutability Self)
+            }
+            FnArg::Receiver(_) => parse_quote!(Self),
+            FnArg::Typed(PatType {
+                ref pat, ref ty, ..
+            }) => {
+                let mut ty = (**ty).clone();
+                namer.visit_type_mut(&mut ty);
+                match (&**pat, &ty) {
+                    (Pat::Ident(PatIdent { ident, .. }), Type::Reference(reference))
+                        if ident == "self" =>
+                    {
+                        self_lifetime = reference.lifetime.clone()
+                    }
+                    _ => (),
+                }
+                ty
+            }
+        };
+        args.push(arg);
+    }
+    let mut output: Type = match sig.output {
+        ReturnType::Default => parse_quote!(()),
+        ReturnType::Type(_, ref ty) if matches!(**ty, Type::Never(_)) => {
+            parse_quote!(::mocktopus::mocking::Never)
+        }
+        ReturnType::Type(_, ref ty) => (**ty).clone(),
+    };
+    // Elided output lifetimes are the receiver's one or the only one of the inputs
+    let output_lifetime = match self_lifetime {
+        Some(lifetime) => Some(lifetime),
+        None if namer.all.len() == 1 => namer.all.pop(),
+        None => None,
+    };
+    if let Some(lifetime) = output_lifetime {
+        ElidedLifetimeNamer(lifetime).visit_type_mut(&mut output);
+    }
+    let mut self_replacer = SelfTypeReplacer { receiver: self_ty };
+    args.iter_mut()
+        .for_each(|arg| self_replacer.visit_type_mut(arg));
+    self_replacer.visit_type_mut(&mut output);
+    self_replacer.visit_generics_mut(&mut sig.generics);
+
+    let fn_ident = &sig.ident;
+    let mock_ident = format_ident!("{}_with", fn_ident);
+    let type_args: Vec<Ident> = sig
+        .generics
+        .params
+        .iter()
+        .filter_map(get_generic_param_ident)
+        .collect();
+    let fn_path = match type_args.is_empty() {
+        true => quote!(<#self_ty>::#fn_ident),
+        false => quote!(<#self_ty>::#fn_ident::<#(#type_args),*>),
+    };
+    let params: Vec<_> = sig.generics.params.iter().collect();
+    let predicates: Vec<_> = sig
+        .generics
+        .where_clause
+        .iter()
+        .flat_map(|where_clause| where_clause.predicates.iter())
+        .collect();
+    // Lifetimes must precede other generic parameters
+    let (lifetime_params, other_params): (Vec<_>, Vec<_>) = params
+        .clone()
+        .into_iter()
+        .partition(|param| matches!(param, GenericParam::Lifetime(_)));
+    let fresh_lifetimes = &namer.fresh;
+    let cfg_attrs = get_cfg_attrs(&item_method.attrs);
+    let fn_name = format!("{}::{}", struct_ident, fn_ident);
+    let mock_doc = format!("Mock [{}] with a closure like `mock_safe` does", fn_name);
+    let mock_method = quote! {
+        #(#cfg_attrs)*
+        #[doc = #mock_doc]
+        pub fn #mock_ident<#(#lifetime_params,)* #(#fresh_lifetimes,)* #(#other_params,)* M>(self, mock: M) -> Self
+        where
+            #(#predicates,)*
+            M: FnMut(#(#args),*)
+                -> ::mocktopus::mocking::MockResult<(#(#args,)*), #output> + 'mocktopus,
+        {
+            Self {
+                context: self.context.mock_safe(#fn_path, mock),
+            }
+        }
+    };
+
+    // Returned values are cloned, so they can't borrow from arguments
+    let mut lifetime_finder = LifetimeFinder::default();
+    lifetime_finder.visit_type(&output);
+    let is_returning_value = match sig.output {
+        ReturnType::Type(_, ref ty) => !matches!(**ty, Type::Never(_)),
+        ReturnType::Default => false,
+    };
+    if lifetime_finder.found || !is_returning_value {
+        return Some(mock_method);
+    }
+    let returns_ident = format_ident!("{}_returns", fn_ident);
+    let returns_doc = format!("Mock [{}] to always return a clone of the value", fn_name);
+    let ignored_args = args.iter().map(|_| quote!(_));
+    Some(quote! {
+        #mock_method
+
+        #(#cfg_attrs)*
+        #[doc = #returns_doc]
+        pub fn #returns_ident<#(#params),*>(self, value: #output) -> Self
+        where
+            #(#predicates,)*
+            for<'__mocktopus_clone> #output: Clone + 'mocktopus,
         {
-            inject_any_fn(context, &FnHeaderBuilder::TraitDefault, attrs, sig, block);
+            self.#mock_ident::<#(#type_args,)* _>(move |#(#ignored_args),*| {
+                ::mocktopus::mocking::MockResult::Return(value.clone())
+            })
         }
+    })
+}
+
+/// Names elided lifetimes, each gets a fresh name
+#[derive(Default)]
+struct FreshLifetimeNamer {
+    fresh: Vec<syn::Lifetime>,
+    /// All distinct lifetimes including named ones
+    all: Vec<syn::Lifetime>,
+}
+
+impl FreshLifetimeNamer {
+    fn fresh(&mut self) -> syn::Lifetime {
+        let lifetime = syn::Lifetime::new(
+            &format!("'__mocktopus_{}", self.fresh.len()),
+            proc_macro2::Span::call_site(),
+        );
+        self.fresh.push(lifetime.clone());
+        self.all.push(lifetime.clone());
+        lifetime
+    }
+}
+
+impl VisitMut for FreshLifetimeNamer {
+    fn visit_type_reference_mut(&mut self, reference: &mut syn::TypeReference) {
+        if reference.lifetime.is_none() {
+            reference.lifetime = Some(self.fresh());
+        }
+        visit_mut::visit_type_reference_mut(self, reference);
+    }
+
+    fn visit_lifetime_mut(&mut self, lifetime: &mut syn::Lifetime) {
+        if lifetime.ident == "_" {
+            *lifetime = self.fresh();
+        } else if !self.all.contains(lifetime) {
+            self.all.push(lifetime.clone());
+        }
+    }
+
+    fn visit_type_bare_fn_mut(&mut self, _: &mut syn::TypeBareFn) {}
+
+    fn visit_parenthesized_generic_arguments_mut(
+        &mut self,
+        _: &mut syn::ParenthesizedGenericArguments,
+    ) {
+    }
+}
+
+#[derive(Default)]
+struct LifetimeFinder {
+    found: bool,
+}
+
+impl<'ast> Visit<'ast> for LifetimeFinder {
+    fn visit_lifetime(&mut self, _: &'ast syn::Lifetime) {
+        self.found = true;
+    }
+
+    fn visit_type_reference(&mut self, _: &'ast syn::TypeReference) {
+        self.found = true;
     }
 }
 
-fn inject_impl(item_impl: &mut ItemImpl) {
+fn inject_impl(item_impl: &mut ItemImpl, args: &MockableArgs) {
     if is_not_mockable(&item_impl.attrs) {
         return;
     }
@@ -85,20 +999,127 @@ fn inject_impl(item_impl: &mut ItemImpl) {
     };
 
     for impl_item in &mut item_impl.items {
-        if let ImplItem::Method(ref mut item_method) = *impl_item {
-            if is_impl_fn_mockabile(&builder, item_method) {
-                inject_any_fn(
-                    context,
-                    &builder,
-                    &item_method.attrs,
-                    &mut item_method.sig,
-                    &mut item_method.block,
-                );
+        let stopped = split_by_stopper_cfg(impl_item);
+        inject_impl_item(impl_item, args, context, &builder);
+        if let Some(stopped) = stopped {
+            *impl_item = ImplItem::Verbatim(quote!(#stopped #impl_item));
+        }
+    }
+}
+
+fn inject_impl_item(
+    impl_item: &mut ImplItem,
+    args: &MockableArgs,
+    context: Context,
+    builder: &FnHeaderBuilder,
+) {
+    if let ImplItem::Method(ref item_method) = *impl_item {
+        if is_const_fn_mockable(args, &item_method.attrs, &item_method.sig) {
+            *impl_item = inject_const_impl_fn(args, context, builder, item_method);
+            return;
+        }
+    }
+    if let ImplItem::Method(ref mut item_method) = *impl_item {
+        if is_impl_fn_mockabile(builder, item_method) {
+            let original = item_method.clone();
+            inject_any_fn(
+                args,
+                context,
+                builder,
+                &mut item_method.attrs,
+                &mut item_method.sig,
+                &mut item_method.block,
+            );
+            if let Some(token_stream) = keep_doc_signature(original, item_method.clone()) {
+                *impl_item = ImplItem::Verbatim(token_stream);
             }
         }
     }
 }
 
+/// Functions, which may have their signatures changed by injecting
+trait FnItem: ToTokens {
+    fn attrs_mut(&mut self) -> &mut Vec<Attribute>;
+
+    fn sig_mut(&mut self) -> &mut Signature;
+}
+
+impl FnItem for ItemFn {
+    fn attrs_mut(&mut self) -> &mut Vec<Attribute> {
+        &mut self.attrs
+    }
+
+    fn sig_mut(&mut self) -> &mut Signature {
+        &mut self.sig
+    }
+}
+
+impl FnItem for ImplItemMethod {
+    fn attrs_mut(&mut self) -> &mut Vec<Attribute> {
+        &mut self.attrs
+    }
+
+    fn sig_mut(&mut self) -> &mut Signature {
+        &mut self.sig
+    }
+}
+
+impl FnItem for TraitItemMethod {
+    fn attrs_mut(&mut self) -> &mut Vec<Attribute> {
+        &mut self.attrs
+    }
+
+    fn sig_mut(&mut self) -> &mut Signature {
+        &mut self.sig
+    }
+}
+
+// Injecting changes signatures of e.g. async functions or ones with `impl Trait` or `_` arguments,
+// so rustdoc gets the functions as they were written, except for outputs boxed on request
+fn keep_doc_signature<F: FnItem>(mut original: F, mut injected: F) -> Option<TokenStream> {
+    if let ReturnType::Type(_, ref ty) = original.sig_mut().output {
+        if let Type::ImplTrait(_) = **ty {
+            original.sig_mut().output = injected.sig_mut().output.clone();
+        }
+    }
+    let original_sig = original.sig_mut().to_token_stream().to_string();
+    if original_sig == injected.sig_mut().to_token_stream().to_string() {
+        return None;
+    }
+    original.attrs_mut().push(parse_quote!(#[cfg(doc)]));
+    injected.attrs_mut().push(parse_quote!(#[cfg(not(doc))]));
+    Some(quote!(#original #injected))
+}
+
+fn inject_const_impl_fn(
+    args: &MockableArgs,
+    context: Context,
+    builder: &FnHeaderBuilder,
+    item_method: &ImplItemMethod,
+) -> ImplItem {
+    let mut const_method = item_method.clone();
+    const_method.attrs.push(parse_quote!(#[cfg(not(test))]));
+    let mut test_method = item_method.clone();
+    test_method.attrs.push(parse_quote!(#[cfg(test)]));
+    test_method.sig.constness = None;
+    inject_any_fn(
+        args,
+        context,
+        builder,
+        &mut test_method.attrs,
+        &mut test_method.sig,
+        &mut test_method.block,
+    );
+    ImplItem::Verbatim(quote!(#const_method #test_method))
+}
+
+fn is_const_fn_mockable(args: &MockableArgs, attrs: &Vec<Attribute>, fn_decl: &Signature) -> bool {
+    args.const_fn
+        && fn_decl.constness.is_some()
+        && fn_decl.variadic.is_none()
+        && !is_not_mockable(attrs)
+}
+
 fn is_impl_fn_mockabile(builder: &FnHeaderBuilder, item_method: &ImplItemMethod) -> bool {
     if let FnHeaderBuilder::TraitImpl(ref segments) = *builder {
         if let Some(segment) = segments.last() {
@@ -112,35 +1133,143 @@ fn is_impl_fn_mockabile(builder: &FnHeaderBuilder, item_method: &ImplItemMethod)
     true
 }
 
+// Skipped functions are pointed at, so it's clear why mocking them has no effect
+fn warn_not_mockable(spanned: &impl Spanned, message: &str, help: &str) {
+    spanned.span().unwrap().warning(message).help(help).emit();
+}
+
+/// Part of a function's signature, which prevents making the function mockable
+struct Unmockable {
+    span: Span,
+    message: &'static str,
+    help: &'static str,
+}
+
+impl Unmockable {
+    fn new(spanned: &impl Spanned, message: &'static str, help: &'static str) -> Self {
+        Unmockable {
+            span: spanned.span(),
+            message,
+            help,
+        }
+    }
+
+    fn warn(&self) {
+        warn_not_mockable(&self.span, self.message, self.help)
+    }
+}
+
+// Both injecting and auditing use it, so they agree on which functions are skipped
+fn check_sig_mockable(sig: &Signature, builder: &FnHeaderBuilder) -> Result<(), Unmockable> {
+    if let Some(ref variadic) = sig.variadic {
+        return Err(Unmockable::new(
+            variadic,
+            "Variadic functions can't be made mockable",
+            "call the function from a mockable function taking a fixed number of arguments",
+        ));
+    }
+    if let Some(destructured_arg) = find_destructured_arg(sig) {
+        return Err(Unmockable::new(
+            destructured_arg,
+            "Functions with destructured arguments can't be made mockable",
+            "take the arguments whole and destructure them in the function body",
+        ));
+    }
+    if let (Some(ref asyncness), FnHeaderBuilder::TraitDefault) = (sig.asyncness, builder) {
+        return Err(Unmockable::new(
+            asyncness,
+            "Async default functions of traits can't be made mockable",
+            "make the functions of trait impls mockable or annotate the trait with `#[async_trait]`",
+        ));
+    }
+    match (find_impl_trait_arg(sig), builder) {
+        // Trait functions must keep signatures declared by the trait
+        (Some(impl_trait_arg), FnHeaderBuilder::TraitDefault | FnHeaderBuilder::TraitImpl(_)) => {
+            Err(Unmockable::new(
+                impl_trait_arg,
+                "Trait functions taking `impl Trait` arguments can't be made mockable",
+                "declare the trait function with type parameters, e.g. `fn f<T: Trait>(a: T)`",
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
 fn inject_any_fn(
+    args: &MockableArgs,
     context: Context,
     builder: &FnHeaderBuilder,
-    attrs: &Vec<Attribute>,
+    attrs: &mut Vec<Attribute>,
     fn_decl: &mut Signature,
     block: &mut Block,
 ) {
     if fn_decl.constness.is_some()
-        || fn_decl.unsafety.is_some()
-        || fn_decl.variadic.is_some()
         || is_not_mockable(attrs)
+        || header_builder::is_header_injected(block)
     {
         return;
     }
 
+    if let Err(unmockable) = check_sig_mockable(fn_decl, builder) {
+        return unmockable.warn();
+    }
+
+    if find_impl_trait_arg(fn_decl).is_some() {
+        desugar_impl_trait_args(fn_decl)
+    }
+
+    // Outputs of async functions become outputs of their futures, so they are boxed first
+    if args.box_impl_trait {
+        box_impl_trait_output(fn_decl, block);
+    }
+
     if let Some(_) = fn_decl.asyncness {
-        inject_async_fn(context, attrs, fn_decl, block);
+        match *builder {
+            FnHeaderBuilder::TraitImpl(_) => inject_trait_impl_async_fn(attrs, fn_decl, block),
+            _ => inject_async_fn(context, args, attrs, fn_decl, block),
+        }
     }
 
     unignore_fn_args(&mut fn_decl.inputs);
-    let header_stmt = builder.build(fn_decl, block.brace_token.span);
+    let header_stmt = match builder.build(fn_decl, block.brace_token.span) {
+        Ok(header_stmt) => header_stmt,
+        Err(error) => {
+            let compile_error = error.to_compile_error();
+            parse_quote!(#compile_error;)
+        }
+    };
     block.stmts.insert(0, header_stmt);
 }
 
+// Trait impls must keep signatures of their traits, so async functions return futures of either
+// their bodies or their mocks, which are returned by the header
+fn inject_trait_impl_async_fn(attrs: &mut Vec<Attribute>, sig: &mut Signature, block: &mut Block) {
+    let output = match sig.output {
+        ReturnType::Default => quote!(()),
+        ReturnType::Type(_, ref ty) if matches!(**ty, Type::Never(_)) => {
+            quote!(::mocktopus::mocking::Never)
+        }
+        ReturnType::Type(_, ref ty) => quote!(#ty),
+    };
+    sig.asyncness = None;
+    sig.output = parse_quote! {
+        -> ::mocktopus::mocking::AsyncMockFuture<impl ::core::future::Future<Output = #output>>
+    };
+    attrs.push(parse_quote!(#[allow(refining_impl_trait)]));
+    let brace = block.brace_token;
+    let body = quote_spanned!(brace.span=> {
+        ::mocktopus::mocking::AsyncMockFuture::Real(async move #block)
+    });
+    *block = parse_quote!(#body);
+    block.brace_token = brace;
+}
+
 // Transform async functions as `async-trait`
 // See: https://github.com/dtolnay/async-trait
 fn inject_async_fn(
     context: Context,
-    attrs: &Vec<Attribute>,
+    mockable_args: &MockableArgs,
+    attrs: &mut Vec<Attribute>,
     outer_sig: &mut Signature,
     block: &mut Block,
 ) {
@@ -161,8 +1290,9 @@ fn inject_async_fn(
 
     let mut generics = outer_sig
         .generics
-        .type_params()
-        .map(|param| param.ident.clone())
+        .params
+        .iter()
+        .filter_map(get_generic_param_ident)
         .collect::<Vec<_>>();
 
     let mut inner_sig = outer_sig.clone();
@@ -170,15 +1300,34 @@ fn inject_async_fn(
     let inner_ident = format_ident!("__{}", outer_sig.ident);
     inner_sig.ident = inner_ident.clone();
 
-    if let Context::Impl { impl_generics, .. } = context {
+    if let Context::Impl {
+        receiver,
+        impl_generics,
+    } = context
+    {
         // declare impl generics on inner fn
         inner_sig
             .generics
             .params
             .extend(impl_generics.params.clone());
+        if let Some(ref impl_where_clause) = impl_generics.where_clause {
+            inner_sig
+                .generics
+                .make_where_clause()
+                .predicates
+                .extend(impl_where_clause.predicates.clone());
+        }
+
+        // inner fn is not associated, so `Self` in bounds and types must be named explicitly
+        SelfTypeReplacer { receiver }.visit_signature_mut(&mut inner_sig);
 
         // add impl generics to inner call
-        generics.extend(impl_generics.type_params().map(|param| param.ident.clone()))
+        generics.extend(
+            impl_generics
+                .params
+                .iter()
+                .filter_map(get_generic_param_ident),
+        )
     }
 
     match inner_sig.inputs.iter_mut().next() {
@@ -225,16 +1374,45 @@ fn inject_async_fn(
                 _ => (),
             };
         }
+        // arbitrary self types, e.g. `self: Arc<Self>`
+        Some(FnArg::Typed(PatType { pat, .. })) => {
+            if let Pat::Ident(PatIdent { ref mut ident, .. }) = **pat {
+                if ident == "self" {
+                    *ident = Ident::new("_self", ident.span());
+                }
+            }
+        }
         _ => {}
     };
 
     for stmt in &mut block.stmts {
         replace_self_in_stmt(stmt);
     }
+    if let Context::Impl { receiver, .. } = context {
+        SelfTypeReplacer { receiver }.visit_block_mut(block);
+    }
+
+    // items are moved out of the standalone fn, so nested fns keep their paths like `outer::inner`
+    let (items, stmts): (Vec<_>, Vec<_>) = block.stmts.drain(..).partition(|stmt| match *stmt {
+        // macro invocations may expand to statements, which must keep their order
+        syn::Stmt::Item(Item::Macro(ItemMacro { ident: None, .. })) => false,
+        syn::Stmt::Item(_) => true,
+        _ => false,
+    });
+    block.stmts = stmts;
+
+    // the original body runs in the standalone fn, so attributes affecting bodies are copied to it
+    // and custom ones, e.g. `#[tracing::instrument]`, are moved to it
+    let inner_attrs = attrs
+        .iter()
+        .filter(|attr| is_body_attr(attr) || !is_builtin_attr(attr))
+        .cloned()
+        .collect();
+    attrs.retain(is_builtin_attr);
 
     // this is the standalone async fn
     let inner_fn = ItemFn {
-        attrs: attrs.clone(),
+        attrs: inner_attrs,
         vis: Visibility::Inherited,
         sig: inner_sig,
         block: Box::new(block.clone()),
@@ -248,8 +1426,9 @@ fn inject_async_fn(
     *block = parse_quote!(#box_pin);
     block.brace_token = brace;
 
-    // insert standalone function at start
+    // insert standalone function and moved items at start
     block.stmts.insert(0, syn::Stmt::Item(Item::Fn(inner_fn)));
+    block.stmts.splice(0..0, items);
 
     let where_clause = outer_sig
         .generics
@@ -262,8 +1441,7 @@ fn inject_async_fn(
     let mut outer_sig_inputs = outer_sig.inputs.iter_mut();
     while let Some(input) = outer_sig_inputs.next() {
         match input {
-            arg
-            @ FnArg::Receiver(Receiver {
+            arg @ FnArg::Receiver(Receiver {
                 reference: Some(_), ..
             }) => {
                 let (self_token, mutability) = match arg {
@@ -312,6 +1490,8 @@ fn inject_async_fn(
                     *arg = parse_quote! {
                         #pat #colon_token #and_token 'mocktopus #mutability #elem
                     };
+                } else {
+                    ElidedLifetimeNamer(parse_quote!('mocktopus)).visit_type_mut(ty);
                 }
             }
         }
@@ -345,9 +1525,16 @@ fn inject_async_fn(
 
     let ret = match &outer_sig.output {
         ReturnType::Default => quote!(()),
+        ReturnType::Type(_, ret) if matches!(**ret, Type::Never(_)) => {
+            quote!(::mocktopus::mocking::Never)
+        }
         ReturnType::Type(_, ret) => quote!(#ret),
     };
-    let bounds = quote!(::core::marker::Send + 'mocktopus);
+    let bounds = if mockable_args.local {
+        quote!('mocktopus)
+    } else {
+        quote!(::core::marker::Send + 'mocktopus)
+    };
     outer_sig.output = parse_quote! {
         -> ::core::pin::Pin<Box<
             dyn ::core::future::Future<Output = #ret> + #bounds
@@ -355,6 +1542,256 @@ fn inject_async_fn(
     };
 }
 
+fn get_generic_param_ident(param: &GenericParam) -> Option<Ident> {
+    match *param {
+        GenericParam::Type(ref type_param) => Some(type_param.ident.clone()),
+        GenericParam::Const(ref const_param) => Some(const_param.ident.clone()),
+        GenericParam::Lifetime(_) => None,
+    }
+}
+
+fn find_destructured_arg(fn_decl: &Signature) -> Option<&FnArg> {
+    fn_decl.inputs.iter().find(|input| match **input {
+        FnArg::Typed(PatType { ref pat, .. }) => !matches!(**pat, Pat::Ident(_) | Pat::Wild(_)),
+        FnArg::Receiver(_) => false,
+    })
+}
+
+fn find_impl_trait_arg(fn_decl: &Signature) -> Option<&FnArg> {
+    fn_decl.inputs.iter().find(|input| {
+        let mut finder = ImplTraitArgsDesugarer::default();
+        finder.visit_fn_arg_mut(&mut (*input).clone());
+        !finder.params.is_empty()
+    })
+}
+
+// Mock IDs of functions with anonymous type parameters can't be named,
+// so they are replaced with named ones, e.g. `fn f(a: impl Display)` becomes `fn f<A: Display>(a: A)`
+fn desugar_impl_trait_args(fn_decl: &mut Signature) {
+    let mut desugarer = ImplTraitArgsDesugarer::default();
+    fn_decl
+        .inputs
+        .iter_mut()
+        .for_each(|input| desugarer.visit_fn_arg_mut(input));
+    fn_decl.generics.params.extend(desugarer.params);
+}
+
+#[derive(Default)]
+struct ImplTraitArgsDesugarer {
+    params: Vec<GenericParam>,
+}
+
+impl VisitMut for ImplTraitArgsDesugarer {
+    // Parameters are ordered like `impl` keywords in the signature, nested ones come after outer ones
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        let impl_trait = match *ty {
+            Type::ImplTrait(ref mut impl_trait) => impl_trait,
+            _ => return visit_mut::visit_type_mut(self, ty),
+        };
+        let index = self.params.len();
+        let ident = format_ident!("__MocktopusImplArg{}", index);
+        self.params.push(parse_quote!(#ident));
+        visit_mut::visit_type_impl_trait_mut(self, impl_trait);
+        let bounds = &impl_trait.bounds;
+        self.params[index] = parse_quote!(#ident: #bounds);
+        *ty = parse_quote!(#ident);
+    }
+}
+
+// Elided lifetimes nested in argument types, e.g. in `Pin<&mut Self>`, must outlive the future,
+// elided lifetimes of outputs in doubles must be the ones chosen by the elision rules
+struct ElidedLifetimeNamer(syn::Lifetime);
+
+impl VisitMut for ElidedLifetimeNamer {
+    fn visit_type_reference_mut(&mut self, reference: &mut syn::TypeReference) {
+        if reference.lifetime.is_none() {
+            reference.lifetime = Some(self.0.clone());
+        }
+        visit_mut::visit_type_reference_mut(self, reference);
+    }
+
+    fn visit_lifetime_mut(&mut self, lifetime: &mut syn::Lifetime) {
+        if lifetime.ident == "_" {
+            *lifetime = self.0.clone();
+        }
+    }
+
+    // Lifetimes elided in `fn(&T)` and `Fn(&T)` are higher-ranked
+    fn visit_type_bare_fn_mut(&mut self, _: &mut syn::TypeBareFn) {}
+
+    fn visit_parenthesized_generic_arguments_mut(
+        &mut self,
+        _: &mut syn::ParenthesizedGenericArguments,
+    ) {
+    }
+}
+
+struct SelfTypeReplacer<'a> {
+    receiver: &'a Type,
+}
+
+impl<'a> VisitMut for SelfTypeReplacer<'a> {
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        match *ty {
+            Type::Path(TypePath {
+                qself: None,
+                ref path,
+            }) if path.is_ident("Self") => *ty = self.receiver.clone(),
+            _ => visit_mut::visit_type_mut(self, ty),
+        }
+    }
+
+    // Paths of associated items, e.g. `Self::CONST`, become `<Receiver>::CONST`
+    fn visit_expr_path_mut(&mut self, expr_path: &mut syn::ExprPath) {
+        let is_self_prefixed = expr_path.qself.is_none()
+            && expr_path.path.leading_colon.is_none()
+            && expr_path.path.segments.len() > 1
+            && expr_path.path.segments[0].ident == "Self";
+        if is_self_prefixed {
+            let mut segments = std::mem::take(&mut expr_path.path.segments).into_pairs();
+            let self_segment = segments.next().expect("path has multiple segments");
+            expr_path.path.leading_colon = self_segment.punct().cloned();
+            expr_path.path.segments = segments.collect();
+            expr_path.qself = Some(syn::QSelf {
+                lt_token: Default::default(),
+                ty: Box::new(self.receiver.clone()),
+                position: 0,
+                as_token: None,
+                gt_token: Default::default(),
+            });
+        }
+        visit_mut::visit_expr_path_mut(self, expr_path);
+    }
+
+    fn visit_expr_struct_mut(&mut self, expr_struct: &mut syn::ExprStruct) {
+        self.replace_self_segment(&mut expr_struct.path);
+        visit_mut::visit_expr_struct_mut(self, expr_struct);
+    }
+
+    fn visit_pat_path_mut(&mut self, pat_path: &mut syn::PatPath) {
+        if pat_path.qself.is_none() {
+            self.replace_self_segment(&mut pat_path.path);
+        }
+        visit_mut::visit_pat_path_mut(self, pat_path);
+    }
+
+    fn visit_pat_struct_mut(&mut self, pat_struct: &mut syn::PatStruct) {
+        self.replace_self_segment(&mut pat_struct.path);
+        visit_mut::visit_pat_struct_mut(self, pat_struct);
+    }
+
+    fn visit_pat_tuple_struct_mut(&mut self, pat_tuple_struct: &mut syn::PatTupleStruct) {
+        self.replace_self_segment(&mut pat_tuple_struct.path);
+        visit_mut::visit_pat_tuple_struct_mut(self, pat_tuple_struct);
+    }
+
+    // `Self` in nested items refers to other types
+    fn visit_item_mut(&mut self, _: &mut Item) {}
+}
+
+impl<'a> SelfTypeReplacer<'a> {
+    // Paths in patterns and struct expressions can't be qualified, so `Self` is replaced with
+    // the receiver's path, e.g. `Self::Variant(value)` becomes `Enum::<T>::Variant(value)`
+    fn replace_self_segment(&self, path: &mut syn::Path) {
+        let receiver_path = match *self.receiver {
+            Type::Path(TypePath {
+                qself: None,
+                ref path,
+            }) => path,
+            _ => return,
+        };
+        let is_self_prefixed = path.leading_colon.is_none()
+            && path
+                .segments
+                .first()
+                .is_some_and(|segment| segment.ident == "Self");
+        if !is_self_prefixed {
+            return;
+        }
+        let mut segments = receiver_path.segments.clone();
+        for segment in &mut segments {
+            if let PathArguments::AngleBracketed(ref mut arguments) = segment.arguments {
+                arguments.colon2_token = Some(Default::default());
+            }
+        }
+        segments.extend(path.segments.iter().skip(1).cloned());
+        path.leading_colon = receiver_path.leading_colon;
+        path.segments = segments;
+    }
+}
+
+// Mocks can't create values of opaque types, but they can create boxed trait objects
+#[derive(Default)]
+struct ReturnFinder {
+    has_return: bool,
+}
+
+impl<'ast> Visit<'ast> for ReturnFinder {
+    fn visit_expr_return(&mut self, _: &'ast ExprReturn) {
+        self.has_return = true;
+    }
+
+    // Returns of nested closures, async blocks and items return from them
+    fn visit_expr_closure(&mut self, _: &'ast ExprClosure) {}
+
+    fn visit_expr_async(&mut self, _: &'ast ExprAsync) {}
+
+    fn visit_item(&mut self, _: &'ast Item) {}
+}
+
+fn box_impl_trait_output(fn_decl: &mut Signature, block: &mut Block) {
+    let bounds = match fn_decl.output {
+        ReturnType::Type(_, ref ty) => match **ty {
+            Type::ImplTrait(ref impl_trait) => impl_trait.bounds.clone(),
+            _ => return,
+        },
+        ReturnType::Default => return,
+    };
+    // Trait objects capture lifetimes with their bounds, so precise capturing bounds are dropped
+    let bounds: Punctuated<TypeParamBound, Add> = bounds
+        .into_iter()
+        .filter(|bound| match *bound {
+            TypeParamBound::Trait(ref trait_bound) => {
+                trait_bound.path.segments[0].ident != escaping::USE_BOUND_NAME
+            }
+            TypeParamBound::Lifetime(_) => true,
+        })
+        .collect();
+    // Futures and streams can be polled only when pinned
+    let is_pinned = bounds.iter().any(|bound| match *bound {
+        TypeParamBound::Trait(ref trait_bound) => trait_bound
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Future" || segment.ident == "Stream"),
+        TypeParamBound::Lifetime(_) => false,
+    });
+
+    // The body with returns is wrapped in a closure, so they produce the unboxed value,
+    // other bodies aren't, because closures returned by them need the expected type.
+    // Bodies of async functions may await, so they are wrapped in async blocks instead.
+    let brace = block.brace_token;
+    let mut return_finder = ReturnFinder::default();
+    return_finder.visit_block(block);
+    let body = match (return_finder.has_return, &block.stmts[..]) {
+        (true, _) if fn_decl.asyncness.is_some() => {
+            quote_spanned!(brace.span=> (async move #block).await)
+        }
+        (true, _) => quote_spanned!(brace.span=> (move || #block)()),
+        // Braces around a single expression would trigger `unused_braces`
+        (false, [syn::Stmt::Expr(expr)]) => quote!(#expr),
+        (false, _) => quote!(#block),
+    };
+    if is_pinned {
+        fn_decl.output = parse_quote!(-> ::core::pin::Pin<Box<dyn #bounds>>);
+        *block = parse_quote!({ Box::pin(#body) });
+    } else {
+        fn_decl.output = parse_quote!(-> Box<dyn #bounds>);
+        *block = parse_quote!({ Box::new(#body) });
+    }
+    block.brace_token = brace;
+}
+
 fn unignore_fn_args(inputs: &mut Punctuated<FnArg, Comma>) {
     for (i, fn_arg) in inputs.iter_mut().enumerate() {
         if let FnArg::Typed(PatType { ref mut pat, .. }) = *fn_arg {
@@ -377,6 +1814,156 @@ fn unignore_fn_args(inputs: &mut Punctuated<FnArg, Comma>) {
 
 const INJECTOR_STOPPER_ATTRS: [&str; 2] = ["mockable", "not_mockable"];
 
+/// Items, which can be annotated with injector stoppers
+trait StoppableItem: Clone + ToTokens {
+    fn attrs_mut(&mut self) -> Option<&mut Vec<Attribute>>;
+}
+
+impl StoppableItem for Item {
+    fn attrs_mut(&mut self) -> Option<&mut Vec<Attribute>> {
+        match *self {
+            Item::Fn(ref mut item_fn) => Some(&mut item_fn.attrs),
+            Item::Mod(ref mut item_mod) => Some(&mut item_mod.attrs),
+            Item::Trait(ref mut item_trait) => Some(&mut item_trait.attrs),
+            Item::Impl(ref mut item_impl) => Some(&mut item_impl.attrs),
+            _ => None,
+        }
+    }
+}
+
+impl StoppableItem for ImplItem {
+    fn attrs_mut(&mut self) -> Option<&mut Vec<Attribute>> {
+        match *self {
+            ImplItem::Method(ref mut item_method) => Some(&mut item_method.attrs),
+            _ => None,
+        }
+    }
+}
+
+impl StoppableItem for TraitItem {
+    fn attrs_mut(&mut self) -> Option<&mut Vec<Attribute>> {
+        match *self {
+            TraitItem::Method(ref mut item_method) => Some(&mut item_method.attrs),
+            _ => None,
+        }
+    }
+}
+
+// Stoppers in `cfg_attr`s of nested items, e.g. `#[cfg_attr(test, not_mockable)]`, aren't expanded
+// before the enclosing item is injected, so the item is split into a copy left for the stopper,
+// which is returned, and a copy to inject, each compiled depending on the predicate
+fn split_by_stopper_cfg<T: StoppableItem>(item: &mut T) -> Option<T> {
+    let stopper_cfg = get_stopper_cfg(item.attrs_mut()?)?;
+    let mut stopped = item.clone();
+    stopped
+        .attrs_mut()?
+        .push(parse_quote!(#[cfg(#stopper_cfg)]));
+    item.attrs_mut()?
+        .push(parse_quote!(#[cfg(not(#stopper_cfg))]));
+    Some(stopped)
+}
+
+/// Predicate, under which `cfg_attr`s apply injector stoppers
+fn get_stopper_cfg(attrs: &[Attribute]) -> Option<TokenStream> {
+    let mut predicates: Vec<TokenStream> = attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("cfg_attr"))
+        .filter_map(|attr| attr.parse_args_with(parse_stopper_cfg).ok())
+        .flatten()
+        .collect();
+    match predicates.len() {
+        0 => None,
+        1 => predicates.pop(),
+        _ => Some(quote!(any(#(#predicates),*))),
+    }
+}
+
+// Parses contents of `cfg_attr`, nested `cfg_attr`s apply stoppers when all predicates hold
+fn parse_stopper_cfg(input: ParseStream) -> syn::Result<Option<TokenStream>> {
+    let predicate: syn::NestedMeta = input.parse()?;
+    let mut nested_predicates = Vec::new();
+    let mut is_stopping = false;
+    while !input.is_empty() {
+        input.parse::<Comma>()?;
+        if input.is_empty() {
+            break;
+        }
+        let path = input.call(syn::Path::parse_mod_style)?;
+        let mut args = Vec::new();
+        while !input.is_empty() && !input.peek(Comma) {
+            args.push(input.parse::<TokenTree>()?);
+        }
+        match args.first() {
+            Some(TokenTree::Group(group)) if path.is_ident("cfg_attr") => {
+                nested_predicates.extend(parse_stopper_cfg.parse2(group.stream())?);
+            }
+            _ => {
+                is_stopping |= path.segments.last().is_some_and(|segment| {
+                    INJECTOR_STOPPER_ATTRS.contains(&segment.ident.to_string().as_str())
+                })
+            }
+        }
+    }
+    let predicate = predicate.into_token_stream();
+    Ok(match (is_stopping, nested_predicates.is_empty()) {
+        (true, _) => Some(predicate),
+        (false, true) => None,
+        (false, false) => Some(quote!(all(#predicate, any(#(#nested_predicates),*)))),
+    })
+}
+
+fn get_cfg_attrs(attrs: &[Attribute]) -> Vec<&Attribute> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("cfg"))
+        .collect()
+}
+
+// Attributes affecting function bodies, lint levels and tool attributes apply to code inside them
+const BODY_ATTRS: [&str; 17] = [
+    "cfg",
+    "cfg_attr",
+    "allow",
+    "warn",
+    "deny",
+    "forbid",
+    "expect",
+    "inline",
+    "cold",
+    "optimize",
+    "coverage",
+    "target_feature",
+    "instruction_set",
+    "rustfmt",
+    "clippy",
+    "rustdoc",
+    "diagnostic",
+];
+
+// Attributes describing how functions are called or documented
+const SIGNATURE_ATTRS: [&str; 5] = [
+    "doc",
+    "must_use",
+    "deprecated",
+    "track_caller",
+    "automatically_derived",
+];
+
+fn get_attr_name(attr: &Attribute) -> String {
+    attr.path.segments[0].ident.to_string()
+}
+
+fn is_body_attr(attr: &Attribute) -> bool {
+    BODY_ATTRS.contains(&get_attr_name(attr).as_str())
+}
+
+fn is_builtin_attr(attr: &Attribute) -> bool {
+    let name = get_attr_name(attr);
+    BODY_ATTRS.contains(&name.as_str())
+        || SIGNATURE_ATTRS.contains(&name.as_str())
+        || get_linkage_attr_name(attr).is_some()
+}
+
 fn is_not_mockable(attrs: &Vec<Attribute>) -> bool {
     attrs
         .iter()
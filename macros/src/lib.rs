@@ -7,9 +7,14 @@
 
 extern crate proc_macro;
 
+mod attr_path;
 mod display_delegate;
+mod escaping;
 mod header_builder;
 mod item_injector;
+mod mockable_args;
+mod mod_loader;
+mod test_wrapper;
 
 use proc_macro::{Span, TokenStream};
 use quote::ToTokens;
@@ -27,6 +32,30 @@ use quote::ToTokens;
 ///     }
 /// }
 /// ```
+/// - module declarations (requires `#![feature(proc_macro_hygiene)]`), the modules' files
+///   are found like by the compiler, also through `#[path]` attributes, and their items are inlined.
+///   The declarations must not be nested in inline modules. Nested module declarations aren't
+///   made mockable, annotate them or their files too. The inlined items lose their locations,
+///   so compiler errors, `line!()`, panics and debuggers point at the declaration,
+///   prefer annotating the files with an inner attribute.
+///
+/// ```
+/// #[mockable]
+/// #[path = "other/module.rs"]
+/// mod module;
+/// ```
+/// - modules and crates with an inner attribute (requires `#![feature(custom_inner_attributes)]`,
+///   crate roots also require `#![feature(prelude_import)]` and `#![allow(internal_features)]`,
+///   modules in files also require `#![feature(proc_macro_hygiene)]`)
+///
+/// ```
+/// // lib.rs
+/// #![feature(custom_inner_attributes, prelude_import)]
+/// #![allow(internal_features)]
+/// #![mocktopus::macros::mockable]
+///
+/// fn mockable() { ... }
+/// ```
 /// - standalone functions
 ///
 /// ```
@@ -49,6 +78,15 @@ use quote::ToTokens;
 ///     fn mockable() { ... }
 /// }
 /// ```
+/// - blanket trait impl blocks, their functions are mocked separately for each implementing type,
+///   e.g. mocking `<u32 as Trait>::mockable` doesn't affect `<String as Trait>::mockable`
+///
+/// ```
+/// #[mockable]
+/// impl<T: Display> Trait for T {
+///     fn mockable(&self) { ... }
+/// }
+/// ```
 /// - traits (makes all default functions inside mockable)
 ///
 /// ```
@@ -57,6 +95,283 @@ use quote::ToTokens;
 ///     fn mockable() { ... }
 /// }
 /// ```
+/// - const standalone functions and const functions in struct impl blocks annotated with
+///   `#[mockable(const_fn)]` (they are mockable only in `cfg(test)` builds, where they aren't const,
+///   so calls in const contexts fail to compile there)
+///
+/// ```
+/// #[mockable(const_fn)]
+/// const fn mockable() -> u32 { ... }
+/// ```
+/// # Generating mock structs
+/// Traits annotated with `#[mockable(mock_struct)]` get a struct named like the trait with
+/// a `Mock` prefix and the same visibility. It implements all trait functions without default
+/// implementations, which panic unless they are mocked, so it can be used e.g. as a trait object.
+/// Default functions are mockable like for any other implementation. Async functions are mocked
+/// with `mock_async` like async functions of other trait impls.
+///
+/// ```
+/// #[mockable(mock_struct)]
+/// pub trait Greeter {
+///     fn greet(&self, name: &str) -> String;
+/// }
+///
+/// fn welcome(greeter: &dyn Greeter) -> String {
+///     greeter.greet("world")
+/// }
+///
+/// #[test]
+/// fn welcome_test() {
+///     MockGreeter::greet.mock_safe(|_, name| MockResult::Return(format!("Hi {}", name)));
+///
+///     assert_eq!("Hi world", welcome(&MockGreeter));
+/// }
+/// ```
+/// Traits with associated types or consts without defaults can't have mock structs.
+///
+/// - functions nested in other functions (they aren't made mockable by the enclosing items,
+///   they can't be named outside of them, so they are mocked with
+///   [mock_by_name](https://docs.rs/mocktopus/*/mocktopus/mocking/fn.mock_by_name.html)
+///   and paths like `my_crate::outer::inner`)
+///
+/// ```
+/// fn outer() -> u32 {
+///     #[mockable]
+///     fn inner() -> u32 { ... }
+///
+///     inner() + 1
+/// }
+/// ```
+/// - unsafe functions and functions with a non-Rust ABI (they are mocked with
+///   [mock_unsafe_fn](https://docs.rs/mocktopus/*/mocktopus/mocking/fn.mock_unsafe_fn.html),
+///   because they don't implement `Mockable`, the ABI of `extern` functions is preserved;
+///   a panic can't unwind out of an `extern "C"` function, so a mock of such function,
+///   which panics, e.g. with `MockResult::Panic` or an unmet expectation, aborts the process)
+///
+/// ```
+/// #[mockable]
+/// unsafe fn mockable() { ... }
+///
+/// #[mockable]
+/// extern "C" fn mockable_extern() { ... }
+/// ```
+/// # Async functions
+/// Async functions return pinned boxed futures, which are `Send`, so their bodies must be `Send` too.
+/// With `#[mockable(local)]` the futures aren't `Send`, so they can hold values like `Rc`s and run
+/// in single-threaded executors like tokio `LocalSet`s. Their mocks can return futures, which aren't
+/// `Send` either, with `MockableAsyncLocal::mock_async_local`.
+///
+/// ```
+/// #[mockable(local)]
+/// async fn mockable(value: Rc<u32>) -> u32 {
+///     tokio::task::yield_now().await;
+///     *value
+/// }
+/// ```
+/// # `#[async_trait]`
+/// Traits and trait impls using [async-trait](https://docs.rs/async-trait) can be annotated
+/// with both attributes in any order. The async functions are made mockable after they are
+/// transformed by `#[async_trait]`, so they keep the `Send` bounds it chooses, e.g. none for
+/// `#[async_trait(?Send)]`.
+///
+/// ```
+/// #[mockable]
+/// #[async_trait]
+/// impl Trait for Structure {
+///     async fn mockable(&self) -> u32 { ... }
+/// }
+/// ```
+/// # Async functions in traits
+/// Async functions of trait impls without `#[async_trait]` must keep signatures declared by their
+/// traits, so they return `AsyncMockFuture`s, which are either futures of the bodies or `'static`
+/// futures returned by the mocks. They are mocked with `mock_async` or `mock_ready`. They are `Send`
+/// if the bodies are, but the mocked futures must always be `Send`. Async default functions of traits
+/// can't be made mockable, only the ones of trait impls.
+///
+/// ```
+/// trait Trait {
+///     async fn mockable(&self) -> u32;
+/// }
+///
+/// #[mockable]
+/// impl Trait for Structure {
+///     async fn mockable(&self) -> u32 { ... }
+/// }
+///
+/// #[tokio::test]
+/// async fn mockable_test() {
+///     Structure::mockable.mock_async(|_| async { 1 });
+/// }
+/// ```
+/// # Exported functions
+/// Standalone functions with `#[no_mangle]`, `#[export_name]` or `#[link_section]` are split in two.
+/// The exported symbol keeps these attributes and runs the real function, while calls made from Rust
+/// by the function's name go to a mockable copy. Both keep the declared ABI.
+///
+/// ```
+/// #[mockable]
+/// #[no_mangle]
+/// pub extern "C" fn exported() { ... }
+/// ```
+/// # Taking `impl Trait`
+/// Arguments of type `impl Trait` in standalone functions and struct impl blocks are replaced
+/// with type parameters added after the declared ones in order of appearance. This allows picking
+/// a mocked version of the function like for explicitly generic functions:
+///
+/// ```
+/// #[mockable]
+/// fn print(value: impl Display) -> String { ... }
+///
+/// #[test]
+/// fn print_test() {
+///     print::<u32>.mock_safe(|_| MockResult::Return("mocked".to_string()));
+/// }
+/// ```
+/// Functions in traits and trait impl blocks must keep their signatures, so functions taking
+/// `impl Trait` arguments are not made mockable there and a warning pointing at the argument is emitted.
+///
+/// # Returning `impl Trait`
+/// Functions returning `impl Trait` are mockable, but mocks can't create values of such opaque
+/// types, they can only return values created by the real function, e.g. with `MockResult::Continue`.
+/// With `#[mockable(box_impl_trait)]` these functions return `Box<dyn Trait>` instead, or
+/// `Pin<Box<dyn Future>>` and `Pin<Box<dyn Stream>>` for futures and streams, which can be created
+/// by mocks, e.g. with `MockResult::return_iter` and `MockResult::return_stream`. Trait objects must be
+/// valid for the returned traits, e.g. `impl Debug + Display` can't be boxed, and lifetime bounds
+/// must be explicit like in `impl Iterator<Item = &'a u32> + 'a`. Precise capturing bounds like
+/// `use<'a, T>` are supported, boxing drops them.
+///
+/// ```
+/// #[mockable(box_impl_trait)]
+/// fn numbers(count: u32) -> impl Iterator<Item = u32> {
+///     0..count
+/// }
+///
+/// #[test]
+/// fn numbers_test() {
+///     numbers.mock_safe(|_| MockResult::Return(Box::new(vec![7, 8].into_iter())));
+///
+///     assert_eq!(vec![7, 8], numbers(10).collect::<Vec<_>>());
+/// }
+/// ```
+/// Async functions returning `impl Trait` are boxed the same way, their futures resolve to the boxed
+/// values, e.g. streams of async functions can be mocked with `MockableAsync::mock_return_stream`.
+///
+/// # Selecting items of modules
+/// With `#[mockable(recurse = false)]` modules nested in an annotated module are not made mockable.
+/// With `#[mockable(only = [name, ...])]` only the listed items of an annotated module are made
+/// mockable: functions, modules and traits by their names and impl blocks by their types' names.
+/// The listed items are made mockable entirely.
+///
+/// ```
+/// #[mockable(only = [Structure, mockable], recurse = false)]
+/// mod module {
+///     struct Structure;
+///
+///     impl Structure { ... }
+///
+///     fn mockable() { ... }
+///
+///     fn not_mockable() { ... }
+/// }
+/// ```
+///
+/// # Mocking only in tests
+/// With `#[mockable(test_only)]` items are made mockable only when compiled with `cfg(test)`,
+/// other builds get the items exactly as they were written. It's a shorthand for
+/// `#[cfg_attr(test, mockable)]` and can be combined with other arguments.
+/// Integration tests and other crates compile the library without `cfg(test)`, so they can't mock it.
+/// With the `disable-mocking` feature of `mocktopus` all items are emitted unchanged in every build
+/// and setting up mocks panics. The feature isn't additive, see the crate documentation.
+///
+/// ```
+/// #[mockable(test_only)]
+/// mod mockable_in_tests {
+///     pub fn get() -> u32 { ... }
+/// }
+/// ```
+///
+/// # Auditing mockability
+/// With `#[mockable(audit)]` a warning is emitted for every public function of the annotated item,
+/// which won't be mockable, with the reason, e.g. being `const`, annotated with `#[not_mockable]`,
+/// having an unsupported signature or not being selected with `only`. Macro invocations, which may
+/// generate functions, are pointed at too. Functions of trait impls count as public.
+///
+/// ```
+/// #[mockable(audit)]
+/// mod module {
+///     pub const fn limit() -> u32 { ... } // warning: Public function `limit` won't be mockable
+/// }
+/// ```
+///
+/// # Statics
+/// In test builds annotated statics are turned into
+/// [MockableStatic](https://docs.rs/mocktopus/*/mocktopus/mocking/struct.MockableStatic.html)s,
+/// which dereference to their values and are initialized lazily on first access. Other builds
+/// keep the statics unchanged, so they don't pay for mocking and keep their types. Statics holding
+/// `LazyLock` or `Lazy` are unwrapped, so their values can be mocked directly. Mutable statics
+/// can't be made mockable. Statics aren't made mockable by annotated modules, because it changes
+/// their types, they must be annotated one by one.
+///
+/// ```
+/// #[mockable]
+/// static CONFIG: LazyLock<Config> = LazyLock::new(Config::load);
+///
+/// #[test]
+/// fn config_test() {
+///     CONFIG.mock_value(Config::default());
+///
+///     assert_eq!(Config::default(), *CONFIG);
+/// }
+/// ```
+///
+/// # Extern blocks
+/// Functions declared in annotated `extern` blocks get unsafe mockable wrappers named like them,
+/// which are mocked with [mock_unsafe_fn](https://docs.rs/mocktopus/*/mocktopus/mocking/fn.mock_unsafe_fn.html).
+/// The declarations themselves are renamed and made private, but they keep linking to the same symbols.
+/// In test builds the wrappers panic unless they are mocked and the block isn't compiled,
+/// so the native library isn't linked. Blocks with variadic functions or statics are compiled
+/// in test builds too, those items are left unchanged. Like statics, extern blocks aren't made
+/// mockable by annotated modules, they must be annotated one by one.
+///
+/// ```
+/// #[mockable]
+/// #[link(name = "sensors")]
+/// extern "C" {
+///     fn read_temperature(sensor: u32) -> f64;
+/// }
+///
+/// #[test]
+/// fn temperature_test() {
+///     unsafe {
+///         mock_unsafe_fn(read_temperature, |_: u32| MockResult::Return(21.5));
+///
+///         assert_eq!(21.5, read_temperature(1));
+///     }
+/// }
+/// ```
+///
+/// With `#[mockable(safe)]` the wrappers are safe functions mocked like any other.
+/// It's sound only if every function of the block can be called with any arguments,
+/// e.g. it doesn't take raw pointers.
+///
+/// ```
+/// #[mockable(safe)]
+/// extern "C" {
+///     fn abs(value: i32) -> i32;
+/// }
+/// ```
+///
+/// # Unsupported items
+/// Items, which can't be made mockable, e.g. variadic functions or functions with destructured
+/// arguments, are left unchanged and a warning pointing at each of them is emitted.
+/// The other items of annotated modules are made mockable as usual.
+///
+/// # Documentation
+/// Making functions mockable may change their signatures, e.g. async functions return boxed futures.
+/// Rustdoc gets the functions as they were written, so annotated public APIs are documented
+/// the same way as if they weren't mockable. Only boxing with `box_impl_trait` is documented,
+/// because it changes the types, which callers get. Generated helper items are `#[doc(hidden)]`.
+///
 /// # Invalid to annotate **(WILL FAIL TO COMPILE OR BREAK MOCKING!)**
 ///
 /// - single functions in struct impls
@@ -85,20 +400,31 @@ use quote::ToTokens;
 /// ```
 /// # Indifferent to annotate
 /// - already mockable items (inside annotated modules)
-/// - module declarations
-///
-/// ```
-/// #[mockable]
-/// mod module;
-/// ```
-/// - const functions (they are impossible to mock)
-/// - unsafe functions (they are impossible to mock)
-/// - any macro generated items (they are impossible to mock)
+/// - macro invocations (the generated items are not visible to `#[mockable]`,
+///   use [mockable_items!](macro.mockable_items.html) inside of the macro instead)
 /// - any other items
 #[proc_macro_attribute]
-pub fn mockable(_: TokenStream, token_stream: TokenStream) -> TokenStream {
-    let mut item: syn::Item = match syn::parse(token_stream.clone()) {
+pub fn mockable(args: TokenStream, token_stream: TokenStream) -> TokenStream {
+    if cfg!(feature = "disable-mocking") {
+        return token_stream;
+    }
+    let raw_args = args.clone().into();
+    let args: mockable_args::MockableArgs = match syn::parse(args) {
+        Ok(args) => args,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let escaped = escaping::escape(token_stream.clone().into());
+    let mut item: syn::Item = match syn::parse2(escaped.clone()) {
         Ok(item) => item,
+        // Inner attribute at the crate root receives the whole crate
+        Err(_) if !args.mock_struct && syn::parse2::<syn::File>(escaped.clone()).is_ok() => {
+            let mut file: syn::File = syn::parse2(escaped).expect("crate was parsed");
+            if args.audit {
+                item_injector::audit_crate(&file, &args);
+            }
+            item_injector::inject_crate(&mut file, &args);
+            return escaping::unescape(file.into_token_stream()).into();
+        }
         Err(err) => {
             Span::call_site()
                 .warning("Failed to make code mockable")
@@ -107,8 +433,187 @@ pub fn mockable(_: TokenStream, token_stream: TokenStream) -> TokenStream {
             return token_stream;
         }
     };
-    item_injector::inject_item(&mut item);
-    item.into_token_stream().into()
+    if args.selects_items() && !matches!(item, syn::Item::Mod(_)) {
+        return syn::Error::new(
+            Span::call_site().into(),
+            "Only modules can select items to make mockable",
+        )
+        .to_compile_error()
+        .into();
+    }
+    if args.test_only {
+        return item_injector::gate_test_only(proc_macro2::TokenStream::from(token_stream), &args)
+            .into();
+    }
+    if let syn::Item::Mod(ref mut item_mod) = item {
+        if item_mod.content.is_none() {
+            if let Err(err) = mod_loader::load_declared_mod(item_mod) {
+                Span::call_site()
+                    .warning("Failed to make module mockable")
+                    .note(err)
+                    .emit();
+                return token_stream;
+            }
+        }
+    }
+    if item_injector::move_after_async_trait(&mut item, raw_args) {
+        return escaping::unescape(item.into_token_stream()).into();
+    }
+    if args.audit {
+        item_injector::audit_item(&item, &args);
+    }
+    let mock_struct = match item {
+        syn::Item::Trait(ref item_trait) if args.mock_struct => {
+            item_injector::build_mock_struct(item_trait)
+        }
+        _ if args.mock_struct => {
+            return syn::Error::new(
+                Span::call_site().into(),
+                "Only traits can have a mock struct",
+            )
+            .to_compile_error()
+            .into()
+        }
+        _ => Default::default(),
+    };
+    if args.safe && !matches!(item, syn::Item::ForeignMod(_)) {
+        return syn::Error::new(
+            Span::call_site().into(),
+            "Only extern blocks can have safe wrappers",
+        )
+        .to_compile_error()
+        .into();
+    }
+    if let syn::Item::ForeignMod(ref item_foreign_mod) = item {
+        let token_stream = item_injector::inject_foreign_mod(item_foreign_mod, &args);
+        return escaping::unescape(token_stream).into();
+    }
+    if let syn::Item::Static(ref item_static) = item {
+        let token_stream =
+            item_injector::inject_static(item_static).unwrap_or_else(|err| err.to_compile_error());
+        return escaping::unescape(token_stream).into();
+    }
+    item_injector::inject_item(&mut item, &args);
+    let mut token_stream = item.into_token_stream();
+    token_stream.extend(mock_struct);
+    escaping::unescape(token_stream).into()
+}
+
+/// Procedural macro, makes items and their sub-items mockable like `#[mockable]` does.
+///
+/// It accepts any number of items, so it can wrap items generated by a `macro_rules!` macro:
+///
+/// ```
+/// macro_rules! getter {
+///     ($name:ident, $value:expr) => {
+///         mockable_items! {
+///             pub fn $name() -> u32 {
+///                 $value
+///             }
+///         }
+///     };
+/// }
+///
+/// getter!(get_one, 1);
+/// getter!(get_two, 2);
+/// ```
+/// All rules of `#[mockable]` apply to every item.
+#[proc_macro]
+pub fn mockable_items(token_stream: TokenStream) -> TokenStream {
+    if cfg!(feature = "disable-mocking") {
+        return token_stream;
+    }
+    let mut file: syn::File = match syn::parse2(escaping::escape(token_stream.into())) {
+        Ok(file) => file,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let args = mockable_args::MockableArgs::default();
+    file.items
+        .iter_mut()
+        .for_each(|item| item_injector::inject_item(item, &args));
+    escaping::unescape(file.into_token_stream()).into()
+}
+
+/// Procedural macro, makes an impl block mockable and generates a builder of doubles of the struct.
+///
+/// The impl block is made mockable like with `#[mockable]`. Next to it a builder named after the struct
+/// is generated, e.g. `CounterDouble` for `Counter`. It has a method mocking each function of the impl
+/// block with a closure like
+/// [MockContext::mock_safe](https://docs.rs/mocktopus/*/mocktopus/mocking/struct.MockContext.html#method.mock_safe)
+/// does, e.g. `get_with` for `get`. Functions returning values, which don't borrow anything,
+/// also have a method mocking them to always return a clone of a value, e.g. `get_returns`.
+/// The configured mocks are applied by running a function with the builder:
+///
+/// ```
+/// struct Counter {
+///     count: u32,
+/// }
+///
+/// #[mockable_struct]
+/// impl Counter {
+///     fn get(&self) -> u32 {
+///         self.count
+///     }
+///
+///     fn add(&mut self, value: u32) {
+///         self.count += value;
+///     }
+/// }
+///
+/// #[test]
+/// fn counter_test() {
+///     CounterDouble::new()
+///         .get_returns(5)
+///         .add_with(|_, _| MockResult::Return(()))
+///         .strict()
+///         .run(|| {
+///             let mut counter = Counter { count: 0 };
+///             counter.add(1);
+///             assert_eq!(5, counter.get());
+///         });
+/// }
+/// ```
+/// The builder is private, a visibility for it can be passed as the argument,
+/// e.g. `#[mockable_struct(pub(crate))]`. It can be converted into a `MockContext` with `into_context`
+/// to mock more functions.
+///
+/// Functions, which can't be mocked with `mock_safe`, e.g. async or unsafe ones, don't have builder
+/// methods. Generic functions do, but their type parameters usually must be given explicitly, e.g.
+/// `.parse_returns::<u32>(5)`. Only a single impl block of a struct can be annotated, because all
+/// builders of a struct have the same name.
+///
+/// # Valid to annotate
+/// - inherent impl blocks of non-generic types
+#[proc_macro_attribute]
+pub fn mockable_struct(args: TokenStream, token_stream: TokenStream) -> TokenStream {
+    if cfg!(feature = "disable-mocking") {
+        return token_stream;
+    }
+    let vis: syn::Visibility = match syn::parse(args) {
+        Ok(vis) => vis,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let mut item: syn::Item = match syn::parse2(escaping::escape(token_stream.into())) {
+        Ok(item) => item,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let double = match item {
+        syn::Item::Impl(ref item_impl) if item_impl.trait_.is_none() => {
+            item_injector::build_double(item_impl, &vis)
+        }
+        _ => {
+            return syn::Error::new(
+                Span::call_site().into(),
+                "Only inherent impl blocks can have doubles",
+            )
+            .to_compile_error()
+            .into()
+        }
+    };
+    item_injector::inject_item(&mut item, &Default::default());
+    let mut token_stream = item.into_token_stream();
+    token_stream.extend(double);
+    escaping::unescape(token_stream).into()
 }
 
 /// Procedural macro, guards items from being made mockable by enclosing item.
@@ -195,6 +700,11 @@ pub fn mockable(_: TokenStream, token_stream: TokenStream) -> TokenStream {
 /// }
 /// ```
 ///
+/// # Conditional annotations
+/// Items nested in annotated items can be guarded with `cfg_attr`, e.g. `#[cfg_attr(test, not_mockable)]`.
+/// The enclosing item makes them mockable only when the predicate doesn't hold, nested `cfg_attr`s
+/// are supported too. The same goes for nested items annotated with `#[cfg_attr(test, mockable)]`.
+///
 /// # Indifferent to annotate
 /// - items not made mockable by enclosing item
 /// - any other items
@@ -202,3 +712,135 @@ pub fn mockable(_: TokenStream, token_stream: TokenStream) -> TokenStream {
 pub fn not_mockable(_: TokenStream, token_stream: TokenStream) -> TokenStream {
     token_stream
 }
+
+/// Procedural macro, defines a test isolated from mocks of other tests.
+///
+/// Available as `#[mocktopus::test]`. Before the test body runs all mocks of the thread are cleared,
+/// after it finishes all expectations are verified and the mocks are cleared again, even if the body
+/// panicked. This keeps tests sharing a thread from seeing each other's mocks.
+///
+/// ```
+/// #[mockable]
+/// fn send(message: &str) { ... }
+///
+/// #[mocktopus::test]
+/// fn send_test() {
+///     send.expect_calls(1);
+///     send.mock_safe(|_| MockResult::Return(()));
+///
+///     send("hello");
+/// }
+/// ```
+/// # Valid to annotate
+/// - non-async functions without arguments, which are valid `#[test]` functions
+#[proc_macro_attribute]
+pub fn mocktopus_test(_: TokenStream, token_stream: TokenStream) -> TokenStream {
+    let item_fn: syn::ItemFn = match syn::parse(token_stream) {
+        Ok(item_fn) => item_fn,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    test_wrapper::wrap_test(item_fn).into()
+}
+
+/// Procedural macro, defines an async test run by tokio and isolated from mocks of other tests.
+///
+/// Available as `#[mocktopus::tokio_test]`. It works like `#[mocktopus::test]`, but the test body
+/// is run to completion by a new tokio runtime, which is dropped before expectations are verified.
+/// The runtime uses the basic scheduler, so the body and all tasks spawned by it are polled in
+/// the test thread and see all its mocks. With `#[mocktopus::tokio_test(threaded_scheduler)]`
+/// the tasks run in worker threads, so they see only mocks set up with `mock_shared` and only
+/// if they are spawned with `mocktopus::mocking::spawn` or wrapped with `MockHandle::run_async`.
+/// The tested crate must depend on tokio with the `rt-core` feature.
+///
+/// ```
+/// #[mockable]
+/// async fn fetch(url: &str) -> String { ... }
+///
+/// #[mocktopus::tokio_test]
+/// async fn fetch_test() {
+///     fetch.expect_calls(1);
+///     fetch.mock_async(|_| async { "mocked".to_string() });
+///
+///     let task = tokio::spawn(async { fetch("example.com").await });
+///
+///     assert_eq!("mocked", task.await.unwrap());
+/// }
+/// ```
+/// # Valid to annotate
+/// - async functions without arguments, which are valid `#[test]` functions
+#[proc_macro_attribute]
+pub fn mocktopus_tokio_test(args: TokenStream, token_stream: TokenStream) -> TokenStream {
+    let scheduler: Option<syn::Ident> = match syn::parse(args) {
+        Ok(scheduler) => scheduler,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let item_fn: syn::ItemFn = match syn::parse(token_stream) {
+        Ok(item_fn) => item_fn,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    test_wrapper::wrap_tokio_test(scheduler, item_fn).into()
+}
+
+/// Procedural macro, defines an async test run by async-std and isolated from mocks of other tests.
+///
+/// Available as `#[mocktopus::async_std_test]`. It works like `#[mocktopus::test]`, but the test
+/// body is run to completion with `async_std::task::block_on` in the test thread, so it sees all
+/// mocks of the test. Tasks spawned with `async_std::task::spawn` run in the global async-std
+/// thread pool, so they see only mocks set up with `mock_shared` and only if they are spawned with
+/// `mocktopus::mocking::spawn_async_std` or wrapped with `MockHandle::run_async`.
+/// The tested crate must depend on async-std.
+///
+/// ```
+/// #[mockable]
+/// async fn fetch(url: &str) -> String { ... }
+///
+/// #[mocktopus::async_std_test]
+/// async fn fetch_test() {
+///     fetch.expect_calls(1);
+///     fetch.mock_async(|_| async { "mocked".to_string() });
+///
+///     assert_eq!("mocked", fetch("example.com").await);
+/// }
+/// ```
+/// # Valid to annotate
+/// - async functions without arguments, which are valid `#[test]` functions
+#[proc_macro_attribute]
+pub fn mocktopus_async_std_test(_: TokenStream, token_stream: TokenStream) -> TokenStream {
+    let item_fn: syn::ItemFn = match syn::parse(token_stream) {
+        Ok(item_fn) => item_fn,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let block_on = quote::quote!(::async_std::task::block_on);
+    test_wrapper::wrap_block_on_test("async_std_test", block_on, item_fn).into()
+}
+
+/// Procedural macro, defines an async test run by smol and isolated from mocks of other tests.
+///
+/// Available as `#[mocktopus::smol_test]`. It works like `#[mocktopus::async_std_test]`, but the
+/// test body is run with `smol::block_on`. Tasks spawned with `smol::spawn` see mocks set up
+/// with `mock_shared` only if they are spawned with `mocktopus::mocking::spawn_smol`
+/// or wrapped with `MockHandle::run_async`. The tested crate must depend on smol.
+///
+/// ```
+/// #[mockable]
+/// async fn fetch(url: &str) -> String { ... }
+///
+/// #[mocktopus::smol_test]
+/// async fn fetch_test() {
+///     fetch.expect_calls(1);
+///     fetch.mock_async(|_| async { "mocked".to_string() });
+///
+///     assert_eq!("mocked", fetch("example.com").await);
+/// }
+/// ```
+/// # Valid to annotate
+/// - async functions without arguments, which are valid `#[test]` functions
+#[proc_macro_attribute]
+pub fn mocktopus_smol_test(_: TokenStream, token_stream: TokenStream) -> TokenStream {
+    let item_fn: syn::ItemFn = match syn::parse(token_stream) {
+        Ok(item_fn) => item_fn,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let block_on = quote::quote!(::smol::block_on);
+    test_wrapper::wrap_block_on_test("smol_test", block_on, item_fn).into()
+}
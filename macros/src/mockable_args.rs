@@ -0,0 +1,113 @@
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::token::Comma;
+use syn::{bracketed, Error, Ident, LitBool, Result, Token};
+
+/// Arguments of `#[mockable(...)]`
+#[derive(Clone)]
+pub struct MockableArgs {
+    pub mock_struct: bool,
+    pub box_impl_trait: bool,
+    /// Whether async functions return futures, which aren't `Send`
+    pub local: bool,
+    /// Whether const functions are made mockable in test builds, where they aren't const
+    pub const_fn: bool,
+    pub test_only: bool,
+    /// Whether functions of extern blocks get safe wrappers instead of unsafe ones
+    pub safe: bool,
+    /// Whether public functions, which won't be mockable, are listed in warnings
+    pub audit: bool,
+    /// Whether modules nested in annotated modules are made mockable
+    pub recurse: bool,
+    /// Names of the only items of annotated modules, which are made mockable
+    pub only: Option<Vec<Ident>>,
+}
+
+impl MockableArgs {
+    /// Whether arguments selecting items of annotated modules are present
+    pub fn selects_items(&self) -> bool {
+        !self.recurse || self.only.is_some()
+    }
+}
+
+impl Default for MockableArgs {
+    fn default() -> Self {
+        MockableArgs {
+            mock_struct: false,
+            box_impl_trait: false,
+            local: false,
+            const_fn: false,
+            test_only: false,
+            safe: false,
+            audit: false,
+            recurse: true,
+            only: None,
+        }
+    }
+}
+
+impl Parse for MockableArgs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut args = MockableArgs::default();
+        while !input.is_empty() {
+            let ident: Ident = input.parse()?;
+            match ident.to_string().as_str() {
+                "mock_struct" => args.mock_struct = true,
+                "box_impl_trait" => args.box_impl_trait = true,
+                "local" => args.local = true,
+                "const_fn" => args.const_fn = true,
+                "test_only" => args.test_only = true,
+                "safe" => args.safe = true,
+                "audit" => args.audit = true,
+                "recurse" => {
+                    input.parse::<Token![=]>()?;
+                    args.recurse = input.parse::<LitBool>()?.value;
+                }
+                "only" => {
+                    input.parse::<Token![=]>()?;
+                    let content;
+                    bracketed!(content in input);
+                    let names = Punctuated::<Ident, Comma>::parse_terminated(&content)?;
+                    args.only = Some(names.into_iter().collect());
+                }
+                _ => return Err(Error::new(ident.span(), "Unknown mockable argument")),
+            }
+            if !input.is_empty() {
+                input.parse::<Comma>()?;
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Emits the arguments applied when the item is made mockable, `test_only` is already handled then
+impl ToTokens for MockableArgs {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        if self.mock_struct {
+            tokens.extend(quote!(mock_struct,));
+        }
+        if self.box_impl_trait {
+            tokens.extend(quote!(box_impl_trait,));
+        }
+        if self.local {
+            tokens.extend(quote!(local,));
+        }
+        if self.const_fn {
+            tokens.extend(quote!(const_fn,));
+        }
+        if self.safe {
+            tokens.extend(quote!(safe,));
+        }
+        if self.audit {
+            tokens.extend(quote!(audit,));
+        }
+        if !self.recurse {
+            tokens.extend(quote!(recurse = false,));
+        }
+        if let Some(ref only) = self.only {
+            tokens.extend(quote!(only = [#(#only),*],));
+        }
+    }
+}
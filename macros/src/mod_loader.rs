@@ -0,0 +1,238 @@
+use crate::escaping;
+use proc_macro::Span;
+use proc_macro2::TokenStream;
+use quote::quote;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use syn::punctuated::Punctuated;
+use syn::token::Comma;
+use syn::{parse_quote, AttrStyle, Expr, ExprLit, ExprMacro, Item, ItemMacro, ItemMod, Lit, Meta};
+
+/// Loads items of a module declared without a body, e.g. `mod module;`, which attribute macros
+/// get without its items. `include!`d files are loaded too, so the module becomes inline.
+/// Nested declared modules are left to the compiler, their files are pinned with `#[path]`
+/// attributes, so they are found although the module is inline.
+///
+/// The module's file is looked up like by the compiler, assuming that the declaration isn't
+/// nested in inline modules of the file containing it.
+///
+/// The loaded files are parsed from their sources, so all their tokens have the call site span.
+/// Compiler errors, `file!()`, `line!()`, panic locations and debug info of the module's items
+/// point at the module declaration.
+pub fn load_declared_mod(item_mod: &mut ItemMod) -> Result<(), String> {
+    let (file_dir, mod_dirs) =
+        get_invocation_dirs().ok_or("the file containing the module declaration is unknown")?;
+    let location = mod_dirs
+        .into_iter()
+        .map(|mod_dir| Location {
+            mod_dir,
+            file_dir: file_dir.clone(),
+            is_file_top_level: true,
+        })
+        .find(|location| location.find_mod_file(item_mod).is_some())
+        .ok_or_else(|| format!("file of module `{}` not found", item_mod.ident))?;
+    location.load_mod(item_mod)
+}
+
+// Files other than `mod.rs`, `lib.rs` and `main.rs` keep their modules in a directory named
+// like them, unless they are crate roots, which can't be told apart
+fn get_invocation_dirs() -> Option<(PathBuf, Vec<PathBuf>)> {
+    let file = Span::call_site().local_file()?;
+    let file_dir = get_dir(&file);
+    let mut mod_dirs = Vec::new();
+    match file.file_stem().and_then(|stem| stem.to_str()) {
+        Some("mod" | "lib" | "main") | None => (),
+        Some(stem) => mod_dirs.push(file_dir.join(stem)),
+    }
+    mod_dirs.push(file_dir.clone());
+    Some((file_dir, mod_dirs))
+}
+
+/// Where items are located, which determines paths of their module files and included files
+struct Location {
+    /// Directory containing files of declared modules
+    mod_dir: PathBuf,
+    /// Directory of the file containing the items, `include!` paths are relative to it
+    file_dir: PathBuf,
+    /// `#[path]` attributes at the top level of a file are relative to the file's directory,
+    /// in inline modules they are relative to the modules' directory
+    is_file_top_level: bool,
+}
+
+impl Location {
+    fn load_items(&self, items: &mut Vec<Item>) -> Result<(), String> {
+        self.expand_includes(items)?;
+        for item in items {
+            if let Item::Mod(ref mut item_mod) = *item {
+                match item_mod.content {
+                    None => self.pin_mod_file(item_mod),
+                    Some(_) => self.load_nested(item_mod)?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Modules without files, e.g. disabled with `#[cfg]`, are left to the compiler
+    fn pin_mod_file(&self, item_mod: &mut ItemMod) {
+        if let Some(path) = self.find_mod_file(item_mod) {
+            let absolute_path = fs::canonicalize(&path).unwrap_or(path);
+            let absolute_path = absolute_path.to_string_lossy();
+            item_mod.attrs.retain(|attr| !attr.path.is_ident("path"));
+            item_mod.attrs.push(parse_quote!(#[path = #absolute_path]));
+        }
+    }
+
+    fn load_nested(&self, item_mod: &mut ItemMod) -> Result<(), String> {
+        let location = Location {
+            mod_dir: match get_path_attr(item_mod) {
+                Some(path) => self.path_dir().join(path),
+                None => self.mod_dir.join(item_mod.ident.to_string()),
+            },
+            file_dir: self.file_dir.clone(),
+            is_file_top_level: false,
+        };
+        match item_mod.content {
+            Some((_, ref mut items)) => location.load_items(items),
+            None => Ok(()),
+        }
+    }
+
+    fn load_mod(&self, item_mod: &mut ItemMod) -> Result<(), String> {
+        let path = self
+            .find_mod_file(item_mod)
+            .ok_or_else(|| format!("file of module `{}` not found", item_mod.ident))?;
+        let mut file = parse_file(&path)?;
+        let file_dir = get_dir(&path);
+        // Modules loaded through `#[path]` keep their modules next to them like `mod.rs` files
+        let location = Location {
+            mod_dir: match get_path_attr(item_mod) {
+                Some(_) => file_dir.clone(),
+                None => self.mod_dir.join(item_mod.ident.to_string()),
+            },
+            file_dir,
+            is_file_top_level: true,
+        };
+        location.load_items(&mut file.items)?;
+        file.items.insert(0, track_file(path));
+
+        item_mod.attrs.retain(|attr| !attr.path.is_ident("path"));
+        item_mod.attrs.extend(file.attrs);
+        item_mod.content = Some((Default::default(), file.items));
+        item_mod.semi = None;
+        Ok(())
+    }
+
+    // Included items may be mockable only when they are visible to the macro
+    fn expand_includes(&self, items: &mut Vec<Item>) -> Result<(), String> {
+        let mut expanded = Vec::with_capacity(items.len());
+        for item in items.drain(..) {
+            let path = match item {
+                Item::Macro(ItemMacro {
+                    ident: None,
+                    ref mac,
+                    ..
+                }) if mac.path.is_ident("include") => {
+                    let expr = mac.parse_body().map_err(|err| err.to_string())?;
+                    self.file_dir.join(eval_str(&expr)?)
+                }
+                item => {
+                    expanded.push(item);
+                    continue;
+                }
+            };
+            let mut file = parse_file(&path)?;
+            // Paths in included files are relative to them
+            let location = Location {
+                mod_dir: self.mod_dir.clone(),
+                file_dir: get_dir(&path),
+                is_file_top_level: self.is_file_top_level,
+            };
+            location.expand_includes(&mut file.items)?;
+            expanded.push(track_file(path));
+            expanded.append(&mut file.items);
+        }
+        *items = expanded;
+        Ok(())
+    }
+
+    fn find_mod_file(&self, item_mod: &ItemMod) -> Option<PathBuf> {
+        if let Some(path) = get_path_attr(item_mod) {
+            let path = self.path_dir().join(path);
+            return Some(path).filter(|path| path.is_file());
+        }
+        let name = item_mod.ident.to_string();
+        let candidates = vec![
+            self.mod_dir.join(format!("{}.rs", name)),
+            self.mod_dir.join(name).join("mod.rs"),
+        ];
+        candidates.into_iter().find(|path| path.is_file())
+    }
+
+    fn path_dir(&self) -> &Path {
+        match self.is_file_top_level {
+            true => &self.file_dir,
+            false => &self.mod_dir,
+        }
+    }
+}
+
+fn get_dir(file: &Path) -> PathBuf {
+    file.parent().unwrap_or_else(|| Path::new("")).to_path_buf()
+}
+
+fn parse_file(path: &Path) -> Result<syn::File, String> {
+    let source = fs::read_to_string(path)
+        .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+    let tokens: TokenStream = source
+        .parse()
+        .map_err(|err| format!("failed to parse {}: {}", path.display(), err))?;
+    syn::parse2(escaping::escape(tokens))
+        .map_err(|err| format!("failed to parse {}: {}", path.display(), err))
+}
+
+// The file's contents are pasted, so including it makes changing it rebuild the crate
+fn track_file(path: PathBuf) -> Item {
+    let absolute_path = fs::canonicalize(&path).unwrap_or(path);
+    let absolute_path = absolute_path.to_string_lossy();
+    Item::Verbatim(quote!(
+        const _: &str = include_str!(#absolute_path);
+    ))
+}
+
+fn get_path_attr(item_mod: &ItemMod) -> Option<String> {
+    item_mod
+        .attrs
+        .iter()
+        .filter(|attr| matches!(attr.style, AttrStyle::Outer) && attr.path.is_ident("path"))
+        .find_map(|attr| match attr.parse_meta() {
+            Ok(Meta::NameValue(name_value)) => match name_value.lit {
+                Lit::Str(path) => Some(path.value()),
+                _ => None,
+            },
+            _ => None,
+        })
+}
+
+// Paths of included files are string literals, optionally built with `concat!` and `env!`,
+// e.g. `include!(concat!(env!("OUT_DIR"), "/generated.rs"))`
+fn eval_str(expr: &Expr) -> Result<String, String> {
+    match *expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(ref lit_str),
+            ..
+        }) => Ok(lit_str.value()),
+        Expr::Macro(ExprMacro { ref mac, .. }) if mac.path.is_ident("concat") => {
+            let parts = mac
+                .parse_body_with(Punctuated::<Expr, Comma>::parse_terminated)
+                .map_err(|err| err.to_string())?;
+            parts.iter().map(eval_str).collect()
+        }
+        Expr::Macro(ExprMacro { ref mac, .. }) if mac.path.is_ident("env") => {
+            let name = eval_str(&mac.parse_body().map_err(|err| err.to_string())?)?;
+            env::var(&name).map_err(|_| format!("environment variable {} is not set", name))
+        }
+        _ => Err("included path must be a string literal, `concat!` or `env!`".to_string()),
+    }
+}
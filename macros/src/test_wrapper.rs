@@ -0,0 +1,96 @@
+use crate::attr_path;
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{Ident, ItemFn, ReturnType};
+
+pub fn wrap_test(item_fn: ItemFn) -> TokenStream {
+    if let Some(asyncness) = item_fn.sig.asyncness {
+        return quote_spanned!(asyncness.span()=>
+            compile_error!("#[mocktopus::test] does not support async functions");
+        );
+    }
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = item_fn;
+    let body_output = match sig.output {
+        ReturnType::Default => quote!(),
+        ReturnType::Type(ref arrow, ref ty) => quote!(#arrow #ty),
+    };
+    let mocktopus = attr_path::get_mocktopus_path();
+    quote! {
+        #[test]
+        #(#attrs)*
+        #vis #sig {
+            #mocktopus::mocking::run_isolated_test(move || #body_output #block)
+        }
+    }
+}
+
+pub fn wrap_tokio_test(scheduler: Option<Ident>, item_fn: ItemFn) -> TokenStream {
+    if item_fn.sig.asyncness.is_none() {
+        return quote_spanned!(item_fn.sig.fn_token.span()=>
+            compile_error!("#[mocktopus::tokio_test] supports only async functions");
+        );
+    }
+    let scheduler = match scheduler {
+        None => quote!(basic_scheduler),
+        Some(ref scheduler) if scheduler == "basic_scheduler" => quote!(basic_scheduler),
+        Some(ref scheduler) if scheduler == "threaded_scheduler" => quote!(threaded_scheduler),
+        Some(scheduler) => {
+            return quote_spanned!(scheduler.span()=>
+                compile_error!("Expected `basic_scheduler` or `threaded_scheduler`");
+            )
+        }
+    };
+    let ItemFn {
+        attrs,
+        vis,
+        mut sig,
+        block,
+    } = item_fn;
+    sig.asyncness = None;
+    let mocktopus = attr_path::get_mocktopus_path();
+    // The runtime is dropped before verifying expectations, so all spawned tasks are finished
+    quote! {
+        #[test]
+        #(#attrs)*
+        #vis #sig {
+            #mocktopus::mocking::run_isolated_test(move || {
+                ::tokio::runtime::Builder::new()
+                    .#scheduler()
+                    .enable_all()
+                    .build()
+                    .expect("Failed to build the tokio runtime")
+                    .block_on(async move #block)
+            })
+        }
+    }
+}
+
+pub fn wrap_block_on_test(attr_name: &str, block_on: TokenStream, item_fn: ItemFn) -> TokenStream {
+    if item_fn.sig.asyncness.is_none() {
+        let message = format!("#[mocktopus::{}] supports only async functions", attr_name);
+        return quote_spanned!(item_fn.sig.fn_token.span()=>
+            compile_error!(#message);
+        );
+    }
+    let ItemFn {
+        attrs,
+        vis,
+        mut sig,
+        block,
+    } = item_fn;
+    sig.asyncness = None;
+    let mocktopus = attr_path::get_mocktopus_path();
+    quote! {
+        #[test]
+        #(#attrs)*
+        #vis #sig {
+            #mocktopus::mocking::run_isolated_test(move || #block_on(async move #block))
+        }
+    }
+}
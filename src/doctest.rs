@@ -0,0 +1,35 @@
+use crate::mocking::run_isolated_test;
+
+/// Runs a documented example, which uses mocks
+///
+/// Doctests are compiled as regular programs, so `#[test]` and `#[mocktopus::test]` functions
+/// inside of them never run. The example's body can be passed to `run` instead. Like
+/// `#[mocktopus::test]` it clears mocks of the thread before and after running the body and
+/// verifies all expectations after the body finishes. The body's result is returned.
+///
+/// ```
+/// use mocktopus::macros::mockable;
+/// use mocktopus::mocking::*;
+///
+/// #[mockable]
+/// fn fetch_price(item: &str) -> u32 {
+///     unimplemented!("calls the server")
+/// }
+///
+/// mocktopus::doctest::run(|| {
+///     fetch_price.mock_safe(|_| MockResult::Return(10));
+///     fetch_price.expect_calls(1);
+///
+///     assert_eq!(10, fetch_price("book"));
+/// });
+/// ```
+///
+/// Mocking in doctests doesn't need `cfg(test)`, but some items are made mockable only in
+/// test builds. The documented crate is compiled for its doctests without `cfg(test)`,
+/// so its items annotated with `#[mockable(test_only)]` or `#[cfg_attr(test, mockable)]` aren't
+/// mockable in them. Items defined inside of doctests are compiled without `cfg(test)` too,
+/// so their const functions and statics aren't mockable and functions of annotated `extern`
+/// blocks aren't stubbed out.
+pub fn run<R>(example: impl FnOnce() -> R) -> R {
+    run_isolated_test(example)
+}
@@ -83,12 +83,39 @@
 //!     fn my_fn() {}
 //! }
 //! ```
-//! This does NOT work for modules in separate file:
+//! Modules in separate files are loaded by the macro, which requires `#![feature(proc_macro_hygiene)]`.
+//! Their code loses its locations, so compiler errors and panics point at the declaration,
+//! annotating the files with `#![mockable]` keeps them:
 //!
 //! ```
-//! #[cfg_attr(test, mockable)] // WRONG, has no effect
+//! #[cfg_attr(test, mockable)]
 //! mod my_module;
 //! ```
+//! Items generated by `macro_rules!` macros can be made mockable by wrapping them inside of the macro:
+//!
+//! ```
+//! macro_rules! my_fn_generator {
+//!     ($name:ident) => {
+//!         mockable_items! {
+//!             fn $name() {}
+//!         }
+//!     };
+//! }
+//! ```
+//! ## Disabling mocking
+//! When Mocktopus is a regular dependency, e.g. because items are annotated without `cfg_attr`,
+//! the `disable-mocking` feature turns `#[mockable]` and `mockable_items!` into no-ops.
+//! They emit the items exactly as they were written, so the mocking machinery isn't called and
+//! compiled code has no runtime or size overhead. Setting up mocks in such builds panics.
+//!
+//! The feature isn't additive. Cargo enables features of a dependency for the whole build,
+//! so when any crate in the dependency graph enables it, no crate can mock in that build.
+//! Enable it only in features of the final binary, never in libraries.
+//!
+//! ```
+//! [features]
+//! production = ["mocktopus/disable-mocking"]
+//! ```
 //! # Mocking
 //! Import tools for mocking in test module:
 //!
@@ -118,6 +145,8 @@
 //! // Mocking default trait method
 //! MyStruct::my_trait_default_method.mock_safe(|| MockResult::Return(3));
 //! ```
+//! Trait methods can be mocked for all implementors at once with
+//! [mock_trait_method_by_name](mocking/fn.mock_trait_method_by_name.html).
 //! Mocking with `mock_safe` is simplest, but the `Mockable` trait has more,
 //! see [documantation](mocking/trait.Mockable.html).
 //!
@@ -149,6 +178,8 @@
 //!     assert_eq!(2, common_fn());
 //! }
 //! ```
+//! Doctests are regular programs, which don't run `#[test]` functions. Their bodies can be wrapped
+//! in [doctest::run](doctest/fn.run.html), which isolates them from mocks like `#[mocktopus::test]`.
 //!
 //! ## Mock closure
 //! `mock_safe` has single argument: a closure, which takes same input as mocked function and returns a `MockResult`.
@@ -203,6 +234,74 @@
 //!     assert_eq!(3, my_function_3(1, 1));
 //! }
 //! ```
+//! If the closure returns `MockResult::ContinueThen`, the mocked function runs normally with given arguments,
+//! but its returned value is passed through given closure before being returned to the caller:
+//!
+//! ```
+//! #[test]
+//! fn my_function_3_then_test() {
+//!     my_function_3.mock_safe(|x, y| MockResult::ContinueThen((x, y), Box::new(|sum| sum * 10)));
+//!
+//!     assert_eq!(20, my_function_3(1, 1));
+//! }
+//! ```
+//! If the closure returns `MockResult::Panic`, the mocked function panics with given message
+//! prefixed with its path, which makes it easy to find out, which mock caused the panic:
+//!
+//! ```
+//! #[test]
+//! #[should_panic(expected = "my_function_3 panicked: out of memory")]
+//! fn my_function_3_panic_test() {
+//!     my_function_3.mock_safe(|_, _| MockResult::Panic("out of memory".to_string()));
+//!
+//!     my_function_3(1, 1);
+//! }
+//! ```
+//!
+//! The mock closure is `FnMut`, so it can own and modify its state between calls:
+//!
+//! ```
+//! #[test]
+//! fn my_function_2_counter_test() {
+//!     let mut counter = 0;
+//!     my_function_2.mock_safe(move || {
+//!         counter += 1;
+//!         MockResult::Return(counter)
+//!     });
+//!
+//!     assert_eq!(1, my_function_2());
+//!     assert_eq!(2, my_function_2());
+//! }
+//! ```
+//!
+//! ## Removing mocks
+//! A mock can be removed in the middle of a test with `clear_mock`, which restores the original behavior
+//! of a single function without affecting other mocks:
+//!
+//! ```
+//! #[test]
+//! fn my_function_3_clear_test() {
+//!     my_function_3.mock_safe(|_, _| MockResult::Return(0));
+//!     assert_eq!(0, my_function_3(1, 1));
+//!
+//!     my_function_3.clear_mock();
+//!
+//!     assert_eq!(2, my_function_3(1, 1));
+//! }
+//! ```
+//!
+//! Mocks live as long as the thread, so tests sharing a thread may see each other's mocks.
+//! Tests annotated with `#[mocktopus::test]` instead of `#[test]` start with no mocks, verify
+//! expectations at the end and clear all their mocks, even when they panic:
+//!
+//! ```
+//! #[mocktopus::test]
+//! fn my_function_3_isolated_test() {
+//!     my_function_3.mock_safe(|_, _| MockResult::Return(0));
+//!
+//!     assert_eq!(0, my_function_3(1, 1));
+//! }
+//! ```
 //!
 //! ## Mocking generics
 //! When mocking generic functions, all its generics must be defined and only this variant will be affected:
@@ -276,8 +375,74 @@
 //!     sleep(10000).await;
 //! }
 //! ```
+//! Wrapping returned futures can be skipped by using `mock_async` from trait `MockableAsync`:
+//!
+//! ```
+//! #[tokio::test]
+//! async fn sleep_async_test() {
+//!     sleep.mock_async(|_| async {});
+//!
+//!     sleep(10000).await;
+//! }
+//! ```
+//! Mocks returning ready values can be shortened with [async_ret!](macro.async_ret.html),
+//! [async_ok!](macro.async_ok.html) and [async_err!](macro.async_err.html), e.g.
+//! `sleep.mock_safe(|_| async_ret!(()))`.
+//!
+//! Functions returning `BoxFuture` or `LocalBoxFuture` from the `futures` crate can be mocked with
+//! `mock_async` and `mock_ready` too. Async functions annotated with `#[mockable(local)]` return
+//! futures, which aren't `Send`, so they can hold `Rc`s and run in tokio `LocalSet`s.
+//! Mocks returning such futures are set up with `mock_async_local` and `mock_ready_local`
+//! from trait [MockableAsyncLocal](mocking/trait.MockableAsyncLocal.html).
+//!
+//! Mocks are not visible in worker threads of multi-threaded runtimes, so futures spawned there
+//! must be wrapped with [MockHandle::run_async](mocking/struct.MockHandle.html#method.run_async),
+//! which sets up mocks created with `mock_shared` whenever the future is polled.
+//! With the `tokio` feature [mocking::spawn](mocking/fn.spawn.html) and
+//! [mocking::spawn_blocking](mocking/fn.spawn_blocking.html) wrap spawned tasks this way.
+//! The `async-std` and `smol` features provide their counterparts for these executors.
+//! Threads spawned with [mocking::spawn_thread](mocking/fn.spawn_thread.html) or in
+//! [mocking::thread_scope](mocking/fn.thread_scope.html) see these mocks too,
+//! other threads see them after installing a handle returned by
+//! [mocking::registry_handle](mocking/fn.registry_handle.html).
+//! With the `global-mocks` feature mocks created with `mock_shared` are visible in all threads
+//! without wrapping, which suits test runners starting one process per test like cargo-nextest.
+//!
+//! Mocks returning `PendingThen` futures stay pending for a given number of polls before resolving,
+//! which helps testing code racing them with `select!` or timeouts. Futures wrapped by
+//! [FutureObserver](mocking/struct.FutureObserver.html) record if they were completed or cancelled
+//! and how many times they were polled.
 //!
 //! # Mocking tricks
+//! ## Returning reference tied to arguments
+//!
+//! Mocks can return references borrowed from the arguments, e.g. fields of `&self`. The returned
+//! value can be created directly by the mock or, when the arguments are needed for something else
+//! first, by a closure receiving references to them passed inside `MockResult::ReturnFromArgs`:
+//!
+//! ```
+//! struct Config {
+//!     name: String,
+//!     alias: String,
+//! }
+//!
+//! #[mockable]
+//! impl Config {
+//!     fn name(&self) -> &str {
+//!         &self.name
+//!     }
+//! }
+//!
+//! #[test]
+//! fn name_test() {
+//!     Config::name.mock_safe(|config| MockResult::ReturnFromArgs((config,), Box::new(|(config,)| &config.alias)));
+//!
+//!     let config = Config { name: "name".to_string(), alias: "alias".to_string() };
+//!     assert_eq!("alias", config.name());
+//! }
+//! ```
+//! There is no need for [as_mut](mocking_utils/fn.as_mut.html) or leaking values.
+//!
 //! ## Returning reference to value created inside mock
 //!
 //! ```
@@ -309,8 +474,8 @@
 //!
 //! #[test]
 //! fn my_fn_test() {
-//!     mock = Some("mocked".to_string());
-//!     my_fn.mock_safe(move || MockResult::Return(mock.unwrap()));
+//!     let mut mock = Some("mocked".to_string());
+//!     my_fn.mock_safe(move || MockResult::Return(mock.take().unwrap()));
 //!
 //!     assert_eq!("mocked", my_fn());
 //!     // assert_eq!("mocked", my_fn()); // WILL PANIC!
@@ -321,10 +486,10 @@
 //!
 //! Returned values can be stored in a vector if mock should return different value on different calls:
 //!
-//! ```//!
+//! ```
 //! #[test]
 //! fn my_fn_test() {
-//!     mut mock = vec!["mocked 1".to_string(), "mocked 2".to_string()];
+//!     let mut mock = vec!["mocked 1".to_string(), "mocked 2".to_string()];
 //!     my_fn.mock_safe(move || MockResult::Return(mock.remove(0)));
 //!
 //!     assert_eq!("mocked 1", my_fn());
@@ -333,6 +498,8 @@
 //! }
 //! ```
 //! The vector can store `MockResult`s for more complex mocking.
+//! Simple sequences of returned values can be set up with
+//! [mock_return_sequence](mocking/trait.Mockable.html#tymethod.mock_return_sequence).
 #![doc(
     html_logo_url = "https://raw.githubusercontent.com/CodeSandwich/mocktopus/master/logo.png",
     html_favicon_url = "https://raw.githubusercontent.com/CodeSandwich/mocktopus/master/logo.png"
@@ -346,9 +513,35 @@ pub mod mocking;
 /// For use in testing code: helper tools for writing tests using mocking
 pub mod mocking_utils;
 
+/// For use in testing code: argument matchers for stubbing with [when!](macro.when.html)
+pub mod matchers;
+
+/// For use in documentation: running documented examples using mocks
+pub mod doctest;
+
 /// For use in tested code: tools making items mockable
 pub mod macros {
     pub use mocktopus_macros::*;
 }
 
+/// Test attribute isolating the test from mocks of other tests running in the same thread
+///
+/// See [mocktopus_test](https://docs.rs/mocktopus_macros/*/mocktopus_macros/attr.mocktopus_test.html).
+pub use mocktopus_macros::mocktopus_test as test;
+
+/// Async test attribute running the test with tokio and isolating it from mocks of other tests
+///
+/// See [mocktopus_tokio_test](https://docs.rs/mocktopus_macros/*/mocktopus_macros/attr.mocktopus_tokio_test.html).
+pub use mocktopus_macros::mocktopus_tokio_test as tokio_test;
+
+/// Async test attribute running the test with async-std and isolating it from mocks of other tests
+///
+/// See [mocktopus_async_std_test](https://docs.rs/mocktopus_macros/*/mocktopus_macros/attr.mocktopus_async_std_test.html).
+pub use mocktopus_macros::mocktopus_async_std_test as async_std_test;
+
+/// Async test attribute running the test with smol and isolating it from mocks of other tests
+///
+/// See [mocktopus_smol_test](https://docs.rs/mocktopus_macros/*/mocktopus_macros/attr.mocktopus_smol_test.html).
+pub use mocktopus_macros::mocktopus_smol_test as smol_test;
+
 mod mock_store;
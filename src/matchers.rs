@@ -0,0 +1,237 @@
+use crate::mocking::{push_fallthrough_mock, MockResult, Mockable};
+use std::marker::Tuple;
+use std::ops::RangeBounds;
+
+/// Checks if a single argument of a mocked function fulfills a condition
+///
+/// Implemented for all matchers created with functions from this module
+/// and for all closures taking a reference to the argument and returning `bool`.
+pub trait Matcher<T> {
+    /// Returns `true` if the argument fulfills the condition
+    fn matches(&self, arg: &T) -> bool;
+}
+
+impl<T, F: Fn(&T) -> bool> Matcher<T> for F {
+    fn matches(&self, arg: &T) -> bool {
+        self(arg)
+    }
+}
+
+/// Checks if all arguments of a mocked function fulfill their conditions
+///
+/// Implemented for tuples of [Matcher](trait.Matcher.html)s, one for each argument.
+pub trait ArgsMatcher<T> {
+    /// Returns `true` if all arguments fulfill their conditions
+    fn matches_args(&self, args: &T) -> bool;
+}
+
+macro_rules! impl_args_matcher {
+    ($($arg:ident $matcher:ident $idx:tt),*) => {
+        impl<$($arg, $matcher: Matcher<$arg>),*> ArgsMatcher<($($arg,)*)> for ($($matcher,)*) {
+            #[allow(unused_variables)]
+            fn matches_args(&self, args: &($($arg,)*)) -> bool {
+                true $(&& self.$idx.matches(&args.$idx))*
+            }
+        }
+    };
+}
+
+impl_args_matcher!();
+impl_args_matcher!(A0 M0 0);
+impl_args_matcher!(A0 M0 0, A1 M1 1);
+impl_args_matcher!(A0 M0 0, A1 M1 1, A2 M2 2);
+impl_args_matcher!(A0 M0 0, A1 M1 1, A2 M2 2, A3 M3 3);
+impl_args_matcher!(A0 M0 0, A1 M1 1, A2 M2 2, A3 M3 3, A4 M4 4);
+impl_args_matcher!(A0 M0 0, A1 M1 1, A2 M2 2, A3 M3 3, A4 M4 4, A5 M5 5);
+impl_args_matcher!(A0 M0 0, A1 M1 1, A2 M2 2, A3 M3 3, A4 M4 4, A5 M5 5, A6 M6 6);
+impl_args_matcher!(A0 M0 0, A1 M1 1, A2 M2 2, A3 M3 3, A4 M4 4, A5 M5 5, A6 M6 6, A7 M7 7);
+
+/// Matches any argument
+pub fn any<T>() -> impl Matcher<T> {
+    |_: &T| true
+}
+
+/// Matches arguments equal to a given value
+pub fn eq<T: PartialEq<U>, U>(value: U) -> impl Matcher<T> {
+    move |arg: &T| *arg == value
+}
+
+/// Matches arguments not equal to a given value
+pub fn ne<T: PartialEq<U>, U>(value: U) -> impl Matcher<T> {
+    move |arg: &T| *arg != value
+}
+
+/// Matches arguments lesser than a given value
+pub fn lt<T: PartialOrd<U>, U>(value: U) -> impl Matcher<T> {
+    move |arg: &T| *arg < value
+}
+
+/// Matches arguments lesser than or equal to a given value
+pub fn le<T: PartialOrd<U>, U>(value: U) -> impl Matcher<T> {
+    move |arg: &T| *arg <= value
+}
+
+/// Matches arguments greater than a given value
+pub fn gt<T: PartialOrd<U>, U>(value: U) -> impl Matcher<T> {
+    move |arg: &T| *arg > value
+}
+
+/// Matches arguments greater than or equal to a given value
+pub fn ge<T: PartialOrd<U>, U>(value: U) -> impl Matcher<T> {
+    move |arg: &T| *arg >= value
+}
+
+/// Matches arguments contained in a given range
+pub fn in_range<T: PartialOrd, R: RangeBounds<T>>(range: R) -> impl Matcher<T> {
+    move |arg: &T| range.contains(arg)
+}
+
+/// Matches string arguments containing a given substring
+pub fn contains<T: AsRef<str>>(substring: impl AsRef<str>) -> impl Matcher<T> {
+    move |arg: &T| arg.as_ref().contains(substring.as_ref())
+}
+
+/// Matches arguments, for which a given predicate returns `true`
+///
+/// Closures are matchers on their own, this function only helps with type inference.
+pub fn predicate<T, F: Fn(&T) -> bool>(predicate: F) -> impl Matcher<T> {
+    predicate
+}
+
+/// Matches arguments not matched by a given matcher
+pub fn not<T, M: Matcher<T>>(matcher: M) -> impl Matcher<T> {
+    move |arg: &T| !matcher.matches(arg)
+}
+
+/// Matches arguments matched by both given matchers
+pub fn and<T, M1: Matcher<T>, M2: Matcher<T>>(matcher_1: M1, matcher_2: M2) -> impl Matcher<T> {
+    move |arg: &T| matcher_1.matches(arg) && matcher_2.matches(arg)
+}
+
+/// Matches arguments matched by any of given matchers
+pub fn or<T, M1: Matcher<T>, M2: Matcher<T>>(matcher_1: M1, matcher_2: M2) -> impl Matcher<T> {
+    move |arg: &T| matcher_1.matches(arg) || matcher_2.matches(arg)
+}
+
+/// Stub of a mocked function for arguments matching given matchers, created with [when!](../macro.when.html)
+pub struct When<F, M> {
+    function: F,
+    matchers: M,
+    once: bool,
+}
+
+impl<F, M> When<F, M> {
+    #[doc(hidden)]
+    pub fn new(function: F, matchers: M) -> Self {
+        When {
+            function,
+            matchers,
+            once: false,
+        }
+    }
+
+    /// Make the stub handle only the first call with matching arguments
+    ///
+    /// After that the stub gets out of the way and all calls are passed
+    /// to older stubs or the real function.
+    ///
+    /// ```
+    /// use mocktopus::matchers::*;
+    ///
+    /// #[mockable]
+    /// fn add(a: u32, b: u32) -> u32 {
+    ///     a + b
+    /// }
+    ///
+    /// #[test]
+    /// fn add_test() {
+    ///     when!(add(eq(5), any())).once().then_return(10);
+    ///
+    ///     assert_eq!(3, add(1, 2));
+    ///     assert_eq!(10, add(5, 2));
+    ///     assert_eq!(7, add(5, 2));
+    /// }
+    /// ```
+    pub fn once(mut self) -> Self {
+        self.once = true;
+        self
+    }
+
+    /// For matching arguments make the function return a clone of given value
+    pub fn then_return<T: Tuple, O: Clone + 'static>(self, value: O)
+    where
+        F: Mockable<T, O>,
+        M: ArgsMatcher<T> + 'static,
+    {
+        self.then(move |_| value.clone())
+    }
+
+    /// For matching arguments make the function panic with given message
+    pub fn then_panic<T: Tuple, O>(self, message: impl Into<String>)
+    where
+        F: Mockable<T, O>,
+        M: ArgsMatcher<T> + 'static,
+    {
+        let message = message.into();
+        self.then(move |_| -> O { panic!("{}", message) })
+    }
+
+    /// For matching arguments make the function return value computed by given handler
+    ///
+    /// The handler receives all the arguments as a tuple.
+    pub fn then<T: Tuple, O, H>(self, mut handler: H)
+    where
+        F: Mockable<T, O>,
+        M: ArgsMatcher<T> + 'static,
+        H: FnMut(T) -> O + 'static,
+    {
+        let matchers = self.matchers;
+        let once = self.once;
+        let mut exhausted = false;
+        let mock = move |args: T| match !exhausted && matchers.matches_args(&args) {
+            true => {
+                exhausted = once;
+                MockResult::Return(handler(args))
+            }
+            false => MockResult::Continue(args),
+        };
+        push_fallthrough_mock(&self.function, mock)
+    }
+}
+
+/// Sets up a stub of a mocked function for arguments matching given [matchers](matchers/index.html)
+///
+/// Every argument must be matched by a matcher. Multiple stubs can be set up for a single function,
+/// when it's called, the most recent stub matching its arguments is used.
+/// If none of the stubs matches, the function runs normally.
+///
+/// ```
+/// use mocktopus::matchers::*;
+///
+/// #[mockable]
+/// fn add(a: u32, b: u32) -> u32 {
+///     a + b
+/// }
+///
+/// #[test]
+/// fn add_test() {
+///     when!(add(eq(1), any())).then_return(10);
+///     when!(add(gt(5), in_range(0..3))).then_return(20);
+///
+///     assert_eq!(10, add(1, 5));
+///     assert_eq!(20, add(6, 2));
+///     assert_eq!(6, add(3, 3));
+/// }
+/// ```
+#[macro_export]
+macro_rules! when {
+    (@function [$($function:tt)*] ($($matcher:expr),* $(,)?)) => {
+        $crate::matchers::When::new($($function)*, ($($matcher,)*))
+    };
+    (@function [$($function:tt)*] $next:tt $($rest:tt)+) => {
+        $crate::when!(@function [$($function)* $next] $($rest)+)
+    };
+    ($($tokens:tt)+) => {
+        $crate::when!(@function [] $($tokens)+)
+    };
+}
@@ -1,35 +1,287 @@
-use crate::mocking::MockResult;
-use std::{any::TypeId, marker::Tuple};
-use std::cell::RefCell;
-use std::collections::HashMap;
+use crate::mocking::{MockResult, UnmockedCall};
+use std::{any::{type_name, Any, TypeId}, marker::Tuple};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::mem::transmute;
 use std::rc::Rc;
+use std::sync::Arc;
+#[cfg(feature = "global-mocks")]
+use std::sync::{LazyLock, PoisonError, RwLock};
 
 pub struct MockStore {
     layers: RefCell<Vec<MockLayer>>,
+    unmocked_calls: RefCell<HashSet<TypeId>>,
+    call_counts: RefCell<HashMap<TypeId, usize>>,
+    named_mocks: RefCell<HashMap<String, NamedMock>>,
+    trait_mocks: RefCell<HashMap<String, TraitMock>>,
+    states: RefCell<HashMap<TypeId, Rc<dyn Any>>>,
+    fallback: RefCell<Option<Rc<RefCell<FallbackHandler>>>>,
+    shared_mocks: RefCell<HashMap<TypeId, SharedMockInstaller>>,
+    /// Mocks of running handles, the innermost one is the last
+    handle_mocks: RefCell<Vec<Vec<(TypeId, SharedMockInstaller)>>>,
+    /// Values of mocked statics by their addresses
+    static_values: RefCell<HashMap<usize, &'static dyn Any>>,
+    /// Mocks of the global registry below all layers and its generation they were installed from
+    global_layer: RefCell<MockLayer>,
+    #[cfg(feature = "global-mocks")]
+    global_generation: Cell<usize>,
+}
+
+/// Sets up a mock shareable between threads in a layer of the current thread
+pub type SharedMockInstaller = Arc<dyn Fn(&mut MockLayer) + Send + Sync>;
+
+type FallbackHandler = Box<dyn FnMut(&UnmockedCall)>;
+
+/// Shared mocks visible in all threads of the process, every change starts a new generation
+#[cfg(feature = "global-mocks")]
+static GLOBAL_MOCKS: LazyLock<RwLock<GlobalMocks>> = LazyLock::new(Default::default);
+
+#[cfg(feature = "global-mocks")]
+#[derive(Default)]
+struct GlobalMocks {
+    // Thread layers start in generation 0, so the first one with mocks is 1
+    generation: usize,
+    installers: HashMap<TypeId, SharedMockInstaller>,
+}
+
+#[cfg(feature = "global-mocks")]
+pub fn add_global(id: TypeId, installer: SharedMockInstaller) {
+    let mut global_mocks = GLOBAL_MOCKS.write().unwrap_or_else(PoisonError::into_inner);
+    global_mocks.generation += 1;
+    global_mocks.installers.insert(id, installer);
+}
+
+#[cfg(feature = "global-mocks")]
+pub fn remove_global(id: TypeId) {
+    let mut global_mocks = GLOBAL_MOCKS.write().unwrap_or_else(PoisonError::into_inner);
+    if global_mocks.installers.remove(&id).is_some() {
+        global_mocks.generation += 1;
+    }
+}
+
+#[cfg(feature = "global-mocks")]
+pub fn clear_global() {
+    let mut global_mocks = GLOBAL_MOCKS.write().unwrap_or_else(PoisonError::into_inner);
+    global_mocks.generation += 1;
+    global_mocks.installers.clear();
 }
 
 impl MockStore {
+    /// With the `global-mocks` feature the global registry is cleared too
     pub fn clear(&self) {
+        #[cfg(feature = "global-mocks")]
+        clear_global();
+        self.call_counts.borrow_mut().clear();
+        self.named_mocks.borrow_mut().clear();
+        self.trait_mocks.borrow_mut().clear();
+        self.states.borrow_mut().clear();
+        self.shared_mocks.borrow_mut().clear();
+        self.static_values.borrow_mut().clear();
         for layer in self.layers.borrow_mut().iter_mut() {
             layer.clear()
         }
     }
 
-    pub fn clear_id(&self, id: TypeId) {
+    /// Clears all mocks and turns off strict mode, the fallback handler and unmocked calls
+    pub fn reset(&self) {
+        self.clear();
+        self.unmocked_calls.borrow_mut().clear();
+        *self.fallback.borrow_mut() = None;
         for layer in self.layers.borrow_mut().iter_mut() {
-            layer.clear_id(id)
+            layer.strict = false;
         }
     }
 
+    /// Mocks of outer contexts are only hidden until the innermost one finishes
+    pub fn clear_id(&self, id: TypeId) {
+        self.shared_mocks.borrow_mut().remove(&id);
+        #[cfg(feature = "global-mocks")]
+        remove_global(id);
+        let mut layers = self.layers.borrow_mut();
+        let (innermost, outer) = layers.split_last_mut().expect("Thread mock level missing");
+        innermost.clear_id(id);
+        if let Some((thread_layer, outer_contexts)) = outer.split_first_mut() {
+            thread_layer.clear_id(id);
+            if outer_contexts.iter().any(|layer| layer.contains(id)) {
+                innermost.cleared.insert(id);
+            }
+        }
+    }
+
+    pub fn call_count(&self, id: TypeId) -> usize {
+        self.call_counts.borrow().get(&id).copied().unwrap_or(0)
+    }
+
+    pub fn reset_call_count(&self, id: TypeId) {
+        self.call_counts.borrow_mut().remove(&id);
+    }
+
+    pub fn set_state(&self, id: TypeId, state: Rc<dyn Any>) {
+        self.states.borrow_mut().insert(id, state);
+    }
+
+    pub fn state(&self, id: TypeId) -> Option<Rc<dyn Any>> {
+        self.states.borrow().get(&id).cloned()
+    }
+
+    pub fn set_static_value(&self, address: usize, value: Option<&'static dyn Any>) {
+        if value.is_some() {
+            check_mocking_enabled();
+        }
+        let mut static_values = self.static_values.borrow_mut();
+        match value {
+            Some(value) => static_values.insert(address, value),
+            None => static_values.remove(&address),
+        };
+    }
+
+    pub fn static_value(&self, address: usize) -> Option<&'static dyn Any> {
+        self.static_values.borrow().get(&address).copied()
+    }
+
+    pub fn is_mocked(&self, id: TypeId) -> bool {
+        for layer in self.layers.borrow().iter().rev() {
+            if layer.contains(id) {
+                return true;
+            }
+            if layer.cleared.contains(&id) {
+                return false;
+            }
+        }
+        self.sync_global_layer();
+        self.global_layer.borrow().contains(id)
+    }
+
+    /// Reinstalls mocks of the global registry if it changed since they were installed
+    #[cfg(feature = "global-mocks")]
+    fn sync_global_layer(&self) {
+        let global_mocks = GLOBAL_MOCKS.read().unwrap_or_else(PoisonError::into_inner);
+        if global_mocks.generation == self.global_generation.get() {
+            return;
+        }
+        let mut global_layer = MockLayer::default();
+        for installer in global_mocks.installers.values() {
+            installer(&mut global_layer);
+        }
+        *self.global_layer.borrow_mut() = global_layer;
+        self.global_generation.set(global_mocks.generation);
+    }
+
+    #[cfg(not(feature = "global-mocks"))]
+    fn sync_global_layer(&self) {}
+
     /// Layer will be in use as long as MockLayerGuard is alive
     /// MockLayerGuards must always be dropped and always in reverse order of their creation
-    pub unsafe fn add_layer(&self, layer: MockLayer) {
+    /// Expectations count calls made since the first time the layer was added
+    pub unsafe fn add_layer(&self, mut layer: MockLayer) {
+        for expectation in &mut layer.expectations {
+            if expectation.start_count.is_none() && expectation.matching_calls.is_none() {
+                expectation.start_count = Some(self.call_count(expectation.id));
+            }
+        }
         self.layers.borrow_mut().push(layer)
     }
 
-    pub unsafe fn remove_layer(&self) {
-        self.layers.borrow_mut().pop();
+    pub unsafe fn remove_layer(&self) -> MockLayer {
+        self.layers.borrow_mut().pop().expect("Mock layer missing")
+    }
+
+    /// Adds expectation to the innermost layer
+    pub fn add_expectation(&self, id: TypeId, fn_name: &'static str, times: usize) {
+        let start_count = self.call_count(id);
+        self.layers
+            .borrow_mut()
+            .last_mut()
+            .expect("Thread mock level missing")
+            .expectations
+            .push(Expectation {
+                id,
+                fn_name,
+                times,
+                start_count: Some(start_count),
+                matching_calls: None,
+            });
+    }
+
+    pub fn verify_layer_expectations(&self, layer: &mut MockLayer) {
+        let expectations = std::mem::take(&mut layer.expectations);
+        self.verify_expectations(expectations)
+    }
+
+    pub fn verify_all_expectations(&self) {
+        let expectations = self
+            .layers
+            .borrow_mut()
+            .iter_mut()
+            .flat_map(|layer| std::mem::take(&mut layer.expectations))
+            .collect();
+        self.verify_expectations(expectations)
+    }
+
+    fn verify_expectations(&self, expectations: Vec<Expectation>) {
+        let failures: Vec<_> = expectations
+            .iter()
+            .filter_map(|expectation| {
+                let (calls, description) = match expectation.matching_calls {
+                    Some(ref matching_calls) => (matching_calls.get(), " with matching arguments"),
+                    None => {
+                        let calls = self
+                            .call_count(expectation.id)
+                            .saturating_sub(expectation.start_count.unwrap_or(0));
+                        (calls, "")
+                    }
+                };
+                match calls == expectation.times {
+                    true => None,
+                    false => Some(format!(
+                        "{}: expected {} calls{}, got {}",
+                        expectation.fn_name, expectation.times, description, calls
+                    )),
+                }
+            })
+            .collect();
+        // Panicking while already panicking would abort the whole test binary
+        if !failures.is_empty() && !std::thread::panicking() {
+            panic!("Mock expectations not met:\n{}", failures.join("\n"));
+        }
+    }
+
+    /// Strict mode of the thread layer is kept when mocks are cleared
+    pub fn set_thread_strict(&self, strict: bool) {
+        self.layers
+            .borrow_mut()
+            .first_mut()
+            .expect("Thread mock level missing")
+            .strict = strict;
+    }
+
+    pub fn set_fallback(&self, handler: Option<FallbackHandler>) {
+        *self.fallback.borrow_mut() = handler.map(|handler| Rc::new(RefCell::new(handler)));
+    }
+
+    /// Fallback handler is not called recursively for calls made by itself
+    fn call_fallback<I: Tuple, O>(&self, fn_name: &'static str) {
+        let fallback = match self.fallback.borrow().clone() {
+            Some(fallback) => fallback,
+            None => return,
+        };
+        let mut handler = match fallback.try_borrow_mut() {
+            Ok(handler) => handler,
+            Err(_) => return,
+        };
+        handler(&UnmockedCall::new(
+            fn_name,
+            type_name::<I>(),
+            type_name::<O>(),
+        ));
+    }
+
+    fn is_strict(&self) -> bool {
+        self.layers.borrow().iter().any(|layer| layer.strict)
+    }
+
+    pub fn layer_count(&self) -> usize {
+        self.layers.borrow().len()
     }
 
     pub unsafe fn add_to_thread_layer<I: Tuple, O>(
@@ -37,32 +289,271 @@ impl MockStore {
         id: TypeId,
         mock: Box<dyn FnMut<I, Output = MockResult<I, O>> + 'static>,
     ) {
+        self.shared_mocks.borrow_mut().remove(&id);
+        self.add_to_layer(0, id, mock)
+    }
+
+    /// Sets up a mock in the thread layer and marks it as shareable between threads
+    pub fn add_shared_to_thread_layer(&self, id: TypeId, installer: SharedMockInstaller) {
+        installer(
+            self.layers
+                .borrow_mut()
+                .first_mut()
+                .expect("Thread mock level missing"),
+        );
+        self.shared_mocks.borrow_mut().insert(id, installer);
+    }
+
+    /// Mocks of running handles shadow the ones of the thread layer
+    pub fn shared_mocks(&self) -> Vec<(TypeId, SharedMockInstaller)> {
+        let mut shared_mocks = self.shared_mocks.borrow().clone();
+        for handle_mocks in self.handle_mocks.borrow().iter() {
+            for (id, installer) in handle_mocks {
+                shared_mocks.insert(*id, installer.clone());
+            }
+        }
+        shared_mocks.into_iter().collect()
+    }
+
+    pub fn push_handle_mocks(&self, mocks: Vec<(TypeId, SharedMockInstaller)>) {
+        self.handle_mocks.borrow_mut().push(mocks);
+    }
+
+    pub fn pop_handle_mocks(&self) {
+        self.handle_mocks
+            .borrow_mut()
+            .pop()
+            .expect("Handle mocks missing");
+    }
+
+    /// When fallthrough mock returns `MockResult::Continue`, the call is passed to the mock below it
+    pub unsafe fn push_to_thread_layer<I: Tuple, O>(
+        &self,
+        id: TypeId,
+        mock: Box<dyn FnMut<I, Output = MockResult<I, O>> + 'static>,
+        fallthrough: bool,
+    ) {
+        self.layers
+            .borrow_mut()
+            .first_mut()
+            .expect("Thread mock level missing")
+            .push(id, mock, fallthrough);
+    }
+
+    pub fn snapshot_thread_layer(&self) -> MockLayer {
+        self.layers
+            .borrow()
+            .first()
+            .expect("Thread mock level missing")
+            .snapshot()
+    }
+
+    /// Expectations of the thread layer are kept
+    pub fn restore_thread_layer(&self, snapshot: &MockLayer) {
+        let mut layers = self.layers.borrow_mut();
+        let layer = layers.first_mut().expect("Thread mock level missing");
+        layer.mocks = snapshot.mocks.clone();
+        layer.cleared = snapshot.cleared.clone();
+        layer.strict = snapshot.strict;
+    }
+
+    pub fn pop_from_thread_layer(&self, id: TypeId) {
+        self.shared_mocks.borrow_mut().remove(&id);
         self.layers
             .borrow_mut()
             .first_mut()
             .expect("Thread mock level missing")
+            .pop(id);
+    }
+
+    /// Mock must live as long as the layer
+    pub unsafe fn add_to_layer<I: Tuple, O>(
+        &self,
+        layer_idx: usize,
+        id: TypeId,
+        mock: Box<dyn FnMut<I, Output = MockResult<I, O>> + 'static>,
+    ) {
+        self.layers
+            .borrow_mut()
+            .get_mut(layer_idx)
+            .expect("Mock layer missing")
             .add(id, mock);
     }
 
-    pub unsafe fn call<I: Tuple, O>(&self, id: TypeId, mut input: I) -> MockResult<I, O> {
+    /// Mock will be moved to the thread layer on the first call of the function with a given path
+    pub unsafe fn add_named<I: Tuple, O>(
+        &self,
+        fn_name: String,
+        mock: Box<dyn FnMut<I, Output = MockResult<I, O>> + 'static>,
+    ) {
+        let named_mock = NamedMock {
+            mock: StoredMock::new(mock).erase(false),
+            signature: type_name::<(I, O)>(),
+        };
+        self.named_mocks.borrow_mut().insert(fn_name, named_mock);
+    }
+
+    /// Mock will be moved to the thread layer on the first call of every implementation
+    /// of the trait method with a given path
+    pub unsafe fn add_trait_named<I: Tuple, O>(
+        &self,
+        trait_fn_name: String,
+        mock: Box<dyn FnMut<I, Output = MockResult<I, O>> + 'static>,
+    ) {
+        let trait_mock = TraitMock {
+            named_mock: NamedMock {
+                mock: StoredMock::new(mock).erase(false),
+                signature: type_name::<(I, O)>(),
+            },
+            bound_ids: HashSet::new(),
+        };
+        self.trait_mocks
+            .borrow_mut()
+            .insert(trait_fn_name, trait_mock);
+    }
+
+    fn bind_named<I: Tuple, O>(&self, id: TypeId, fn_name: &'static str) {
+        if self.named_mocks.borrow().is_empty() {
+            return self.bind_trait_named::<I, O>(id, fn_name);
+        }
+        let named_mock = match self.named_mocks.borrow_mut().remove(fn_name) {
+            Some(named_mock) => named_mock,
+            None => return self.bind_trait_named::<I, O>(id, fn_name),
+        };
+        self.bind_named_mock::<I, O>(id, fn_name, named_mock);
+    }
+
+    /// Implementations mocked separately don't use the trait mock
+    fn bind_trait_named<I: Tuple, O>(&self, id: TypeId, fn_name: &'static str) {
+        if self.trait_mocks.borrow().is_empty() || self.is_mocked(id) {
+            return;
+        }
+        let trait_fn_name = match trait_fn_name(fn_name) {
+            Some(trait_fn_name) => trait_fn_name,
+            None => return,
+        };
+        let named_mock = match self.trait_mocks.borrow_mut().get_mut(&trait_fn_name) {
+            Some(trait_mock) => match trait_mock.bound_ids.insert(id) {
+                true => trait_mock.named_mock.clone(),
+                false => return,
+            },
+            None => return,
+        };
+        self.bind_named_mock::<I, O>(id, fn_name, named_mock);
+    }
+
+    fn bind_named_mock<I: Tuple, O>(
+        &self,
+        id: TypeId,
+        fn_name: &'static str,
+        named_mock: NamedMock,
+    ) {
+        let signature = type_name::<(I, O)>();
+        if named_mock.signature != signature {
+            panic!(
+                "Mock of {} set up by name has signature {}, expected {}",
+                fn_name, named_mock.signature, signature
+            );
+        }
+        self.layers
+            .borrow_mut()
+            .first_mut()
+            .expect("Thread mock level missing")
+            .add_erased(id, named_mock.mock);
+    }
+
+    /// Observer is not notified about calls made by itself
+    unsafe fn notify_observers<I: Tuple>(&self, id: TypeId, input: &I) {
+        let observers: Vec<_> = self
+            .layers
+            .borrow()
+            .iter()
+            .flat_map(|layer| layer.observers.get(&id).into_iter().flatten().cloned())
+            .collect();
+        for observer in observers {
+            if let Ok(mut observer) = observer.try_borrow_mut() {
+                let observer: &mut Box<dyn FnMut(&I)> = transmute(&mut *observer);
+                observer(input);
+            }
+        }
+    }
+
+    /// Makes the next call of the function ignore all its mocks
+    pub fn add_unmocked_call(&self, id: TypeId) {
+        self.unmocked_calls.borrow_mut().insert(id);
+    }
+
+    pub fn remove_unmocked_call(&self, id: TypeId) {
+        self.unmocked_calls.borrow_mut().remove(&id);
+    }
+
+    pub unsafe fn call<I: Tuple, O>(
+        &self,
+        id: TypeId,
+        fn_name: &'static str,
+        mut input: I,
+    ) -> MockResult<I, O> {
+        if self.unmocked_calls.borrow_mut().remove(&id) {
+            return MockResult::Continue(input);
+        }
+        self.bind_named::<I, O>(id, fn_name);
+        if !self.is_mocked(id) {
+            if self.is_strict() {
+                panic!("Strict mode: called {} without a mock", fn_name);
+            }
+            self.call_fallback::<I, O>(fn_name);
+        }
+        *self.call_counts.borrow_mut().entry(id).or_insert(0) += 1;
+        self.notify_observers(id, &input);
+        self.sync_global_layer();
         // Do not hold RefCell borrow while calling mock, it can try to modify mocks
         let layer_count = self.layers.borrow().len();
         for layer_idx in (0..layer_count).rev() {
-            let mock_opt = self
-                .layers
-                .borrow()
-                .get(layer_idx)
-                .expect("Mock layer removed while iterating")
-                .get(id);
-            if let Some(mock) = mock_opt {
-                match mock.call(input) {
-                    MockLayerResult::Handled(result) => return result,
-                    MockLayerResult::Unhandled(new_input) => input = new_input,
+            let (mocks, cleared) = {
+                let layers = self.layers.borrow();
+                let layer = layers
+                    .get(layer_idx)
+                    .expect("Mock layer removed while iterating");
+                (layer.get(id), layer.cleared.contains(&id))
+            };
+            input = match call_mocks(mocks, input) {
+                MockLayerResult::Handled(result) => return result,
+                MockLayerResult::Unhandled(input) => input,
+            };
+            if cleared {
+                return MockResult::Continue(input);
+            }
+        }
+        let mocks = self.global_layer.borrow().get(id);
+        match call_mocks(mocks, input) {
+            MockLayerResult::Handled(result) => result,
+            MockLayerResult::Unhandled(input) => MockResult::Continue(input),
+        }
+    }
+}
+
+/// Calls mocks of a stack starting with the top one until one of them handles the call
+unsafe fn call_mocks<I: Tuple, O>(
+    mocks: Vec<ErasedStoredMock>,
+    mut input: I,
+) -> MockLayerResult<I, O> {
+    for mock in mocks {
+        let fallthrough = mock.fallthrough;
+        match mock.call(input) {
+            MockLayerResult::Handled(MockResult::Pass(new_input)) => input = new_input,
+            MockLayerResult::Handled(MockResult::Continue(new_input)) if fallthrough => {
+                input = new_input
+            }
+            MockLayerResult::Handled(result) => return MockLayerResult::Handled(result),
+            MockLayerResult::Unhandled(new_input) => {
+                input = new_input;
+                if !fallthrough {
+                    break;
                 }
             }
         }
-        MockResult::Continue(input)
     }
+    MockLayerResult::Unhandled(input)
 }
 
 //TODO tests
@@ -75,36 +566,234 @@ impl Default for MockStore {
     fn default() -> Self {
         MockStore {
             layers: RefCell::new(vec![MockLayer::default()]),
+            unmocked_calls: RefCell::new(HashSet::new()),
+            call_counts: RefCell::new(HashMap::new()),
+            named_mocks: RefCell::new(HashMap::new()),
+            trait_mocks: RefCell::new(HashMap::new()),
+            states: RefCell::new(HashMap::new()),
+            fallback: RefCell::new(None),
+            shared_mocks: RefCell::new(HashMap::new()),
+            handle_mocks: RefCell::new(Vec::new()),
+            static_values: RefCell::new(HashMap::new()),
+            global_layer: RefCell::new(MockLayer::default()),
+            #[cfg(feature = "global-mocks")]
+            global_generation: Cell::new(0),
         }
     }
 }
 
+/// Every function has a stack of mocks, the top one is in use and it can pass calls to the ones below
 #[derive(Default)]
 pub struct MockLayer {
-    mocks: HashMap<TypeId, ErasedStoredMock>,
+    mocks: HashMap<TypeId, Vec<ErasedStoredMock>>,
+    observers: HashMap<TypeId, Vec<ErasedObserver>>,
+    /// Paths of functions, which were named when mocked
+    names: HashMap<TypeId, &'static str>,
+    /// Functions, which are not mocked in this layer and all layers below
+    cleared: HashSet<TypeId>,
+    expectations: Vec<Expectation>,
+    pub strict: bool,
 }
 
 impl MockLayer {
     fn clear(&mut self) {
-        self.mocks.clear()
+        self.mocks.clear();
+        self.cleared.clear();
+        self.observers.clear();
+        self.expectations.clear();
+    }
+
+    /// Expectation will count calls made after the layer is added to a store
+    pub fn add_expectation(&mut self, id: TypeId, fn_name: &'static str, times: usize) {
+        self.expectations.push(Expectation {
+            id,
+            fn_name,
+            times,
+            start_count: None,
+            matching_calls: None,
+        });
+    }
+
+    /// Expectation will count calls with arguments accepted by the matcher
+    pub unsafe fn add_matching_expectation<I: Tuple>(
+        &mut self,
+        id: TypeId,
+        fn_name: &'static str,
+        times: usize,
+        mut matcher: Box<dyn FnMut(&I) -> bool + 'static>,
+    ) {
+        let matching_calls = Rc::new(Cell::new(0));
+        let observer_calls = matching_calls.clone();
+        let observer = Box::new(move |input: &I| {
+            if matcher(input) {
+                observer_calls.set(observer_calls.get() + 1);
+            }
+        }) as Box<dyn FnMut(&I)>;
+        let erased = transmute::<Box<dyn FnMut(&I)>, Box<dyn FnMut(&())>>(observer);
+        let erased: ErasedObserver = Rc::new(RefCell::new(erased));
+        self.observers.entry(id).or_default().push(erased);
+        self.expectations.push(Expectation {
+            id,
+            fn_name,
+            times,
+            start_count: None,
+            matching_calls: Some(matching_calls),
+        });
+    }
+
+    /// Mocks of the other layer replace mocks of the same functions in this layer
+    pub fn merge(&mut self, other: MockLayer) {
+        self.mocks.extend(other.mocks);
+        self.names.extend(other.names);
+        self.cleared.extend(other.cleared);
+        for (id, observers) in other.observers {
+            self.observers.entry(id).or_default().extend(observers);
+        }
+        self.expectations.extend(other.expectations);
+        self.strict |= other.strict;
+    }
+
+    /// Snapshot shares mocks with the layer, but not expectations
+    fn snapshot(&self) -> MockLayer {
+        MockLayer {
+            mocks: self.mocks.clone(),
+            observers: HashMap::new(),
+            names: self.names.clone(),
+            cleared: self.cleared.clone(),
+            expectations: Vec::new(),
+            strict: self.strict,
+        }
     }
 
     fn clear_id(&mut self, id: TypeId) {
         self.mocks.remove(&id);
     }
 
+    pub fn mocked_ids(&self) -> impl Iterator<Item = TypeId> + '_ {
+        self.mocks.keys().copied()
+    }
+
+    pub fn set_name(&mut self, id: TypeId, name: &'static str) {
+        self.names.insert(id, name);
+    }
+
+    /// Paths of all mocked functions, which were named
+    pub fn mocked_names(&self) -> Vec<&'static str> {
+        let mut names: Vec<_> = self
+            .mocked_ids()
+            .filter_map(|id| self.names.get(&id).copied())
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    fn contains(&self, id: TypeId) -> bool {
+        self.mocks.contains_key(&id)
+    }
+
+    /// Replaces the top mock of the stack
     pub unsafe fn add<I: Tuple, O>(
         &mut self,
         id: TypeId,
         mock: Box<dyn FnMut<I, Output = MockResult<I, O>> + 'static>,
     ) {
-        let stored = StoredMock::new(mock).erase();
-        self.mocks.insert(id, stored);
+        self.add_erased(id, StoredMock::new(mock).erase(false))
+    }
+
+    fn add_erased(&mut self, id: TypeId, mock: ErasedStoredMock) {
+        self.cleared.remove(&id);
+        let stack = self.mocks.entry(id).or_default();
+        stack.pop();
+        stack.push(mock);
+    }
+
+    unsafe fn push<I: Tuple, O>(
+        &mut self,
+        id: TypeId,
+        mock: Box<dyn FnMut<I, Output = MockResult<I, O>> + 'static>,
+        fallthrough: bool,
+    ) {
+        self.cleared.remove(&id);
+        let stored = StoredMock::new(mock).erase(fallthrough);
+        self.mocks.entry(id).or_default().push(stored);
+    }
+
+    fn pop(&mut self, id: TypeId) {
+        if let Some(stack) = self.mocks.get_mut(&id) {
+            stack.pop();
+            if stack.is_empty() {
+                self.mocks.remove(&id);
+            }
+        }
     }
 
-    unsafe fn get(&self, id: TypeId) -> Option<ErasedStoredMock> {
-        self.mocks.get(&id).cloned()
+    /// Returns all mocks of the stack starting with the top one
+    unsafe fn get(&self, id: TypeId) -> Vec<ErasedStoredMock> {
+        self.mocks
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .rev()
+            .cloned()
+            .collect()
+    }
+}
+
+struct Expectation {
+    id: TypeId,
+    fn_name: &'static str,
+    times: usize,
+    start_count: Option<usize>,
+    /// Set only for expectations of calls with matching arguments
+    matching_calls: Option<Rc<Cell<usize>>>,
+}
+
+/// Observer of arguments of all calls of a function with erased arguments type
+type ErasedObserver = Rc<RefCell<Box<dyn FnMut(&())>>>;
+
+/// Mock of a function identified by its path, which wasn't called yet
+#[derive(Clone)]
+struct NamedMock {
+    mock: ErasedStoredMock,
+    signature: &'static str,
+}
+
+/// Mock of all implementations of a trait method identified by its path
+struct TraitMock {
+    named_mock: NamedMock,
+    /// Implementations, which were already called
+    bound_ids: HashSet<TypeId>,
+}
+
+/// Turns a path of a trait method implementation like `<my_crate::Struct as my_crate::Trait>::method`
+/// into a path of the trait method like `my_crate::Trait::method`
+fn trait_fn_name(fn_name: &str) -> Option<String> {
+    let mut depth = 0;
+    let mut trait_start = None;
+    let mut previous = ' ';
+    for (idx, character) in fn_name.char_indices() {
+        match character {
+            '<' => depth += 1,
+            // Skip arrows of function types like `fn(u8) -> u8`
+            '>' if previous != '-' => {
+                depth -= 1;
+                if depth == 0 {
+                    let trait_path = &fn_name[trait_start?..idx];
+                    let fn_path = fn_name[idx + 1..].strip_prefix("::")?;
+                    return Some(format!("{}::{}", trait_path, fn_path));
+                }
+            }
+            ' ' if depth == 1 && fn_name[idx..].starts_with(" as ") => {
+                trait_start = Some(idx + " as ".len())
+            }
+            _ => (),
+        }
+        if depth == 0 {
+            return None;
+        }
+        previous = character;
     }
+    None
 }
 
 pub enum MockLayerResult<I, O> {
@@ -115,6 +804,7 @@ pub enum MockLayerResult<I, O> {
 #[derive(Clone)]
 struct ErasedStoredMock {
     mock: StoredMock<(), ()>,
+    fallthrough: bool,
 }
 
 impl ErasedStoredMock {
@@ -124,6 +814,15 @@ impl ErasedStoredMock {
     }
 }
 
+// Annotated items aren't made mockable with the feature, so mocks would silently never run
+fn check_mocking_enabled() {
+    if cfg!(feature = "disable-mocking") {
+        panic!(
+            "Mocks can't be set up, because the `disable-mocking` feature of mocktopus is enabled"
+        );
+    }
+}
+
 /// Guarantees that while mock is running it's not overwritten, destroyed, or called again
 #[derive(Clone)]
 struct StoredMock<I: Tuple, O> {
@@ -132,6 +831,7 @@ struct StoredMock<I: Tuple, O> {
 
 impl<I: Tuple, O> StoredMock<I, O> {
     fn new(mock: Box<dyn FnMut<I, Output = MockResult<I, O>> + 'static>) -> Self {
+        check_mocking_enabled();
         StoredMock {
             mock: Rc::new(RefCell::new(mock)),
         }
@@ -144,10 +844,11 @@ impl<I: Tuple, O> StoredMock<I, O> {
         }
     }
 
-    fn erase(self) -> ErasedStoredMock {
+    fn erase(self, fallthrough: bool) -> ErasedStoredMock {
         unsafe {
             ErasedStoredMock {
                 mock: transmute(self),
+                fallthrough,
             }
         }
     }
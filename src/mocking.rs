@@ -1,13 +1,46 @@
-use crate::mock_store::{MockLayer, MockStore};
-use std::{any::{Any, TypeId}, marker::Tuple};
+use crate::matchers::ArgsMatcher;
+use crate::mock_store::{MockLayer, MockStore, SharedMockInstaller};
+use crate::mocking_utils::ArgCaptor;
+use futures_core::Stream;
+use std::{any::{type_name, Any, TypeId}, marker::Tuple};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::future::{self, Future};
 use std::marker::PhantomData;
-use std::mem::transmute;
+use std::mem::{forget, transmute, transmute_copy};
+use std::ops::Deref;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::ptr;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::task::{Context, Poll};
 
 /// Trait for setting up mocks
 ///
 /// The trait is implemented for all functions, so its methods can be called on any function.
 ///
 /// Note: methods have any effect only if called on functions [annotated as mockable](https://docs.rs/mocktopus_macros).
+///
+/// Mockable functions keep coercing to function pointers and calls through the pointers run the mocks
+/// like direct calls do. The mocks must be set up on the functions though, setting them up on function
+/// pointers has no effect, because a pointer doesn't tell, which function it points to.
+///
+/// ```
+/// #[mockable]
+/// fn get_string() -> String {
+///     "not mocked".to_string()
+/// }
+///
+/// #[test]
+/// fn get_string_test() {
+///     let pointer: fn() -> String = get_string;
+///     get_string.mock_safe(|| MockResult::Return("mocked".to_string()));
+///
+///     assert_eq!("mocked", pointer());
+/// }
+/// ```
 pub trait Mockable<T: Tuple, O> {
     /// Core function for setting up mocks
     ///
@@ -64,171 +97,3221 @@ pub trait Mockable<T: Tuple, O> {
     /// ```
     fn mock_safe<M: FnMut<T, Output = MockResult<T, O>> + 'static>(&self, mock: M);
 
-    /// Stop mocking this function.
+    /// Set up a mock on top of the current one, which will be restored by [pop_mock](#tymethod.pop_mock)
     ///
-    /// All future invocations will be forwarded to the real implementation.
-    fn clear_mock(&self);
-
-    #[doc(hidden)]
-    /// Called before every execution of a mockable function. Checks if mock is set and if it is, calls it.
-    fn call_mock(&self, input: T) -> MockResult<T, O>;
-
-    #[doc(hidden)]
-    /// Returns a unique ID of the function, which is used to set and get its mock.
-    unsafe fn get_mock_id(&self) -> TypeId;
-}
-
-/// Controls mocked function behavior when returned from [mock closure](trait.Mockable.html)
-pub enum MockResult<T, O> {
-    /// Function runs normally as if it was called with given arguments.
-    /// The arguments are passed inside enum variant as a tuple.
-    Continue(T),
-
-    /// Function returns immediately with a given value. The returned value is passed inside enum variant.
-    Return(O),
-}
-
-thread_local! {
-    static MOCK_STORE: MockStore = MockStore::default()
-}
+    /// Works like [mock_safe](#tymethod.mock_safe), but instead of replacing the current mock, it only shadows it.
+    /// It's useful for test helpers, which need to temporarily override a mock set up by their caller.
+    /// The pushed mock can defer to the shadowed one by returning `MockResult::Pass`, which allows
+    /// composing independent mocks, e.g. logging and fault injection, into a chain.
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn get_string() -> String {
+    ///     "not mocked".to_string()
+    /// }
+    ///
+    /// #[test]
+    /// fn get_string_test() {
+    ///     get_string.mock_safe(|| MockResult::Return("mocked".to_string()));
+    ///     get_string.push_mock(|| MockResult::Return("pushed".to_string()));
+    ///     assert_eq!("pushed", get_string());
+    ///
+    ///     get_string.pop_mock();
+    ///
+    ///     assert_eq!("mocked", get_string());
+    /// }
+    /// ```
+    fn push_mock<M: FnMut<T, Output = MockResult<T, O>> + 'static>(&self, mock: M);
 
-/// Clear all mocks in the ThreadLocal; only necessary if tests share threads
-pub fn clear_mocks() {
-    MOCK_STORE.with(|mock_store| mock_store.clear())
-}
+    /// Remove the most recent mock set up with [push_mock](#tymethod.push_mock) or
+    /// [mock_safe](#tymethod.mock_safe) and restore the one below it
+    ///
+    /// If there is no mock below, the function starts running normally.
+    fn pop_mock(&self);
 
-impl<T: Tuple, O, F: FnOnce<T, Output = O>> Mockable<T, O> for F {
-    unsafe fn mock_raw<M: FnMut<T, Output = MockResult<T, O>>>(&self, mock: M) {
-        let id = self.get_mock_id();
-        let boxed = Box::new(mock) as Box<dyn FnMut<_, Output = _>>;
-        let static_boxed: Box<dyn FnMut<T, Output = MockResult<T, O>> + 'static> = transmute(boxed);
-        MOCK_STORE.with(|mock_store| mock_store.add_to_thread_layer(id, static_boxed))
-    }
+    /// A safe variant of [mock_raw](#tymethod.mock_raw) for closures borrowing local data
+    ///
+    /// The mock is removed when the [mock_scope](fn.mock_scope.html) call, which created the passed
+    /// scope, returns, so the closure can safely use any values, which outlive that call.
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn get_string(context: &Context) -> &String {
+    ///     context.get_string()
+    /// }
+    ///
+    /// #[test]
+    /// fn get_string_test() {
+    ///     let mocked = "mocked".to_string();
+    ///     mock_scope(|scope| {
+    ///         get_string.mock_scoped(scope, |_| MockResult::Return(&mocked));
+    ///
+    ///         assert_eq!("mocked", get_string(&Context::default()));
+    ///     });
+    /// }
+    /// ```
+    fn mock_scoped<'env, M: FnMut<T, Output = MockResult<T, O>> + 'env>(
+        &self,
+        scope: &MockScope<'env>,
+        mock: M,
+    );
 
-    fn mock_safe<M: FnMut<T, Output = MockResult<T, O>> + 'static>(&self, mock: M) {
-        unsafe { self.mock_raw(mock) }
-    }
+    /// Mock the function to return given values, one per call, in order
+    ///
+    /// When all values are returned, the function behaves according to passed
+    /// [SequenceEnd](enum.SequenceEnd.html).
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn get_number() -> u32 {
+    ///     0
+    /// }
+    ///
+    /// #[test]
+    /// fn get_number_test() {
+    ///     get_number.mock_return_sequence(vec![1, 2], SequenceEnd::Continue);
+    ///
+    ///     assert_eq!(1, get_number());
+    ///     assert_eq!(2, get_number());
+    ///     assert_eq!(0, get_number());
+    /// }
+    /// ```
+    fn mock_return_sequence<S: IntoIterator<Item = O>>(&self, values: S, end: SequenceEnd)
+    where
+        O: Clone + 'static;
 
-    fn clear_mock(&self) {
-        let id = unsafe { self.get_mock_id() };
-        MOCK_STORE.with(|mock_store| mock_store.clear_id(id))
-    }
+    /// Mock the function with a closure, which can be shared with other threads
+    ///
+    /// Works like [mock_safe](#tymethod.mock_safe), but the mock is also captured by
+    /// [MockHandle::current](struct.MockHandle.html#method.current), so it can be set up
+    /// in other threads. All threads share a single instance of the mock, so it must be `Fn`.
+    /// With the `global-mocks` feature the mock is also added to a registry global for the whole
+    /// process, so it's visible in every thread, even the ones not using a handle. Mocks of
+    /// the thread and its [MockContext](struct.MockContext.html)s shadow it. The global mocks are
+    /// removed with [clear_mock](#tymethod.clear_mock), `clear_global_mocks` and
+    /// [clear_all_mocks](fn.clear_all_mocks.html) called in any thread, so the feature is meant
+    /// for test setups running one test per process, like cargo-nextest does.
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn get_number() -> u32 {
+    ///     0
+    /// }
+    ///
+    /// #[test]
+    /// fn get_number_test() {
+    ///     get_number.mock_shared(|| MockResult::Return(1));
+    ///     let handle = MockHandle::current();
+    ///
+    ///     std::thread::spawn(move || {
+    ///         handle.apply();
+    ///         assert_eq!(1, get_number());
+    ///     }).join().unwrap();
+    /// }
+    /// ```
+    fn mock_shared<M>(&self, mock: M)
+    where
+        M: Fn<T, Output = MockResult<T, O>> + Send + Sync + 'static;
 
-    fn call_mock(&self, input: T) -> MockResult<T, O> {
-        unsafe {
-            let id = self.get_mock_id();
-            MOCK_STORE.with(|mock_store| mock_store.call(id, input))
-        }
-    }
+    /// Mock the function to return a given value on the next call
+    ///
+    /// The value is moved into the mock, so it doesn't need to be `Clone`.
+    /// All further calls run the function normally.
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn connect() -> Box<dyn std::io::Read> {
+    ///     unimplemented!()
+    /// }
+    ///
+    /// #[test]
+    /// fn connect_test() {
+    ///     connect.mock_return_once(Box::new(std::io::empty()));
+    ///
+    ///     let mut buf = Vec::new();
+    ///     std::io::Read::read_to_end(&mut connect(), &mut buf).unwrap();
+    ///     assert!(buf.is_empty());
+    /// }
+    /// ```
+    fn mock_return_once(&self, value: O)
+    where
+        O: 'static;
 
-    unsafe fn get_mock_id(&self) -> TypeId {
-        (|| ()).type_id()
-    }
-}
+    /// Mock the function only for arguments matching a predicate
+    ///
+    /// The predicate receives the arguments as a tuple. If it returns `true`, the handler is called
+    /// with the arguments and its result is returned from the mocked function.
+    /// Otherwise the function runs normally.
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn divide(a: u32, b: u32) -> u32 {
+    ///     a / b
+    /// }
+    ///
+    /// #[test]
+    /// fn divide_test() {
+    ///     divide.mock_if(|&(_, b)| b == 0, |_, _| u32::MAX);
+    ///
+    ///     assert_eq!(u32::MAX, divide(1, 0));
+    ///     assert_eq!(2, divide(4, 2));
+    /// }
+    /// ```
+    fn mock_if<P, H>(&self, predicate: P, handler: H)
+    where
+        P: FnMut(&T) -> bool + 'static,
+        H: FnMut<T, Output = O> + 'static;
 
-/// `MockContext` allows for safe capture of local variables.
-///
-/// It does this by forcing only mocking the actual function while in the body
-/// of [run](#tymethod.run).
-///
-/// # Examples
-///
-/// Simple function replacement:
-///
-/// ```
-/// use mocktopus::macros::mockable;
-/// use mocktopus::mocking::{MockContext, MockResult};
-///
-/// #[mockable]
-/// fn f() -> i32 {
-///     0
-/// }
-///
-/// MockContext::new()
-///     .mock_safe(f, || MockResult::Return(1))
-///     .run(|| {
-///         assert_eq!(f(), 1);
-///     });
-/// ```
-///
-/// Using local variables:
-///
-/// ```
-/// use mocktopus::macros::mockable;
-/// use mocktopus::mocking::{MockContext, MockResult};
-///
-/// #[mockable]
-/// fn as_str(s: &String) -> &str {
-///     &s
-/// }
-///
-/// let mut count = 0;
-/// MockContext::new()
-///     .mock_safe(as_str, |s| { count += 1; MockResult::Return(&s) })
-///     .run(|| {
-///         assert_eq!(as_str(&"abc".to_string()), "abc");
-///     });
-/// assert_eq!(count, 1);
-/// ```
-#[derive(Default)]
-pub struct MockContext<'a> {
-    mock_layer: MockLayer,
-    phantom_lifetime: PhantomData<&'a ()>,
-}
+    /// Mock only a single call of the function
+    ///
+    /// Calls are counted from zero starting with the first call after setting up the mock,
+    /// so `0` is the next call. The handler is called with the arguments of the `n`th call
+    /// and its result is returned from the mocked function. All other calls run normally.
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn connect() -> Result<(), String> {
+    ///     Ok(())
+    /// }
+    ///
+    /// #[test]
+    /// fn connect_test() {
+    ///     connect.mock_nth_call(1, || Err("connection refused".to_string()));
+    ///
+    ///     assert!(connect().is_ok());
+    ///     assert!(connect().is_err());
+    ///     assert!(connect().is_ok());
+    /// }
+    /// ```
+    fn mock_nth_call<H>(&self, n: usize, handler: H)
+    where
+        H: FnMut<T, Output = O> + 'static;
 
-impl<'a> MockContext<'a> {
-    /// Create a new MockContext object.
-    pub fn new() -> Self {
-        Self::default()
-    }
+    /// Mock the function to panic with a given message whenever it's called
+    ///
+    /// The message is prefixed with the function's path, see `MockResult::Panic`.
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn load_config() -> String {
+    ///     "config".to_string()
+    /// }
+    ///
+    /// #[test]
+    /// #[should_panic(expected = "config missing")]
+    /// fn load_config_test() {
+    ///     load_config.mock_panic("config missing");
+    ///
+    ///     load_config();
+    /// }
+    /// ```
+    fn mock_panic<S: Into<String>>(&self, message: S);
 
-    /// Set up a function to be mocked.
+    /// Mock the function with a closure, which has access to a state stored alongside the mock
     ///
-    /// This function doesn't actually mock the function.  It registers it as a
-    /// function that will be mocked when [`run`](#method.run) is called.
-    pub fn mock_safe<I: Tuple, O, F, M>(self, mockable: F, mock: M) -> Self
+    /// The closure receives a mutable reference to the state and the arguments as a tuple.
+    /// The state can be retrieved with [mock_state](#tymethod.mock_state) at any time, even after
+    /// the mock is removed. It's replaced when another stateful mock is set up for the function
+    /// and removed by [clear_all_mocks](fn.clear_all_mocks.html).
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn send(message: &str) {
+    ///     unimplemented!()
+    /// }
+    ///
+    /// #[test]
+    /// fn send_test() {
+    ///     send.mock_with_state(Vec::new(), |sent: &mut Vec<String>, (message,)| {
+    ///         sent.push(message.to_string());
+    ///         MockResult::Return(())
+    ///     });
+    ///
+    ///     send("hello");
+    ///     send("bye");
+    ///
+    ///     assert_eq!(vec!["hello", "bye"], send.mock_state::<Vec<String>>());
+    /// }
+    /// ```
+    fn mock_with_state<S, H>(&self, initial_state: S, handler: H)
     where
-        F: Mockable<I, O>,
-        M: FnMut<I, Output = MockResult<I, O>> + 'a,
-    {
-        unsafe { self.mock_raw(mockable, mock) }
-    }
+        S: 'static,
+        H: FnMut(&mut S, T) -> MockResult<T, O> + 'static;
 
-    /// Set up a function to be mocked.
+    /// Get a clone of the state of a mock set up with [mock_with_state](#tymethod.mock_with_state)
     ///
-    /// This is an unsafe version of [`mock_safe`](#method.mock_safe),
-    /// without lifetime constraint on mock
-    pub unsafe fn mock_raw<I: Tuple, O, F, M>(mut self, mockable: F, mock: M) -> Self
+    /// Panics if there is no state of a given type for this function.
+    fn mock_state<S: Clone + 'static>(&self) -> S;
+
+    /// Record calls of this function without changing its behavior
+    ///
+    /// Returns a captor, which receives clones of arguments of all further calls.
+    /// The spy is put on top of the current mock of the function and passes all calls to it,
+    /// so it can be used with both mocked and unmocked functions. It's removed by
+    /// [pop_mock](#tymethod.pop_mock) or replaced by [mock_safe](#tymethod.mock_safe) like any other mock.
+    /// Calls handled by mocks of a [MockContext](struct.MockContext.html) are not recorded.
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn add(a: u32, b: u32) -> u32 {
+    ///     a + b
+    /// }
+    ///
+    /// #[test]
+    /// fn add_test() {
+    ///     let spy = add.spy();
+    ///
+    ///     assert_eq!(3, add(1, 2));
+    ///
+    ///     spy.assert_called_with(&(1, 2));
+    /// }
+    /// ```
+    fn spy(&self) -> ArgCaptor<T>
     where
-        F: Mockable<I, O>,
-        M: FnMut<I, Output = MockResult<I, O>>,
-    {
-        let mock_box = Box::new(mock) as Box<dyn FnMut<_, Output = _>>;
-        let mock_box_static: Box<dyn FnMut<I, Output = MockResult<I, O>> + 'static> =
-            std::mem::transmute(mock_box);
-        self.mock_layer.add(mockable.get_mock_id(), mock_box_static);
-        self
-    }
+        T: Clone + 'static;
 
-    /// Run the function while mocking all the functions.
+    /// Call the real implementation of the function, ignoring all its mocks
     ///
-    /// This function will mock all functions registered for mocking, run the
-    /// function passed in, then deregister those functions.  It does this in a
-    /// panic-safe way.  Note that functions are only mocked in the current
-    /// thread and other threads may invoke the real implementations.
+    /// The arguments are passed as a tuple. It's useful inside mock closures, which need to
+    /// inspect the real returned value or wrap the real implementation with extra logic.
     ///
-    /// Register a function for mocking with [`mock_safe`](#method.mock_safe).
+    /// ```
+    /// #[mockable]
+    /// fn add(a: u32, b: u32) -> u32 {
+    ///     a + b
+    /// }
+    ///
+    /// #[test]
+    /// fn add_test() {
+    ///     add.mock_safe(|a, b| MockResult::Return(add.call_real((a, b)) * 10));
+    ///
+    ///     assert_eq!(30, add(1, 2));
+    /// }
+    /// ```
+    fn call_real(&self, input: T) -> O
+    where
+        Self: Fn<T, Output = O>;
+
+    /// Stop mocking this function.
+    ///
+    /// All future invocations will be forwarded to the real implementation.
+    /// Mocks of other functions are not affected.
+    /// When called inside [MockContext::run](struct.MockContext.html#method.run), mocks of
+    /// the function set up by outer contexts are only hidden and they are restored when it finishes.
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn get_string() -> String {
+    ///     "not mocked".to_string()
+    /// }
+    ///
+    /// #[test]
+    /// fn get_string_test() {
+    ///     get_string.mock_safe(|| MockResult::Return("mocked".to_string()));
+    ///     assert_eq!("mocked", get_string());
+    ///
+    ///     get_string.clear_mock();
+    ///
+    ///     assert_eq!("not mocked", get_string());
+    /// }
+    /// ```
+    fn clear_mock(&self);
+
+    /// Check if this function has a mock set up
+    ///
+    /// Mocks of all currently running [MockContext](struct.MockContext.html)s are taken into account.
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn get_string() -> String {
+    ///     "not mocked".to_string()
+    /// }
+    ///
+    /// #[test]
+    /// fn get_string_test() {
+    ///     assert!(!get_string.is_mocked());
+    ///
+    ///     get_string.mock_safe(|| MockResult::Return("mocked".to_string()));
+    ///
+    ///     assert!(get_string.is_mocked());
+    /// }
+    /// ```
+    fn is_mocked(&self) -> bool;
+
+    /// Number of times this function was called in the current thread
+    ///
+    /// All calls are counted, no matter if the function was mocked or not,
+    /// except for calls made with [call_real](#tymethod.call_real).
+    /// Counting starts from zero again after [clear_all_mocks](fn.clear_all_mocks.html).
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn get_string() -> String {
+    ///     "not mocked".to_string()
+    /// }
+    ///
+    /// #[test]
+    /// fn get_string_test() {
+    ///     get_string.mock_safe(|| MockResult::Return("mocked".to_string()));
+    ///
+    ///     get_string();
+    ///     get_string();
+    ///
+    ///     assert_eq!(2, get_string.call_count());
+    /// }
+    /// ```
+    fn call_count(&self) -> usize;
+
+    /// Start counting calls of this function from zero
+    fn reset_call_count(&self);
+
+    /// Expect this function to be called exactly given number of times
+    ///
+    /// Only calls made after setting up the expectation are counted, as in
+    /// [call_count](#tymethod.call_count). If it's set up inside [MockContext::run](struct.MockContext.html#method.run)
+    /// or [mock_scope](fn.mock_scope.html), it's verified when they finish.
+    /// Otherwise it must be verified with [verify_expectations](fn.verify_expectations.html).
+    /// Failed verification panics with a message listing all unmet expectations.
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn get_string() -> String {
+    ///     "not mocked".to_string()
+    /// }
+    ///
+    /// #[test]
+    /// fn get_string_test() {
+    ///     MockContext::new().run(|| {
+    ///         get_string.expect_calls(2);
+    ///
+    ///         get_string();
+    ///         get_string();
+    ///     });
+    /// }
+    /// ```
+    fn expect_calls(&self, times: usize);
+
+    /// Expect this function not to be called
+    ///
+    /// Same as [expect_calls](#tymethod.expect_calls) with zero calls expected.
+    fn expect_no_calls(&self);
+
+    #[doc(hidden)]
+    /// Called before every execution of a mockable function. Checks if mock is set and if it is, calls it.
+    /// The returned result is always either `Continue`, `Return` or `Panic`. Panics are raised by
+    /// the function itself, so `#[track_caller]` functions report the location of their caller.
+    fn call_mock(&self, input: T) -> MockResult<T, O>
+    where
+        Self: Fn<T, Output = O>;
+
+    #[doc(hidden)]
+    /// Returns a unique ID of the function, which is used to set and get its mock.
+    unsafe fn get_mock_id(&self) -> TypeId;
+}
+
+/// Trait for setting up mocks of async functions
+///
+/// The trait is implemented for all functions returning boxed futures or
+/// [AsyncMockFuture](enum.AsyncMockFuture.html)s, which includes all async functions
+/// [annotated as mockable](https://docs.rs/mocktopus_macros). Functions returning `BoxFuture`
+/// and `LocalBoxFuture` from the `futures` crate are mocked the same way as async functions:
+///
+/// ```
+/// #[mockable]
+/// fn get_number(number: &u32) -> BoxFuture<'_, u32> {
+///     Box::pin(async move { *number })
+/// }
+///
+/// #[tokio::test]
+/// async fn get_number_test() {
+///     get_number.mock_ready(|_| MockResult::Return(1));
+///
+///     assert_eq!(1, get_number(&0).await);
+/// }
+/// ```
+/// The mocks always return `Send` futures, even for functions returning `LocalBoxFuture`.
+/// Mocks returning futures, which aren't `Send`, are set up with
+/// [MockableAsyncLocal](trait.MockableAsyncLocal.html).
+pub trait MockableAsync<'a, T: Tuple, R> {
+    /// Mock the function with a closure returning a future
+    ///
+    /// The future returned by the closure is boxed and pinned,
+    /// so there is no need to wrap it in `MockResult::Return(Box::pin(...))`.
+    ///
+    /// ```
+    /// #[mockable]
+    /// async fn get_string(ms: u64) -> String {
+    ///     tokio::time::delay_for(std::time::Duration::from_millis(ms)).await;
+    ///     "not mocked".to_string()
+    /// }
+    ///
+    /// #[tokio::test]
+    /// async fn get_string_test() {
+    ///     get_string.mock_async(|_| async { "mocked".to_string() });
+    ///
+    ///     assert_eq!("mocked", get_string(10000).await);
+    /// }
+    /// ```
+    fn mock_async<M, F>(&self, mock: M)
+    where
+        M: FnMut<T, Output = F> + 'static,
+        F: Future<Output = R> + Send + 'a;
+
+    /// Mock the function with a closure returning plain values instead of futures
+    ///
+    /// Works like [mock_safe](trait.Mockable.html#tymethod.mock_safe), but the value passed
+    /// inside `MockResult::Return` is the value, to which the returned future resolves.
+    /// It's wrapped in an immediately ready future. `MockResult::ContinueThen` is not supported,
+    /// because its closure can't be safely sent to other threads together with the future.
+    ///
+    /// ```
+    /// #[mockable]
+    /// async fn get_string(ms: u64) -> String {
+    ///     tokio::time::delay_for(std::time::Duration::from_millis(ms)).await;
+    ///     "not mocked".to_string()
+    /// }
+    ///
+    /// #[tokio::test]
+    /// async fn get_string_test() {
+    ///     get_string.mock_ready(|_| MockResult::Return("mocked".to_string()));
+    ///
+    ///     assert_eq!("mocked", get_string(10000).await);
+    /// }
+    /// ```
+    fn mock_ready<M>(&self, mock: M)
+    where
+        M: FnMut<T, Output = MockResult<T, R>> + 'static,
+        R: Send + 'a;
+
+    /// Mock the function to return a future resolving to a stream of given values whenever it's called
+    ///
+    /// It's a shorthand for async functions returning pinned boxed streams, e.g. made mockable with
+    /// `#[mockable(box_impl_trait)]`. Every stream yields clones of the values, which are ready
+    /// immediately. The streams are created when the futures resolve, so they don't need to be `Send`.
+    ///
+    /// ```
+    /// #[mockable(box_impl_trait)]
+    /// async fn subscribe(topic: &str) -> impl Stream<Item = String> {
+    ///     unimplemented!()
+    /// }
+    ///
+    /// #[tokio::test]
+    /// async fn subscribe_test() {
+    ///     subscribe.mock_return_stream(vec!["a".to_string(), "b".to_string()]);
+    ///
+    ///     let messages = subscribe("news").await.collect::<Vec<_>>().await;
+    ///     assert_eq!(vec!["a", "b"], messages);
+    /// }
+    /// ```
+    fn mock_return_stream<V>(&self, values: V)
+    where
+        R: BoxedStream<V>,
+        V: Clone + Send + 'static;
+
+    /// Mock the function to return immediately ready futures resolving to given values,
+    /// one per call, in order
+    ///
+    /// Works like [mock_return_sequence](trait.Mockable.html#tymethod.mock_return_sequence),
+    /// when all values are returned, the function behaves according to passed
+    /// [SequenceEnd](enum.SequenceEnd.html).
+    ///
+    /// ```
+    /// #[mockable]
+    /// async fn fetch(url: &str) -> Result<String, String> {
+    ///     unimplemented!()
+    /// }
+    ///
+    /// #[tokio::test]
+    /// async fn fetch_test() {
+    ///     let responses = vec![Err("timeout".to_string()), Ok("content".to_string())];
+    ///     fetch.mock_return_sequence_async(responses, SequenceEnd::Panic);
+    ///
+    ///     assert_eq!(Err("timeout".to_string()), fetch("example.com").await);
+    ///     assert_eq!(Ok("content".to_string()), fetch("example.com").await);
+    /// }
+    /// ```
+    fn mock_return_sequence_async<S: IntoIterator<Item = R>>(&self, values: S, end: SequenceEnd)
+    where
+        R: Clone + Send + 'static;
+}
+
+impl<'a, T: Tuple, R, O, MF> MockableAsync<'a, T, R> for MF
+where
+    MF: FnOnce<T, Output = O>,
+    O: BoxedFuture<'a, R>,
+{
+    fn mock_async<M, F>(&self, mut mock: M)
+    where
+        M: FnMut<T, Output = F> + 'static,
+        F: Future<Output = R> + Send + 'a,
+    {
+        let mock = TupledMock(move |input: T| {
+            MockResult::Return(O::from_future(Box::pin(mock.call_mut(input))))
+        });
+        self.mock_safe(mock)
+    }
+
+    fn mock_ready<M>(&self, mut mock: M)
+    where
+        M: FnMut<T, Output = MockResult<T, R>> + 'static,
+        R: Send + 'a,
+    {
+        let mock = TupledMock(move |input: T| match mock.call_mut(input) {
+            MockResult::Continue(input) => MockResult::Continue(input),
+            MockResult::Return(output) => {
+                MockResult::Return(O::from_future(Box::pin(future::ready(output))))
+            }
+            MockResult::ContinueThen(..) => {
+                panic!("MockResult::ContinueThen is not supported by mock_ready, use mock_safe")
+            }
+            MockResult::Panic(message) => MockResult::Panic(message),
+            MockResult::Pass(input) => MockResult::Pass(input),
+            MockResult::ReturnFromArgs(input, create) => {
+                MockResult::Return(O::from_future(Box::pin(future::ready(create(&input)))))
+            }
+        });
+        self.mock_safe(mock)
+    }
+
+    fn mock_return_stream<V>(&self, values: V)
+    where
+        R: BoxedStream<V>,
+        V: Clone + Send + 'static,
+    {
+        let mock = TupledMock(move |_: T| {
+            let values = values.clone();
+            MockResult::Return(O::from_future(Box::pin(
+                async move { R::from_values(values) },
+            )))
+        });
+        self.mock_safe(mock)
+    }
+
+    fn mock_return_sequence_async<S: IntoIterator<Item = R>>(&self, values: S, end: SequenceEnd)
+    where
+        R: Clone + Send + 'static,
+    {
+        self.mock_ready(SequenceMock {
+            values: values.into_iter().collect(),
+            end,
+        })
+    }
+}
+
+/// Trait for setting up mocks of async functions returning futures, which aren't `Send`
+///
+/// The trait is implemented for all functions returning boxed futures without the `Send` bound,
+/// which includes async functions [annotated as mockable](https://docs.rs/mocktopus_macros)
+/// with `#[mockable(local)]`, functions returning `LocalBoxFuture` from the `futures` crate and
+/// functions of traits annotated with `#[async_trait(?Send)]`. Unlike the mocks set up with
+/// [MockableAsync](trait.MockableAsync.html), these mocks may return futures holding `Rc`s or
+/// other values, which can't be sent to other threads, so they can be polled with single-threaded
+/// executors like tokio `LocalSet`s.
+///
+/// ```
+/// #[mockable(local)]
+/// async fn get_shared(value: Rc<String>) -> Rc<String> {
+///     value
+/// }
+///
+/// #[tokio::test]
+/// async fn get_shared_test() {
+///     let mocked = Rc::new("mocked".to_string());
+///     get_shared.mock_async_local(move |_| {
+///         let mocked = mocked.clone();
+///         async move { mocked }
+///     });
+///
+///     let local_set = tokio::task::LocalSet::new();
+///     let result = local_set.run_until(get_shared(Rc::new("real".to_string()))).await;
+///
+///     assert_eq!("mocked", *result);
+/// }
+/// ```
+pub trait MockableAsyncLocal<'a, T: Tuple, R> {
+    /// Mock the function with a closure returning a future, which doesn't need to be `Send`
+    ///
+    /// Works like [mock_async](trait.MockableAsync.html#tymethod.mock_async).
+    fn mock_async_local<M, F>(&self, mock: M)
+    where
+        M: FnMut<T, Output = F> + 'static,
+        F: Future<Output = R> + 'a;
+
+    /// Mock the function with a closure returning plain values, which don't need to be `Send`,
+    /// instead of futures
+    ///
+    /// Works like [mock_ready](trait.MockableAsync.html#tymethod.mock_ready).
+    fn mock_ready_local<M>(&self, mock: M)
+    where
+        M: FnMut<T, Output = MockResult<T, R>> + 'static,
+        R: 'a;
+}
+
+impl<'a, T: Tuple, R, O, MF> MockableAsyncLocal<'a, T, R> for MF
+where
+    MF: FnOnce<T, Output = O>,
+    O: BoxedLocalFuture<'a, R>,
+{
+    fn mock_async_local<M, F>(&self, mut mock: M)
+    where
+        M: FnMut<T, Output = F> + 'static,
+        F: Future<Output = R> + 'a,
+    {
+        let mock = TupledMock(move |input: T| {
+            MockResult::Return(O::from_local_future(Box::pin(mock.call_mut(input))))
+        });
+        self.mock_safe(mock)
+    }
+
+    fn mock_ready_local<M>(&self, mut mock: M)
+    where
+        M: FnMut<T, Output = MockResult<T, R>> + 'static,
+        R: 'a,
+    {
+        let mock = TupledMock(move |input: T| match mock.call_mut(input) {
+            MockResult::Continue(input) => MockResult::Continue(input),
+            MockResult::Return(output) => {
+                MockResult::Return(O::from_local_future(Box::pin(future::ready(output))))
+            }
+            MockResult::ContinueThen(..) => {
+                panic!(
+                    "MockResult::ContinueThen is not supported by mock_ready_local, use mock_safe"
+                )
+            }
+            MockResult::Panic(message) => MockResult::Panic(message),
+            MockResult::Pass(input) => MockResult::Pass(input),
+            MockResult::ReturnFromArgs(input, create) => MockResult::Return(O::from_local_future(
+                Box::pin(future::ready(create(&input))),
+            )),
+        });
+        self.mock_safe(mock)
+    }
+}
+
+/// Trait for setting up mocks of functions returning `Result`
+///
+/// The trait is implemented for all functions returning `Result`.
+/// For async functions see [MockableAsyncResult](trait.MockableAsyncResult.html).
+pub trait MockableResult<T: Tuple, V, E> {
+    /// Mock the function to return a clone of given `Ok` value whenever it's called
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn parse(text: &str) -> Result<u32, String> {
+    ///     text.parse().map_err(|_| "invalid number".to_string())
+    /// }
+    ///
+    /// #[test]
+    /// fn parse_test() {
+    ///     parse.mock_return_ok(10);
+    ///
+    ///     assert_eq!(Ok(10), parse("abc"));
+    /// }
+    /// ```
+    fn mock_return_ok(&self, value: V)
+    where
+        V: Clone + 'static;
+
+    /// Mock the function to return a clone of given `Err` value whenever it's called
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn parse(text: &str) -> Result<u32, String> {
+    ///     text.parse().map_err(|_| "invalid number".to_string())
+    /// }
+    ///
+    /// #[test]
+    /// fn parse_test() {
+    ///     parse.mock_return_err("disk on fire".to_string());
+    ///
+    ///     assert_eq!(Err("disk on fire".to_string()), parse("10"));
+    /// }
+    /// ```
+    fn mock_return_err(&self, error: E)
+    where
+        E: Clone + 'static;
+}
+
+impl<T: Tuple, V, E, F> MockableResult<T, V, E> for F
+where
+    F: Mockable<T, Result<V, E>>,
+{
+    fn mock_return_ok(&self, value: V)
+    where
+        V: Clone + 'static,
+    {
+        let mock = TupledMock(move |_: T| MockResult::Return(Ok(value.clone())));
+        self.mock_safe(mock)
+    }
+
+    fn mock_return_err(&self, error: E)
+    where
+        E: Clone + 'static,
+    {
+        let mock = TupledMock(move |_: T| MockResult::Return(Err(error.clone())));
+        self.mock_safe(mock)
+    }
+}
+
+/// Trait for setting up mocks of async functions returning `Result`
+///
+/// The trait is implemented for all functions returning boxed futures resolving to `Result`,
+/// which includes all such async functions [annotated as mockable](https://docs.rs/mocktopus_macros).
+pub trait MockableAsyncResult<'a, T: Tuple, V, E> {
+    /// Mock the function to return an immediately ready future resolving to
+    /// a clone of given `Ok` value whenever it's called
+    ///
+    /// ```
+    /// #[mockable]
+    /// async fn fetch(url: &str) -> Result<String, String> {
+    ///     unimplemented!()
+    /// }
+    ///
+    /// #[tokio::test]
+    /// async fn fetch_test() {
+    ///     fetch.mock_return_ok("content".to_string());
+    ///
+    ///     assert_eq!(Ok("content".to_string()), fetch("example.com").await);
+    /// }
+    /// ```
+    fn mock_return_ok(&self, value: V)
+    where
+        V: Clone + Send + 'static;
+
+    /// Mock the function to return an immediately ready future resolving to
+    /// a clone of given `Err` value whenever it's called
+    ///
+    /// ```
+    /// #[mockable]
+    /// async fn fetch(url: &str) -> Result<String, String> {
+    ///     unimplemented!()
+    /// }
+    ///
+    /// #[tokio::test]
+    /// async fn fetch_test() {
+    ///     fetch.mock_return_err("timeout".to_string());
+    ///
+    ///     assert_eq!(Err("timeout".to_string()), fetch("example.com").await);
+    /// }
+    /// ```
+    fn mock_return_err(&self, error: E)
+    where
+        E: Clone + Send + 'static;
+}
+
+impl<'a, T: Tuple, V: Send + 'a, E: Send + 'a, O, F> MockableAsyncResult<'a, T, V, E> for F
+where
+    F: FnOnce<T, Output = O>,
+    O: BoxedFuture<'a, Result<V, E>>,
+{
+    fn mock_return_ok(&self, value: V)
+    where
+        V: Clone + Send + 'static,
+    {
+        let mock = TupledMock(move |_: T| {
+            MockResult::Return(O::from_future(Box::pin(future::ready(Ok(value.clone())))))
+        });
+        self.mock_safe(mock)
+    }
+
+    fn mock_return_err(&self, error: E)
+    where
+        E: Clone + Send + 'static,
+    {
+        let mock = TupledMock(move |_: T| {
+            MockResult::Return(O::from_future(Box::pin(future::ready(Err(error.clone())))))
+        });
+        self.mock_safe(mock)
+    }
+}
+
+/// Controls mocked function behavior when returned from [mock closure](trait.Mockable.html)
+pub enum MockResult<T, O> {
+    /// Function runs normally as if it was called with given arguments.
+    /// The arguments are passed inside enum variant as a tuple.
+    Continue(T),
+
+    /// Function returns immediately with a given value. The returned value is passed inside enum variant.
+    Return(O),
+
+    /// Function runs normally as if it was called with given arguments, then its returned value is passed
+    /// through a given closure. The arguments and the closure are passed inside enum variant.
+    ContinueThen(T, Box<dyn FnOnce(O) -> O>),
+
+    /// Function panics with a given message prefixed with the function's path.
+    /// The message is passed inside enum variant.
+    Panic(String),
+
+    /// Call is passed with given arguments to the next mock of the function, i.e. the one shadowed with
+    /// [push_mock](trait.Mockable.html#tymethod.push_mock) or set up in an outer
+    /// [MockContext](struct.MockContext.html). If there are no more mocks, the function runs normally.
+    /// The arguments are passed inside enum variant as a tuple.
+    Pass(T),
+
+    /// Function returns immediately with a value created by a given closure from references to
+    /// given arguments. It allows returning references tied to lifetimes of the arguments,
+    /// e.g. a field of `&self`. The arguments and the closure are passed inside enum variant.
+    ReturnFromArgs(T, Box<dyn FnOnce(&T) -> O>),
+}
+
+impl<T, O> MockResult<T, O> {
+    /// Function returns immediately an iterator over given values
+    ///
+    /// It's a shorthand for functions returning boxed iterators, e.g. made mockable with
+    /// `#[mockable(box_impl_trait)]`:
+    ///
+    /// ```
+    /// #[mockable(box_impl_trait)]
+    /// fn numbers() -> impl Iterator<Item = u32> {
+    ///     0..10
+    /// }
+    ///
+    /// #[test]
+    /// fn numbers_test() {
+    ///     numbers.mock_safe(|| MockResult::return_iter(vec![1, 2]));
+    ///
+    ///     assert_eq!(vec![1, 2], numbers().collect::<Vec<_>>());
+    /// }
+    /// ```
+    pub fn return_iter<V>(values: V) -> Self
+    where
+        O: BoxedIterator<V>,
+    {
+        MockResult::Return(O::from_values(values))
+    }
+
+    /// Function returns immediately a stream of given values, which are ready immediately
+    ///
+    /// It's a shorthand for functions returning pinned boxed streams, e.g. made mockable with
+    /// `#[mockable(box_impl_trait)]`:
+    ///
+    /// ```
+    /// #[mockable(box_impl_trait)]
+    /// fn events() -> impl Stream<Item = u32> {
+    ///     tokio::stream::iter(0..10)
+    /// }
+    ///
+    /// #[tokio::test]
+    /// async fn events_test() {
+    ///     events.mock_safe(|| MockResult::return_stream(vec![1, 2]));
+    ///
+    ///     assert_eq!(vec![1, 2], events().collect::<Vec<_>>().await);
+    /// }
+    /// ```
+    pub fn return_stream<V>(values: V) -> Self
+    where
+        O: BoxedStream<V>,
+    {
+        MockResult::Return(O::from_values(values))
+    }
+}
+
+/// Creates a `MockResult::Return` with a future resolving to a given value
+///
+/// It's a shorthand for `MockResult::Return(Box::pin(async move { value }))` in mocks of
+/// [async functions](trait.MockableAsync.html). The value is evaluated inside the future,
+/// so it can use variables moved into it. See also [async_ok!](macro.async_ok.html) and
+/// [async_err!](macro.async_err.html).
+///
+/// ```
+/// #[mockable]
+/// async fn get_string() -> String {
+///     "not mocked".to_string()
+/// }
+///
+/// #[tokio::test]
+/// async fn get_string_test() {
+///     get_string.mock_safe(|| async_ret!("mocked".to_string()));
+///
+///     assert_eq!("mocked", get_string().await);
+/// }
+/// ```
+#[macro_export]
+macro_rules! async_ret {
+    ($value:expr $(,)?) => {
+        $crate::mocking::MockResult::Return($crate::mocking::BoxedFuture::from_future(
+            ::std::boxed::Box::pin(async move { $value }),
+        ))
+    };
+}
+
+/// Creates a `MockResult::Return` with a future resolving to `Ok` with a given value
+///
+/// See [async_ret!](macro.async_ret.html).
+///
+/// ```
+/// #[mockable]
+/// async fn fetch(url: &str) -> Result<String, String> {
+///     unimplemented!()
+/// }
+///
+/// #[tokio::test]
+/// async fn fetch_test() {
+///     fetch.mock_safe(|_| async_ok!("content".to_string()));
+///
+///     assert_eq!(Ok("content".to_string()), fetch("example.com").await);
+/// }
+/// ```
+#[macro_export]
+macro_rules! async_ok {
+    ($value:expr $(,)?) => {
+        $crate::async_ret!(::std::result::Result::Ok($value))
+    };
+}
+
+/// Creates a `MockResult::Return` with a future resolving to `Err` with a given error
+///
+/// See [async_ret!](macro.async_ret.html).
+///
+/// ```
+/// #[mockable]
+/// async fn fetch(url: &str) -> Result<String, String> {
+///     unimplemented!()
+/// }
+///
+/// #[tokio::test]
+/// async fn fetch_test() {
+///     fetch.mock_safe(|_| async_err!("timeout".to_string()));
+///
+///     assert_eq!(Err("timeout".to_string()), fetch("example.com").await);
+/// }
+/// ```
+#[macro_export]
+macro_rules! async_err {
+    ($error:expr $(,)?) => {
+        $crate::async_ret!(::std::result::Result::Err($error))
+    };
+}
+
+/// Boxed iterator, which can be returned with [return_iter](enum.MockResult.html#method.return_iter)
+pub trait BoxedIterator<V> {
+    /// Creates an iterator over given values
+    fn from_values(values: V) -> Self;
+}
+
+impl<'a, V> BoxedIterator<V> for Box<dyn Iterator<Item = V::Item> + 'a>
+where
+    V: IntoIterator,
+    V::IntoIter: 'a,
+{
+    fn from_values(values: V) -> Self {
+        Box::new(values.into_iter())
+    }
+}
+
+impl<'a, V> BoxedIterator<V> for Box<dyn Iterator<Item = V::Item> + Send + 'a>
+where
+    V: IntoIterator,
+    V::IntoIter: Send + 'a,
+{
+    fn from_values(values: V) -> Self {
+        Box::new(values.into_iter())
+    }
+}
+
+/// Pinned boxed stream, which can be returned with
+/// [return_stream](enum.MockResult.html#method.return_stream)
+pub trait BoxedStream<V> {
+    /// Creates a stream of given values
+    fn from_values(values: V) -> Self;
+}
+
+impl<'a, V> BoxedStream<V> for Pin<Box<dyn Stream<Item = V::Item> + 'a>>
+where
+    V: IntoIterator,
+    V::IntoIter: 'a,
+{
+    fn from_values(values: V) -> Self {
+        Box::pin(IterStream(values.into_iter()))
+    }
+}
+
+impl<'a, V> BoxedStream<V> for Pin<Box<dyn Stream<Item = V::Item> + Send + 'a>>
+where
+    V: IntoIterator,
+    V::IntoIter: Send + 'a,
+{
+    fn from_values(values: V) -> Self {
+        Box::pin(IterStream(values.into_iter()))
+    }
+}
+
+/// Boxed future, which can be returned by mocks of [async functions](trait.MockableAsync.html)
+pub trait BoxedFuture<'a, R> {
+    /// Creates a future from a boxed one
+    fn from_future(future: Pin<Box<dyn Future<Output = R> + Send + 'a>>) -> Self;
+}
+
+impl<'a, R> BoxedFuture<'a, R> for Pin<Box<dyn Future<Output = R> + Send + 'a>> {
+    fn from_future(future: Pin<Box<dyn Future<Output = R> + Send + 'a>>) -> Self {
+        future
+    }
+}
+
+impl<'a, R> BoxedFuture<'a, R> for Pin<Box<dyn Future<Output = R> + 'a>> {
+    fn from_future(future: Pin<Box<dyn Future<Output = R> + Send + 'a>>) -> Self {
+        future
+    }
+}
+
+/// Boxed future without the `Send` bound, which can be returned by mocks of
+/// [async functions](trait.MockableAsyncLocal.html)
+pub trait BoxedLocalFuture<'a, R> {
+    /// Creates a future from a boxed one
+    fn from_local_future(future: Pin<Box<dyn Future<Output = R> + 'a>>) -> Self;
+}
+
+impl<'a, R> BoxedLocalFuture<'a, R> for Pin<Box<dyn Future<Output = R> + 'a>> {
+    fn from_local_future(future: Pin<Box<dyn Future<Output = R> + 'a>>) -> Self {
+        future
+    }
+}
+
+impl<R, F: Future<Output = R>> BoxedFuture<'static, R> for AsyncMockFuture<F> {
+    fn from_future(future: Pin<Box<dyn Future<Output = R> + Send + 'static>>) -> Self {
+        AsyncMockFuture::Mocked(future)
+    }
+}
+
+/// Future returned by async functions of trait impls [annotated as mockable](https://docs.rs/mocktopus_macros)
+///
+/// Trait impls must keep signatures declared by their traits, so their async functions return
+/// either the futures of their bodies or futures returned by their mocks. The mocked futures must
+/// be `'static`, so mocks can't borrow the arguments in them.
+pub enum AsyncMockFuture<F: Future> {
+    /// Future of the function body
+    Real(F),
+    /// Future returned by the function mock
+    Mocked(Pin<Box<dyn Future<Output = F::Output> + Send>>),
+}
+
+impl<F: Future> Future for AsyncMockFuture<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<F::Output> {
+        // The real future is never moved out of the pinned enum
+        match unsafe { self.get_unchecked_mut() } {
+            AsyncMockFuture::Real(future) => unsafe { Pin::new_unchecked(future) }.poll(cx),
+            AsyncMockFuture::Mocked(future) => future.as_mut().poll(cx),
+        }
+    }
+}
+
+/// Stream of values of an iterator, which are always ready
+struct IterStream<I>(I);
+
+// The iterator is never pinned, it's only moved with the stream
+impl<I> Unpin for IterStream<I> {}
+
+impl<I: Iterator> Stream for IterStream<I> {
+    type Item = I::Item;
+
+    fn poll_next(self: Pin<&mut Self>, _: &mut Context) -> Poll<Option<I::Item>> {
+        Poll::Ready(self.get_mut().0.next())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+/// Future, which is pending for a given number of polls and then resolves with a value
+///
+/// It lets mocks of async functions control, when their futures are ready, so code racing them,
+/// e.g. with `select!`, timeouts or backpressure, can be tested without custom `Future`s:
+///
+/// ```
+/// #[mockable]
+/// async fn fetch(url: &str) -> String {
+///     unimplemented!()
+/// }
+///
+/// #[tokio::test]
+/// async fn fetch_test() {
+///     fetch.mock_async(|_| PendingThen::new(2, "mocked".to_string()));
+///
+///     let result = tokio::select! {
+///         body = fetch("example.com") => body,
+///         _ = async {} => "cached".to_string(),
+///     };
+///
+///     assert_eq!("cached", result);
+/// }
+/// ```
+/// Every time the future is pending it wakes its task, so the executor polls it again.
+pub struct PendingThen<T> {
+    pending_polls: usize,
+    value: Option<T>,
+}
+
+impl<T> PendingThen<T> {
+    /// Create a future, which is pending for `pending_polls` polls and then resolves with `value`
+    pub fn new(pending_polls: usize, value: T) -> Self {
+        PendingThen {
+            pending_polls,
+            value: Some(value),
+        }
+    }
+
+    /// Number of polls left, which will return `Poll::Pending`
+    pub fn pending_polls(&self) -> usize {
+        self.pending_polls
+    }
+}
+
+// The value is never pinned, it's only moved out of the future
+impl<T> Unpin for PendingThen<T> {}
+
+impl<T> Future for PendingThen<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        let this = self.get_mut();
+        if this.pending_polls > 0 {
+            this.pending_polls -= 1;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        let value = this
+            .value
+            .take()
+            .expect("PendingThen polled after completion");
+        Poll::Ready(value)
+    }
+}
+
+/// Observer of futures, which records if they were polled to completion or dropped before it
+///
+/// It lets tests check, that the tested code cancels in-flight mocked operations,
+/// e.g. on timeouts or when other `select!` branches finish first. It also counts polls of
+/// the futures, which helps finding busy polling or lost wakeups. The observer can be cloned
+/// and moved into mocks, all clones share the records.
+///
+/// ```
+/// #[mockable]
+/// async fn fetch(url: &str) -> String {
+///     unimplemented!()
+/// }
+///
+/// #[tokio::test]
+/// async fn fetch_test() {
+///     let observer = FutureObserver::new();
+///     let mock_observer = observer.clone();
+///     fetch.mock_async(move |_| mock_observer.observe(PendingThen::new(2, "mocked".to_string())));
+///
+///     tokio::select! {
+///         _ = fetch("example.com") => (),
+///         _ = async {} => (),
+///     };
+///
+///     assert_eq!(1, observer.cancelled_count());
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct FutureObserver {
+    records: Arc<FutureRecords>,
+}
+
+#[derive(Default)]
+struct FutureRecords {
+    observed: AtomicUsize,
+    polled: AtomicUsize,
+    completed: AtomicUsize,
+    cancelled: AtomicUsize,
+}
+
+impl FutureObserver {
+    /// Create an observer, which didn't observe any futures yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wrap a future, so its completion or cancellation is recorded by the observer
+    pub fn observe<F: Future>(&self, future: F) -> ObservedFuture<F> {
+        self.records.observed.fetch_add(1, Ordering::SeqCst);
+        ObservedFuture {
+            future,
+            records: self.records.clone(),
+            poll_count: 0,
+            is_completed: false,
+        }
+    }
+
+    /// Total number of polls of all observed futures
+    pub fn poll_count(&self) -> usize {
+        self.records.polled.load(Ordering::SeqCst)
+    }
+
+    /// Number of observed futures, which were polled to completion
+    pub fn completed_count(&self) -> usize {
+        self.records.completed.load(Ordering::SeqCst)
+    }
+
+    /// Number of observed futures, which were dropped before completion
+    pub fn cancelled_count(&self) -> usize {
+        self.records.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Number of observed futures, which are neither completed nor dropped yet
+    pub fn in_flight_count(&self) -> usize {
+        self.records.observed.load(Ordering::SeqCst)
+            - self.completed_count()
+            - self.cancelled_count()
+    }
+}
+
+/// Future returned by [FutureObserver::observe](struct.FutureObserver.html#method.observe)
+pub struct ObservedFuture<F> {
+    future: F,
+    records: Arc<FutureRecords>,
+    poll_count: usize,
+    is_completed: bool,
+}
+
+impl<F> ObservedFuture<F> {
+    /// Number of polls of this future
+    pub fn poll_count(&self) -> usize {
+        self.poll_count
+    }
+}
+
+impl<F: Future> Future for ObservedFuture<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<F::Output> {
+        // The future is never moved out of the pinned struct
+        let this = unsafe { self.get_unchecked_mut() };
+        this.poll_count += 1;
+        this.records.polled.fetch_add(1, Ordering::SeqCst);
+        let poll = unsafe { Pin::new_unchecked(&mut this.future) }.poll(cx);
+        if poll.is_ready() && !this.is_completed {
+            this.is_completed = true;
+            this.records.completed.fetch_add(1, Ordering::SeqCst);
+        }
+        poll
+    }
+}
+
+impl<F> Drop for ObservedFuture<F> {
+    fn drop(&mut self) {
+        if !self.is_completed {
+            self.records.cancelled.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Controls mocked function behavior after all values set up with
+/// [mock_return_sequence](trait.Mockable.html#tymethod.mock_return_sequence) were returned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SequenceEnd {
+    /// Function panics.
+    Panic,
+
+    /// Function keeps returning the last value from the sequence.
+    RepeatLast,
+
+    /// Function runs normally as if it was not mocked.
+    Continue,
+}
+
+struct SequenceMock<O> {
+    values: VecDeque<O>,
+    end: SequenceEnd,
+}
+
+impl<T: Tuple, O: Clone> FnOnce<T> for SequenceMock<O> {
+    type Output = MockResult<T, O>;
+
+    extern "rust-call" fn call_once(mut self, input: T) -> Self::Output {
+        self.call_mut(input)
+    }
+}
+
+impl<T: Tuple, O: Clone> FnMut<T> for SequenceMock<O> {
+    extern "rust-call" fn call_mut(&mut self, input: T) -> Self::Output {
+        if self.end == SequenceEnd::RepeatLast && self.values.len() == 1 {
+            return MockResult::Return(self.values[0].clone());
+        }
+        match self.values.pop_front() {
+            Some(value) => MockResult::Return(value),
+            None if self.end == SequenceEnd::Continue => MockResult::Continue(input),
+            None => panic!("Mocked function called more times than values in sequence"),
+        }
+    }
+}
+
+struct ConditionalMock<P, H> {
+    predicate: P,
+    handler: H,
+}
+
+impl<T: Tuple, O, P: FnMut(&T) -> bool, H: FnMut<T, Output = O>> FnOnce<T>
+    for ConditionalMock<P, H>
+{
+    type Output = MockResult<T, O>;
+
+    extern "rust-call" fn call_once(mut self, input: T) -> Self::Output {
+        self.call_mut(input)
+    }
+}
+
+impl<T: Tuple, O, P: FnMut(&T) -> bool, H: FnMut<T, Output = O>> FnMut<T>
+    for ConditionalMock<P, H>
+{
+    extern "rust-call" fn call_mut(&mut self, input: T) -> Self::Output {
+        match (self.predicate)(&input) {
+            true => MockResult::Return(self.handler.call_mut(input)),
+            false => MockResult::Continue(input),
+        }
+    }
+}
+
+/// Adapts a closure taking all the arguments as a single tuple into a mock closure
+struct TupledMock<F>(F);
+
+impl<T: Tuple, O, F: FnMut(T) -> MockResult<T, O>> FnOnce<T> for TupledMock<F> {
+    type Output = MockResult<T, O>;
+
+    extern "rust-call" fn call_once(mut self, input: T) -> Self::Output {
+        self.call_mut(input)
+    }
+}
+
+impl<T: Tuple, O, F: FnMut(T) -> MockResult<T, O>> FnMut<T> for TupledMock<F> {
+    extern "rust-call" fn call_mut(&mut self, input: T) -> Self::Output {
+        (self.0)(input)
+    }
+}
+
+thread_local! {
+    static MOCK_STORE: MockStore = MockStore::default()
+}
+
+/// Clear all mocks in the ThreadLocal; only necessary if tests share threads
+///
+/// This removes mocks set up with [Mockable](trait.Mockable.html) as well as mocks of all
+/// currently running [MockContext](struct.MockContext.html)s and resets call counts.
+/// With the `global-mocks` feature the global mocks are removed too, so they aren't visible
+/// in any thread.
+pub fn clear_all_mocks() {
+    MOCK_STORE.with(|mock_store| mock_store.clear())
+}
+
+/// Clear all mocks set up with [mock_shared](trait.Mockable.html#tymethod.mock_shared)
+/// in the global registry, so they aren't visible in any thread
+///
+/// Mocks already installed in the threads, e.g. in their thread layers, are not removed.
+/// Requires the `global-mocks` feature.
+#[cfg(feature = "global-mocks")]
+pub fn clear_global_mocks() {
+    crate::mock_store::clear_global()
+}
+
+/// Runs a test body between resetting all mocks of the current thread
+///
+/// Used by `#[mocktopus::test]`. Besides clearing mocks and call counts, resetting turns off
+/// strict mode and the fallback handler. Expectations are verified after the body returns,
+/// mocks are reset again even if the body or the verification panics.
+#[doc(hidden)]
+pub fn run_isolated_test<R>(test: impl FnOnce() -> R) -> R {
+    MOCK_STORE.with(|mock_store| mock_store.reset());
+    let _guard = ResetMocksGuard;
+    let result = test();
+    verify_expectations();
+    result
+}
+
+/// Resets all mocks of the current thread when dropped, even if the thread is panicking
+struct ResetMocksGuard;
+
+impl Drop for ResetMocksGuard {
+    fn drop(&mut self) {
+        MOCK_STORE.with(|mock_store| mock_store.reset())
+    }
+}
+
+/// Clears all mocks of the current thread when dropped, even if the thread is panicking
+///
+/// Mocks set up with [Mockable](trait.Mockable.html) live until the thread finishes, so when a test
+/// panics they may leak into the next test run in the same thread, e.g. with `--test-threads=1`.
+/// Creating the guard at the beginning of a test guarantees, that the mocks are removed.
+/// [MockContext](struct.MockContext.html) always removes its mocks, so it doesn't need the guard.
+///
+/// ```
+/// use mocktopus::macros::mockable;
+/// use mocktopus::mocking::*;
+///
+/// #[mockable]
+/// fn get_number() -> u32 {
+///     0
+/// }
+///
+/// #[test]
+/// fn get_number_test() {
+///     let _guard = ClearMocksGuard::new();
+///     get_number.mock_safe(|| MockResult::Return(1));
+///
+///     assert_eq!(1, get_number());
+/// }
+/// ```
+pub struct ClearMocksGuard {
+    // Mocks are thread local, so the guard must be dropped in the thread, which created it
+    phantom_not_send: PhantomData<*const ()>,
+}
+
+impl ClearMocksGuard {
+    /// Create a guard for mocks of the current thread
+    pub fn new() -> Self {
+        ClearMocksGuard {
+            phantom_not_send: PhantomData,
+        }
+    }
+}
+
+impl Default for ClearMocksGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for ClearMocksGuard {
+    fn drop(&mut self) {
+        clear_all_mocks()
+    }
+}
+
+/// Mock falls through to the mock below it when it returns `MockResult::Continue`
+///
+/// The mock is static like the ones of [mock_safe](trait.Mockable.html#tymethod.mock_safe),
+/// so it stays valid for the whole thread lifetime.
+pub(crate) fn push_fallthrough_mock<T, O, F, M>(function: &F, mock: M)
+where
+    T: Tuple,
+    F: Mockable<T, O>,
+    M: FnMut(T) -> MockResult<T, O> + 'static,
+{
+    let id = unsafe { function.get_mock_id() };
+    let boxed =
+        Box::new(TupledMock(mock)) as Box<dyn FnMut<T, Output = MockResult<T, O>> + 'static>;
+    MOCK_STORE.with(|mock_store| unsafe { mock_store.push_to_thread_layer(id, boxed, true) })
+}
+
+/// Verify all expectations set up in the current thread
+///
+/// Verified expectations are removed. Panics with a message listing all unmet expectations.
+/// See [expect_calls](trait.Mockable.html#tymethod.expect_calls).
+pub fn verify_expectations() {
+    MOCK_STORE.with(|mock_store| mock_store.verify_all_expectations())
+}
+
+/// Enable or disable strict mode in the current thread
+///
+/// In strict mode calling a mockable function, which has no mock set up, panics with its path.
+/// This catches tests accidentally running real implementations, e.g. doing I/O.
+/// Calls made with [call_real](trait.Mockable.html#tymethod.call_real) are always allowed.
+/// Strict mode can be also enabled only for a [MockContext](struct.MockContext.html#method.strict).
+///
+/// ```
+/// use mocktopus::macros::mockable;
+/// use mocktopus::mocking::*;
+///
+/// #[mockable]
+/// fn read_config() -> String {
+///     std::fs::read_to_string("config.toml").unwrap()
+/// }
+///
+/// #[test]
+/// #[should_panic(expected = "Strict mode")]
+/// fn read_config_test() {
+///     set_strict_mode(true);
+///
+///     read_config();
+/// }
+/// ```
+pub fn set_strict_mode(strict: bool) {
+    MOCK_STORE.with(|mock_store| mock_store.set_thread_strict(strict))
+}
+
+/// Mock a function identified by its path in the current thread
+///
+/// The path is the one returned by [type_name](https://doc.rust-lang.org/std/any/fn.type_name.html)
+/// for the function, e.g. `my_crate::api::fetch` or `my_crate::api::Client::get`. Generic functions
+/// are mocked separately for every set of type parameters, e.g. `my_crate::parse<u32>`.
+/// This allows setting up mocks, when functions are known only at runtime, e.g. from a config file.
+///
+/// The mock is bound to the function on its first call, from then on it behaves as if it was set up
+/// with [mock_raw](trait.Mockable.html#tymethod.mock_raw), so it can be e.g. removed with
+/// [clear_mock](trait.Mockable.html#tymethod.clear_mock). Setting up a mock of a function by path
+/// replaces only other mocks set up by path, which weren't bound yet.
+///
+/// # Safety
+/// The mock must have the same argument and return types as the function, including lifetimes.
+/// It's checked on the first call only by comparing type names, which ignore lifetimes,
+/// and the call panics if they don't match.
+///
+/// ```
+/// use mocktopus::macros::mockable;
+/// use mocktopus::mocking::*;
+///
+/// mod api {
+///     #[mockable]
+///     pub fn fetch(url: String) -> String {
+///         unimplemented!()
+///     }
+/// }
+///
+/// #[test]
+/// fn fetch_test() {
+///     unsafe {
+///         mock_by_name("my_crate::api::fetch", |_: String| MockResult::Return("content".to_string()));
+///     }
+///
+///     assert_eq!("content", api::fetch("example.com".to_string()));
+/// }
+/// ```
+pub unsafe fn mock_by_name<I, O, M>(path: &str, mock: M)
+where
+    I: Tuple,
+    M: FnMut<I, Output = MockResult<I, O>>,
+{
+    let boxed = Box::new(mock) as Box<dyn FnMut<_, Output = _>>;
+    let static_boxed: Box<dyn FnMut<I, Output = MockResult<I, O>> + 'static> = transmute(boxed);
+    MOCK_STORE.with(|mock_store| mock_store.add_named(path.to_string(), static_boxed))
+}
+
+/// Mock every implementation of a trait method identified by its path in the current thread
+///
+/// The path consists of the trait path and the method name, e.g. `my_crate::Greeter::greet`, for
+/// generic traits and methods it contains type parameters like `my_crate::Parser<u32>::parse<u8>`.
+/// The mock replaces default implementations of traits [annotated as mockable](https://docs.rs/mocktopus_macros)
+/// and implementations in annotated trait impls for every implementor without a need to mock them
+/// one by one. Implementors can still be mocked separately, which replaces this mock for them.
+///
+/// The mock is bound to every implementation on its first call like with
+/// [mock_by_name](fn.mock_by_name.html), but it's kept for other implementations. All of them call
+/// the same instance of the mock, so its state is shared.
+///
+/// # Safety
+/// The mock must have the same argument and return types as every implementation, including lifetimes.
+/// It's checked on the first call of every implementation only by comparing type names, which ignore
+/// lifetimes, and the call panics if they don't match. Implementations have different signatures
+/// if they take or return `Self`, so such methods can only be mocked one implementor at a time.
+///
+/// ```
+/// use mocktopus::macros::mockable;
+/// use mocktopus::mocking::*;
+///
+/// #[mockable]
+/// trait Greeter {
+///     fn greeting() -> String {
+///         "Hello".to_string()
+///     }
+/// }
+///
+/// struct English;
+/// impl Greeter for English {}
+///
+/// struct Pirate;
+/// impl Greeter for Pirate {}
+///
+/// #[test]
+/// fn greeting_test() {
+///     unsafe {
+///         mock_trait_method_by_name("my_crate::Greeter::greeting", || {
+///             MockResult::Return("Ahoy".to_string())
+///         });
+///     }
+///
+///     assert_eq!("Ahoy", English::greeting());
+///     assert_eq!("Ahoy", Pirate::greeting());
+/// }
+/// ```
+pub unsafe fn mock_trait_method_by_name<I, O, M>(path: &str, mock: M)
+where
+    I: Tuple,
+    M: FnMut<I, Output = MockResult<I, O>>,
+{
+    let boxed = Box::new(mock) as Box<dyn FnMut<_, Output = _>>;
+    let static_boxed: Box<dyn FnMut<I, Output = MockResult<I, O>> + 'static> = transmute(boxed);
+    MOCK_STORE.with(|mock_store| mock_store.add_trait_named(path.to_string(), static_boxed))
+}
+
+/// Mock an unsafe or `extern` function in the current thread
+///
+/// Unsafe functions and functions with a non-Rust ABI, e.g. `extern "C" fn`, don't implement
+/// `Fn` traits, so they can't be mocked with [Mockable](trait.Mockable.html) methods. The mock works like one set up with
+/// [mock_by_name](fn.mock_by_name.html) for the function's path, so it replaces previous mocks of
+/// the function and it can be removed with [clear_all_mocks](fn.clear_all_mocks.html).
+/// Mocks of such functions can't return `MockResult::ContinueThen`. The argument and return types
+/// aren't inferred from the function, so the mock closure may need type annotations.
+///
+/// # Safety
+/// The mock must have the same argument and return types as the function, including lifetimes.
+/// It's checked on the first call only by comparing type names, which ignore lifetimes,
+/// and the call panics if they don't match.
+///
+/// ```
+/// use mocktopus::macros::mockable;
+/// use mocktopus::mocking::*;
+///
+/// #[mockable]
+/// unsafe fn read(ptr: *const u32) -> u32 {
+///     *ptr
+/// }
+///
+/// #[test]
+/// fn read_test() {
+///     unsafe {
+///         mock_unsafe_fn(read, |_: *const u32| MockResult::Return(1));
+///
+///         assert_eq!(1, read(std::ptr::null()));
+///     }
+/// }
+/// ```
+pub unsafe fn mock_unsafe_fn<F, I, O, M>(function: F, mock: M)
+where
+    I: Tuple,
+    M: FnMut<I, Output = MockResult<I, O>>,
+{
+    let _ = function;
+    mock_by_name(type_name::<F>(), mock)
+}
+
+/// Calls the mock of an unsafe or `extern` function, used by its injected header
+#[doc(hidden)]
+pub unsafe fn call_unsafe_fn_mock<F, T: Tuple, O>(function: &F, input: T) -> MockResult<T, O> {
+    let id = unsafe_fn_mock_id(function);
+    let fn_name = type_name::<F>();
+    match MOCK_STORE.with(|mock_store| mock_store.call(id, fn_name, input)) {
+        MockResult::ContinueThen(..) => {
+            panic!(
+                "{}: MockResult::ContinueThen is not supported for unsafe and extern functions",
+                fn_name
+            )
+        }
+        MockResult::ReturnFromArgs(input, create) => MockResult::Return(create(&input)),
+        result => result,
+    }
+}
+
+fn unsafe_fn_mock_id<F>(_function: &F) -> TypeId {
+    (|| ()).type_id()
+}
+
+/// Static value, which can be mocked in the current thread
+///
+/// It's lazily initialized on first access like `LazyLock` and it dereferences to the mock value
+/// if there is one in the current thread. Statics [annotated as mockable](https://docs.rs/mocktopus_macros)
+/// are turned into it in test builds, `LazyLock` statics are unwrapped, so their values are mocked
+/// directly. `D` is the type the static was declared with, e.g. `LazyLock<T>`, it isn't used.
+/// The mocks don't depend on [MockContext](struct.MockContext.html)s, they live until they are
+/// cleared or until [clear_all_mocks](fn.clear_all_mocks.html) is called.
+///
+/// ```
+/// #[mockable]
+/// static TIMEOUT: LazyLock<Duration> = LazyLock::new(|| Duration::from_secs(30));
+///
+/// #[test]
+/// fn timeout_test() {
+///     TIMEOUT.mock_value(Duration::from_secs(1));
+///
+///     assert_eq!(Duration::from_secs(1), *TIMEOUT);
+/// }
+/// ```
+pub struct MockableStatic<T: 'static, D = T> {
+    value: LazyLock<T>,
+    declared: PhantomData<fn() -> D>,
+}
+
+impl<T: 'static, D> MockableStatic<T, D> {
+    /// Create a static initialized with the result of `init`
+    pub const fn new(init: fn() -> T) -> Self {
+        MockableStatic {
+            value: LazyLock::new(init),
+            declared: PhantomData,
+        }
+    }
+
+    /// Mock the value of the static in the current thread
+    ///
+    /// The value is leaked, so references to it stay valid even after the mock is replaced.
+    pub fn mock_value(&'static self, value: T) {
+        let value: &'static T = Box::leak(Box::new(value));
+        MOCK_STORE.with(|mock_store| mock_store.set_static_value(self.address(), Some(value)))
+    }
+
+    /// Remove the mock of the static in the current thread
+    pub fn clear_mock(&'static self) {
+        MOCK_STORE.with(|mock_store| mock_store.set_static_value(self.address(), None))
+    }
+
+    /// Check if the static has a mock in the current thread
+    pub fn is_mocked(&'static self) -> bool {
+        self.mock_value_ref().is_some()
+    }
+
+    /// Get the real value of the static regardless of its mock
+    pub fn real_value(&self) -> &T {
+        &self.value
+    }
+
+    fn mock_value_ref(&self) -> Option<&'static T> {
+        // The thread local storage may be already destroyed when accessed from destructors
+        MOCK_STORE
+            .try_with(|mock_store| mock_store.static_value(self.address()))
+            .ok()
+            .flatten()
+            .and_then(|value| value.downcast_ref())
+    }
+
+    // Statics have unique and constant addresses, so they identify mocks
+    fn address(&self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+impl<T: 'static, D> Deref for MockableStatic<T, D> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self.mock_value_ref() {
+            Some(value) => value,
+            None => &self.value,
+        }
+    }
+}
+
+/// The `!` type, which without a feature gate can be named only in return types
+///
+/// It's needed for mocks of unsafe and `extern` functions returning `!`,
+/// which can't infer their output types:
+///
+/// ```
+/// #[mockable]
+/// unsafe fn fail() -> ! {
+///     panic!("not mocked")
+/// }
+///
+/// #[test]
+/// fn fail_test() {
+///     unsafe {
+///         mock_unsafe_fn(fail, || MockResult::<_, Never>::Panic("mocked".to_string()));
+///     }
+/// }
+/// ```
+pub type Never = <fn() -> ! as FnOnce<()>>::Output;
+
+/// Replaces an argument of a mockable function with one passed with `MockResult::Continue`,
+/// the replaced value was moved to the mock, so it's not dropped
+#[doc(hidden)]
+pub unsafe fn restore_arg<T>(arg: *const T, value: T) {
+    ptr::write(arg as *mut T, value)
+}
+
+/// Outcome of a mock call made by the injected header, shared by all mockable functions
+#[doc(hidden)]
+pub enum MockHeader<T, O> {
+    Continue(T),
+    Return(O),
+    Stop(MockStop),
+}
+
+/// Panic raised by the mock, which must be resumed after the header forgets the arguments
+#[doc(hidden)]
+pub enum MockStop {
+    Panic(String),
+    Unwind(Box<dyn Any + Send>),
+}
+
+impl MockStop {
+    /// Raises the panic, so with `#[track_caller]` it points at the function's caller
+    #[track_caller]
+    pub fn raise<F>(self, _function: &F) -> ! {
+        match self {
+            MockStop::Panic(message) => panic!("{} panicked: {}", type_name::<F>(), message),
+            MockStop::Unwind(unwind) => resume_unwind(unwind),
+        }
+    }
+}
+
+/// Runs the mock call of the injected header and converts the returned value
+/// to the function's return type, which may differ from the mock's one only in lifetimes
+#[doc(hidden)]
+pub unsafe fn run_mock_header<T, O, R>(
+    call_mock: impl FnOnce() -> MockResult<T, O>,
+) -> MockHeader<T, R> {
+    match catch_unwind(AssertUnwindSafe(call_mock)) {
+        Ok(MockResult::Continue(input)) => MockHeader::Continue(input),
+        Ok(MockResult::Return(output)) => {
+            let returned = transmute_copy(&output);
+            forget(output);
+            MockHeader::Return(returned)
+        }
+        Ok(MockResult::Panic(message)) => MockHeader::Stop(MockStop::Panic(message)),
+        Ok(_) => unreachable!(),
+        Err(unwind) => MockHeader::Stop(MockStop::Unwind(unwind)),
+    }
+}
+
+/// Set a handler called for every call of a mockable function, which has no mock set up
+///
+/// It's called in the current thread before the function runs normally. It receives
+/// a description of the call, which can be used e.g. for logging or failing the test
+/// by panicking. Calls made by the handler itself don't call it again.
+/// Calls made with [call_real](trait.Mockable.html#tymethod.call_real) don't call it at all.
+/// The handler replaces the previous one and is kept until
+/// [clear_fallback_handler](fn.clear_fallback_handler.html) is called.
+///
+/// ```
+/// use mocktopus::macros::mockable;
+/// use mocktopus::mocking::*;
+///
+/// #[mockable]
+/// fn add(a: u32, b: u32) -> u32 {
+///     a + b
+/// }
+///
+/// #[test]
+/// fn add_test() {
+///     set_fallback_handler(|call| println!("Unmocked call of {}", call.path()));
+///
+///     assert_eq!(3, add(1, 2));
+/// }
+/// ```
+pub fn set_fallback_handler<H: FnMut(&UnmockedCall) + 'static>(handler: H) {
+    MOCK_STORE.with(|mock_store| mock_store.set_fallback(Some(Box::new(handler))))
+}
+
+/// Remove the handler set with [set_fallback_handler](fn.set_fallback_handler.html)
+pub fn clear_fallback_handler() {
+    MOCK_STORE.with(|mock_store| mock_store.set_fallback(None))
+}
+
+/// Description of a call of a mockable function without a mock,
+/// passed to a [fallback handler](fn.set_fallback_handler.html)
+#[derive(Clone, Copy, Debug)]
+pub struct UnmockedCall {
+    path: &'static str,
+    args_type: &'static str,
+    return_type: &'static str,
+}
+
+impl UnmockedCall {
+    pub(crate) fn new(
+        path: &'static str,
+        args_type: &'static str,
+        return_type: &'static str,
+    ) -> Self {
+        UnmockedCall {
+            path,
+            args_type,
+            return_type,
+        }
+    }
+
+    /// Path of the called function, see [mock_by_name](fn.mock_by_name.html)
+    pub fn path(&self) -> &'static str {
+        self.path
+    }
+
+    /// Name of type of the function arguments tuple
+    pub fn args_type(&self) -> &'static str {
+        self.args_type
+    }
+
+    /// Name of the function return type
+    pub fn return_type(&self) -> &'static str {
+        self.return_type
+    }
+}
+
+/// An alias of [clear_all_mocks](fn.clear_all_mocks.html)
+pub fn clear_mocks() {
+    clear_all_mocks()
+}
+
+impl<T: Tuple, O, F: FnOnce<T, Output = O>> Mockable<T, O> for F {
+    unsafe fn mock_raw<M: FnMut<T, Output = MockResult<T, O>>>(&self, mock: M) {
+        let id = self.get_mock_id();
+        let boxed = Box::new(mock) as Box<dyn FnMut<_, Output = _>>;
+        let static_boxed: Box<dyn FnMut<T, Output = MockResult<T, O>> + 'static> = transmute(boxed);
+        MOCK_STORE.with(|mock_store| mock_store.add_to_thread_layer(id, static_boxed))
+    }
+
+    fn mock_safe<M: FnMut<T, Output = MockResult<T, O>> + 'static>(&self, mock: M) {
+        unsafe { self.mock_raw(mock) }
+    }
+
+    fn push_mock<M: FnMut<T, Output = MockResult<T, O>> + 'static>(&self, mock: M) {
+        let id = unsafe { self.get_mock_id() };
+        let boxed = Box::new(mock) as Box<dyn FnMut<T, Output = MockResult<T, O>> + 'static>;
+        MOCK_STORE.with(|mock_store| unsafe { mock_store.push_to_thread_layer(id, boxed, false) })
+    }
+
+    fn pop_mock(&self) {
+        let id = unsafe { self.get_mock_id() };
+        MOCK_STORE.with(|mock_store| mock_store.pop_from_thread_layer(id))
+    }
+
+    fn mock_scoped<'env, M: FnMut<T, Output = MockResult<T, O>> + 'env>(
+        &self,
+        scope: &MockScope<'env>,
+        mock: M,
+    ) {
+        unsafe {
+            let id = self.get_mock_id();
+            let boxed = Box::new(mock) as Box<dyn FnMut<_, Output = _>>;
+            let static_boxed: Box<dyn FnMut<T, Output = MockResult<T, O>> + 'static> =
+                transmute(boxed);
+            MOCK_STORE.with(|mock_store| mock_store.add_to_layer(scope.layer_idx, id, static_boxed))
+        }
+    }
+
+    fn mock_return_sequence<S: IntoIterator<Item = O>>(&self, values: S, end: SequenceEnd)
+    where
+        O: Clone + 'static,
+    {
+        self.mock_safe(SequenceMock {
+            values: values.into_iter().collect(),
+            end,
+        })
+    }
+
+    fn mock_shared<M>(&self, mock: M)
+    where
+        M: Fn<T, Output = MockResult<T, O>> + Send + Sync + 'static,
+    {
+        let id = unsafe { self.get_mock_id() };
+        let mock = Arc::new(mock);
+        let installer = move |mock_layer: &mut MockLayer| {
+            let mock = mock.clone();
+            let boxed = Box::new(TupledMock(move |input: T| mock.call(input)))
+                as Box<dyn FnMut<_, Output = _>>;
+            unsafe {
+                let static_boxed: Box<dyn FnMut<T, Output = MockResult<T, O>> + 'static> =
+                    transmute(boxed);
+                mock_layer.add(id, static_boxed)
+            }
+        };
+        // The installer owns all its data, so it stays valid for the whole program lifetime
+        let installer = unsafe { erase_installer(Arc::new(installer)) };
+        #[cfg(feature = "global-mocks")]
+        crate::mock_store::add_global(id, installer.clone());
+        MOCK_STORE.with(|mock_store| mock_store.add_shared_to_thread_layer(id, installer))
+    }
+
+    fn mock_return_once(&self, value: O)
+    where
+        O: 'static,
+    {
+        let mut value = Some(value);
+        let mock = TupledMock(move |input: T| match value.take() {
+            Some(value) => MockResult::Return(value),
+            None => MockResult::Continue(input),
+        });
+        self.mock_safe(mock)
+    }
+
+    fn mock_if<P, H>(&self, predicate: P, handler: H)
+    where
+        P: FnMut(&T) -> bool + 'static,
+        H: FnMut<T, Output = O> + 'static,
+    {
+        self.mock_safe(ConditionalMock { predicate, handler })
+    }
+
+    fn mock_nth_call<H>(&self, n: usize, handler: H)
+    where
+        H: FnMut<T, Output = O> + 'static,
+    {
+        let mut calls = 0;
+        let predicate = move |_: &T| {
+            let is_nth = calls == n;
+            calls += 1;
+            is_nth
+        };
+        self.mock_safe(ConditionalMock { predicate, handler })
+    }
+
+    fn mock_panic<S: Into<String>>(&self, message: S) {
+        let message = message.into();
+        let mock = TupledMock(move |_: T| MockResult::Panic(message.clone()));
+        self.mock_safe(mock)
+    }
+
+    fn mock_with_state<S, H>(&self, initial_state: S, mut handler: H)
+    where
+        S: 'static,
+        H: FnMut(&mut S, T) -> MockResult<T, O> + 'static,
+    {
+        let state = Rc::new(RefCell::new(initial_state));
+        let mock_state = state.clone();
+        let mock = TupledMock(move |input: T| handler(&mut mock_state.borrow_mut(), input));
+        let id = unsafe { self.get_mock_id() };
+        MOCK_STORE.with(|mock_store| mock_store.set_state(id, state));
+        self.mock_safe(mock)
+    }
+
+    fn mock_state<S: Clone + 'static>(&self) -> S {
+        let id = unsafe { self.get_mock_id() };
+        MOCK_STORE
+            .with(|mock_store| mock_store.state(id))
+            .and_then(|state| state.downcast::<RefCell<S>>().ok())
+            .map(|state| state.borrow().clone())
+            .unwrap_or_else(|| {
+                panic!(
+                    "No mock state of type {} for {}",
+                    type_name::<S>(),
+                    type_name::<Self>()
+                )
+            })
+    }
+
+    fn spy(&self) -> ArgCaptor<T>
+    where
+        T: Clone + 'static,
+    {
+        let captor = ArgCaptor::new();
+        let mock_captor = captor.clone();
+        let mock = move |args: T| {
+            mock_captor.capture(args.clone());
+            MockResult::Continue(args)
+        };
+        push_fallthrough_mock(self, mock);
+        captor
+    }
+
+    fn call_real(&self, input: T) -> O
+    where
+        Self: Fn<T, Output = O>,
+    {
+        let id = unsafe { self.get_mock_id() };
+        MOCK_STORE.with(|mock_store| mock_store.add_unmocked_call(id));
+        let _unmocked_call_guard = UnmockedCallGuard(id);
+        self.call(input)
+    }
+
+    fn clear_mock(&self) {
+        let id = unsafe { self.get_mock_id() };
+        MOCK_STORE.with(|mock_store| mock_store.clear_id(id))
+    }
+
+    fn is_mocked(&self) -> bool {
+        let id = unsafe { self.get_mock_id() };
+        MOCK_STORE.with(|mock_store| mock_store.is_mocked(id))
+    }
+
+    fn call_count(&self) -> usize {
+        let id = unsafe { self.get_mock_id() };
+        MOCK_STORE.with(|mock_store| mock_store.call_count(id))
+    }
+
+    fn reset_call_count(&self) {
+        let id = unsafe { self.get_mock_id() };
+        MOCK_STORE.with(|mock_store| mock_store.reset_call_count(id))
+    }
+
+    fn expect_calls(&self, times: usize) {
+        let id = unsafe { self.get_mock_id() };
+        let fn_name = type_name::<Self>();
+        MOCK_STORE.with(|mock_store| mock_store.add_expectation(id, fn_name, times))
+    }
+
+    fn expect_no_calls(&self) {
+        self.expect_calls(0)
+    }
+
+    fn call_mock(&self, input: T) -> MockResult<T, O>
+    where
+        Self: Fn<T, Output = O>,
+    {
+        let result = unsafe {
+            let id = self.get_mock_id();
+            let fn_name = type_name::<Self>();
+            MOCK_STORE.with(|mock_store| mock_store.call(id, fn_name, input))
+        };
+        match result {
+            MockResult::ContinueThen(input, then) => {
+                MockResult::Return(then(self.call_real(input)))
+            }
+            MockResult::ReturnFromArgs(input, create) => MockResult::Return(create(&input)),
+            result => result,
+        }
+    }
+
+    unsafe fn get_mock_id(&self) -> TypeId {
+        (|| ()).type_id()
+    }
+}
+
+/// `MockContext` allows for safe capture of local variables.
+///
+/// It does this by forcing only mocking the actual function while in the body
+/// of [run](#tymethod.run).
+///
+/// # Examples
+///
+/// Simple function replacement:
+///
+/// ```
+/// use mocktopus::macros::mockable;
+/// use mocktopus::mocking::{MockContext, MockResult};
+///
+/// #[mockable]
+/// fn f() -> i32 {
+///     0
+/// }
+///
+/// MockContext::new()
+///     .mock_safe(f, || MockResult::Return(1))
+///     .run(|| {
+///         assert_eq!(f(), 1);
+///     });
+/// ```
+///
+/// Using local variables:
+///
+/// ```
+/// use mocktopus::macros::mockable;
+/// use mocktopus::mocking::{MockContext, MockResult};
+///
+/// #[mockable]
+/// fn as_str(s: &String) -> &str {
+///     &s
+/// }
+///
+/// let mut count = 0;
+/// MockContext::new()
+///     .mock_safe(as_str, |s| { count += 1; MockResult::Return(&s) })
+///     .run(|| {
+///         assert_eq!(as_str(&"abc".to_string()), "abc");
+///     });
+/// assert_eq!(count, 1);
+/// ```
+///
+/// Returning references to local variables, which would otherwise require
+/// [mock_raw](trait.Mockable.html#tymethod.mock_raw):
+///
+/// ```
+/// use mocktopus::macros::mockable;
+/// use mocktopus::mocking::{MockContext, MockResult};
+///
+/// #[derive(Default)]
+/// struct Config {
+///     name: String,
+/// }
+///
+/// #[mockable]
+/// fn get_name(config: &Config) -> &String {
+///     &config.name
+/// }
+///
+/// let mocked = "mocked".to_string();
+/// MockContext::new()
+///     .mock_safe(get_name, |_| MockResult::Return(&mocked))
+///     .run(|| {
+///         assert_eq!(get_name(&Config::default()), "mocked");
+///     });
+/// ```
+///
+/// The borrow checker makes sure, that the local variables outlive the context.
+#[derive(Default)]
+pub struct MockContext<'a> {
+    mock_layer: MockLayer,
+    shared_mocks: Vec<(TypeId, SharedContextMock<'a>)>,
+    hooks: ContextHooks<'a>,
+    phantom_lifetime: PhantomData<&'a ()>,
+}
+
+/// Sets up a mock shareable between threads in a context layer
+type SharedContextMock<'a> = Arc<dyn Fn(&mut MockLayer) + Send + Sync + 'a>;
+
+type ContextHook<'a> = Box<dyn FnOnce() + 'a>;
+
+#[derive(Default)]
+struct ContextHooks<'a> {
+    enter: Vec<ContextHook<'a>>,
+    exit: Vec<ContextHook<'a>>,
+}
+
+impl<'a> ContextHooks<'a> {
+    /// Exit hooks are run when the returned guard is dropped, even if an enter hook panicked
+    fn enter(self) -> ExitHooksGuard<'a> {
+        let guard = ExitHooksGuard(self.exit);
+        for hook in self.enter {
+            hook();
+        }
+        guard
+    }
+}
+
+impl<'a> MockContext<'a> {
+    /// Create a new MockContext object.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set up a function to be mocked.
+    ///
+    /// This function doesn't actually mock the function.  It registers it as a
+    /// function that will be mocked when [`run`](#method.run) is called.
+    pub fn mock_safe<I: Tuple, O, F, M>(self, mockable: F, mock: M) -> Self
+    where
+        F: Mockable<I, O>,
+        M: FnMut<I, Output = MockResult<I, O>> + 'a,
+    {
+        unsafe { self.mock_raw(mockable, mock) }
+    }
+
+    /// Set up a function to be mocked.
+    ///
+    /// This is an unsafe version of [`mock_safe`](#method.mock_safe),
+    /// without lifetime constraint on mock
+    pub unsafe fn mock_raw<I: Tuple, O, F, M>(mut self, mockable: F, mock: M) -> Self
+    where
+        F: Mockable<I, O>,
+        M: FnMut<I, Output = MockResult<I, O>>,
+    {
+        let id = mockable.get_mock_id();
+        let mock_box = Box::new(mock) as Box<dyn FnMut<_, Output = _>>;
+        let mock_box_static: Box<dyn FnMut<I, Output = MockResult<I, O>> + 'static> =
+            std::mem::transmute(mock_box);
+        self.mock_layer.add(id, mock_box_static);
+        self.mock_layer.set_name(id, type_name::<F>());
+        self.shared_mocks.retain(|(shared_id, _)| *shared_id != id);
+        self
+    }
+
+    /// Set up a function to be mocked with a mock, which can be shared with other threads.
+    ///
+    /// Works like [`mock_safe`](#method.mock_safe), but the mock is also set up in contexts
+    /// created with [`fork`](#method.fork). All of them share a single instance of the mock,
+    /// so it must be `Fn`.
+    pub fn mock_shared<I: Tuple, O, F, M>(mut self, mockable: F, mock: M) -> Self
+    where
+        F: Mockable<I, O>,
+        M: Fn<I, Output = MockResult<I, O>> + Send + Sync + 'a,
+    {
+        let id = unsafe { mockable.get_mock_id() };
+        let name = type_name::<F>();
+        let mock = Arc::new(mock);
+        let setup = move |mock_layer: &mut MockLayer| {
+            let mock = mock.clone();
+            let mock_box = Box::new(TupledMock(move |input: I| mock.call(input)))
+                as Box<dyn FnMut<_, Output = _>>;
+            unsafe {
+                let mock_box_static: Box<dyn FnMut<I, Output = MockResult<I, O>> + 'static> =
+                    transmute(mock_box);
+                mock_layer.add(id, mock_box_static);
+            }
+            mock_layer.set_name(id, name);
+        };
+        setup(&mut self.mock_layer);
+        let setup: Arc<dyn Fn(&mut MockLayer) + Send + Sync + 'a> = Arc::new(setup);
+        self.shared_mocks.retain(|(shared_id, _)| *shared_id != id);
+        self.shared_mocks.push((id, setup));
+        self
+    }
+
+    /// Create a copy of the context, which can be run in other threads.
+    ///
+    /// Only mocks set up with [`mock_shared`](#method.mock_shared) and the strict mode are copied.
+    /// Expectations and other mocks stay only in this context. It allows code under test,
+    /// which uses threads, to see the test's mocks.
+    ///
+    /// ```
+    /// use mocktopus::macros::mockable;
+    /// use mocktopus::mocking::{MockContext, MockResult};
+    ///
+    /// #[mockable]
+    /// fn get_number() -> u32 {
+    ///     0
+    /// }
+    ///
+    /// let context = MockContext::new().mock_shared(get_number, || MockResult::Return(1));
+    /// let forked = context.fork();
+    /// context.run(|| {
+    ///     let worker = std::thread::spawn(move || forked.run(get_number));
+    ///     assert_eq!(1, worker.join().unwrap());
+    ///     assert_eq!(1, get_number());
+    /// });
+    /// ```
+    pub fn fork(&self) -> ForkedMockContext<'a> {
+        ForkedMockContext {
+            shared_mocks: self.shared_mocks.clone(),
+            strict: self.mock_layer.strict,
+        }
+    }
+
+    /// Expect a function to be called exactly given number of times inside [`run`](#method.run).
+    ///
+    /// The expectation is verified when [`run`](#method.run) finishes,
+    /// see [expect_calls](trait.Mockable.html#tymethod.expect_calls).
+    pub fn expect_calls<I: Tuple, O, F: Mockable<I, O>>(
+        mut self,
+        mockable: F,
+        times: usize,
+    ) -> Self {
+        let id = unsafe { mockable.get_mock_id() };
+        self.mock_layer.add_expectation(id, type_name::<F>(), times);
+        self
+    }
+
+    /// Enable strict mode inside [`run`](#method.run).
+    ///
+    /// Calling a mockable function without a mock panics, see
+    /// [set_strict_mode](fn.set_strict_mode.html).
+    pub fn strict(mut self) -> Self {
+        self.mock_layer.strict = true;
+        self
+    }
+
+    /// Expect a function to be called with arguments matching given
+    /// [matchers](../matchers/index.html) exactly given number of times inside [`run`](#method.run).
+    ///
+    /// The expectation is verified together with the others set up for the context, so a single
+    /// report lists all unmet expectations. The matchers may borrow local variables.
+    ///
+    /// ```
+    /// use mocktopus::macros::mockable;
+    /// use mocktopus::matchers::{any, eq};
+    /// use mocktopus::mocking::{MockContext, MockResult};
+    ///
+    /// #[mockable]
+    /// fn send(address: &str, message: &str) {
+    ///     unimplemented!()
+    /// }
+    ///
+    /// MockContext::new()
+    ///     .mock_safe(send, |_, _| MockResult::Return(()))
+    ///     .expect_calls_with(send, (eq("alice"), any()), 2)
+    ///     .expect_calls_with(send, (eq("bob"), eq("bye")), 1)
+    ///     .run(|| {
+    ///         send("alice", "hello");
+    ///         send("alice", "bye");
+    ///         send("bob", "bye");
+    ///     });
+    /// ```
+    pub fn expect_calls_with<I: Tuple, O, F, M>(
+        mut self,
+        mockable: F,
+        matchers: M,
+        times: usize,
+    ) -> Self
+    where
+        F: Mockable<I, O>,
+        M: ArgsMatcher<I> + 'a,
+    {
+        unsafe {
+            let matcher = Box::new(move |input: &I| matchers.matches_args(input))
+                as Box<dyn FnMut(&I) -> bool + 'a>;
+            let static_matcher: Box<dyn FnMut(&I) -> bool + 'static> = transmute(matcher);
+            self.mock_layer.add_matching_expectation(
+                mockable.get_mock_id(),
+                type_name::<F>(),
+                times,
+                static_matcher,
+            );
+        }
+        self
+    }
+
+    /// Register a hook run when the context is entered.
+    ///
+    /// Hooks run in order of registration after the mocks are set up, so they can already call
+    /// mocked functions. It's useful e.g. for starting a fake clock used by the mocks.
+    pub fn on_enter<H: FnOnce() + 'a>(mut self, hook: H) -> Self {
+        self.hooks.enter.push(Box::new(hook));
+        self
+    }
+
+    /// Register a hook run when the context is exited.
+    ///
+    /// Hooks run in reverse order of registration before the mocks are removed and the
+    /// expectations are verified. They run even if the code inside the context panics.
+    /// A panic inside an exit hook during such unwinding aborts the process.
+    ///
+    /// ```
+    /// use mocktopus::macros::mockable;
+    /// use mocktopus::mocking::{MockContext, MockResult};
+    /// use std::cell::RefCell;
+    ///
+    /// #[mockable]
+    /// fn send(message: &str) {
+    ///     unimplemented!()
+    /// }
+    ///
+    /// let log = RefCell::new(Vec::new());
+    /// MockContext::new()
+    ///     .mock_safe(send, |message| {
+    ///         log.borrow_mut().push(message.to_string());
+    ///         MockResult::Return(())
+    ///     })
+    ///     .on_enter(|| log.borrow_mut().push("start".to_string()))
+    ///     .on_exit(|| log.borrow_mut().push("flush".to_string()))
+    ///     .on_exit(|| log.borrow_mut().push("stop".to_string()))
+    ///     .run(|| send("hello"));
+    ///
+    /// assert_eq!(vec!["start", "hello", "stop", "flush"], *log.borrow());
+    /// ```
+    pub fn on_exit<H: FnOnce() + 'a>(mut self, hook: H) -> Self {
+        self.hooks.exit.push(Box::new(hook));
+        self
+    }
+
+    /// Paths of all functions mocked by the context, sorted alphabetically.
+    ///
+    /// It's useful for debugging and for checking, that a
+    /// [fixture](struct.MockFixture.html) sets up all mocks it should.
+    ///
+    /// ```
+    /// use mocktopus::macros::mockable;
+    /// use mocktopus::mocking::{MockContext, MockResult};
+    ///
+    /// mod db {
+    ///     #[mockable]
+    ///     pub fn connected() -> bool {
+    ///         unimplemented!()
+    ///     }
+    /// }
+    ///
+    /// let context = MockContext::new().mock_safe(db::connected, || MockResult::Return(true));
+    ///
+    /// assert_eq!(vec!["my_crate::db::connected"], context.mocked_functions());
+    /// ```
+    pub fn mocked_functions(&self) -> Vec<&'static str> {
+        self.mock_layer.mocked_names()
+    }
+
+    /// Add all mocks and expectations of another context to this one.
+    ///
+    /// Mocks of the other context replace mocks of the same functions in this one.
+    /// The merged context is strict if any of them is. Hooks of the other context are entered
+    /// after and exited before hooks of this one.
+    ///
+    /// ```
+    /// use mocktopus::macros::mockable;
+    /// use mocktopus::mocking::{MockContext, MockResult};
+    ///
+    /// #[mockable]
+    /// fn db_connected() -> bool {
+    ///     unimplemented!()
+    /// }
+    ///
+    /// #[mockable]
+    /// fn db_get(key: &str) -> Option<String> {
+    ///     unimplemented!()
+    /// }
+    ///
+    /// let infra = MockContext::new()
+    ///     .mock_safe(db_connected, || MockResult::Return(true))
+    ///     .mock_safe(db_get, |_| MockResult::Return(None));
+    /// let scenario = MockContext::new().mock_safe(db_get, |_| MockResult::Return(Some("value".to_string())));
+    ///
+    /// infra.merge(scenario).run(|| {
+    ///     assert!(db_connected());
+    ///     assert_eq!(Some("value".to_string()), db_get("key"));
+    /// });
+    /// ```
+    pub fn merge(mut self, other: MockContext<'a>) -> Self {
+        let other_ids: Vec<_> = other.mock_layer.mocked_ids().collect();
+        self.shared_mocks
+            .retain(|(shared_id, _)| !other_ids.contains(shared_id));
+        self.shared_mocks.extend(other.shared_mocks);
+        self.mock_layer.merge(other.mock_layer);
+        self.hooks.enter.extend(other.hooks.enter);
+        self.hooks.exit.extend(other.hooks.exit);
+        self
+    }
+
+    /// Set up all mocks and expectations of a fixture.
+    ///
+    /// See [MockFixture](struct.MockFixture.html).
+    pub fn with_fixture(self, fixture: &MockFixture<'a>) -> Self {
+        fixture
+            .setups
+            .iter()
+            .fold(self, |context, setup| setup(context))
+    }
+
+    /// Run the function while mocking all the functions.
+    ///
+    /// This function will mock all functions registered for mocking, run the
+    /// function passed in, then deregister those functions.  It does this in a
+    /// panic-safe way: if the function panics, the mocks are removed and the
+    /// expectations aren't verified, so the original panic is reported.  Note that
+    /// functions are only mocked in the current thread and other threads may invoke
+    /// the real implementations.
+    ///
+    /// Register a function for mocking with [`mock_safe`](#method.mock_safe).
     pub fn run<T, F: FnOnce() -> T>(self, f: F) -> T {
-        MOCK_STORE.with(|mock_store| unsafe { mock_store.add_layer(self.mock_layer) });
+        let MockContext {
+            mock_layer, hooks, ..
+        } = self;
+        MOCK_STORE.with(|mock_store| unsafe { mock_store.add_layer(mock_layer) });
+        let _mock_level_guard = MockLayerGuard;
+        let _exit_hooks_guard = hooks.enter();
+        f()
+    }
+
+    /// Run the future while mocking all the functions.
+    ///
+    /// Async version of [`run`](#method.run). The mocks are set up only while the returned future
+    /// is being polled, so they are kept across await points, but they aren't visible to other
+    /// tasks running in the meantime. The expectations are verified when the future completes.
+    /// If it's dropped before completion, the mocks are just removed. The context is entered
+    /// on the first poll and exited on completion or when the future is dropped.
+    ///
+    /// ```
+    /// use mocktopus::macros::mockable;
+    /// use mocktopus::mocking::{MockContext, MockResult};
+    ///
+    /// #[mockable]
+    /// fn f() -> i32 {
+    ///     0
+    /// }
+    ///
+    /// #[tokio::test]
+    /// async fn f_test() {
+    ///     MockContext::new()
+    ///         .mock_safe(f, || MockResult::Return(1))
+    ///         .run_async(async {
+    ///             tokio::task::yield_now().await;
+    ///             assert_eq!(f(), 1);
+    ///         })
+    ///         .await;
+    /// }
+    /// ```
+    pub fn run_async<F: Future>(self, future: F) -> MockContextFuture<'a, F> {
+        MockContextFuture {
+            mock_layer: Some(self.mock_layer),
+            future,
+            hooks: Some(self.hooks),
+            exit_hooks: None,
+            phantom_lifetime: PhantomData,
+        }
+    }
+}
+
+/// Reusable set of mocks and expectations for [MockContext](struct.MockContext.html)s
+///
+/// A fixture is declared once and then applied to any number of contexts with
+/// [with_fixture](struct.MockContext.html#method.with_fixture). Every context receives
+/// its own clones of the mocks, so their state isn't shared between tests.
+///
+/// ```
+/// use mocktopus::macros::mockable;
+/// use mocktopus::mocking::{MockContext, MockFixture, MockResult};
+///
+/// #[mockable]
+/// fn db_get(key: &str) -> Option<String> {
+///     unimplemented!()
+/// }
+///
+/// #[mockable]
+/// fn db_connected() -> bool {
+///     unimplemented!()
+/// }
+///
+/// fn db_fixture() -> MockFixture<'static> {
+///     MockFixture::new()
+///         .mock_safe(db_connected, || MockResult::Return(true))
+///         .mock_safe(db_get, |_| MockResult::Return(None))
+/// }
+///
+/// MockContext::new()
+///     .with_fixture(&db_fixture())
+///     .mock_safe(db_get, |_| MockResult::Return(Some("value".to_string())))
+///     .run(|| {
+///         assert!(db_connected());
+///         assert_eq!(Some("value".to_string()), db_get("key"));
+///     });
+/// ```
+#[derive(Default)]
+pub struct MockFixture<'a> {
+    setups: Vec<Box<dyn Fn(MockContext<'a>) -> MockContext<'a> + 'a>>,
+}
+
+impl<'a> MockFixture<'a> {
+    /// Create a new fixture without any mocks
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set up a function to be mocked in every context using the fixture.
+    ///
+    /// Works like [MockContext::mock_safe](struct.MockContext.html#method.mock_safe),
+    /// but every context receives a clone of the mock.
+    pub fn mock_safe<I: Tuple, O, F, M>(mut self, mockable: F, mock: M) -> Self
+    where
+        F: Mockable<I, O> + Clone + 'a,
+        M: FnMut<I, Output = MockResult<I, O>> + Clone + 'a,
+    {
+        let setup =
+            move |context: MockContext<'a>| context.mock_safe(mockable.clone(), mock.clone());
+        self.setups.push(Box::new(setup));
+        self
+    }
+
+    /// Expect a function to be called exactly given number of times in every context using the fixture.
+    ///
+    /// See [MockContext::expect_calls](struct.MockContext.html#method.expect_calls).
+    pub fn expect_calls<I: Tuple, O, F>(mut self, mockable: F, times: usize) -> Self
+    where
+        F: Mockable<I, O> + Clone + 'a,
+    {
+        let setup = move |context: MockContext<'a>| context.expect_calls(mockable.clone(), times);
+        self.setups.push(Box::new(setup));
+        self
+    }
+
+    /// Add all mocks and expectations of another fixture to this one.
+    pub fn with_fixture(mut self, fixture: MockFixture<'a>) -> Self {
+        self.setups.extend(fixture.setups);
+        self
+    }
+}
+
+/// Copy of a [MockContext](struct.MockContext.html), which can be sent to other threads
+///
+/// It's created with [MockContext::fork](struct.MockContext.html#method.fork).
+#[derive(Clone)]
+pub struct ForkedMockContext<'a> {
+    shared_mocks: Vec<(TypeId, SharedContextMock<'a>)>,
+    strict: bool,
+}
+
+impl<'a> ForkedMockContext<'a> {
+    /// Create a context with the copied mocks, which can be extended with more mocks.
+    pub fn context(&self) -> MockContext<'a> {
+        let mut context = MockContext::new();
+        for (id, setup) in &self.shared_mocks {
+            setup(&mut context.mock_layer);
+            context.shared_mocks.push((*id, setup.clone()));
+        }
+        context.mock_layer.strict = self.strict;
+        context
+    }
+
+    /// Run the function while mocking all the functions.
+    ///
+    /// See [MockContext::run](struct.MockContext.html#method.run).
+    pub fn run<T, F: FnOnce() -> T>(&self, f: F) -> T {
+        self.context().run(f)
+    }
+}
+
+/// Future returned by [MockContext::run_async](struct.MockContext.html#method.run_async)
+pub struct MockContextFuture<'a, F> {
+    mock_layer: Option<MockLayer>,
+    future: F,
+    hooks: Option<ContextHooks<'a>>,
+    exit_hooks: Option<ExitHooksGuard<'a>>,
+    phantom_lifetime: PhantomData<&'a ()>,
+}
+
+impl<'a, F: Future> Future for MockContextFuture<'a, F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<F::Output> {
+        // The future is never moved out of the pinned struct
+        let this = unsafe { self.get_unchecked_mut() };
+        let mock_layer = this
+            .mock_layer
+            .take()
+            .expect("MockContextFuture polled after completion");
+        MOCK_STORE.with(|mock_store| unsafe { mock_store.add_layer(mock_layer) });
+        let poll = {
+            let _poll_guard = PollLayerGuard(&mut this.mock_layer);
+            if let Some(hooks) = this.hooks.take() {
+                this.exit_hooks = Some(hooks.enter());
+            }
+            let poll = unsafe { Pin::new_unchecked(&mut this.future) }.poll(cx);
+            if poll.is_ready() {
+                this.exit_hooks = None;
+            }
+            poll
+        };
+        if poll.is_ready() {
+            let mut mock_layer = this.mock_layer.take().expect("Mock layer missing");
+            MOCK_STORE.with(|mock_store| mock_store.verify_layer_expectations(&mut mock_layer));
+        }
+        poll
+    }
+}
+
+/// Snapshot of mocks set up in the current thread
+///
+/// It captures all mocks set up with [Mockable](trait.Mockable.html) methods and the
+/// [strict mode](fn.set_strict_mode.html) flag, which can be later restored any number of times.
+/// Mocks of running [MockContext](struct.MockContext.html)s and call expectations are not captured.
+/// Restored mocks are shared with the captured ones, so they keep their internal state.
+///
+/// ```
+/// use mocktopus::macros::mockable;
+/// use mocktopus::mocking::*;
+///
+/// #[mockable]
+/// fn get_number() -> u32 {
+///     0
+/// }
+///
+/// #[test]
+/// fn get_number_test() {
+///     get_number.mock_safe(|| MockResult::Return(1));
+///     let snapshot = MockRegistrySnapshot::capture();
+///
+///     for value in 2..5 {
+///         get_number.mock_safe(move || MockResult::Return(value));
+///         assert_eq!(value, get_number());
+///
+///         snapshot.restore();
+///         assert_eq!(1, get_number());
+///     }
+/// }
+/// ```
+pub struct MockRegistrySnapshot {
+    thread_layer: MockLayer,
+}
+
+impl MockRegistrySnapshot {
+    /// Capture mocks set up in the current thread
+    pub fn capture() -> Self {
+        MockRegistrySnapshot {
+            thread_layer: MOCK_STORE.with(|mock_store| mock_store.snapshot_thread_layer()),
+        }
+    }
+
+    /// Replace mocks set up in the current thread with the captured ones
+    pub fn restore(&self) {
+        MOCK_STORE.with(|mock_store| mock_store.restore_thread_layer(&self.thread_layer))
+    }
+}
+
+/// Handle to mocks of a thread, which can be set up in other threads
+///
+/// Mocks are normally visible only in the thread, which set them up. The handle captures all mocks
+/// set up with [mock_shared](trait.Mockable.html#tymethod.mock_shared) in the current thread,
+/// which weren't replaced or removed since. They can be then set up in another thread with
+/// [apply](#method.apply), e.g. in worker threads spawned by the tested code.
+///
+/// ```
+/// use mocktopus::macros::mockable;
+/// use mocktopus::mocking::*;
+///
+/// #[mockable]
+/// fn get_number() -> u32 {
+///     0
+/// }
+///
+/// #[test]
+/// fn get_number_test() {
+///     get_number.mock_shared(|| MockResult::Return(1));
+///     let handle = MockHandle::current();
+///
+///     let workers: Vec<_> = (0..4)
+///         .map(|_| {
+///             let handle = handle.clone();
+///             std::thread::spawn(move || {
+///                 handle.apply();
+///                 get_number()
+///             })
+///         })
+///         .collect();
+///
+///     assert_eq!(4, workers.into_iter().map(|worker| worker.join().unwrap()).sum::<u32>());
+/// }
+/// ```
+#[derive(Clone)]
+pub struct MockHandle {
+    mocks: Vec<(TypeId, SharedMockInstaller)>,
+}
+
+impl MockHandle {
+    /// Capture shareable mocks of the current thread
+    pub fn current() -> Self {
+        MockHandle {
+            mocks: MOCK_STORE.with(|mock_store| mock_store.shared_mocks()),
+        }
+    }
+
+    /// Set up the captured mocks in the current thread
+    ///
+    /// They replace mocks of the same functions like [mock_safe](trait.Mockable.html#tymethod.mock_safe) does.
+    pub fn apply(&self) {
+        for (id, installer) in &self.mocks {
+            MOCK_STORE
+                .with(|mock_store| mock_store.add_shared_to_thread_layer(*id, installer.clone()));
+        }
+    }
+
+    /// Set up the captured mocks in a thread at its start, so it inherits mocks of the thread,
+    /// which created the handle
+    ///
+    /// It works like [apply](#method.apply).
+    pub fn install(&self) {
+        self.apply()
+    }
+
+    /// Run a function with the captured mocks set up in the current thread
+    ///
+    /// The mocks are removed when the function finishes. Unlike with [apply](#method.apply),
+    /// they shadow mocks of the current thread like a [MockContext](struct.MockContext.html) does.
+    /// Handles created inside the function capture them too.
+    pub fn run<T, F: FnOnce() -> T>(&self, f: F) -> T {
+        let mock_layer = self.mock_layer();
+        MOCK_STORE.with(|mock_store| unsafe { mock_store.add_layer(mock_layer) });
         let _mock_level_guard = MockLayerGuard;
+        MOCK_STORE.with(|mock_store| mock_store.push_handle_mocks(self.mocks.clone()));
+        let _handle_mocks_guard = HandleMocksGuard;
         f()
     }
+
+    /// Run a future with the captured mocks set up in every thread, which polls it
+    ///
+    /// The mocks are set up only for the duration of each poll, like by [run](#method.run).
+    /// The returned future is `Send` if the wrapped one is, so it can be spawned on a multi-threaded
+    /// runtime, whose worker threads don't see mocks of the test thread.
+    ///
+    /// ```
+    /// #[mockable]
+    /// async fn get_number() -> u32 {
+    ///     0
+    /// }
+    ///
+    /// #[tokio::test(threaded_scheduler)]
+    /// async fn get_number_test() {
+    ///     get_number.mock_shared(|| MockResult::Return(Box::pin(async { 1 })));
+    ///     let handle = MockHandle::current();
+    ///
+    ///     let task = tokio::spawn(handle.run_async(async { get_number().await }));
+    ///
+    ///     assert_eq!(1, task.await.unwrap());
+    /// }
+    /// ```
+    pub fn run_async<F: Future>(&self, future: F) -> MockHandleFuture<F> {
+        MockHandleFuture {
+            handle: self.clone(),
+            future,
+        }
+    }
+
+    fn mock_layer(&self) -> MockLayer {
+        let mut mock_layer = MockLayer::default();
+        for (_, installer) in &self.mocks {
+            installer(&mut mock_layer);
+        }
+        mock_layer
+    }
+}
+
+/// Future returned by [MockHandle::run_async](struct.MockHandle.html#method.run_async)
+pub struct MockHandleFuture<F> {
+    handle: MockHandle,
+    future: F,
+}
+
+impl<F: Future> Future for MockHandleFuture<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<F::Output> {
+        // The future is never moved out of the pinned struct
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        this.handle.run(|| future.poll(cx))
+    }
+}
+
+/// Capture shareable mocks of the current thread in a handle, which can be cloned and sent to
+/// other threads
+///
+/// It's a shorthand for [MockHandle::current](struct.MockHandle.html#method.current).
+/// A thread spawned by the tested code sees the mocks after it calls
+/// [install](struct.MockHandle.html#method.install) on the handle at its start.
+pub fn registry_handle() -> MockHandle {
+    MockHandle::current()
+}
+
+/// Spawn a thread, which sees shared mocks of the current thread
+///
+/// It works like `std::thread::spawn`, but the closure is run with
+/// [MockHandle::current](struct.MockHandle.html#method.current), so it sees mocks set up with
+/// [mock_shared](trait.Mockable.html#tymethod.mock_shared). Threads spawned by the thread with
+/// this function see them too.
+///
+/// ```
+/// #[mockable]
+/// fn get_number() -> u32 {
+///     0
+/// }
+///
+/// #[test]
+/// fn get_number_test() {
+///     get_number.mock_shared(|| MockResult::Return(1));
+///
+///     let thread = mocktopus::mocking::spawn_thread(get_number);
+///
+///     assert_eq!(1, thread.join().unwrap());
+/// }
+/// ```
+pub fn spawn_thread<F, T>(f: F) -> std::thread::JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let handle = MockHandle::current();
+    std::thread::spawn(move || handle.run(f))
+}
+
+/// Create a scope for spawning threads, which see shared mocks of the current thread
+///
+/// It works like `std::thread::scope`, but threads spawned with
+/// [MockThreadScope::spawn](struct.MockThreadScope.html#method.spawn) see mocks set up with
+/// [mock_shared](trait.Mockable.html#tymethod.mock_shared) in the thread spawning them.
+/// The mocks are removed from the threads when they finish, all of them are joined before
+/// this function returns. Other mocks can't be shared, because they aren't `Send`.
+///
+/// ```
+/// #[mockable]
+/// fn get_number() -> u32 {
+///     0
+/// }
+///
+/// #[test]
+/// fn get_number_test() {
+///     get_number.mock_shared(|| MockResult::Return(1));
+///     let mut numbers = vec![0; 4];
+///
+///     mocktopus::mocking::thread_scope(|scope| {
+///         for number in &mut numbers {
+///             scope.spawn(move || *number = get_number());
+///         }
+///     });
+///
+///     assert_eq!(vec![1; 4], numbers);
+/// }
+/// ```
+pub fn thread_scope<'env, F, T>(f: F) -> T
+where
+    F: for<'scope> FnOnce(MockThreadScope<'scope, 'env>) -> T,
+{
+    std::thread::scope(|scope| f(MockThreadScope { scope }))
+}
+
+/// Scope for spawning threads, which see shared mocks, created with [thread_scope](fn.thread_scope.html)
+#[derive(Clone, Copy)]
+pub struct MockThreadScope<'scope, 'env: 'scope> {
+    scope: &'scope std::thread::Scope<'scope, 'env>,
+}
+
+impl<'scope, 'env> MockThreadScope<'scope, 'env> {
+    /// Spawn a scoped thread, which sees shared mocks of the current thread
+    ///
+    /// It works like `std::thread::Scope::spawn`, the thread can spawn more threads in the scope.
+    pub fn spawn<F, T>(&self, f: F) -> std::thread::ScopedJoinHandle<'scope, T>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        let handle = MockHandle::current();
+        self.scope.spawn(move || handle.run(f))
+    }
+}
+
+/// Spawn a tokio task, which sees shared mocks of the current thread
+///
+/// It works like `tokio::spawn`, but the future is run with
+/// [MockHandle::current](struct.MockHandle.html#method.current), so every worker thread polling it
+/// sees mocks set up with [mock_shared](trait.Mockable.html#tymethod.mock_shared).
+/// Tasks spawned by the task with this function see them too. Requires the `tokio` feature.
+///
+/// ```
+/// #[mockable]
+/// fn get_number() -> u32 {
+///     0
+/// }
+///
+/// #[tokio::test(threaded_scheduler)]
+/// async fn get_number_test() {
+///     get_number.mock_shared(|| MockResult::Return(1));
+///
+///     let task = mocktopus::mocking::spawn(async { get_number() });
+///
+///     assert_eq!(1, task.await.unwrap());
+/// }
+/// ```
+#[cfg(feature = "tokio")]
+pub fn spawn<F>(future: F) -> tokio::task::JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(MockHandle::current().run_async(future))
+}
+
+/// Run a blocking function on a tokio thread pool, which sees shared mocks of the current thread
+///
+/// It works like `tokio::task::spawn_blocking`, but the function is run with
+/// [MockHandle::current](struct.MockHandle.html#method.current). Requires the `tokio` feature.
+#[cfg(feature = "tokio")]
+pub fn spawn_blocking<F, R>(f: F) -> tokio::task::JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let handle = MockHandle::current();
+    tokio::task::spawn_blocking(move || handle.run(f))
+}
+
+/// Spawn an async-std task, which sees shared mocks of the current thread
+///
+/// It works like `async_std::task::spawn` and like [spawn](fn.spawn.html) does for tokio.
+/// Requires the `async-std` feature.
+#[cfg(feature = "async-std")]
+pub fn spawn_async_std<F>(future: F) -> async_std::task::JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    async_std::task::spawn(MockHandle::current().run_async(future))
+}
+
+/// Run a blocking function on an async-std thread pool, which sees shared mocks of the current thread
+///
+/// It works like `async_std::task::spawn_blocking` and like
+/// [spawn_blocking](fn.spawn_blocking.html) does for tokio. Requires the `async-std` feature.
+#[cfg(feature = "async-std")]
+pub fn spawn_blocking_async_std<F, R>(f: F) -> async_std::task::JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let handle = MockHandle::current();
+    async_std::task::spawn_blocking(move || handle.run(f))
+}
+
+/// Spawn a smol task, which sees shared mocks of the current thread
+///
+/// It works like `smol::spawn` and like [spawn](fn.spawn.html) does for tokio.
+/// Requires the `smol` feature.
+#[cfg(feature = "smol")]
+pub fn spawn_smol<F>(future: F) -> smol::Task<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    smol::spawn(MockHandle::current().run_async(future))
+}
+
+/// Run a blocking function on the smol thread pool, which sees shared mocks of the current thread
+///
+/// It works like `smol::unblock` and like [spawn_blocking](fn.spawn_blocking.html) does for tokio.
+/// Requires the `smol` feature.
+#[cfg(feature = "smol")]
+pub fn unblock_smol<F, R>(f: F) -> impl Future<Output = R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let handle = MockHandle::current();
+    smol::unblock(move || handle.run(f))
+}
+
+unsafe fn erase_installer<'a>(
+    installer: Arc<dyn Fn(&mut MockLayer) + Send + Sync + 'a>,
+) -> SharedMockInstaller {
+    transmute(installer)
+}
+
+/// Scope for mocks borrowing local data, created with [mock_scope](fn.mock_scope.html)
+///
+/// Mocks are registered in it with [mock_scoped](trait.Mockable.html#tymethod.mock_scoped).
+pub struct MockScope<'env> {
+    layer_idx: usize,
+    // Invariance prevents shortening 'env to a lifetime of data, which doesn't outlive the scope
+    phantom_lifetime: PhantomData<Cell<&'env ()>>,
+}
+
+/// Run a closure with a scope for mocks borrowing local data
+///
+/// All mocks registered in the scope are active until the end of this call, then they are removed.
+/// They shadow mocks set up with [mock_safe](trait.Mockable.html#tymethod.mock_safe) and
+/// [mock_raw](trait.Mockable.html#tymethod.mock_raw) like a [MockContext](struct.MockContext.html) does.
+///
+/// ```
+/// use mocktopus::macros::mockable;
+/// use mocktopus::mocking::{mock_scope, MockResult, Mockable};
+///
+/// #[mockable]
+/// fn f() -> i32 {
+///     0
+/// }
+///
+/// let mut count = 0;
+/// mock_scope(|scope| {
+///     f.mock_scoped(scope, || { count += 1; MockResult::Return(1) });
+///     assert_eq!(f(), 1);
+/// });
+/// assert_eq!(count, 1);
+/// ```
+pub fn mock_scope<'env, R, F: FnOnce(&MockScope<'env>) -> R>(f: F) -> R {
+    let layer_idx = MOCK_STORE.with(|mock_store| unsafe {
+        mock_store.add_layer(MockLayer::default());
+        mock_store.layer_count() - 1
+    });
+    let _mock_level_guard = MockLayerGuard;
+    f(&MockScope {
+        layer_idx,
+        phantom_lifetime: PhantomData,
+    })
+}
+
+/// Makes sure that the unmocked call mark is removed even if the called function wasn't mockable
+struct UnmockedCallGuard(TypeId);
+
+impl Drop for UnmockedCallGuard {
+    fn drop(&mut self) {
+        MOCK_STORE.with(|mock_store| mock_store.remove_unmocked_call(self.0));
+    }
+}
+
+/// Takes the layer back from the store after a poll, even if it panicked
+struct PollLayerGuard<'a>(&'a mut Option<MockLayer>);
+
+impl<'a> Drop for PollLayerGuard<'a> {
+    fn drop(&mut self) {
+        *self.0 = Some(MOCK_STORE.with(|mock_store| unsafe { mock_store.remove_layer() }));
+    }
+}
+
+/// Stops capturing mocks of a running handle by new handles
+struct HandleMocksGuard;
+
+impl Drop for HandleMocksGuard {
+    fn drop(&mut self) {
+        MOCK_STORE.with(|mock_store| mock_store.pop_handle_mocks());
+    }
+}
+
+/// Runs context exit hooks in reverse order of registration
+struct ExitHooksGuard<'a>(Vec<ContextHook<'a>>);
+
+impl<'a> Drop for ExitHooksGuard<'a> {
+    fn drop(&mut self) {
+        while let Some(hook) = self.0.pop() {
+            hook();
+        }
+    }
 }
 
 struct MockLayerGuard;
 
 impl<'a> Drop for MockLayerGuard {
     fn drop(&mut self) {
-        MOCK_STORE.with(|mock_store| unsafe { mock_store.remove_layer() });
+        let mut layer = MOCK_STORE.with(|mock_store| unsafe { mock_store.remove_layer() });
+        // Panicking again while unwinding would abort and hide the original panic
+        if !std::thread::panicking() {
+            MOCK_STORE.with(|mock_store| mock_store.verify_layer_expectations(&mut layer));
+        }
     }
 }
@@ -1,3 +1,7 @@
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::rc::Rc;
+
 /// Converts non-mutable reference to a mutable one
 ///
 /// Allows creating multiple mutable references to a single item breaking Rust's safety policy.
@@ -26,3 +30,110 @@
 pub unsafe fn as_mut<T>(t_ref: &T) -> &mut T {
     &mut *(t_ref as *const T as *mut T)
 }
+
+/// Captures arguments passed to mock closures for later inspection
+///
+/// All clones of a captor share captured arguments, so one can be moved into a mock closure
+/// while the other is used for assertions.
+///
+/// ```
+/// #[mockable]
+/// fn send(address: &str, message: String) {
+///     unimplemented!()
+/// }
+///
+/// #[test]
+/// fn send_test() {
+///     let captor = ArgCaptor::new();
+///     let mock_captor = captor.clone();
+///     send.mock_safe(move |address, message| {
+///         mock_captor.capture((address.to_string(), message));
+///         MockResult::Return(())
+///     });
+///
+///     send("alice", "hello".to_string());
+///     send("bob", "bye".to_string());
+///
+///     assert_eq!(2, captor.len());
+///     captor.assert_called_with(&("bob".to_string(), "bye".to_string()));
+/// }
+/// ```
+pub struct ArgCaptor<T> {
+    args: Rc<RefCell<Vec<T>>>,
+}
+
+impl<T> ArgCaptor<T> {
+    /// Create a new captor with no captured arguments
+    pub fn new() -> Self {
+        ArgCaptor {
+            args: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    /// Store arguments of a single call
+    pub fn capture(&self, args: T) {
+        self.args.borrow_mut().push(args)
+    }
+
+    /// Number of captured calls
+    pub fn len(&self) -> usize {
+        self.args.borrow().len()
+    }
+
+    /// Check if no calls were captured
+    pub fn is_empty(&self) -> bool {
+        self.args.borrow().is_empty()
+    }
+
+    /// Remove all captured arguments
+    pub fn clear(&self) {
+        self.args.borrow_mut().clear()
+    }
+}
+
+impl<T: Clone> ArgCaptor<T> {
+    /// Arguments of all captured calls, from the oldest one
+    pub fn all(&self) -> Vec<T> {
+        self.args.borrow().clone()
+    }
+
+    /// Arguments of the most recent captured call
+    pub fn last(&self) -> Option<T> {
+        self.args.borrow().last().cloned()
+    }
+}
+
+impl<T: PartialEq + Debug> ArgCaptor<T> {
+    /// Panics if arguments of the most recent captured call are not equal to expected ones
+    pub fn assert_called_with(&self, expected: &T) {
+        match self.args.borrow().last() {
+            Some(last) => assert_eq!(expected, last, "Captured arguments differ"),
+            None => panic!("No calls captured, expected call with {:?}", expected),
+        }
+    }
+
+    /// Panics if none of captured calls had arguments equal to expected ones
+    pub fn assert_any_called_with(&self, expected: &T) {
+        let args = self.args.borrow();
+        assert!(
+            args.contains(expected),
+            "No captured call with {:?}, captured calls: {:?}",
+            expected,
+            *args
+        );
+    }
+}
+
+impl<T> Clone for ArgCaptor<T> {
+    fn clone(&self) -> Self {
+        ArgCaptor {
+            args: self.args.clone(),
+        }
+    }
+}
+
+impl<T> Default for ArgCaptor<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,44 @@
+#![cfg(feature = "disable-mocking")]
+
+use mocktopus::macros::*;
+use mocktopus::mocking::*;
+
+#[mockable]
+const fn const_function() -> u32 {
+    1
+}
+
+// Const fns stay const only when they aren't made mockable
+const CONST: u32 = const_function();
+
+#[mockable]
+fn function() -> &'static str {
+    "not mocked"
+}
+
+mockable_items! {
+    fn item_function() -> &'static str {
+        "not mocked"
+    }
+}
+
+#[test]
+fn when_not_mocked_then_runs_normally() {
+    assert_eq!(1, CONST);
+    assert_eq!("not mocked", function());
+    assert_eq!("not mocked", item_function());
+}
+
+#[test]
+#[should_panic(expected = "`disable-mocking` feature")]
+fn when_mocked_then_panics() {
+    function.mock_safe(|| MockResult::Return("mocked"));
+}
+
+#[test]
+#[should_panic(expected = "`disable-mocking` feature")]
+fn when_mocked_in_context_then_panics() {
+    MockContext::new()
+        .mock_safe(item_function, || MockResult::Return("mocked"))
+        .run(item_function);
+}
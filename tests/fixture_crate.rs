@@ -0,0 +1,73 @@
+// Compiler output for crates using mocktopus, e.g. warnings, can't be checked inside of them,
+// so these tests run cargo on a fixture crate
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn fixture_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/mockable_crate")
+}
+
+/// Runs cargo on the fixture crate, panics if cargo fails
+fn run_cargo(args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO"))
+        .args(args)
+        .arg("--manifest-path")
+        .arg(fixture_dir().join("Cargo.toml"))
+        .env(
+            "CARGO_TARGET_DIR",
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("target/fixtures"),
+        )
+        .output()
+        .expect("Failed to run cargo");
+    assert!(
+        output.status.success(),
+        "cargo {:?} failed:\n{}{}",
+        args,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+/// Returns the 1-based line and column of the first occurrence of `pattern` after `after`
+fn fixture_location(after: &str, pattern: &str) -> (usize, usize) {
+    let source = std::fs::read_to_string(fixture_dir().join("src/lib.rs")).unwrap();
+    let start = source.find(after).expect("Fixture code missing");
+    let offset = start + source[start..].find(pattern).expect("Fixture code missing");
+    let line_start = source[..offset].rfind('\n').map_or(0, |idx| idx + 1);
+    (source[..offset].lines().count(), offset - line_start + 1)
+}
+
+#[test]
+fn when_trait_impl_fn_takes_impl_trait_then_warns_at_argument() {
+    let (line, column) = fixture_location("impl Printer for Console", "value: impl Display");
+
+    let output = run_cargo(&["build"]);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let warning = "warning: Trait functions taking `impl Trait` arguments can't be made mockable";
+    let location = format!("src/lib.rs:{}:{}", line, column);
+    let warning_idx = stderr.find(warning).expect(&stderr);
+    assert!(stderr[warning_idx..].contains(&location), "{}", stderr);
+}
+
+#[test]
+fn when_async_fn_documented_then_signature_is_unchanged() {
+    run_cargo(&["doc", "--no-deps"]);
+
+    let doc_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("target/fixtures/doc/mockable_crate/fn.fetch.html");
+    let doc = std::fs::read_to_string(doc_path).unwrap();
+    assert!(doc.contains("async fn"), "{}", doc);
+    assert!(!doc.contains("Pin"), "{}", doc);
+}
+
+#[test]
+fn when_doctests_use_doctest_run_then_they_run_mocks() {
+    let output = run_cargo(&["test", "--doc"]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("test result: ok. 2 passed"), "{}", stdout);
+}
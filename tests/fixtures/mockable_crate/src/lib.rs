@@ -0,0 +1,59 @@
+//! Crate built by `tests/fixture_crate.rs` to check compiler output for items using mocktopus
+
+use mocktopus::macros::*;
+use std::fmt::Display;
+use std::sync::LazyLock;
+
+pub trait Printer {
+    fn print(&self, value: impl Display) -> String;
+}
+
+pub struct Console;
+
+#[mockable]
+impl Printer for Console {
+    fn print(&self, value: impl Display) -> String {
+        value.to_string()
+    }
+}
+
+/// Fetches the resource
+#[mockable]
+pub async fn fetch(url: &str) -> String {
+    url.to_string()
+}
+
+#[mockable]
+pub static GREETING: LazyLock<String> = LazyLock::new(|| "Hello".to_string());
+
+/// Builds only if the static keeps its declared type outside of tests
+pub fn greeting() -> &'static LazyLock<String> {
+    &GREETING
+}
+
+/// Greets the person
+///
+/// ```
+/// use mockable_crate::greet;
+/// use mocktopus::mocking::*;
+///
+/// mocktopus::doctest::run(|| {
+///     greet.mock_safe(|_| MockResult::Return("Hi".to_string()));
+///     greet.expect_calls(1);
+///
+///     assert_eq!("Hi", greet("Alice"));
+/// });
+/// ```
+///
+/// ```should_panic
+/// use mockable_crate::greet;
+/// use mocktopus::mocking::*;
+///
+/// mocktopus::doctest::run(|| {
+///     greet.expect_calls(1);
+/// });
+/// ```
+#[mockable]
+pub fn greet(name: &str) -> String {
+    format!("Hello, {}", name)
+}
@@ -0,0 +1,168 @@
+#![cfg(all(feature = "global-mocks", not(feature = "disable-mocking")))]
+
+// Global mocks are visible to all tests of the binary, so every test mocks its own functions
+// and tests clearing all of them run alone
+
+use mocktopus::macros::*;
+use mocktopus::mocking::*;
+use std::sync::{PoisonError, RwLock, RwLockReadGuard};
+use std::thread;
+
+static CLEAR_ALL_LOCK: RwLock<()> = RwLock::new(());
+
+fn keep_global_mocks() -> RwLockReadGuard<'static, ()> {
+    CLEAR_ALL_LOCK
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+mod when_mocked_shared {
+    use super::*;
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[test]
+    fn then_spawned_thread_sees_mock_without_handle() {
+        let _lock = keep_global_mocks();
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        let result = thread::spawn(|| add(3, 4)).join().unwrap();
+
+        assert_eq!(12, result);
+        assert_eq!(12, add(3, 4));
+    }
+}
+
+mod when_mocked_shared_again {
+    use super::*;
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[test]
+    fn then_threads_see_new_mock() {
+        let _lock = keep_global_mocks();
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+        let first = thread::spawn(|| add(3, 4)).join().unwrap();
+
+        add.mock_shared(|a, b| MockResult::Return(a - b));
+        let second = thread::spawn(|| add(4, 3)).join().unwrap();
+
+        assert_eq!(12, first);
+        assert_eq!(1, second);
+    }
+}
+
+mod when_mocked_in_thread {
+    use super::*;
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[test]
+    fn then_thread_mock_shadows_global_mock() {
+        let _lock = keep_global_mocks();
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        let result = thread::spawn(|| {
+            add.mock_safe(|a, b| MockResult::Return(a - b));
+            add(4, 3)
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(1, result);
+        assert_eq!(12, add(4, 3));
+    }
+
+    #[mockable]
+    fn sub(a: u32, b: u32) -> u32 {
+        a - b
+    }
+
+    #[test]
+    fn then_passed_calls_reach_global_mock() {
+        let _lock = keep_global_mocks();
+        sub.mock_shared(|a, b| MockResult::Return(a * b));
+
+        let result = thread::spawn(|| {
+            let mock_context = MockContext::new().mock_safe(sub, |a, b| MockResult::Pass((a, b)));
+            mock_context.run(|| sub(4, 3))
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(12, result);
+    }
+}
+
+mod when_cleared {
+    use super::*;
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[test]
+    fn then_mock_is_removed_from_all_threads() {
+        let _lock = keep_global_mocks();
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+        assert_eq!(12, thread::spawn(|| add(3, 4)).join().unwrap());
+
+        thread::spawn(|| add.clear_mock()).join().unwrap();
+
+        assert_eq!(7, thread::spawn(|| add(3, 4)).join().unwrap());
+        assert!(!thread::spawn(|| add.is_mocked()).join().unwrap());
+    }
+
+    #[mockable]
+    fn sub(a: u32, b: u32) -> u32 {
+        a - b
+    }
+
+    #[test]
+    fn then_all_mocks_are_removed_from_all_threads() {
+        let _lock = CLEAR_ALL_LOCK
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        sub.mock_shared(|a, b| MockResult::Return(a * b));
+        assert_eq!(12, thread::spawn(|| sub(4, 3)).join().unwrap());
+
+        thread::spawn(clear_all_mocks).join().unwrap();
+
+        assert_eq!(1, thread::spawn(|| sub(4, 3)).join().unwrap());
+        assert!(!thread::spawn(|| sub.is_mocked()).join().unwrap());
+    }
+}
+
+mod when_strict {
+    use super::*;
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[test]
+    fn then_global_mock_counts_as_mock() {
+        let _lock = keep_global_mocks();
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        let result = thread::spawn(|| {
+            set_strict_mode(true);
+            add(3, 4)
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(12, result);
+    }
+}
@@ -1,4 +1,5 @@
-#![feature(proc_macro_hygiene)]
+#![cfg(not(feature = "disable-mocking"))]
+#![feature(custom_inner_attributes, proc_macro_hygiene)]
 
 // Test if injecting works even if mocktopus is aliased
 extern crate mocktopus as mocktopus_aliased;
@@ -82,6 +83,12 @@ mod injector_injects_annotated_traits {
 #[mockable]
 mod module_annotated_declaration;
 
+#[mockable]
+#[path = "module_path_declaration/renamed.rs"]
+mod module_path_declaration;
+
+mod module_inner_annotated;
+
 mod injector_injects_annotated_items {
     use super::*;
 
@@ -192,7 +199,7 @@ mod injector_injects_annotated_items {
         }
     }
 
-    mod does_not_inject_mod_with_annotated_declaration {
+    mod injects_mod_with_annotated_declaration {
         use super::*;
 
         #[test]
@@ -201,11 +208,360 @@ mod injector_injects_annotated_items {
         }
 
         #[test]
-        fn when_mocked_then_runs_normally() {
+        fn when_mocked_then_runs_mock() {
             module_annotated_declaration::function.mock_safe(|| MockResult::Return("mocked"));
 
-            assert_eq!("not mocked", module_annotated_declaration::function());
+            assert_eq!("mocked", module_annotated_declaration::function());
+        }
+    }
+
+    mod injects_mod_with_annotated_declaration_with_path {
+        use super::*;
+
+        #[test]
+        fn when_not_mocked_then_runs_normally() {
+            assert_eq!("not mocked", module_path_declaration::function());
+            assert_eq!("not mocked", module_path_declaration::nested::function());
+            assert_eq!("not mocked", module_path_declaration::included_function());
+        }
+
+        #[test]
+        fn when_mocked_then_runs_mock() {
+            module_path_declaration::function.mock_safe(|| MockResult::Return("mocked"));
+            module_path_declaration::nested::function.mock_safe(|| MockResult::Return("mocked"));
+            module_path_declaration::included_function.mock_safe(|| MockResult::Return("mocked"));
+
+            assert_eq!("mocked", module_path_declaration::function());
+            assert_eq!("mocked", module_path_declaration::nested::function());
+            assert_eq!("mocked", module_path_declaration::included_function());
+        }
+    }
+}
+
+mod injector_injects_modules_with_inner_attribute {
+    use super::*;
+
+    mod module {
+        #![mockable]
+
+        pub fn function() -> &'static str {
+            "not mocked"
+        }
+    }
+
+    #[test]
+    fn when_not_mocked_then_runs_normally() {
+        assert_eq!("not mocked", module::function());
+        assert_eq!("not mocked", module_inner_annotated::function());
+    }
+
+    #[test]
+    fn when_mocked_then_runs_mock() {
+        module::function.mock_safe(|| MockResult::Return("mocked"));
+        module_inner_annotated::function.mock_safe(|| MockResult::Return("mocked"));
+
+        assert_eq!("mocked", module::function());
+        assert_eq!("mocked", module_inner_annotated::function());
+    }
+}
+
+mod injector_injects_test_only_items_in_tests {
+    use super::*;
+
+    #[mockable(test_only)]
+    fn function() -> &'static str {
+        "not mocked"
+    }
+
+    #[mockable(test_only, box_impl_trait)]
+    fn numbers() -> impl Iterator<Item = u32> {
+        0..3
+    }
+
+    #[mocktopus_aliased::macros::mockable(test_only)]
+    fn renamed_function() -> &'static str {
+        "not mocked"
+    }
+
+    #[test]
+    fn when_not_mocked_then_runs_normally() {
+        assert_eq!("not mocked", function());
+        assert_eq!(vec![0, 1, 2], numbers().collect::<Vec<_>>());
+        assert_eq!("not mocked", renamed_function());
+    }
+
+    #[test]
+    fn when_mocked_then_runs_mock() {
+        function.mock_safe(|| MockResult::Return("mocked"));
+        numbers.mock_safe(|| MockResult::return_iter(vec![7]));
+        renamed_function.mock_safe(|| MockResult::Return("mocked"));
+
+        assert_eq!("mocked", function());
+        assert_eq!(vec![7], numbers().collect::<Vec<_>>());
+        assert_eq!("mocked", renamed_function());
+    }
+}
+
+mod injector_injects_selected_module_items {
+    use super::*;
+
+    #[mockable(only = [selected, Struct, nested])]
+    mod only {
+        pub fn selected() -> &'static str {
+            "not mocked"
+        }
+
+        pub fn not_selected() -> &'static str {
+            "not mocked"
+        }
+
+        pub struct Struct;
+
+        impl Struct {
+            pub fn selected() -> &'static str {
+                "not mocked"
+            }
+        }
+
+        pub mod nested {
+            pub fn selected() -> &'static str {
+                "not mocked"
+            }
+
+            pub fn not_listed() -> &'static str {
+                "not mocked"
+            }
+        }
+    }
+
+    #[mockable(recurse = false)]
+    mod not_recursive {
+        pub fn selected() -> &'static str {
+            "not mocked"
+        }
+
+        pub mod nested {
+            pub fn not_selected() -> &'static str {
+                "not mocked"
+            }
+        }
+    }
+
+    #[test]
+    fn when_mocked_then_selected_items_run_mock() {
+        only::selected.mock_safe(|| MockResult::Return("mocked"));
+        only::Struct::selected.mock_safe(|| MockResult::Return("mocked"));
+        only::nested::selected.mock_safe(|| MockResult::Return("mocked"));
+        only::nested::not_listed.mock_safe(|| MockResult::Return("mocked"));
+        not_recursive::selected.mock_safe(|| MockResult::Return("mocked"));
+
+        assert_eq!("mocked", only::selected());
+        assert_eq!("mocked", only::Struct::selected());
+        assert_eq!("mocked", only::nested::selected());
+        assert_eq!("mocked", only::nested::not_listed());
+        assert_eq!("mocked", not_recursive::selected());
+    }
+
+    #[test]
+    fn when_mocked_then_not_selected_items_run_normally() {
+        only::not_selected.mock_safe(|| MockResult::Return("mocked"));
+        not_recursive::nested::not_selected.mock_safe(|| MockResult::Return("mocked"));
+
+        assert_eq!("not mocked", only::not_selected());
+        assert_eq!("not mocked", not_recursive::nested::not_selected());
+    }
+}
+
+mod injector_skips_unsupported_items_of_modules {
+    use super::*;
+
+    #[mockable]
+    mod module {
+        pub fn destructured((first, second): (u32, u32)) -> u32 {
+            first + second
+        }
+
+        pub fn supported() -> &'static str {
+            "not mocked"
+        }
+    }
+
+    #[test]
+    fn when_mocked_then_supported_items_run_mock() {
+        module::destructured.mock_safe(|_| MockResult::Return(0));
+        module::supported.mock_safe(|| MockResult::Return("mocked"));
+
+        assert_eq!(3, module::destructured((1, 2)));
+        assert_eq!("mocked", module::supported());
+    }
+}
+
+// The audit's warnings are emitted when compiling the tests
+mod injector_audits_unmockable_public_fns {
+    use super::*;
+
+    macro_rules! generate_fn {
+        () => {
+            pub fn generated() -> u32 {
+                1
+            }
+        };
+    }
+
+    #[mockable(audit)]
+    mod module {
+        use super::*;
+
+        pub fn mockable() -> u32 {
+            1
+        }
+
+        pub const fn const_fn() -> u32 {
+            1
+        }
+
+        #[not_mockable]
+        pub fn not_mockable() -> u32 {
+            1
         }
+
+        pub fn destructured((first, _): (u32, u32)) -> u32 {
+            first
+        }
+
+        generate_fn!();
+
+        pub struct Struct;
+
+        #[not_mockable]
+        impl Struct {
+            pub fn method() -> u32 {
+                1
+            }
+        }
+
+        impl Drop for Struct {
+            fn drop(&mut self) {}
+        }
+    }
+
+    #[test]
+    fn when_audited_then_mocks_mockable_fns() {
+        module::mockable.mock_safe(|| MockResult::Return(2));
+        module::const_fn.mock_safe(|| MockResult::Return(2));
+
+        assert_eq!(2, module::mockable());
+        assert_eq!(1, module::const_fn());
+        assert_eq!(1, module::not_mockable());
+        assert_eq!(1, module::destructured((1, 2)));
+        assert_eq!(1, module::generated());
+        assert_eq!(1, module::Struct::method());
+    }
+}
+
+mod injector_keeps_cfgs_of_module_items {
+    use super::*;
+
+    #[mockable]
+    mod module {
+        use super::*;
+
+        #[cfg(not(test))]
+        pub fn function() -> Missing {
+            missing()
+        }
+
+        #[cfg(test)]
+        pub fn function() -> &'static str {
+            "not mocked"
+        }
+
+        #[cfg(not(test))]
+        pub mod nested {
+            pub fn function() -> Missing {
+                missing()
+            }
+        }
+
+        pub struct Struct;
+
+        impl Struct {
+            #[cfg(not(test))]
+            pub async fn method(&self) -> Missing {
+                missing()
+            }
+
+            #[cfg(test)]
+            pub fn method(&self) -> &'static str {
+                "not mocked"
+            }
+        }
+
+        #[mockable(mock_struct)]
+        pub trait Trait {
+            #[cfg(not(test))]
+            fn missing(&self) -> Missing;
+
+            fn function(&self) -> &'static str;
+        }
+
+        #[mockable(mock_struct)]
+        #[cfg(not(test))]
+        pub trait MissingTrait {
+            fn missing(&self) -> Missing;
+        }
+    }
+
+    #[test]
+    fn when_mocked_then_enabled_items_run_mock() {
+        module::function.mock_safe(|| MockResult::Return("mocked"));
+        module::Struct::method.mock_safe(|_| MockResult::Return("mocked"));
+        <module::MockTrait as module::Trait>::function.mock_safe(|_| MockResult::Return("mocked"));
+
+        assert_eq!("mocked", module::function());
+        assert_eq!("mocked", module::Struct.method());
+        assert_eq!("mocked", module::Trait::function(&module::MockTrait));
+    }
+}
+
+mod injector_keeps_attrs_of_fns {
+    #![deny(deprecated, unused_must_use)]
+
+    use super::*;
+
+    #[mockable]
+    #[deprecated]
+    #[must_use]
+    #[inline]
+    #[allow(unused_variables)]
+    async fn async_function(unused: u32) -> &'static str {
+        "not mocked"
+    }
+
+    #[mockable]
+    #[deprecated]
+    #[must_use]
+    #[cold]
+    #[allow(unused_variables)]
+    fn function(unused: u32) -> &'static str {
+        "not mocked"
+    }
+
+    #[tokio::test]
+    #[allow(deprecated)]
+    async fn when_not_mocked_then_runs_normally() {
+        assert_eq!("not mocked", async_function(0).await);
+        assert_eq!("not mocked", function(0));
+    }
+
+    #[tokio::test]
+    #[allow(deprecated)]
+    async fn when_mocked_then_runs_mock() {
+        async_function.mock_safe(|_| MockResult::Return(Box::pin(async { "mocked" })));
+        function.mock_safe(|_| MockResult::Return("mocked"));
+
+        assert_eq!("mocked", async_function(0).await);
+        assert_eq!("mocked", function(0));
     }
 }
 
@@ -434,6 +790,71 @@ mod injector_does_not_inject_items_twice {
             assert_eq!(4, mocked_mod::mocked_mod::mocked_fn(1));
         }
     }
+
+    mod injects_double_annotated_fn_with_renamed_attr_once {
+        use super::*;
+        use crate::mocktopus_aliased::macros::mockable as mockable_alias;
+
+        #[mockable]
+        #[mockable_alias]
+        pub fn mocked_fn(x: u32) -> u32 {
+            x * 2
+        }
+
+        #[test]
+        fn when_not_mocked_then_runs_normally() {
+            assert_eq!(2, mocked_fn(1));
+        }
+
+        #[test]
+        fn when_mocked_then_runs_mock_once() {
+            unsafe {
+                mocked_fn.mock_raw(|x| MockResult::Continue((x + 1,)));
+            }
+
+            assert_eq!(4, mocked_fn(1));
+        }
+    }
+
+    mod injects_double_annotated_impl_block_with_renamed_attr_once {
+        use super::*;
+        use crate::mocktopus_aliased::macros::mockable as mockable_alias;
+
+        struct MockedStruct;
+
+        #[mockable]
+        #[mockable_alias]
+        impl MockedStruct {
+            pub fn mocked_fn(x: u32) -> u32 {
+                x * 2
+            }
+        }
+
+        #[test]
+        fn when_mocked_then_runs_mock_once() {
+            unsafe { MockedStruct::mocked_fn.mock_raw(|x| MockResult::Continue((x + 1,))) }
+
+            assert_eq!(4, MockedStruct::mocked_fn(1));
+        }
+    }
+
+    mod injects_double_annotated_async_fn_with_renamed_attr_once {
+        use super::*;
+        use crate::mocktopus_aliased::macros::mockable as mockable_alias;
+
+        #[mockable]
+        #[mockable_alias]
+        pub async fn mocked_fn(x: u32) -> u32 {
+            x * 2
+        }
+
+        #[tokio::test]
+        async fn when_mocked_then_runs_mock_once() {
+            mocked_fn.mock_ready(|x| MockResult::Continue((x + 1,)));
+
+            assert_eq!(4, mocked_fn(1).await);
+        }
+    }
 }
 
 mod injector_does_not_inject_not_mockable_items {
@@ -559,20 +980,298 @@ mod injector_does_not_inject_not_mockable_items {
             assert_eq!("not mocked", mocked_mod::mocked_mod::not_mocked_fn());
         }
     }
-}
-
-mod injector_ignores_const_fns {
-    use super::*;
 
-    #[mockable]
-    pub const fn const_fn() -> u32 {
-        1
-    }
+    mod does_not_inject_not_mockable_fn_in_impl_block {
+        use super::*;
 
-    #[test]
-    fn when_not_mocked_then_returns_1() {
-        assert_eq!(1, const_fn());
-    }
+        struct MockedStruct;
+
+        #[mockable]
+        impl MockedStruct {
+            pub fn mocked_fn() -> &'static str {
+                "not mocked"
+            }
+
+            #[not_mockable]
+            pub fn not_mocked_fn() -> &'static str {
+                "not mocked"
+            }
+
+            // Mockable async fns must return Send futures, holding Rc across await would not compile
+            #[not_mockable]
+            pub async fn not_mocked_async_fn(&self) -> u32 {
+                let value = std::rc::Rc::new(1);
+                async {}.await;
+                *value
+            }
+        }
+
+        #[test]
+        fn when_mocked_then_runs_mock_only_for_mockable_fn() {
+            unsafe {
+                MockedStruct::mocked_fn.mock_raw(|| MockResult::Return("mocked"));
+                MockedStruct::not_mocked_fn.mock_raw(|| MockResult::Return("mocked"));
+            }
+
+            assert_eq!("mocked", MockedStruct::mocked_fn());
+            assert_eq!("not mocked", MockedStruct::not_mocked_fn());
+        }
+
+        #[tokio::test]
+        async fn when_async_fn_not_mockable_then_runs_normally() {
+            assert_eq!(1, MockedStruct.not_mocked_async_fn().await);
+        }
+    }
+
+    mod does_not_inject_not_mockable_fn_in_trait_impl_block {
+        use super::*;
+
+        trait Trait {
+            fn mocked_fn() -> &'static str;
+
+            fn not_mocked_fn() -> &'static str;
+        }
+
+        struct MockedStruct;
+
+        #[mockable]
+        impl Trait for MockedStruct {
+            fn mocked_fn() -> &'static str {
+                "not mocked"
+            }
+
+            #[not_mockable]
+            fn not_mocked_fn() -> &'static str {
+                "not mocked"
+            }
+        }
+
+        #[test]
+        fn when_mocked_then_runs_mock_only_for_mockable_fn() {
+            unsafe {
+                MockedStruct::mocked_fn.mock_raw(|| MockResult::Return("mocked"));
+                MockedStruct::not_mocked_fn.mock_raw(|| MockResult::Return("mocked"));
+            }
+
+            assert_eq!("mocked", MockedStruct::mocked_fn());
+            assert_eq!("not mocked", MockedStruct::not_mocked_fn());
+        }
+    }
+
+    mod does_not_inject_not_mockable_default_fn_in_trait {
+        use super::*;
+
+        #[mockable]
+        trait Trait {
+            fn mocked_fn() -> &'static str {
+                "not mocked"
+            }
+
+            #[not_mockable]
+            fn not_mocked_fn() -> &'static str {
+                "not mocked"
+            }
+        }
+
+        struct Struct;
+
+        impl Trait for Struct {}
+
+        #[test]
+        fn when_mocked_then_runs_mock_only_for_mockable_fn() {
+            unsafe {
+                Struct::mocked_fn.mock_raw(|| MockResult::Return("mocked"));
+                Struct::not_mocked_fn.mock_raw(|| MockResult::Return("mocked"));
+            }
+
+            assert_eq!("mocked", Struct::mocked_fn());
+            assert_eq!("not mocked", Struct::not_mocked_fn());
+        }
+    }
+}
+
+mod injector_applies_cfg_attr_wrapped_annotations {
+    use super::*;
+    use async_trait::async_trait;
+
+    #[mockable]
+    mod mocked_mod {
+        use super::*;
+
+        pub fn mocked_fn() -> &'static str {
+            "not mocked"
+        }
+
+        #[cfg_attr(test, not_mockable)]
+        pub fn not_mocked_fn() -> &'static str {
+            "not mocked"
+        }
+
+        #[cfg_attr(not(test), not_mockable)]
+        pub fn mocked_fn_not_mockable_outside_tests() -> &'static str {
+            "not mocked"
+        }
+
+        #[cfg_attr(test, cfg_attr(all(), not_mockable))]
+        pub fn not_mocked_fn_with_nested_cfg_attr() -> &'static str {
+            "not mocked"
+        }
+
+        #[cfg_attr(test, inline, not_mockable)]
+        pub fn not_mocked_fn_with_multiple_attrs() -> &'static str {
+            "not mocked"
+        }
+
+        #[cfg_attr(test, mockable)]
+        pub fn mocked_fn_annotated_again() -> &'static str {
+            "not mocked"
+        }
+
+        #[cfg_attr(test, not_mockable)]
+        pub mod not_mocked_mod {
+            pub fn not_mocked_fn() -> &'static str {
+                "not mocked"
+            }
+        }
+    }
+
+    struct Struct;
+
+    #[mockable]
+    impl Struct {
+        fn mocked_fn() -> &'static str {
+            "not mocked"
+        }
+
+        #[cfg_attr(test, not_mockable)]
+        fn not_mocked_fn() -> &'static str {
+            "not mocked"
+        }
+    }
+
+    #[mockable]
+    trait Trait {
+        fn mocked_fn() -> &'static str {
+            "not mocked"
+        }
+
+        #[cfg_attr(test, not_mockable)]
+        fn not_mocked_fn() -> &'static str {
+            "not mocked"
+        }
+    }
+
+    impl Trait for Struct {}
+
+    #[async_trait]
+    trait AsyncTrait {
+        async fn get(&self) -> &'static str;
+    }
+
+    struct MockableBeforeAsyncTrait;
+
+    #[cfg_attr(test, mockable)]
+    #[cfg_attr(test, async_trait)]
+    impl AsyncTrait for MockableBeforeAsyncTrait {
+        async fn get(&self) -> &'static str {
+            "not mocked"
+        }
+    }
+
+    struct MockableAfterAsyncTrait;
+
+    #[cfg_attr(test, async_trait)]
+    #[cfg_attr(test, mockable)]
+    impl AsyncTrait for MockableAfterAsyncTrait {
+        async fn get(&self) -> &'static str {
+            "not mocked"
+        }
+    }
+
+    #[test]
+    fn when_mocked_then_runs_mock_only_for_mockable_fns_of_mod() {
+        unsafe {
+            mocked_mod::mocked_fn.mock_raw(|| MockResult::Return("mocked"));
+            mocked_mod::not_mocked_fn.mock_raw(|| MockResult::Return("mocked"));
+            mocked_mod::mocked_fn_not_mockable_outside_tests
+                .mock_raw(|| MockResult::Return("mocked"));
+            mocked_mod::not_mocked_fn_with_nested_cfg_attr
+                .mock_raw(|| MockResult::Return("mocked"));
+            mocked_mod::not_mocked_fn_with_multiple_attrs.mock_raw(|| MockResult::Return("mocked"));
+            mocked_mod::mocked_fn_annotated_again.mock_raw(|| MockResult::Return("mocked"));
+            mocked_mod::not_mocked_mod::not_mocked_fn.mock_raw(|| MockResult::Return("mocked"));
+        }
+
+        assert_eq!("mocked", mocked_mod::mocked_fn());
+        assert_eq!("not mocked", mocked_mod::not_mocked_fn());
+        assert_eq!("mocked", mocked_mod::mocked_fn_not_mockable_outside_tests());
+        assert_eq!(
+            "not mocked",
+            mocked_mod::not_mocked_fn_with_nested_cfg_attr()
+        );
+        assert_eq!(
+            "not mocked",
+            mocked_mod::not_mocked_fn_with_multiple_attrs()
+        );
+        assert_eq!("mocked", mocked_mod::mocked_fn_annotated_again());
+        assert_eq!("not mocked", mocked_mod::not_mocked_mod::not_mocked_fn());
+    }
+
+    #[test]
+    fn when_mocked_then_runs_mock_only_for_mockable_fns_of_impl_and_trait() {
+        unsafe {
+            <Struct>::mocked_fn.mock_raw(|| MockResult::Return("mocked"));
+            <Struct>::not_mocked_fn.mock_raw(|| MockResult::Return("mocked"));
+            <Struct as Trait>::mocked_fn.mock_raw(|| MockResult::Return("mocked"));
+            <Struct as Trait>::not_mocked_fn.mock_raw(|| MockResult::Return("mocked"));
+        }
+
+        assert_eq!("mocked", <Struct>::mocked_fn());
+        assert_eq!("not mocked", <Struct>::not_mocked_fn());
+        assert_eq!("mocked", <Struct as Trait>::mocked_fn());
+        assert_eq!("not mocked", <Struct as Trait>::not_mocked_fn());
+    }
+
+    struct RenamedMockableBeforeAsyncTrait;
+
+    #[mocktopus_aliased::macros::mockable]
+    #[async_trait]
+    impl AsyncTrait for RenamedMockableBeforeAsyncTrait {
+        async fn get(&self) -> &'static str {
+            "not mocked"
+        }
+    }
+
+    #[tokio::test]
+    async fn when_async_trait_impl_mocked_then_runs_mock_regardless_of_attrs_order() {
+        MockableBeforeAsyncTrait::get
+            .mock_safe(|_| MockResult::Return(Box::pin(async { "mocked" })));
+        MockableAfterAsyncTrait::get
+            .mock_safe(|_| MockResult::Return(Box::pin(async { "mocked" })));
+        RenamedMockableBeforeAsyncTrait::get
+            .mock_safe(|_| MockResult::Return(Box::pin(async { "mocked" })));
+
+        assert_eq!("mocked", MockableBeforeAsyncTrait.get().await);
+        assert_eq!("mocked", MockableAfterAsyncTrait.get().await);
+        assert_eq!("mocked", RenamedMockableBeforeAsyncTrait.get().await);
+    }
+}
+
+mod injector_ignores_const_fns {
+    use super::*;
+
+    #[mockable]
+    pub const fn const_fn() -> u32 {
+        1
+    }
+
+    const VALUE: u32 = const_fn();
+
+    #[test]
+    fn when_not_mocked_then_returns_1() {
+        assert_eq!(1, const_fn());
+        assert_eq!(1, VALUE);
+    }
 
     #[test]
     fn when_mocked_then_returns_1() {
@@ -584,7 +1283,240 @@ mod injector_ignores_const_fns {
     }
 }
 
-mod injector_ignores_unsafe_fns {
+mod injector_injects_const_fns_in_test_builds {
+    use super::*;
+
+    #[mockable(const_fn)]
+    pub const fn const_fn() -> u32 {
+        1
+    }
+
+    #[test]
+    fn when_not_mocked_then_returns_1() {
+        assert_eq!(1, const_fn());
+    }
+
+    #[test]
+    fn when_mocked_then_returns_mock() {
+        unsafe {
+            const_fn.mock_raw(|| MockResult::Return(2));
+        }
+
+        assert_eq!(2, const_fn());
+    }
+}
+
+mod injector_injects_const_impl_fns_in_test_builds {
+    use super::*;
+
+    struct Struct;
+
+    #[mockable(const_fn)]
+    impl Struct {
+        const fn const_fn() -> u32 {
+            1
+        }
+    }
+
+    #[test]
+    fn when_not_mocked_then_returns_1() {
+        assert_eq!(1, Struct::const_fn());
+    }
+
+    #[test]
+    fn when_mocked_then_returns_mock() {
+        unsafe {
+            Struct::const_fn.mock_raw(|| MockResult::Return(2));
+        }
+
+        assert_eq!(2, Struct::const_fn());
+    }
+}
+
+mod injector_injects_const_fns_in_mods_in_test_builds {
+    use super::*;
+
+    #[mockable(const_fn)]
+    mod mocked_mod {
+        pub const fn const_fn() -> u32 {
+            1
+        }
+    }
+
+    #[test]
+    fn when_mocked_then_returns_mock() {
+        unsafe {
+            mocked_mod::const_fn.mock_raw(|| MockResult::Return(2));
+        }
+
+        assert_eq!(2, mocked_mod::const_fn());
+    }
+}
+
+mod injector_generates_mock_structs_for_traits {
+    use super::*;
+
+    #[mockable(mock_struct)]
+    pub trait Greeter {
+        fn greet(&self, name: &str) -> String;
+
+        fn greet_twice(&self, name: &str) -> String {
+            format!("{} {}", self.greet(name), self.greet(name))
+        }
+
+        fn create() -> Self
+        where
+            Self: Sized;
+    }
+
+    #[mockable(mock_struct)]
+    trait Converter<T> {
+        fn convert<U: Into<T>>(&self, value: U) -> T;
+    }
+
+    #[mockable(mock_struct)]
+    trait Fetcher {
+        async fn fetch(&self, url: &str) -> String;
+    }
+
+    fn welcome(greeter: &dyn Greeter) -> String {
+        greeter.greet("world")
+    }
+
+    #[test]
+    fn when_not_mocked_then_panics() {
+        let result = std::panic::catch_unwind(|| welcome(&MockGreeter));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn when_mocked_then_trait_object_runs_mock() {
+        MockGreeter::greet.mock_safe(|_, name| MockResult::Return(format!("Hi {}", name)));
+
+        assert_eq!("Hi world", welcome(&MockGreeter));
+    }
+
+    #[test]
+    fn when_required_fn_mocked_then_default_fn_uses_it() {
+        MockGreeter::greet.mock_safe(|_, name| MockResult::Return(format!("Hi {}", name)));
+
+        assert_eq!("Hi world Hi world", MockGreeter.greet_twice("world"));
+    }
+
+    #[test]
+    fn when_default_fn_mocked_then_runs_mock() {
+        MockGreeter::greet_twice.mock_safe(|_, _| MockResult::Return("mocked".to_string()));
+
+        assert_eq!("mocked", MockGreeter.greet_twice("world"));
+    }
+
+    #[test]
+    fn when_static_fn_mocked_then_runs_mock() {
+        MockGreeter::create.mock_safe(|| MockResult::Return(MockGreeter));
+
+        let _: MockGreeter = Greeter::create();
+    }
+
+    #[test]
+    fn when_generic_trait_mocked_then_runs_mock() {
+        <MockConverter as Converter<u64>>::convert::<u32>
+            .mock_safe(|_, value| MockResult::Return(u64::from(value) * 2));
+
+        assert_eq!(4u64, MockConverter.convert(2u32));
+    }
+
+    #[tokio::test]
+    async fn when_async_fn_mocked_then_runs_mock() {
+        MockFetcher::fetch.mock_async(|_, url| async move { format!("mocked {}", url) });
+
+        assert_eq!("mocked url", MockFetcher.fetch("url").await);
+    }
+}
+
+mod injector_injects_impl_trait_returning_fns {
+    use super::*;
+
+    #[mockable]
+    fn numbers(count: u32) -> impl Iterator<Item = u32> {
+        0..count
+    }
+
+    #[test]
+    fn when_not_mocked_then_runs_normally() {
+        assert_eq!(vec![0, 1], numbers(2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn when_continue_mocked_then_runs_with_modified_args() {
+        numbers.mock_safe(|count| MockResult::Continue((count + 1,)));
+
+        assert_eq!(vec![0, 1, 2], numbers(2).collect::<Vec<_>>());
+    }
+}
+
+mod injector_boxes_impl_trait_outputs {
+    use super::*;
+    use std::future::Future;
+
+    #[mockable(box_impl_trait)]
+    fn numbers(count: u32) -> impl Iterator<Item = u32> {
+        if count == 0 {
+            return 10..11;
+        }
+        0..count
+    }
+
+    struct Numbers(Vec<u32>);
+
+    #[mockable(box_impl_trait)]
+    impl Numbers {
+        fn iter<'a>(&'a self) -> impl Iterator<Item = &'a u32> + 'a {
+            self.0.iter()
+        }
+    }
+
+    #[mockable(box_impl_trait)]
+    fn number_future(number: u32) -> impl Future<Output = u32> {
+        async move { number }
+    }
+
+    #[test]
+    fn when_not_mocked_then_runs_normally() {
+        assert_eq!(vec![0, 1], numbers(2).collect::<Vec<_>>());
+        assert_eq!(vec![10], numbers(0).collect::<Vec<_>>());
+        assert_eq!(vec![&1, &2], Numbers(vec![1, 2]).iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn when_return_mocked_then_returns_mock() {
+        numbers.mock_safe(|_| MockResult::Return(Box::new(vec![7, 8].into_iter())));
+
+        assert_eq!(vec![7, 8], numbers(2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn when_method_return_mocked_then_returns_mock() {
+        static MOCKED: [u32; 2] = [5, 6];
+        Numbers::iter.mock_safe(|_| MockResult::Return(Box::new(MOCKED.iter())));
+
+        assert_eq!(vec![&5, &6], Numbers(vec![1, 2]).iter().collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn when_future_not_mocked_then_runs_normally() {
+        assert_eq!(1, number_future(1).await);
+    }
+
+    #[tokio::test]
+    async fn when_future_return_mocked_then_returns_mock() {
+        number_future.mock_safe(|_| MockResult::Return(Box::pin(async { 2 })));
+
+        assert_eq!(2, number_future(1).await);
+    }
+}
+
+mod injector_injects_unsafe_fns {
     use super::*;
 
     #[mockable]
@@ -592,32 +1524,290 @@ mod injector_ignores_unsafe_fns {
         "not mocked"
     }
 
+    #[mockable]
+    unsafe fn read(ptr: *const u32) -> u32 {
+        *ptr
+    }
+
     #[test]
     fn when_not_mocked_then_runs_normally() {
         assert_eq!("not mocked", unsafe { function() });
     }
 
-    // Trait Mockable is not implemented for unsafe functions
+    #[test]
+    fn when_mocked_then_runs_mock() {
+        unsafe {
+            mock_unsafe_fn(function, || MockResult::Return("mocked"));
+
+            assert_eq!("mocked", function());
+        }
+    }
+
+    #[test]
+    fn when_continue_mocked_then_runs_with_modified_args() {
+        let values = [1, 2];
+        unsafe {
+            mock_unsafe_fn(read, |ptr: *const u32| {
+                MockResult::<_, u32>::Continue((ptr.add(1),))
+            });
+
+            assert_eq!(2, read(values.as_ptr()));
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "MockResult::ContinueThen is not supported for unsafe and extern functions"
+    )]
+    fn when_continue_then_mocked_then_panics() {
+        unsafe {
+            mock_unsafe_fn(function, || {
+                MockResult::ContinueThen((), Box::new(|s: &'static str| s))
+            });
+
+            function();
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "has signature")]
+    fn when_mocked_with_wrong_signature_then_panics() {
+        unsafe {
+            mock_unsafe_fn(function, || MockResult::Return(1u32));
+
+            function();
+        }
+    }
 }
 
-mod injector_ignores_unsafe_impls {
+mod injector_injects_unsafe_impls {
     use super::*;
 
     struct Struct;
 
     #[mockable]
     impl Struct {
-        unsafe fn function() -> &'static str {
+        unsafe fn function(&self) -> &'static str {
             "not mocked"
         }
+
+        unsafe fn generic<T: Into<u32>>(value: T) -> u32 {
+            value.into()
+        }
+    }
+
+    trait Trait {
+        unsafe fn trait_function(self) -> &'static str;
+    }
+
+    #[mockable]
+    impl Trait for Struct {
+        unsafe fn trait_function(self) -> &'static str {
+            "not mocked"
+        }
+    }
+
+    #[test]
+    fn when_not_mocked_then_runs_normally() {
+        assert_eq!("not mocked", unsafe { Struct.function() });
+    }
+
+    #[test]
+    fn when_mocked_then_runs_mock() {
+        unsafe {
+            mock_unsafe_fn(Struct::function, |_: &Struct| MockResult::Return("mocked"));
+
+            assert_eq!("mocked", Struct.function());
+        }
+    }
+
+    #[test]
+    fn when_generic_mocked_then_runs_mock_for_mocked_type_only() {
+        unsafe {
+            mock_unsafe_fn(Struct::generic::<u8>, |_: u8| MockResult::Return(0u32));
+
+            assert_eq!(0, Struct::generic(1u8));
+            assert_eq!(1, Struct::generic(1u16));
+        }
+    }
+
+    #[test]
+    fn when_trait_fn_mocked_then_runs_mock() {
+        unsafe {
+            mock_unsafe_fn(<Struct as Trait>::trait_function, |_: Struct| {
+                MockResult::Return("mocked")
+            });
+
+            assert_eq!("mocked", Struct.trait_function());
+        }
+    }
+}
+
+mod injector_injects_extern_fns {
+    use super::*;
+
+    #[mockable]
+    extern "C" fn function(value: u32) -> u32 {
+        value
+    }
+
+    struct Struct;
+
+    #[mockable]
+    impl Struct {
+        extern "C" fn method(&self) -> u32 {
+            0
+        }
     }
 
     #[test]
     fn when_not_mocked_then_runs_normally() {
-        assert_eq!("not mocked", unsafe { Struct::function() });
+        assert_eq!(1, function(1));
+        assert_eq!(0, Struct.method());
+    }
+
+    #[test]
+    fn when_mocked_then_runs_mock() {
+        unsafe {
+            mock_unsafe_fn(function, |value: u32| MockResult::Return(value + 1));
+            mock_unsafe_fn(Struct::method, |_: &Struct| MockResult::Return(1u32));
+        }
+
+        assert_eq!(2, function(1));
+        assert_eq!(1, Struct.method());
     }
 
-    // Trait Mockable is not implemented for unsafe functions
+    #[test]
+    fn when_mocked_then_keeps_abi() {
+        unsafe {
+            mock_unsafe_fn(function, |value: u32| MockResult::Return(value + 1));
+        }
+        let function_ptr: extern "C" fn(u32) -> u32 = function;
+
+        assert_eq!(2, function_ptr(1));
+    }
+}
+
+mod injector_keeps_linkage_attrs {
+    use super::*;
+
+    #[mockable]
+    #[no_mangle]
+    pub extern "C" fn mocktopus_injecting_no_mangle(value: u32) -> u32 {
+        value
+    }
+
+    #[mockable]
+    #[export_name = "mocktopus_injecting_export_name"]
+    pub extern "C" fn export_name(value: u32) -> u32 {
+        value
+    }
+
+    #[mockable]
+    #[unsafe(no_mangle)]
+    pub extern "C" fn mocktopus_injecting_unsafe_no_mangle(value: u32) -> u32 {
+        value
+    }
+
+    extern "C" {
+        #[link_name = "mocktopus_injecting_no_mangle"]
+        fn no_mangle_symbol(value: u32) -> u32;
+
+        #[link_name = "mocktopus_injecting_export_name"]
+        fn export_name_symbol(value: u32) -> u32;
+
+        #[link_name = "mocktopus_injecting_unsafe_no_mangle"]
+        fn unsafe_no_mangle_symbol(value: u32) -> u32;
+    }
+
+    fn mock_all() {
+        unsafe {
+            mock_unsafe_fn(mocktopus_injecting_no_mangle, |value: u32| {
+                MockResult::Return(value + 1)
+            });
+            mock_unsafe_fn(export_name, |value: u32| MockResult::Return(value + 1));
+            mock_unsafe_fn(mocktopus_injecting_unsafe_no_mangle, |value: u32| {
+                MockResult::Return(value + 1)
+            });
+        }
+    }
+
+    #[test]
+    fn when_mocked_then_rust_callers_run_mock() {
+        mock_all();
+
+        assert_eq!(2, mocktopus_injecting_no_mangle(1));
+        assert_eq!(2, export_name(1));
+        assert_eq!(2, mocktopus_injecting_unsafe_no_mangle(1));
+    }
+
+    #[test]
+    fn when_mocked_then_symbol_callers_run_normally() {
+        mock_all();
+
+        unsafe {
+            assert_eq!(1, no_mangle_symbol(1));
+            assert_eq!(1, export_name_symbol(1));
+            assert_eq!(1, unsafe_no_mangle_symbol(1));
+        }
+    }
+}
+
+mod injector_wraps_fns_of_extern_blocks {
+    use super::*;
+
+    // The native library doesn't exist, test builds don't link it
+    #[mockable]
+    #[link(name = "mocktopus_missing_native_library")]
+    extern "C" {
+        /// Documented native function
+        pub fn missing_native_fn(value: u32, _: *const u8) -> u32;
+
+        #[link_name = "mocktopus_missing_native_symbol"]
+        fn renamed_native_fn();
+    }
+
+    #[mockable(safe)]
+    extern "C" {
+        fn abs(value: i32) -> i32;
+
+        fn printf(format: *const u8, ...) -> i32;
+    }
+
+    #[test]
+    fn when_mocked_then_runs_mock() {
+        unsafe {
+            mock_unsafe_fn(missing_native_fn, |value: u32, _: *const u8| {
+                MockResult::Return(value + 1)
+            });
+            mock_unsafe_fn(renamed_native_fn, || MockResult::Return(()));
+
+            assert_eq!(2, missing_native_fn(1, std::ptr::null()));
+            renamed_native_fn();
+        }
+    }
+
+    #[test]
+    fn when_safe_and_mocked_then_runs_mock() {
+        abs.mock_safe(|value| MockResult::Return(value));
+
+        assert_eq!(-1, abs(-1));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "`missing_native_fn` is a native function, in test builds it must be mocked"
+    )]
+    fn when_not_mocked_then_panics() {
+        unsafe {
+            missing_native_fn(1, std::ptr::null());
+        }
+    }
+
+    #[test]
+    fn when_block_has_variadic_fns_then_keeps_them() {
+        assert_eq!(0, unsafe { printf(b"\0".as_ptr()) });
+    }
 }
 
 mod injector_does_not_inject_macro_generated_fns {
@@ -649,6 +1839,47 @@ mod injector_does_not_inject_macro_generated_fns {
     }
 }
 
+mod injector_injects_macro_generated_items_wrapped_in_mockable_items {
+    use super::*;
+
+    macro_rules! fn_generating_macro {
+        ($name:ident, $value:expr) => {
+            mockable_items! {
+                pub fn $name() -> u32 {
+                    $value
+                }
+
+                pub struct Struct;
+
+                impl Struct {
+                    pub fn $name() -> u32 {
+                        $value
+                    }
+                }
+            }
+        };
+    }
+
+    fn_generating_macro!(macro_generated_fn, 1);
+
+    #[test]
+    fn when_not_mocked_then_returns_1() {
+        assert_eq!(1, macro_generated_fn());
+        assert_eq!(1, Struct::macro_generated_fn());
+    }
+
+    #[test]
+    fn when_mocked_then_runs_mock() {
+        unsafe {
+            macro_generated_fn.mock_raw(|| MockResult::Return(2));
+            Struct::macro_generated_fn.mock_raw(|| MockResult::Return(3));
+        }
+
+        assert_eq!(2, macro_generated_fn());
+        assert_eq!(3, Struct::macro_generated_fn());
+    }
+}
+
 mod injector_unignores_args {
     use super::*;
 
@@ -901,7 +2132,7 @@ mod injecting_lifetimed_fn_of_same_lifetimed_trait_impl_of_same_lifetimed_struct
         fn function(arg: &'a str) -> &'a str;
     }
 
-    struct Struct<'a>(&'a str);
+    struct Struct<'a>(std::marker::PhantomData<&'a str>);
 
     #[mockable]
     impl<'a> Trait<'a> for Struct<'a> {
@@ -922,3 +2153,43 @@ mod injecting_lifetimed_fn_of_same_lifetimed_trait_impl_of_same_lifetimed_struct
         assert_eq!("mocked", Struct::function("not mocked"));
     }
 }
+
+mod injector_skips_async_default_fns_of_traits {
+    use super::*;
+
+    #[mockable]
+    trait Trait {
+        async fn default_method(&self, text: &str) -> usize {
+            text.len()
+        }
+
+        fn sync_default_method(&self) -> &'static str {
+            "not mocked"
+        }
+    }
+
+    struct Struct;
+
+    impl Trait for Struct {}
+
+    struct OverridingStruct;
+
+    impl Trait for OverridingStruct {
+        async fn default_method(&self, _: &str) -> usize {
+            0
+        }
+    }
+
+    #[tokio::test]
+    async fn when_not_mocked_then_runs_normally() {
+        assert_eq!(3, Struct.default_method("abc").await);
+        assert_eq!(0, OverridingStruct.default_method("abc").await);
+    }
+
+    #[test]
+    fn when_sync_default_mocked_then_returns_mock() {
+        <Struct as Trait>::sync_default_method.mock_safe(|_| MockResult::Return("mocked"));
+
+        assert_eq!("mocked", Struct.sync_default_method());
+    }
+}
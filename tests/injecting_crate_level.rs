@@ -0,0 +1,42 @@
+#![cfg(not(feature = "disable-mocking"))]
+#![feature(custom_inner_attributes, prelude_import)]
+#![allow(internal_features)]
+#![mocktopus::macros::mockable]
+
+use mocktopus::mocking::*;
+
+fn function() -> &'static str {
+    "not mocked"
+}
+
+struct Struct;
+
+impl Struct {
+    fn method(&self) -> &'static str {
+        "not mocked"
+    }
+}
+
+mod module {
+    pub fn function() -> Option<&'static str> {
+        Some("not mocked")
+    }
+}
+
+#[test]
+fn when_not_mocked_then_runs_normally() {
+    assert_eq!("not mocked", function());
+    assert_eq!("not mocked", Struct.method());
+    assert_eq!(Some("not mocked"), module::function());
+}
+
+#[test]
+fn when_mocked_then_runs_mock() {
+    function.mock_safe(|| MockResult::Return("mocked"));
+    Struct::method.mock_safe(|_| MockResult::Return("mocked"));
+    module::function.mock_safe(|| MockResult::Return(None));
+
+    assert_eq!("mocked", function());
+    assert_eq!("mocked", Struct.method());
+    assert_eq!(None, module::function());
+}
@@ -1,3 +1,4 @@
+#![cfg(not(feature = "disable-mocking"))]
 #![no_std]
 
 extern crate mocktopus;
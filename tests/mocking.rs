@@ -1,3 +1,4 @@
+#![cfg(not(feature = "disable-mocking"))]
 extern crate mocktopus;
 
 mod mocking_fns;
@@ -503,6 +504,116 @@ mod mock_context {
         assert_eq!("not mocked 2", mockable_2());
     }
 
+    #[test]
+    fn nested_context_mocks_are_restored_after_inner_context_finishes() {
+        MockContext::new()
+            .mock_safe(mockable_1, || MockResult::Return("mocked 1 context 1"))
+            .run(|| {
+                MockContext::new()
+                    .mock_safe(mockable_1, || MockResult::Return("mocked 1 context 2"))
+                    .run(|| {
+                        MockContext::new()
+                            .mock_safe(mockable_1, || MockResult::Return("mocked 1 context 3"))
+                            .run(|| assert_eq!("mocked 1 context 3", mockable_1()));
+                        assert_eq!("mocked 1 context 2", mockable_1());
+                    });
+                assert_eq!("mocked 1 context 1", mockable_1());
+            });
+        assert_eq!("not mocked 1", mockable_1());
+    }
+
+    #[test]
+    fn clearing_mock_in_nested_context_hides_outer_mocks_until_it_finishes() {
+        MockContext::new()
+            .mock_safe(mockable_1, || MockResult::Return("mocked 1 context 1"))
+            .run(|| {
+                MockContext::new()
+                    .mock_safe(mockable_1, || MockResult::Return("mocked 1 context 2"))
+                    .run(|| {
+                        mockable_1.clear_mock();
+
+                        assert_eq!("not mocked 1", mockable_1());
+                        assert!(!mockable_1.is_mocked());
+                    });
+                assert_eq!("mocked 1 context 1", mockable_1());
+                assert!(mockable_1.is_mocked());
+            });
+    }
+
+    #[test]
+    fn clearing_mock_in_context_removes_thread_mock() {
+        mockable_1.mock_safe(|| MockResult::Return("mocked 1"));
+        MockContext::new().run(|| {
+            mockable_1.clear_mock();
+
+            assert_eq!("not mocked 1", mockable_1());
+        });
+        assert_eq!("not mocked 1", mockable_1());
+    }
+
+    #[test]
+    fn mocking_in_context_after_clearing_mock_sets_up_mock() {
+        MockContext::new()
+            .mock_safe(mockable_1, || MockResult::Return("mocked 1 context 1"))
+            .run(|| {
+                mock_scope(|scope| {
+                    mockable_1.clear_mock();
+                    mockable_1.mock_scoped(scope, || MockResult::Return("mocked 1 scope"));
+
+                    assert_eq!("mocked 1 scope", mockable_1());
+                });
+                assert_eq!("mocked 1 context 1", mockable_1());
+            });
+    }
+
+    #[derive(Default)]
+    struct Context {
+        string: String,
+    }
+
+    #[mockable]
+    fn get_string(context: &Context) -> &String {
+        &context.string
+    }
+
+    #[test]
+    fn context_mocks_can_return_references_to_local_data() {
+        let mocked = "mocked".to_string();
+        MockContext::new()
+            .mock_safe(get_string, |_| MockResult::Return(&mocked))
+            .run(|| {
+                assert_eq!("mocked", get_string(&Context::default()));
+            });
+        assert_eq!("", get_string(&Context::default()));
+    }
+
+    #[test]
+    fn context_mocks_can_mutate_local_data() {
+        let mut calls = Vec::new();
+        MockContext::new()
+            .mock_safe(mockable_1, || {
+                calls.push("mockable_1");
+                MockResult::Return("mocked 1")
+            })
+            .run(|| {
+                mockable_1();
+                mockable_1();
+            });
+        assert_eq!(vec!["mockable_1", "mockable_1"], calls);
+    }
+
+    #[test]
+    fn context_mocks_can_borrow_local_data_across_await_points() {
+        let mocked = "mocked".to_string();
+        let future = MockContext::new()
+            .mock_safe(get_string, |_| MockResult::Return(&mocked))
+            .run_async(async { get_string(&Context::default()).clone() });
+
+        let result = tokio::runtime::Runtime::new().unwrap().block_on(future);
+
+        assert_eq!("mocked", result);
+    }
+
     #[test]
     fn calling_function_with_shadowed_mock_from_inside_mock_closure_calls_shadowed_mock() {
         assert_eq!("not mocked", mockable_string());
@@ -518,3 +629,3804 @@ mod mock_context {
         assert_eq!("not mocked, mocked", mockable_string());
     }
 }
+
+mod mock_return_sequence {
+    use super::*;
+
+    #[mockable]
+    fn function() -> &'static str {
+        "not mocked"
+    }
+
+    #[test]
+    fn when_mocked_then_returns_values_in_order() {
+        function.mock_return_sequence(vec!["mocked 1", "mocked 2"], SequenceEnd::Continue);
+
+        assert_eq!("mocked 1", function());
+        assert_eq!("mocked 2", function());
+    }
+
+    #[test]
+    fn when_exhausted_with_continue_then_runs_normally() {
+        function.mock_return_sequence(vec!["mocked"], SequenceEnd::Continue);
+
+        assert_eq!("mocked", function());
+        assert_eq!("not mocked", function());
+        assert_eq!("not mocked", function());
+    }
+
+    #[test]
+    fn when_exhausted_with_repeat_last_then_returns_last_value() {
+        function.mock_return_sequence(vec!["mocked 1", "mocked 2"], SequenceEnd::RepeatLast);
+
+        assert_eq!("mocked 1", function());
+        assert_eq!("mocked 2", function());
+        assert_eq!("mocked 2", function());
+    }
+
+    #[test]
+    #[should_panic(expected = "Mocked function called more times than values in sequence")]
+    fn when_exhausted_with_panic_then_panics() {
+        function.mock_return_sequence(vec!["mocked"], SequenceEnd::Panic);
+
+        assert_eq!("mocked", function());
+        function();
+    }
+}
+
+mod mock_return_sequence_async {
+    use super::*;
+
+    #[mockable]
+    async fn fetch(url: &str) -> Result<String, String> {
+        Ok(format!("fetched {}", url))
+    }
+
+    #[tokio::test]
+    async fn when_mocked_then_returns_values_in_order() {
+        let responses = vec![Err("timeout".to_string()), Ok("mocked".to_string())];
+        fetch.mock_return_sequence_async(responses, SequenceEnd::Continue);
+
+        assert_eq!(Err("timeout".to_string()), fetch("a").await);
+        assert_eq!(Ok("mocked".to_string()), fetch("a").await);
+        assert_eq!(Ok("fetched a".to_string()), fetch("a").await);
+    }
+
+    #[tokio::test]
+    async fn when_exhausted_with_repeat_last_then_returns_last_value() {
+        let responses = vec![Err("timeout".to_string()), Ok("mocked".to_string())];
+        fetch.mock_return_sequence_async(responses, SequenceEnd::RepeatLast);
+
+        assert_eq!(Err("timeout".to_string()), fetch("a").await);
+        assert_eq!(Ok("mocked".to_string()), fetch("a").await);
+        assert_eq!(Ok("mocked".to_string()), fetch("a").await);
+    }
+
+    #[test]
+    #[should_panic(expected = "Mocked function called more times than values in sequence")]
+    fn when_exhausted_with_panic_then_panics_on_call() {
+        fetch.mock_return_sequence_async(vec![Ok("mocked".to_string())], SequenceEnd::Panic);
+
+        drop(fetch("a"));
+        drop(fetch("a"));
+    }
+
+    #[tokio::test]
+    async fn when_futures_awaited_out_of_order_then_values_follow_calls() {
+        fetch.mock_return_sequence_async(
+            vec![Ok("1".to_string()), Ok("2".to_string())],
+            SequenceEnd::Panic,
+        );
+
+        let first = fetch("a");
+        let second = fetch("a");
+
+        assert_eq!(Ok("2".to_string()), second.await);
+        assert_eq!(Ok("1".to_string()), first.await);
+    }
+}
+
+mod mock_panic {
+    use super::*;
+
+    #[mockable]
+    fn function(_: &str) -> &'static str {
+        "not mocked"
+    }
+
+    #[test]
+    #[should_panic(expected = "mocked panic")]
+    fn when_mocked_then_panics_with_message() {
+        function.mock_panic("mocked panic");
+
+        function("argument");
+    }
+
+    #[test]
+    fn when_mocked_and_cleared_then_runs_normally() {
+        function.mock_panic("mocked panic");
+        function.clear_mock();
+
+        assert_eq!("not mocked", function("argument"));
+    }
+}
+
+mod call_real {
+    use super::*;
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[mockable]
+    fn factorial(n: u32) -> u32 {
+        match n {
+            0 => 1,
+            _ => n * factorial(n - 1),
+        }
+    }
+
+    fn not_mockable(a: u32) -> u32 {
+        a
+    }
+
+    #[test]
+    fn when_not_mocked_then_runs_normally() {
+        assert_eq!(3, add.call_real((1, 2)));
+    }
+
+    #[test]
+    fn when_mocked_then_runs_normally() {
+        add.mock_safe(|_, _| MockResult::Return(0));
+
+        assert_eq!(3, add.call_real((1, 2)));
+        assert_eq!(0, add(1, 2));
+    }
+
+    #[test]
+    fn when_called_inside_mock_then_runs_normally() {
+        add.mock_safe(|a, b| MockResult::Return(add.call_real((a, b)) * 10));
+
+        assert_eq!(30, add(1, 2));
+    }
+
+    #[test]
+    fn when_called_inside_context_then_ignores_all_mock_layers() {
+        add.mock_safe(|_, _| MockResult::Return(0));
+        MockContext::new()
+            .mock_safe(add, |a, b| MockResult::Return(add.call_real((a, b)) + 100))
+            .run(|| {
+                assert_eq!(103, add(1, 2));
+            });
+    }
+
+    #[test]
+    fn when_real_implementation_recurses_then_recursive_calls_are_mocked() {
+        factorial.mock_safe(|n| match n {
+            1 => MockResult::Return(100),
+            _ => MockResult::Continue((n,)),
+        });
+
+        assert_eq!(600, factorial.call_real((3,)));
+    }
+
+    #[test]
+    fn when_called_on_not_mockable_function_then_does_not_affect_next_call() {
+        add.mock_safe(|_, _| MockResult::Return(0));
+
+        assert_eq!(1, not_mockable.call_real((1,)));
+        assert_eq!(0, add(1, 2));
+    }
+}
+
+mod mock_result_continue_then {
+    use super::*;
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    struct Struct(u32);
+
+    #[mockable]
+    impl Struct {
+        fn get(&self) -> u32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn when_mocked_then_runs_normally_and_transforms_result() {
+        add.mock_safe(|a, b| MockResult::ContinueThen((a, b), Box::new(|sum| sum * 10)));
+
+        assert_eq!(30, add(1, 2));
+    }
+
+    #[test]
+    fn when_mocked_with_modified_args_then_runs_with_modified_args() {
+        add.mock_safe(|a, b| MockResult::ContinueThen((a + 1, b), Box::new(|sum| sum * 10)));
+
+        assert_eq!(40, add(1, 2));
+    }
+
+    #[test]
+    fn when_mocked_method_then_runs_normally_and_transforms_result() {
+        Struct::get.mock_safe(|s| MockResult::ContinueThen((s,), Box::new(|value| value + 1)));
+
+        assert_eq!(2, Struct(1).get());
+    }
+
+    #[test]
+    fn when_mocked_inside_context_then_real_implementation_ignores_outer_mocks() {
+        add.mock_safe(|_, _| MockResult::Return(0));
+        MockContext::new()
+            .mock_safe(add, |a, b| {
+                MockResult::ContinueThen((a, b), Box::new(|sum| sum + 100))
+            })
+            .run(|| {
+                assert_eq!(103, add(1, 2));
+            });
+    }
+
+    #[test]
+    #[should_panic(expected = "too big")]
+    fn when_then_closure_panics_then_panics() {
+        add.mock_safe(|a, b| MockResult::ContinueThen((a, b), Box::new(|_| panic!("too big"))));
+
+        add(1, 2);
+    }
+}
+
+mod clear_all_mocks {
+    use super::*;
+
+    #[mockable]
+    fn mockable_1() -> &'static str {
+        "not mocked 1"
+    }
+
+    #[mockable]
+    fn mockable_2() -> &'static str {
+        "not mocked 2"
+    }
+
+    #[test]
+    fn when_clearing_all_mocks_then_all_functions_run_normally() {
+        mockable_1.mock_safe(|| MockResult::Return("mocked 1"));
+        mockable_2.mock_safe(|| MockResult::Return("mocked 2"));
+
+        clear_all_mocks();
+
+        assert_eq!("not mocked 1", mockable_1());
+        assert_eq!("not mocked 2", mockable_2());
+    }
+
+    #[test]
+    fn when_clearing_all_mocks_then_new_mocks_can_be_set() {
+        mockable_1.mock_safe(|| MockResult::Return("mocked 1"));
+
+        clear_all_mocks();
+        mockable_2.mock_safe(|| MockResult::Return("mocked 2"));
+
+        assert_eq!("not mocked 1", mockable_1());
+        assert_eq!("mocked 2", mockable_2());
+    }
+}
+
+mod is_mocked {
+    use super::*;
+
+    #[mockable]
+    fn mockable_1() -> &'static str {
+        "not mocked 1"
+    }
+
+    #[mockable]
+    fn mockable_2() -> &'static str {
+        "not mocked 2"
+    }
+
+    #[test]
+    fn when_not_mocked_then_returns_false() {
+        assert!(!mockable_1.is_mocked());
+    }
+
+    #[test]
+    fn when_mocked_then_returns_true_only_for_mocked_function() {
+        mockable_1.mock_safe(|| MockResult::Return("mocked 1"));
+
+        assert!(mockable_1.is_mocked());
+        assert!(!mockable_2.is_mocked());
+    }
+
+    #[test]
+    fn when_mock_cleared_then_returns_false() {
+        mockable_1.mock_safe(|| MockResult::Return("mocked 1"));
+
+        mockable_1.clear_mock();
+
+        assert!(!mockable_1.is_mocked());
+    }
+
+    #[test]
+    fn when_mocked_in_context_then_returns_true_only_inside_run_closure() {
+        MockContext::new()
+            .mock_safe(mockable_1, || MockResult::Return("mocked 1"))
+            .run(|| {
+                assert!(mockable_1.is_mocked());
+            });
+        assert!(!mockable_1.is_mocked());
+    }
+}
+
+mod mock_scoped {
+    use super::*;
+
+    #[mockable]
+    fn mockable_1() -> &'static str {
+        "not mocked 1"
+    }
+
+    #[mockable]
+    fn as_str(s: &String) -> &str {
+        s
+    }
+
+    #[test]
+    fn scoped_mocks_mock_only_inside_scope() {
+        mock_scope(|scope| {
+            mockable_1.mock_scoped(scope, || MockResult::Return("mocked 1"));
+
+            assert_eq!("mocked 1", mockable_1());
+        });
+        assert_eq!("not mocked 1", mockable_1());
+    }
+
+    #[test]
+    fn scoped_mocks_can_borrow_local_data() {
+        let mocked = "mocked".to_string();
+        let mut count = 0;
+        mock_scope(|scope| {
+            as_str.mock_scoped(scope, |_| {
+                count += 1;
+                MockResult::Return(&mocked)
+            });
+
+            assert_eq!("mocked", as_str(&"not mocked".to_string()));
+        });
+        assert_eq!(1, count);
+    }
+
+    #[test]
+    fn scoped_mocks_shadow_thread_mocks_only_inside_scope() {
+        mockable_1.mock_safe(|| MockResult::Return("mocked 1"));
+        mock_scope(|scope| {
+            mockable_1.mock_scoped(scope, || MockResult::Return("mocked 1 scope"));
+
+            assert_eq!("mocked 1 scope", mockable_1());
+        });
+        assert_eq!("mocked 1", mockable_1());
+    }
+
+    #[test]
+    fn scoped_mocks_work_inside_nested_context() {
+        mock_scope(|scope| {
+            MockContext::new()
+                .mock_safe(mockable_1, || MockResult::Return("mocked 1 context"))
+                .run(|| {
+                    mockable_1.mock_scoped(scope, || MockResult::Return("mocked 1 scope"));
+
+                    assert_eq!("mocked 1 context", mockable_1());
+                });
+            assert_eq!("mocked 1 scope", mockable_1());
+        });
+        assert_eq!("not mocked 1", mockable_1());
+    }
+
+    #[test]
+    fn scoped_mocks_are_removed_when_scope_panics() {
+        let result = std::panic::catch_unwind(|| {
+            mock_scope(|scope| {
+                mockable_1.mock_scoped(scope, || MockResult::Return("mocked 1"));
+                panic!("inside scope");
+            })
+        });
+
+        assert!(result.is_err());
+        assert_eq!("not mocked 1", mockable_1());
+    }
+}
+
+mod mock_if {
+    use super::*;
+
+    #[mockable]
+    fn divide(a: u32, b: u32) -> u32 {
+        a / b
+    }
+
+    #[mockable]
+    fn length(s: String) -> usize {
+        s.len()
+    }
+
+    #[test]
+    fn when_predicate_matches_then_returns_handler_result() {
+        divide.mock_if(|&(_, b)| b == 0, |a, _| a * 100);
+
+        assert_eq!(300, divide(3, 0));
+    }
+
+    #[test]
+    fn when_predicate_does_not_match_then_runs_normally() {
+        divide.mock_if(|&(_, b)| b == 0, |a, _| a * 100);
+
+        assert_eq!(2, divide(4, 2));
+    }
+
+    #[test]
+    fn when_arguments_have_drop_then_they_are_passed_to_real_function() {
+        length.mock_if(|(s,)| s.is_empty(), |_| 100);
+
+        assert_eq!(100, length(String::new()));
+        assert_eq!(3, length("abc".to_string()));
+    }
+
+    #[test]
+    fn when_handler_has_state_then_it_is_kept_between_calls() {
+        let mut calls = 0;
+        divide.mock_if(
+            |&(_, b)| b == 0,
+            move |_, _| {
+                calls += 1;
+                calls
+            },
+        );
+
+        assert_eq!(1, divide(1, 0));
+        assert_eq!(1, divide(1, 1));
+        assert_eq!(2, divide(1, 0));
+    }
+}
+
+mod push_and_pop_mock {
+    use super::*;
+
+    #[mockable]
+    fn mockable_1() -> &'static str {
+        "not mocked 1"
+    }
+
+    #[test]
+    fn when_pushed_then_shadows_previous_mock() {
+        mockable_1.mock_safe(|| MockResult::Return("mocked 1"));
+
+        mockable_1.push_mock(|| MockResult::Return("pushed 1"));
+
+        assert_eq!("pushed 1", mockable_1());
+    }
+
+    #[test]
+    fn when_popped_then_restores_previous_mock() {
+        mockable_1.mock_safe(|| MockResult::Return("mocked 1"));
+        mockable_1.push_mock(|| MockResult::Return("pushed 1"));
+
+        mockable_1.pop_mock();
+
+        assert_eq!("mocked 1", mockable_1());
+    }
+
+    #[test]
+    fn when_popped_last_mock_then_runs_normally() {
+        mockable_1.push_mock(|| MockResult::Return("pushed 1"));
+
+        mockable_1.pop_mock();
+
+        assert_eq!("not mocked 1", mockable_1());
+        assert!(!mockable_1.is_mocked());
+    }
+
+    #[test]
+    fn when_popped_not_mocked_then_does_nothing() {
+        mockable_1.pop_mock();
+
+        assert_eq!("not mocked 1", mockable_1());
+    }
+
+    #[test]
+    fn when_mocked_safe_after_push_then_replaces_only_top_mock() {
+        mockable_1.mock_safe(|| MockResult::Return("mocked 1 A"));
+        mockable_1.push_mock(|| MockResult::Return("pushed 1"));
+        mockable_1.mock_safe(|| MockResult::Return("mocked 1 B"));
+        assert_eq!("mocked 1 B", mockable_1());
+
+        mockable_1.pop_mock();
+
+        assert_eq!("mocked 1 A", mockable_1());
+    }
+
+    #[test]
+    fn when_cleared_then_removes_whole_stack() {
+        mockable_1.mock_safe(|| MockResult::Return("mocked 1"));
+        mockable_1.push_mock(|| MockResult::Return("pushed 1"));
+
+        mockable_1.clear_mock();
+
+        assert_eq!("not mocked 1", mockable_1());
+    }
+
+    #[test]
+    fn when_pushed_mock_calls_itself_then_runs_normally() {
+        mockable_1.mock_safe(|| MockResult::Return("mocked 1"));
+        mockable_1.push_mock(|| MockResult::Return(mockable_1()));
+
+        assert_eq!("not mocked 1", mockable_1());
+    }
+}
+
+mod arg_captor {
+    use super::*;
+
+    #[mockable]
+    fn send(_address: &str, _message: String) -> bool {
+        true
+    }
+
+    fn mock_send_with_captor() -> ArgCaptor<(String, String)> {
+        let captor = ArgCaptor::new();
+        let mock_captor = captor.clone();
+        send.mock_safe(move |address, message| {
+            mock_captor.capture((address.to_string(), message));
+            MockResult::Return(false)
+        });
+        captor
+    }
+
+    fn args(address: &str, message: &str) -> (String, String) {
+        (address.to_string(), message.to_string())
+    }
+
+    #[test]
+    fn when_not_called_then_captures_nothing() {
+        let captor = mock_send_with_captor();
+
+        assert!(captor.is_empty());
+        assert_eq!(None, captor.last());
+    }
+
+    #[test]
+    fn when_called_then_captures_all_calls_in_order() {
+        let captor = mock_send_with_captor();
+
+        send("alice", "hello".to_string());
+        send("bob", "bye".to_string());
+
+        assert_eq!(2, captor.len());
+        assert_eq!(
+            vec![args("alice", "hello"), args("bob", "bye")],
+            captor.all()
+        );
+        assert_eq!(Some(args("bob", "bye")), captor.last());
+    }
+
+    #[test]
+    fn when_cleared_then_captures_nothing() {
+        let captor = mock_send_with_captor();
+        send("alice", "hello".to_string());
+
+        captor.clear();
+
+        assert!(captor.is_empty());
+    }
+
+    #[test]
+    fn when_last_call_matches_then_assert_called_with_passes() {
+        let captor = mock_send_with_captor();
+
+        send("alice", "hello".to_string());
+        send("bob", "bye".to_string());
+
+        captor.assert_called_with(&args("bob", "bye"));
+        captor.assert_any_called_with(&args("alice", "hello"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Captured arguments differ")]
+    fn when_last_call_does_not_match_then_assert_called_with_panics() {
+        let captor = mock_send_with_captor();
+
+        send("alice", "hello".to_string());
+        send("bob", "bye".to_string());
+
+        captor.assert_called_with(&args("alice", "hello"));
+    }
+
+    #[test]
+    #[should_panic(expected = "No calls captured")]
+    fn when_not_called_then_assert_called_with_panics() {
+        let captor = mock_send_with_captor();
+
+        captor.assert_called_with(&args("alice", "hello"));
+    }
+
+    #[test]
+    #[should_panic(expected = "No captured call with")]
+    fn when_no_call_matches_then_assert_any_called_with_panics() {
+        let captor = mock_send_with_captor();
+
+        send("alice", "hello".to_string());
+
+        captor.assert_any_called_with(&args("bob", "bye"));
+    }
+}
+
+mod call_count {
+    use super::*;
+
+    #[mockable]
+    fn mockable_1() -> &'static str {
+        "not mocked 1"
+    }
+
+    #[mockable]
+    fn mockable_2() -> &'static str {
+        "not mocked 2"
+    }
+
+    #[test]
+    fn when_not_called_then_returns_zero() {
+        assert_eq!(0, mockable_1.call_count());
+    }
+
+    #[test]
+    fn when_not_mocked_then_counts_calls() {
+        mockable_1();
+        mockable_1();
+
+        assert_eq!(2, mockable_1.call_count());
+        assert_eq!(0, mockable_2.call_count());
+    }
+
+    #[test]
+    fn when_mocked_then_counts_calls() {
+        mockable_1.mock_safe(|| MockResult::Return("mocked 1"));
+
+        mockable_1();
+        mockable_1.clear_mock();
+        mockable_1();
+
+        assert_eq!(2, mockable_1.call_count());
+    }
+
+    #[test]
+    fn when_called_real_then_does_not_count_call() {
+        mockable_1.mock_safe(|| MockResult::ContinueThen((), Box::new(|s| s)));
+
+        mockable_1();
+        mockable_1.call_real(());
+
+        assert_eq!(1, mockable_1.call_count());
+    }
+
+    #[test]
+    fn when_called_inside_context_then_counts_calls() {
+        MockContext::new()
+            .mock_safe(mockable_1, || MockResult::Return("mocked 1"))
+            .run(|| {
+                mockable_1();
+            });
+        mockable_1();
+
+        assert_eq!(2, mockable_1.call_count());
+    }
+
+    #[test]
+    fn when_reset_then_counts_from_zero() {
+        mockable_1();
+
+        mockable_1.reset_call_count();
+        mockable_1();
+
+        assert_eq!(1, mockable_1.call_count());
+    }
+
+    #[test]
+    fn when_all_mocks_cleared_then_counts_from_zero() {
+        mockable_1();
+        mockable_2();
+
+        clear_all_mocks();
+        mockable_1();
+
+        assert_eq!(1, mockable_1.call_count());
+        assert_eq!(0, mockable_2.call_count());
+    }
+}
+
+mod expectations {
+    use super::*;
+
+    #[mockable]
+    fn mockable_1() -> &'static str {
+        "not mocked 1"
+    }
+
+    #[mockable]
+    fn mockable_2() -> &'static str {
+        "not mocked 2"
+    }
+
+    #[test]
+    fn when_expected_calls_made_in_context_then_passes() {
+        MockContext::new().run(|| {
+            mockable_1.expect_calls(2);
+            mockable_2.expect_no_calls();
+
+            mockable_1();
+            mockable_1();
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "mockable_1: expected 2 calls, got 1")]
+    fn when_too_few_calls_made_in_context_then_panics_on_exit() {
+        MockContext::new().run(|| {
+            mockable_1.expect_calls(2);
+
+            mockable_1();
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "mockable_2: expected 0 calls, got 1")]
+    fn when_unexpected_call_made_in_context_then_panics_on_exit() {
+        MockContext::new().run(|| {
+            mockable_2.expect_no_calls();
+
+            mockable_2();
+        });
+    }
+
+    #[test]
+    fn when_calls_made_before_expectation_then_they_are_not_counted() {
+        mockable_1();
+        MockContext::new().run(|| {
+            mockable_1();
+            mockable_1.expect_calls(1);
+
+            mockable_1();
+        });
+    }
+
+    #[test]
+    fn when_expected_with_context_builder_then_counts_calls_inside_run() {
+        mockable_1();
+        MockContext::new()
+            .mock_safe(mockable_1, || MockResult::Return("mocked 1"))
+            .expect_calls(mockable_1, 1)
+            .run(|| {
+                assert_eq!("mocked 1", mockable_1());
+            });
+    }
+
+    #[test]
+    #[should_panic(expected = "mockable_1: expected 2 calls, got 0")]
+    fn when_expected_with_context_builder_and_not_called_then_panics_on_exit() {
+        MockContext::new().expect_calls(mockable_1, 2).run(|| {});
+    }
+
+    #[test]
+    fn when_expected_in_nested_context_then_verified_on_its_exit() {
+        MockContext::new().run(|| {
+            mockable_1.expect_calls(2);
+            MockContext::new().run(|| {
+                mockable_1.expect_calls(1);
+
+                mockable_1();
+            });
+            mockable_1();
+        });
+    }
+
+    #[test]
+    fn when_expected_calls_made_outside_context_then_verification_passes() {
+        mockable_1.expect_calls(1);
+
+        mockable_1();
+
+        verify_expectations();
+    }
+
+    #[test]
+    #[should_panic(expected = "Mock expectations not met")]
+    fn when_too_many_calls_made_outside_context_then_verification_panics() {
+        mockable_1.expect_calls(1);
+
+        mockable_1();
+        mockable_1();
+
+        verify_expectations();
+    }
+
+    #[mockable]
+    fn send(address: &str, message: &str) -> bool {
+        let _ = (address, message);
+        false
+    }
+
+    #[test]
+    fn when_matching_calls_made_in_context_then_verification_passes() {
+        MockContext::new()
+            .expect_calls_with(
+                send,
+                (mocktopus::matchers::eq("alice"), mocktopus::matchers::any()),
+                2,
+            )
+            .expect_calls_with(
+                send,
+                (mocktopus::matchers::any(), mocktopus::matchers::eq("bye")),
+                1,
+            )
+            .run(|| {
+                send("alice", "hello");
+                send("alice", "bye");
+                send("bob", "hello");
+            });
+    }
+
+    #[test]
+    fn when_matching_calls_made_then_mocks_still_run() {
+        MockContext::new()
+            .mock_safe(send, |_, _| MockResult::Return(true))
+            .expect_calls_with(
+                send,
+                (mocktopus::matchers::eq("alice"), mocktopus::matchers::any()),
+                1,
+            )
+            .run(|| {
+                assert!(send("alice", "hello"));
+            });
+    }
+
+    #[test]
+    #[should_panic(expected = "send: expected 1 calls with matching arguments, got 0")]
+    fn when_no_matching_calls_made_in_context_then_panics() {
+        MockContext::new()
+            .expect_calls_with(
+                send,
+                (mocktopus::matchers::eq("alice"), mocktopus::matchers::any()),
+                1,
+            )
+            .run(|| {
+                send("bob", "hello");
+            });
+    }
+
+    #[test]
+    fn when_matching_expectations_not_met_then_all_are_reported() {
+        let result = std::panic::catch_unwind(|| {
+            MockContext::new()
+                .expect_calls(mockable_1, 1)
+                .expect_calls_with(
+                    send,
+                    (mocktopus::matchers::eq("alice"), mocktopus::matchers::any()),
+                    1,
+                )
+                .run(|| ());
+        });
+
+        let message = result.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.contains("mockable_1: expected 1 calls, got 0"));
+        assert!(message.contains("send: expected 1 calls with matching arguments, got 0"));
+    }
+
+    #[test]
+    fn when_matchers_borrow_local_data_then_they_are_used() {
+        let address = "alice".to_string();
+        MockContext::new()
+            .expect_calls_with(
+                send,
+                (
+                    mocktopus::matchers::predicate(|a: &&str| *a == address),
+                    mocktopus::matchers::any(),
+                ),
+                1,
+            )
+            .run(|| {
+                send("alice", "hello");
+            });
+    }
+
+    #[test]
+    fn when_calls_made_outside_context_then_matching_expectations_ignore_them() {
+        send("alice", "hello");
+        MockContext::new()
+            .expect_calls_with(
+                send,
+                (mocktopus::matchers::eq("alice"), mocktopus::matchers::any()),
+                1,
+            )
+            .run(|| {
+                send("alice", "hello");
+            });
+        send("alice", "hello");
+    }
+
+    #[test]
+    fn when_verified_then_expectations_are_removed() {
+        mockable_1.expect_no_calls();
+        verify_expectations();
+
+        mockable_1();
+
+        verify_expectations();
+    }
+}
+
+mod when_matchers {
+    use super::*;
+    use mocktopus::matchers::*;
+    use mocktopus::when;
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[mockable]
+    fn greet(name: &str) -> String {
+        format!("Hello, {}", name)
+    }
+
+    #[test]
+    fn when_args_match_then_returns_stubbed_value() {
+        when!(add(eq(1), any())).then_return(10);
+
+        assert_eq!(10, add(1, 5));
+    }
+
+    #[test]
+    fn when_args_do_not_match_then_runs_normally() {
+        when!(add(eq(1), any())).then_return(10);
+
+        assert_eq!(7, add(2, 5));
+    }
+
+    #[test]
+    fn when_multiple_stubs_then_most_recent_matching_is_used() {
+        when!(add(any(), any())).then_return(10);
+        when!(add(gt(5), in_range(0..3))).then_return(20);
+
+        assert_eq!(20, add(6, 2));
+        assert_eq!(10, add(6, 3));
+        assert_eq!(10, add(1, 1));
+    }
+
+    #[test]
+    fn when_matchers_composed_then_composition_is_used() {
+        when!(add(not(eq(1)), or(lt(3), and(ge(10), le(11))))).then_return(10);
+
+        assert_eq!(10, add(2, 2));
+        assert_eq!(10, add(2, 11));
+        assert_eq!(3, add(1, 2));
+        assert_eq!(7, add(2, 5));
+    }
+
+    #[test]
+    fn when_string_arg_matched_then_returns_stubbed_value() {
+        when!(greet(contains("mock"))).then_return("stubbed".to_string());
+
+        assert_eq!("stubbed", greet("mocktopus"));
+        assert_eq!("Hello, world", greet("world"));
+    }
+
+    #[test]
+    fn when_predicate_used_then_it_is_matched() {
+        when!(add(predicate(|a: &u32| a % 2 == 0), ne(0))).then_return(10);
+
+        assert_eq!(10, add(4, 1));
+        assert_eq!(4, add(4, 0));
+    }
+
+    #[test]
+    fn when_stubbed_with_handler_then_handler_receives_args() {
+        when!(add(any(), any())).then(|(a, b)| a * b);
+
+        assert_eq!(12, add(3, 4));
+    }
+
+    #[test]
+    #[should_panic(expected = "stubbed panic")]
+    fn when_stubbed_with_panic_then_panics() {
+        when!(add(eq(1), eq(2))).then_panic("stubbed panic");
+
+        add(1, 2);
+    }
+
+    #[test]
+    fn when_mock_cleared_then_stubs_are_removed() {
+        when!(add(any(), any())).then_return(10);
+
+        add.clear_mock();
+
+        assert_eq!(3, add(1, 2));
+    }
+
+    #[test]
+    fn when_stubbed_once_then_only_first_matching_call_is_stubbed() {
+        when!(add(eq(5), any())).once().then_return(10);
+
+        assert_eq!(3, add(1, 2));
+        assert_eq!(10, add(5, 2));
+        assert_eq!(7, add(5, 2));
+    }
+
+    #[test]
+    fn when_stubbed_once_then_older_stubs_are_used_after_it() {
+        when!(add(any(), any())).then_return(20);
+        when!(add(any(), any())).once().then_return(10);
+
+        assert_eq!(10, add(1, 2));
+        assert_eq!(20, add(1, 2));
+    }
+
+    #[test]
+    fn when_stubbed_once_with_panic_then_panics_once() {
+        when!(add(any(), any())).once().then_panic("stubbed panic");
+
+        assert!(std::panic::catch_unwind(|| add(1, 2)).is_err());
+        assert_eq!(3, add(1, 2));
+    }
+}
+
+mod strict_mode {
+    use super::*;
+
+    #[mockable]
+    fn mockable_1() -> u32 {
+        1
+    }
+
+    #[mockable]
+    fn mockable_2() -> u32 {
+        2
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Strict mode: called mocking::strict_mode::mockable_1 without a mock"
+    )]
+    fn when_strict_and_not_mocked_then_panics() {
+        set_strict_mode(true);
+
+        mockable_1();
+    }
+
+    #[test]
+    fn when_strict_and_mocked_then_runs_mock() {
+        set_strict_mode(true);
+        mockable_1.mock_safe(|| MockResult::Return(10));
+
+        assert_eq!(10, mockable_1());
+    }
+
+    #[test]
+    fn when_strict_and_mock_continues_then_runs_normally() {
+        set_strict_mode(true);
+        mockable_1.mock_safe(|| MockResult::Continue(()));
+
+        assert_eq!(1, mockable_1());
+    }
+
+    #[test]
+    fn when_strict_and_called_real_then_runs_normally() {
+        set_strict_mode(true);
+
+        assert_eq!(1, mockable_1.call_real(()));
+    }
+
+    #[test]
+    fn when_strict_mode_disabled_then_runs_normally() {
+        set_strict_mode(true);
+        set_strict_mode(false);
+
+        assert_eq!(1, mockable_1());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Strict mode: called mocking::strict_mode::mockable_2 without a mock"
+    )]
+    fn when_context_strict_and_not_mocked_then_panics() {
+        MockContext::new()
+            .mock_safe(mockable_1, || MockResult::Return(10))
+            .strict()
+            .run(|| {
+                assert_eq!(10, mockable_1());
+                mockable_2();
+            });
+    }
+
+    #[test]
+    fn when_context_strict_and_finished_then_strict_mode_is_disabled() {
+        MockContext::new().strict().run(|| ());
+
+        assert_eq!(2, mockable_2());
+    }
+}
+
+mod spy {
+    use super::*;
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[test]
+    fn when_spied_then_runs_normally_and_records_args() {
+        let spy = add.spy();
+
+        assert_eq!(3, add(1, 2));
+        assert_eq!(7, add(3, 4));
+
+        assert_eq!(vec![(1, 2), (3, 4)], spy.all());
+    }
+
+    #[test]
+    fn when_spied_on_mocked_then_runs_mock_and_records_args() {
+        add.mock_safe(|a, b| MockResult::Return(a * b));
+        let spy = add.spy();
+
+        assert_eq!(12, add(3, 4));
+
+        spy.assert_called_with(&(3, 4));
+    }
+
+    #[test]
+    fn when_spy_popped_then_stops_recording() {
+        let spy = add.spy();
+        add(1, 2);
+
+        add.pop_mock();
+        add(3, 4);
+
+        assert_eq!(vec![(1, 2)], spy.all());
+        assert!(!add.is_mocked());
+    }
+
+    #[test]
+    fn when_called_real_then_call_is_not_recorded() {
+        let spy = add.spy();
+
+        assert_eq!(3, add.call_real((1, 2)));
+
+        assert!(spy.is_empty());
+    }
+}
+
+mod mock_nth_call {
+    use super::*;
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[test]
+    fn when_mocked_first_call_then_only_first_call_is_mocked() {
+        add.mock_nth_call(0, |a, b| a * b);
+
+        assert_eq!(12, add(3, 4));
+        assert_eq!(7, add(3, 4));
+    }
+
+    #[test]
+    fn when_mocked_nth_call_then_only_nth_call_is_mocked() {
+        add.mock_nth_call(2, |_, _| 0);
+
+        assert_eq!(3, add(1, 2));
+        assert_eq!(3, add(1, 2));
+        assert_eq!(0, add(1, 2));
+        assert_eq!(3, add(1, 2));
+    }
+
+    #[test]
+    fn when_calls_made_before_mocking_then_they_are_not_counted() {
+        add(1, 2);
+        add(1, 2);
+
+        add.mock_nth_call(0, |_, _| 0);
+
+        assert_eq!(0, add(1, 2));
+    }
+}
+
+mod mock_return_result {
+    use super::*;
+
+    #[mockable]
+    fn parse(text: &str) -> Result<u32, String> {
+        text.parse().map_err(|_| "invalid number".to_string())
+    }
+
+    #[test]
+    fn when_ok_mocked_then_returns_ok() {
+        parse.mock_return_ok(10);
+
+        assert_eq!(Ok(10), parse("abc"));
+        assert_eq!(Ok(10), parse("abc"));
+    }
+
+    #[test]
+    fn when_err_mocked_then_returns_err() {
+        parse.mock_return_err("mocked".to_string());
+
+        assert_eq!(Err("mocked".to_string()), parse("10"));
+        assert_eq!(Err("mocked".to_string()), parse("10"));
+    }
+}
+
+mod mock_result_panic {
+    use super::*;
+
+    #[mockable]
+    fn function(_: &str) -> &'static str {
+        "not mocked"
+    }
+
+    struct Struct;
+
+    #[mockable]
+    impl Struct {
+        fn method(&self) -> u32 {
+            1
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "mocking::mock_result_panic::function panicked: mocked panic")]
+    fn when_mocked_then_panics_with_path_and_message() {
+        function.mock_safe(|_| MockResult::Panic("mocked panic".to_string()));
+
+        function("argument");
+    }
+
+    #[test]
+    #[should_panic(expected = "mocking::mock_result_panic::Struct::method panicked: mocked panic")]
+    fn when_method_mocked_then_panics_with_path_and_message() {
+        Struct::method.mock_safe(|_| MockResult::Panic("mocked panic".to_string()));
+
+        Struct.method();
+    }
+
+    #[test]
+    #[should_panic(expected = "mocking::mock_result_panic::function panicked: mocked panic")]
+    fn when_mocked_with_mock_panic_then_panics_with_path_and_message() {
+        function.mock_panic("mocked panic");
+
+        function("argument");
+    }
+}
+
+mod mock_registry_snapshot {
+    use super::*;
+
+    #[mockable]
+    fn mockable_1() -> u32 {
+        1
+    }
+
+    #[mockable]
+    fn mockable_2() -> u32 {
+        2
+    }
+
+    #[test]
+    fn when_restored_then_captured_mocks_are_set_up() {
+        mockable_1.mock_safe(|| MockResult::Return(10));
+        let snapshot = MockRegistrySnapshot::capture();
+        mockable_1.mock_safe(|| MockResult::Return(20));
+        mockable_2.mock_safe(|| MockResult::Return(30));
+
+        snapshot.restore();
+
+        assert_eq!(10, mockable_1());
+        assert_eq!(2, mockable_2());
+    }
+
+    #[test]
+    fn when_restored_after_clear_then_captured_mocks_are_set_up() {
+        mockable_1.mock_safe(|| MockResult::Return(10));
+        let snapshot = MockRegistrySnapshot::capture();
+        clear_all_mocks();
+
+        snapshot.restore();
+
+        assert_eq!(10, mockable_1());
+    }
+
+    #[test]
+    fn when_restored_multiple_times_then_mocks_keep_state() {
+        let mut counter = 0;
+        mockable_1.mock_safe(move || {
+            counter += 1;
+            MockResult::Return(counter)
+        });
+        let snapshot = MockRegistrySnapshot::capture();
+
+        assert_eq!(1, mockable_1());
+        snapshot.restore();
+        assert_eq!(2, mockable_1());
+        snapshot.restore();
+        assert_eq!(3, mockable_1());
+    }
+
+    #[test]
+    fn when_restored_then_strict_mode_is_restored() {
+        let snapshot = MockRegistrySnapshot::capture();
+        set_strict_mode(true);
+
+        snapshot.restore();
+
+        assert_eq!(1, mockable_1());
+    }
+
+    #[test]
+    fn when_restored_inside_context_then_context_mocks_are_kept() {
+        let snapshot = MockRegistrySnapshot::capture();
+        MockContext::new()
+            .mock_safe(mockable_1, || MockResult::Return(10))
+            .run(|| {
+                snapshot.restore();
+
+                assert_eq!(10, mockable_1());
+            });
+    }
+}
+
+mod mock_by_name {
+    use super::*;
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[mockable]
+    fn identity<T>(value: T) -> T {
+        value
+    }
+
+    struct Struct;
+
+    #[mockable]
+    impl Struct {
+        fn method(&self) -> u32 {
+            1
+        }
+    }
+
+    #[test]
+    fn when_mocked_by_name_then_runs_mock() {
+        unsafe {
+            mock_by_name("mocking::mock_by_name::add", |a: u32, b: u32| {
+                MockResult::Return(a * b)
+            });
+        }
+
+        assert_eq!(12, add(3, 4));
+        assert!(add.is_mocked());
+    }
+
+    #[test]
+    fn when_method_mocked_by_name_then_runs_mock() {
+        unsafe {
+            mock_by_name("mocking::mock_by_name::Struct::method", |_: &Struct| {
+                MockResult::Return(10u32)
+            });
+        }
+
+        assert_eq!(10, Struct.method());
+    }
+
+    #[test]
+    fn when_generic_mocked_by_name_then_only_given_variant_runs_mock() {
+        unsafe {
+            mock_by_name("mocking::mock_by_name::identity<u32>", |_: u32| {
+                MockResult::Return(10u32)
+            });
+        }
+
+        assert_eq!(10, identity(1u32));
+        assert_eq!(1, identity(1u64));
+    }
+
+    #[test]
+    fn when_other_name_mocked_then_runs_normally() {
+        unsafe {
+            mock_by_name("mocking::mock_by_name::other", |a: u32, b: u32| {
+                MockResult::Return(a * b)
+            });
+        }
+
+        assert_eq!(7, add(3, 4));
+    }
+
+    #[test]
+    fn when_mocked_by_name_and_cleared_then_runs_normally() {
+        unsafe {
+            mock_by_name("mocking::mock_by_name::add", |a: u32, b: u32| {
+                MockResult::Return(a * b)
+            });
+        }
+        assert_eq!(12, add(3, 4));
+
+        add.clear_mock();
+
+        assert_eq!(7, add(3, 4));
+    }
+
+    #[test]
+    fn when_mocked_by_name_and_all_cleared_before_call_then_runs_normally() {
+        unsafe {
+            mock_by_name("mocking::mock_by_name::add", |a: u32, b: u32| {
+                MockResult::Return(a * b)
+            });
+        }
+
+        clear_all_mocks();
+
+        assert_eq!(7, add(3, 4));
+    }
+
+    #[test]
+    #[should_panic(expected = "Mock of mocking::mock_by_name::add set up by name has signature")]
+    fn when_mocked_by_name_with_wrong_signature_then_panics() {
+        unsafe {
+            mock_by_name("mocking::mock_by_name::add", |a: u64| MockResult::Return(a));
+        }
+
+        add(3, 4);
+    }
+}
+
+mod mock_with_state {
+    use super::*;
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[test]
+    fn when_mocked_with_state_then_state_is_updated() {
+        add.mock_with_state(0u32, |calls, (a, b)| {
+            *calls += 1;
+            MockResult::Return(a * b)
+        });
+
+        assert_eq!(12, add(3, 4));
+        assert_eq!(2, add(1, 2));
+
+        assert_eq!(2, add.mock_state::<u32>());
+    }
+
+    #[test]
+    fn when_mock_cleared_then_state_is_kept() {
+        add.mock_with_state(Vec::new(), |args: &mut Vec<(u32, u32)>, input| {
+            args.push(input);
+            MockResult::Continue(input)
+        });
+        assert_eq!(7, add(3, 4));
+
+        add.clear_mock();
+
+        assert_eq!(vec![(3, 4)], add.mock_state::<Vec<(u32, u32)>>());
+    }
+
+    #[test]
+    fn when_mocked_with_state_again_then_state_is_replaced() {
+        add.mock_with_state(1u32, |_, input| MockResult::Continue(input));
+        add.mock_with_state(2u32, |_, input| MockResult::Continue(input));
+
+        assert_eq!(2, add.mock_state::<u32>());
+    }
+
+    #[test]
+    #[should_panic(expected = "No mock state of type u64 for mocking::mock_with_state::add")]
+    fn when_state_type_is_wrong_then_panics() {
+        add.mock_with_state(1u32, |_, input| MockResult::Continue(input));
+
+        add.mock_state::<u64>();
+    }
+
+    #[test]
+    #[should_panic(expected = "No mock state of type u32 for mocking::mock_with_state::add")]
+    fn when_all_mocks_cleared_then_state_is_removed() {
+        add.mock_with_state(1u32, |_, input| MockResult::Continue(input));
+
+        clear_all_mocks();
+
+        add.mock_state::<u32>();
+    }
+}
+
+mod mock_return_once {
+    use super::*;
+
+    struct NotClone(u32);
+
+    #[mockable]
+    fn create(value: u32) -> NotClone {
+        NotClone(value)
+    }
+
+    #[test]
+    fn when_mocked_then_returns_value_once() {
+        create.mock_return_once(NotClone(10));
+
+        assert_eq!(10, create(1).0);
+        assert_eq!(1, create(1).0);
+        assert_eq!(2, create(2).0);
+    }
+
+    #[test]
+    fn when_mocked_again_then_returns_new_value_once() {
+        create.mock_return_once(NotClone(10));
+        create.mock_return_once(NotClone(20));
+
+        assert_eq!(20, create(1).0);
+        assert_eq!(1, create(1).0);
+    }
+}
+
+mod fallback_handler {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[mockable]
+    fn mockable_1() -> u32 {
+        1
+    }
+
+    fn record_calls() -> Rc<RefCell<Vec<UnmockedCall>>> {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let handler_calls = calls.clone();
+        set_fallback_handler(move |call| handler_calls.borrow_mut().push(*call));
+        calls
+    }
+
+    #[test]
+    fn when_not_mocked_then_handler_is_called_and_runs_normally() {
+        let calls = record_calls();
+
+        assert_eq!(3, add(1, 2));
+
+        let calls = calls.borrow();
+        assert_eq!(1, calls.len());
+        assert_eq!("mocking::fallback_handler::add", calls[0].path());
+        assert_eq!("(u32, u32)", calls[0].args_type());
+        assert_eq!("u32", calls[0].return_type());
+    }
+
+    #[test]
+    fn when_mocked_then_handler_is_not_called() {
+        let calls = record_calls();
+        add.mock_safe(|a, b| MockResult::Continue((a, b)));
+
+        assert_eq!(3, add(1, 2));
+
+        assert!(calls.borrow().is_empty());
+    }
+
+    #[test]
+    fn when_called_real_then_handler_is_not_called() {
+        let calls = record_calls();
+
+        assert_eq!(3, add.call_real((1, 2)));
+
+        assert!(calls.borrow().is_empty());
+    }
+
+    #[test]
+    fn when_handler_calls_mockable_then_handler_is_not_called_recursively() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let handler_calls = calls.clone();
+        set_fallback_handler(move |call| {
+            handler_calls.borrow_mut().push(call.path());
+            mockable_1();
+        });
+
+        add(1, 2);
+
+        assert_eq!(vec!["mocking::fallback_handler::add"], *calls.borrow());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unexpected call of mocking::fallback_handler::add")]
+    fn when_handler_panics_then_call_panics() {
+        set_fallback_handler(|call| panic!("Unexpected call of {}", call.path()));
+
+        add(1, 2);
+    }
+
+    #[test]
+    fn when_handler_cleared_then_it_is_not_called() {
+        let calls = record_calls();
+
+        clear_fallback_handler();
+        add(1, 2);
+
+        assert!(calls.borrow().is_empty());
+    }
+}
+
+mod mock_handle {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[mockable]
+    fn mockable_1() -> u32 {
+        1
+    }
+
+    #[test]
+    fn when_shared_mock_set_up_then_it_runs_in_current_thread() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        assert_eq!(12, add(3, 4));
+    }
+
+    #[test]
+    fn when_handle_applied_in_other_thread_then_mocks_run_there() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+        let handle = MockHandle::current();
+
+        let result = thread::spawn(move || {
+            handle.apply();
+            add(3, 4)
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(12, result);
+    }
+
+    #[cfg(not(feature = "global-mocks"))]
+    #[test]
+    fn when_handle_not_applied_in_other_thread_then_runs_normally() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        let result = thread::spawn(|| add(3, 4)).join().unwrap();
+
+        assert_eq!(7, result);
+    }
+
+    #[test]
+    fn when_handle_applied_in_many_threads_then_they_share_mock() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mock_calls = calls.clone();
+        mockable_1.mock_shared(move || {
+            mock_calls.fetch_add(1, Ordering::SeqCst);
+            MockResult::Return(10)
+        });
+        let handle = MockHandle::current();
+
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let handle = handle.clone();
+                thread::spawn(move || {
+                    handle.apply();
+                    mockable_1()
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            assert_eq!(10, worker.join().unwrap());
+        }
+        assert_eq!(4, calls.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn when_handle_applied_then_other_thread_can_pass_mocks_further() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+        let handle = MockHandle::current();
+
+        let result = thread::spawn(move || {
+            handle.apply();
+            let handle = MockHandle::current();
+            thread::spawn(move || {
+                handle.apply();
+                add(3, 4)
+            })
+            .join()
+            .unwrap()
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(12, result);
+    }
+
+    #[cfg(not(feature = "global-mocks"))]
+    #[test]
+    fn when_shared_mock_replaced_then_it_is_not_captured() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+        add.mock_safe(|a, b| MockResult::Return(a - b));
+        let handle = MockHandle::current();
+
+        let result = thread::spawn(move || {
+            handle.apply();
+            add(3, 4)
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(7, result);
+    }
+
+    #[test]
+    fn when_shared_mock_cleared_then_it_is_not_captured() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+        add.clear_mock();
+        let handle = MockHandle::current();
+
+        let result = thread::spawn(move || {
+            handle.apply();
+            add(3, 4)
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(7, result);
+    }
+
+    #[mockable]
+    async fn add_async(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[cfg(not(feature = "global-mocks"))]
+    #[test]
+    fn when_handle_run_in_other_thread_then_mocks_run_only_inside() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+        let handle = MockHandle::current();
+
+        let (inside, outside) = thread::spawn(move || (handle.run(|| add(3, 4)), add(3, 4)))
+            .join()
+            .unwrap();
+
+        assert_eq!(12, inside);
+        assert_eq!(7, outside);
+    }
+
+    #[test]
+    fn when_handle_run_then_mocks_shadow_thread_mocks() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+        let handle = MockHandle::current();
+        add.mock_safe(|a, b| MockResult::Return(a - b));
+
+        assert_eq!(12, handle.run(|| add(4, 3)));
+        assert_eq!(1, add(4, 3));
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn when_future_run_with_handle_on_workers_then_mocks_follow_it() {
+        add_async.mock_shared(|a, b| MockResult::Return(Box::pin(async move { a * b })));
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+        let handle = MockHandle::current();
+
+        let tasks: Vec<_> = (0..8)
+            .map(|i| {
+                tokio::spawn(handle.run_async(async move {
+                    let _ = tokio::task::yield_now().await;
+                    let sum = add_async(i, 2).await;
+                    let _ = tokio::task::yield_now().await;
+                    sum + add(i, 3)
+                }))
+            })
+            .collect();
+
+        for (i, task) in tasks.into_iter().enumerate() {
+            assert_eq!(5 * i as u32, task.await.unwrap());
+        }
+    }
+
+    #[test]
+    fn when_handle_created_inside_handle_run_then_it_captures_its_mocks() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+        let handle = MockHandle::current();
+
+        let result = thread::spawn(move || {
+            let inner_handle = handle.run(MockHandle::current);
+            thread::spawn(move || inner_handle.run(|| add(3, 4)))
+                .join()
+                .unwrap()
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(12, result);
+    }
+
+    #[cfg(not(feature = "global-mocks"))]
+    #[tokio::test(threaded_scheduler)]
+    async fn when_future_not_run_with_handle_on_workers_then_runs_normally() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        let result = tokio::spawn(async { add(3, 4) }).await.unwrap();
+
+        assert_eq!(7, result);
+    }
+
+    #[test]
+    fn when_registry_handle_installed_in_other_thread_then_mocks_run_there() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+        let handle = registry_handle();
+
+        let results: Vec<_> = (0..2)
+            .map(|_| {
+                let handle = handle.clone();
+                thread::spawn(move || {
+                    handle.install();
+                    add(3, 4)
+                })
+            })
+            .map(|thread| thread.join().unwrap())
+            .collect();
+
+        assert_eq!(vec![12, 12], results);
+    }
+
+    #[test]
+    fn when_thread_spawned_then_it_sees_shared_mocks() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        let result = spawn_thread(|| add(3, 4)).join().unwrap();
+
+        assert_eq!(12, result);
+    }
+
+    #[test]
+    fn when_thread_spawned_by_spawned_thread_then_it_sees_shared_mocks() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        let result = spawn_thread(|| spawn_thread(|| add(3, 4)).join().unwrap())
+            .join()
+            .unwrap();
+
+        assert_eq!(12, result);
+    }
+
+    #[cfg(not(feature = "global-mocks"))]
+    #[test]
+    fn when_thread_spawned_then_its_mocks_stay_in_it() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        let result = spawn_thread(|| {
+            mockable_1.mock_safe(|| MockResult::Return(2));
+            mockable_1()
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(2, result);
+        assert_eq!(1, mockable_1());
+        assert_eq!(12, add(3, 4));
+    }
+
+    #[test]
+    fn when_threads_spawned_in_scope_then_they_see_shared_mocks() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+        let mut results = vec![0; 3];
+
+        thread_scope(|scope| {
+            for (i, result) in results.iter_mut().enumerate() {
+                scope.spawn(move || *result = add(i as u32, 2));
+            }
+        });
+
+        assert_eq!(vec![0, 2, 4], results);
+    }
+
+    #[test]
+    fn when_thread_spawned_in_scope_by_scoped_thread_then_it_sees_shared_mocks() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        let result = thread_scope(|scope| {
+            scope
+                .spawn(move || scope.spawn(|| add(3, 4)).join().unwrap())
+                .join()
+                .unwrap()
+        });
+
+        assert_eq!(12, result);
+    }
+
+    #[test]
+    fn when_scoped_thread_borrows_local_data_then_it_sees_shared_mocks() {
+        let numbers = vec![3, 4];
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        let result =
+            thread_scope(|scope| scope.spawn(|| add(numbers[0], numbers[1])).join().unwrap());
+
+        assert_eq!(12, result);
+    }
+
+    #[cfg(not(feature = "global-mocks"))]
+    #[test]
+    fn when_mocked_in_scoped_thread_then_mock_stays_in_it() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        thread_scope(|scope| {
+            scope.spawn(|| mockable_1.mock_safe(|| MockResult::Return(2)));
+        });
+
+        assert_eq!(1, mockable_1());
+        assert_eq!(12, add(3, 4));
+    }
+}
+
+mod mock_result_pass {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[test]
+    fn when_only_mock_passes_then_runs_normally_with_passed_args() {
+        add.mock_safe(|a, b| MockResult::Pass((a, b + 1)));
+
+        assert_eq!(4, add(1, 2));
+    }
+
+    #[test]
+    fn when_pushed_mock_passes_then_shadowed_mock_runs() {
+        add.mock_safe(|a, b| MockResult::Return(a * b));
+        add.push_mock(|a, b| MockResult::Pass((a + 1, b)));
+
+        assert_eq!(8, add(3, 2));
+    }
+
+    #[test]
+    fn when_pushed_mock_continues_then_shadowed_mock_does_not_run() {
+        add.mock_safe(|a, b| MockResult::Return(a * b));
+        add.push_mock(|a, b| MockResult::Continue((a + 1, b)));
+
+        assert_eq!(6, add(3, 2));
+    }
+
+    #[test]
+    fn when_chain_of_mocks_pass_then_all_run_in_order() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let log_1 = log.clone();
+        let log_2 = log.clone();
+        add.mock_safe(move |a, b| {
+            log_1.borrow_mut().push("fault injection");
+            match a {
+                0 => MockResult::Return(0),
+                _ => MockResult::Pass((a, b)),
+            }
+        });
+        add.push_mock(move |a, b| {
+            log_2.borrow_mut().push("logging");
+            MockResult::Pass((a, b))
+        });
+
+        assert_eq!(3, add(1, 2));
+        assert_eq!(0, add(0, 2));
+
+        assert_eq!(
+            vec!["logging", "fault injection", "logging", "fault injection"],
+            *log.borrow()
+        );
+    }
+
+    #[test]
+    fn when_context_mock_passes_then_thread_mock_runs() {
+        add.mock_safe(|a, b| MockResult::Return(a * b));
+
+        MockContext::new()
+            .mock_safe(add, |a, b| MockResult::Pass((a, b + 1)))
+            .run(|| {
+                assert_eq!(9, add(3, 2));
+            });
+    }
+
+    #[test]
+    fn when_pushed_mock_recursively_calls_function_then_shadowed_mock_is_skipped() {
+        add.mock_safe(|a, b| MockResult::Return(a * b));
+        add.push_mock(|a, b| MockResult::Return(add(a, b) + 100));
+
+        assert_eq!(103, add(1, 2));
+    }
+}
+
+mod mock_context_run_async {
+    use super::*;
+
+    #[mockable]
+    fn mockable_1() -> u32 {
+        1
+    }
+
+    #[tokio::test]
+    async fn when_run_async_then_mocks_are_kept_across_await_points() {
+        MockContext::new()
+            .mock_safe(mockable_1, || MockResult::Return(10))
+            .run_async(async {
+                assert_eq!(10, mockable_1());
+                let _ = tokio::task::yield_now().await;
+                assert_eq!(10, mockable_1());
+            })
+            .await;
+
+        assert_eq!(1, mockable_1());
+    }
+
+    #[tokio::test]
+    async fn when_run_async_then_returns_future_output() {
+        let result = MockContext::new()
+            .mock_safe(mockable_1, || MockResult::Return(10))
+            .run_async(async { mockable_1() })
+            .await;
+
+        assert_eq!(10, result);
+    }
+
+    #[tokio::test]
+    async fn when_run_async_then_mocks_are_not_visible_to_other_tasks() {
+        let mocked = MockContext::new()
+            .mock_safe(mockable_1, || MockResult::Return(10))
+            .run_async(async {
+                let _ = tokio::task::yield_now().await;
+                mockable_1()
+            });
+        let not_mocked = async {
+            let _ = tokio::task::yield_now().await;
+            mockable_1()
+        };
+
+        assert_eq!((10, 1), tokio::join!(mocked, not_mocked));
+    }
+
+    #[tokio::test]
+    async fn when_run_async_future_dropped_then_mocks_are_removed() {
+        let future = MockContext::new()
+            .mock_safe(mockable_1, || MockResult::Return(10))
+            .run_async(async { mockable_1() });
+
+        drop(future);
+
+        assert_eq!(1, mockable_1());
+    }
+
+    #[tokio::test]
+    async fn when_run_async_then_expectations_count_calls_from_all_polls() {
+        MockContext::new()
+            .expect_calls(mockable_1, 2)
+            .run_async(async {
+                mockable_1();
+                let _ = tokio::task::yield_now().await;
+                mockable_1();
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "Mock expectations not met")]
+    async fn when_run_async_and_expectations_not_met_then_panics() {
+        MockContext::new()
+            .expect_calls(mockable_1, 2)
+            .run_async(async {
+                mockable_1();
+                let _ = tokio::task::yield_now().await;
+            })
+            .await;
+    }
+}
+
+mod mock_fixture {
+    use super::*;
+
+    #[mockable]
+    fn mockable_1() -> u32 {
+        1
+    }
+
+    #[mockable]
+    fn mockable_2() -> u32 {
+        2
+    }
+
+    fn fixture() -> MockFixture<'static> {
+        MockFixture::new()
+            .mock_safe(mockable_1, || MockResult::Return(10))
+            .mock_safe(mockable_2, || MockResult::Return(20))
+    }
+
+    #[test]
+    fn when_fixture_applied_then_its_mocks_are_set_up() {
+        MockContext::new().with_fixture(&fixture()).run(|| {
+            assert_eq!(10, mockable_1());
+            assert_eq!(20, mockable_2());
+        });
+        assert_eq!(1, mockable_1());
+    }
+
+    #[test]
+    fn when_fixture_applied_then_context_mocks_can_override_it() {
+        MockContext::new()
+            .with_fixture(&fixture())
+            .mock_safe(mockable_2, || MockResult::Return(30))
+            .run(|| {
+                assert_eq!(10, mockable_1());
+                assert_eq!(30, mockable_2());
+            });
+    }
+
+    #[test]
+    fn when_fixture_applied_to_many_contexts_then_each_gets_fresh_mock_state() {
+        let mut counter = 0;
+        let fixture = MockFixture::new().mock_safe(mockable_1, move || {
+            counter += 1;
+            MockResult::Return(counter)
+        });
+
+        MockContext::new().with_fixture(&fixture).run(|| {
+            assert_eq!(1, mockable_1());
+            assert_eq!(2, mockable_1());
+        });
+        MockContext::new().with_fixture(&fixture).run(|| {
+            assert_eq!(1, mockable_1());
+        });
+    }
+
+    #[test]
+    fn when_fixture_borrows_local_data_then_mocks_use_it() {
+        let value = 40;
+        let fixture = MockFixture::new().mock_safe(mockable_1, || MockResult::Return(value));
+
+        MockContext::new().with_fixture(&fixture).run(|| {
+            assert_eq!(40, mockable_1());
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Mock expectations not met")]
+    fn when_fixture_expectations_not_met_then_panics() {
+        let fixture = fixture().expect_calls(mockable_1, 1);
+
+        MockContext::new().with_fixture(&fixture).run(|| ());
+    }
+
+    #[test]
+    fn when_fixtures_combined_then_all_mocks_are_set_up() {
+        let fixture = MockFixture::new()
+            .mock_safe(mockable_1, || MockResult::Return(10))
+            .with_fixture(MockFixture::new().mock_safe(mockable_2, || MockResult::Return(20)));
+
+        MockContext::new().with_fixture(&fixture).run(|| {
+            assert_eq!(10, mockable_1());
+            assert_eq!(20, mockable_2());
+        });
+    }
+}
+
+mod mock_context_fork {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[mockable]
+    fn mockable_1() -> u32 {
+        1
+    }
+
+    #[mockable]
+    fn mockable_2() -> u32 {
+        2
+    }
+
+    #[test]
+    fn when_forked_context_run_in_other_thread_then_shared_mocks_are_set_up() {
+        let context = MockContext::new()
+            .mock_shared(mockable_1, || MockResult::Return(10))
+            .mock_safe(mockable_2, || MockResult::Return(20));
+        let forked = context.fork();
+
+        context.run(|| {
+            let worker = thread::spawn(move || forked.run(|| (mockable_1(), mockable_2())));
+
+            assert_eq!((10, 2), worker.join().unwrap());
+            assert_eq!(10, mockable_1());
+            assert_eq!(20, mockable_2());
+        });
+    }
+
+    #[test]
+    fn when_forked_context_is_not_run_then_other_thread_runs_normally() {
+        MockContext::new()
+            .mock_shared(mockable_1, || MockResult::Return(10))
+            .run(|| {
+                assert_eq!(1, thread::spawn(mockable_1).join().unwrap());
+            });
+    }
+
+    #[test]
+    fn when_forked_context_run_in_scoped_threads_then_mocks_can_borrow_local_data() {
+        let calls = AtomicUsize::new(0);
+        let context = MockContext::new().mock_shared(mockable_1, || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            MockResult::Return(10)
+        });
+        let forked = context.fork();
+
+        context.run(|| {
+            thread::scope(|scope| {
+                for _ in 0..4 {
+                    let forked = forked.clone();
+                    scope.spawn(move || forked.run(|| assert_eq!(10, mockable_1())));
+                }
+            });
+            mockable_1();
+        });
+
+        assert_eq!(5, calls.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn when_shared_mock_replaced_then_it_is_not_forked() {
+        let context = MockContext::new()
+            .mock_shared(mockable_1, || MockResult::Return(10))
+            .mock_safe(mockable_1, || MockResult::Return(20));
+        let forked = context.fork();
+
+        let result = thread::spawn(move || forked.run(mockable_1))
+            .join()
+            .unwrap();
+
+        assert_eq!(1, result);
+    }
+
+    #[test]
+    fn when_forked_context_extended_then_both_mocks_are_set_up() {
+        let forked = MockContext::new()
+            .mock_shared(mockable_1, || MockResult::Return(10))
+            .fork();
+
+        let result = thread::spawn(move || {
+            forked
+                .context()
+                .mock_safe(mockable_2, || MockResult::Return(20))
+                .run(|| (mockable_1(), mockable_2()))
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!((10, 20), result);
+    }
+
+    #[test]
+    #[should_panic(expected = "Strict mode")]
+    fn when_strict_context_forked_then_forked_is_strict() {
+        let forked = MockContext::new().strict().fork();
+
+        forked.run(mockable_1);
+    }
+}
+
+mod mock_context_merge {
+    use super::*;
+    use std::thread;
+
+    #[mockable]
+    fn mockable_1() -> u32 {
+        1
+    }
+
+    #[mockable]
+    fn mockable_2() -> u32 {
+        2
+    }
+
+    #[test]
+    fn when_merged_then_mocks_of_both_are_set_up() {
+        let context_a = MockContext::new().mock_safe(mockable_1, || MockResult::Return(10));
+        let context_b = MockContext::new().mock_safe(mockable_2, || MockResult::Return(20));
+
+        context_a.merge(context_b).run(|| {
+            assert_eq!(10, mockable_1());
+            assert_eq!(20, mockable_2());
+        });
+    }
+
+    #[test]
+    fn when_merged_then_mocks_of_other_context_win() {
+        let context_a = MockContext::new()
+            .mock_safe(mockable_1, || MockResult::Return(10))
+            .mock_safe(mockable_2, || MockResult::Return(20));
+        let context_b = MockContext::new().mock_safe(mockable_1, || MockResult::Return(30));
+
+        context_a.merge(context_b).run(|| {
+            assert_eq!(30, mockable_1());
+            assert_eq!(20, mockable_2());
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "mockable_2: expected 1 calls, got 0")]
+    fn when_merged_then_expectations_of_both_are_verified() {
+        let context_a = MockContext::new().expect_calls(mockable_1, 1);
+        let context_b = MockContext::new().expect_calls(mockable_2, 1);
+
+        context_a.merge(context_b).run(|| {
+            mockable_1();
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Strict mode")]
+    fn when_merged_with_strict_context_then_merged_is_strict() {
+        let context_a = MockContext::new();
+        let context_b = MockContext::new().strict();
+
+        context_a.merge(context_b).run(mockable_1);
+    }
+
+    #[test]
+    fn when_shared_mock_replaced_by_merged_mock_then_it_is_not_forked() {
+        let context_a = MockContext::new().mock_shared(mockable_1, || MockResult::Return(10));
+        let context_b = MockContext::new().mock_safe(mockable_1, || MockResult::Return(20));
+        let forked = context_a.merge(context_b).fork();
+
+        assert_eq!(
+            1,
+            thread::spawn(move || forked.run(mockable_1))
+                .join()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn when_merged_with_shared_mocks_then_they_are_forked() {
+        let context_a = MockContext::new().mock_shared(mockable_1, || MockResult::Return(10));
+        let context_b = MockContext::new().mock_shared(mockable_2, || MockResult::Return(20));
+        let forked = context_a.merge(context_b).fork();
+
+        let result = thread::spawn(move || forked.run(|| (mockable_1(), mockable_2())));
+
+        assert_eq!((10, 20), result.join().unwrap());
+    }
+}
+
+mod mock_context_mocked_functions {
+    use super::*;
+
+    #[mockable]
+    fn mockable_1() -> u32 {
+        1
+    }
+
+    #[mockable]
+    fn mockable_2() -> u32 {
+        2
+    }
+
+    struct Struct;
+
+    #[mockable]
+    impl Struct {
+        fn method(&self) -> u32 {
+            3
+        }
+    }
+
+    #[test]
+    fn when_no_mocks_then_returns_empty() {
+        assert!(MockContext::new().mocked_functions().is_empty());
+    }
+
+    #[test]
+    fn when_mocked_then_returns_sorted_paths() {
+        let context = MockContext::new()
+            .mock_safe(mockable_2, || MockResult::Return(20))
+            .mock_safe(Struct::method, |_| MockResult::Return(30))
+            .mock_shared(mockable_1, || MockResult::Return(10))
+            .mock_safe(mockable_2, || MockResult::Return(20));
+
+        assert_eq!(
+            vec![
+                "mocking::mock_context_mocked_functions::Struct::method",
+                "mocking::mock_context_mocked_functions::mockable_1",
+                "mocking::mock_context_mocked_functions::mockable_2",
+            ],
+            context.mocked_functions()
+        );
+    }
+
+    #[test]
+    fn when_expectation_set_up_then_function_is_not_returned() {
+        let context = MockContext::new().expect_calls(mockable_1, 1);
+
+        assert!(context.mocked_functions().is_empty());
+    }
+
+    #[test]
+    fn when_fixture_and_forked_and_merged_then_returns_all_paths() {
+        let fixture = MockFixture::new().mock_safe(mockable_1, || MockResult::Return(10));
+        let forked = MockContext::new()
+            .mock_shared(mockable_2, || MockResult::Return(20))
+            .fork();
+
+        let context = MockContext::new()
+            .with_fixture(&fixture)
+            .merge(forked.context());
+
+        assert_eq!(
+            vec![
+                "mocking::mock_context_mocked_functions::mockable_1",
+                "mocking::mock_context_mocked_functions::mockable_2",
+            ],
+            context.mocked_functions()
+        );
+    }
+}
+
+mod mocktopus_test {
+    use super::*;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    #[mockable]
+    pub fn function() -> &'static str {
+        "not mocked"
+    }
+
+    #[mocktopus::test]
+    fn when_mocked_in_test_then_returns_mock() {
+        function.mock_safe(|| MockResult::Return("mocked"));
+
+        assert_eq!("mocked", function());
+    }
+
+    #[mocktopus::test]
+    fn when_expectation_met_then_passes() {
+        function.expect_calls(1);
+
+        assert_eq!("not mocked", function());
+    }
+
+    #[mocktopus::test]
+    #[should_panic(expected = "expected 2 calls, got 1")]
+    fn when_expectation_unmet_then_panics() {
+        function.expect_calls(2);
+
+        function();
+    }
+
+    #[mocktopus::test]
+    fn when_returns_result_then_question_mark_works() -> Result<(), String> {
+        function.mock_safe(|| MockResult::Return("mocked"));
+
+        let parsed: Result<&str, String> = Ok(function());
+
+        assert_eq!("mocked", parsed?);
+        Ok(())
+    }
+
+    #[test]
+    fn when_mocks_set_before_then_cleared_before_body() {
+        function.mock_safe(|| MockResult::Return("mocked"));
+
+        let result = run_isolated_test(|| function());
+
+        assert_eq!("not mocked", result);
+    }
+
+    #[test]
+    fn when_run_after_other_test_then_starts_with_reset_thread() {
+        run_isolated_test(|| {
+            function.mock_safe(|| MockResult::Return("mocked"));
+            function();
+            set_strict_mode(true);
+            set_fallback_handler(|_| panic!("fallback handler called"));
+        });
+
+        run_isolated_test(|| {
+            assert_eq!(0, function.call_count());
+            assert_eq!("not mocked", function());
+            assert_eq!(1, function.call_count());
+        });
+    }
+
+    #[test]
+    fn when_body_panics_then_mocks_cleared() {
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            run_isolated_test(|| {
+                function.mock_safe(|| MockResult::Return("mocked"));
+                panic!("test failed");
+            })
+        }));
+
+        assert!(result.is_err());
+        assert_eq!("not mocked", function());
+    }
+
+    #[test]
+    fn when_expectation_unmet_then_mocks_cleared() {
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            run_isolated_test(|| {
+                function.mock_safe(|| MockResult::Return("mocked"));
+                function.expect_calls(2);
+            })
+        }));
+
+        assert!(result.is_err());
+        assert_eq!("not mocked", function());
+    }
+}
+
+mod mocktopus_tokio_test {
+    use super::*;
+
+    #[mockable]
+    async fn fetch(url: &str) -> String {
+        format!("fetched {}", url)
+    }
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[mocktopus::tokio_test]
+    async fn when_mocked_in_test_then_spawned_tasks_see_mock() {
+        fetch.mock_async(|_| async { "mocked".to_string() });
+
+        let task = tokio::spawn(async { fetch("a").await });
+
+        assert_eq!("mocked", task.await.unwrap());
+    }
+
+    #[mocktopus::tokio_test]
+    #[should_panic(expected = "expected 2 calls, got 1")]
+    async fn when_expectation_unmet_then_panics() {
+        fetch.expect_calls(2);
+
+        fetch("a").await;
+    }
+
+    #[mocktopus::tokio_test]
+    async fn when_returns_result_then_question_mark_works() -> Result<(), String> {
+        let parsed: Result<String, String> = Ok(fetch("a").await);
+
+        assert_eq!("fetched a", parsed?);
+        Ok(())
+    }
+
+    #[mocktopus::tokio_test(threaded_scheduler)]
+    async fn when_threaded_then_shared_mocks_follow_wrapped_tasks() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+        let handle = MockHandle::current();
+
+        let task = tokio::spawn(handle.run_async(async { add(3, 4) }));
+
+        assert_eq!(12, task.await.unwrap());
+        assert_eq!(12, add(3, 4));
+    }
+}
+
+mod mocktopus_async_std_test {
+    use super::*;
+
+    #[mockable]
+    async fn fetch(url: &str) -> String {
+        format!("fetched {}", url)
+    }
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[mocktopus::async_std_test]
+    async fn when_mocked_in_test_then_body_sees_mock() {
+        fetch.mock_async(|_| async { "mocked".to_string() });
+
+        assert_eq!("mocked", fetch("a").await);
+    }
+
+    #[mocktopus::async_std_test]
+    #[should_panic(expected = "expected 2 calls, got 1")]
+    async fn when_expectation_unmet_then_panics() {
+        fetch.expect_calls(2);
+
+        fetch("a").await;
+    }
+
+    #[mocktopus::async_std_test]
+    async fn when_returns_result_then_question_mark_works() -> Result<(), String> {
+        let parsed: Result<String, String> = Ok(fetch("a").await);
+
+        assert_eq!("fetched a", parsed?);
+        Ok(())
+    }
+
+    #[mocktopus::async_std_test]
+    async fn when_task_spawned_then_shared_mocks_follow_wrapped_tasks() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+        let handle = MockHandle::current();
+
+        let task = async_std::task::spawn(handle.run_async(async { add(3, 4) }));
+
+        assert_eq!(12, task.await);
+    }
+}
+
+mod mocktopus_smol_test {
+    use super::*;
+
+    #[mockable]
+    async fn fetch(url: &str) -> String {
+        format!("fetched {}", url)
+    }
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[mocktopus::smol_test]
+    async fn when_mocked_in_test_then_body_sees_mock() {
+        fetch.mock_async(|_| async { "mocked".to_string() });
+
+        assert_eq!("mocked", fetch("a").await);
+    }
+
+    #[mocktopus::smol_test]
+    #[should_panic(expected = "expected 2 calls, got 1")]
+    async fn when_expectation_unmet_then_panics() {
+        fetch.expect_calls(2);
+
+        fetch("a").await;
+    }
+
+    #[mocktopus::smol_test]
+    async fn when_task_spawned_then_shared_mocks_follow_wrapped_tasks() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+        let handle = MockHandle::current();
+
+        let task = smol::spawn(handle.run_async(async { add(3, 4) }));
+
+        assert_eq!(12, task.await);
+    }
+}
+
+mod mock_context_hooks {
+    use super::*;
+    use std::cell::RefCell;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    #[mockable]
+    pub fn function() -> &'static str {
+        "not mocked"
+    }
+
+    #[test]
+    fn when_run_then_hooks_run_around_body_with_exit_hooks_reversed() {
+        let log = RefCell::new(Vec::new());
+
+        MockContext::new()
+            .on_enter(|| log.borrow_mut().push("enter 1"))
+            .on_enter(|| log.borrow_mut().push("enter 2"))
+            .on_exit(|| log.borrow_mut().push("exit 1"))
+            .on_exit(|| log.borrow_mut().push("exit 2"))
+            .run(|| log.borrow_mut().push("body"));
+
+        assert_eq!(
+            vec!["enter 1", "enter 2", "body", "exit 2", "exit 1"],
+            *log.borrow()
+        );
+    }
+
+    #[test]
+    fn when_hooks_run_then_mocks_are_set_up() {
+        let log = RefCell::new(Vec::new());
+
+        MockContext::new()
+            .mock_safe(function, || MockResult::Return("mocked"))
+            .on_enter(|| log.borrow_mut().push(function()))
+            .on_exit(|| log.borrow_mut().push(function()))
+            .run(|| {});
+
+        assert_eq!(vec!["mocked", "mocked"], *log.borrow());
+        assert_eq!("not mocked", function());
+    }
+
+    #[test]
+    fn when_body_panics_then_exit_hooks_run() {
+        let log = RefCell::new(Vec::new());
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            MockContext::new()
+                .on_exit(|| log.borrow_mut().push("exit"))
+                .run(|| panic!("test failed"))
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(vec!["exit"], *log.borrow());
+    }
+
+    #[test]
+    fn when_merged_then_other_hooks_nested_inside() {
+        let log = RefCell::new(Vec::new());
+        let other = MockContext::new()
+            .on_enter(|| log.borrow_mut().push("other enter"))
+            .on_exit(|| log.borrow_mut().push("other exit"));
+
+        MockContext::new()
+            .on_enter(|| log.borrow_mut().push("enter"))
+            .on_exit(|| log.borrow_mut().push("exit"))
+            .merge(other)
+            .run(|| {});
+
+        assert_eq!(
+            vec!["enter", "other enter", "other exit", "exit"],
+            *log.borrow()
+        );
+    }
+
+    #[tokio::test]
+    async fn when_run_async_then_hooks_run_around_future() {
+        let log = RefCell::new(Vec::new());
+
+        MockContext::new()
+            .on_enter(|| log.borrow_mut().push("enter"))
+            .on_exit(|| log.borrow_mut().push("exit"))
+            .run_async(async {
+                let _ = tokio::task::yield_now().await;
+                log.borrow_mut().push("body");
+            })
+            .await;
+
+        assert_eq!(vec!["enter", "body", "exit"], *log.borrow());
+    }
+
+    #[test]
+    fn when_async_context_dropped_before_poll_then_hooks_not_run() {
+        let log = RefCell::new(Vec::new());
+
+        let future = MockContext::new()
+            .on_enter(|| log.borrow_mut().push("enter"))
+            .on_exit(|| log.borrow_mut().push("exit"))
+            .run_async(async {});
+        drop(future);
+
+        assert!(log.borrow().is_empty());
+    }
+}
+
+mod cleanup_on_panic {
+    use super::*;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    #[mockable]
+    pub fn function() -> &'static str {
+        "not mocked"
+    }
+
+    fn panic_message(result: Result<(), Box<dyn std::any::Any + Send>>) -> String {
+        let payload = result.unwrap_err();
+        match payload.downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => payload.downcast_ref::<String>().unwrap().clone(),
+        }
+    }
+
+    #[test]
+    fn when_guard_dropped_during_panic_then_mocks_cleared() {
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            let _guard = ClearMocksGuard::new();
+            function.mock_safe(|| MockResult::Return("mocked"));
+            panic!("test failed");
+        }));
+
+        assert!(result.is_err());
+        assert_eq!("not mocked", function());
+    }
+
+    #[test]
+    fn when_guard_dropped_then_mocks_cleared() {
+        {
+            let _guard = ClearMocksGuard::new();
+            function.mock_safe(|| MockResult::Return("mocked"));
+            assert_eq!("mocked", function());
+        }
+
+        assert_eq!("not mocked", function());
+    }
+
+    #[test]
+    fn when_context_with_unmet_expectation_panics_then_original_panic_reported() {
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            MockContext::new()
+                .mock_safe(function, || MockResult::Return("mocked"))
+                .expect_calls(function, 1)
+                .run(|| panic!("test failed"))
+        }));
+
+        assert_eq!("test failed", panic_message(result));
+        assert_eq!("not mocked", function());
+    }
+
+    #[test]
+    fn when_scope_panics_then_original_panic_reported() {
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            mock_scope(|scope| {
+                function.mock_scoped(scope, || MockResult::Return("mocked"));
+                panic!("test failed");
+            })
+        }));
+
+        assert_eq!("test failed", panic_message(result));
+        assert_eq!("not mocked", function());
+    }
+}
+
+mod mock_result_return_from_args {
+    use super::*;
+
+    struct Config {
+        name: String,
+        alias: String,
+    }
+
+    #[mockable]
+    impl Config {
+        fn name(&self) -> &str {
+            &self.name
+        }
+    }
+
+    #[mockable]
+    fn pick<'a>(values: &'a [String], index: usize) -> &'a str {
+        &values[index]
+    }
+
+    #[mockable]
+    async fn first_len(values: Vec<String>) -> usize {
+        values[0].len()
+    }
+
+    fn config() -> Config {
+        Config {
+            name: "name".to_string(),
+            alias: "alias".to_string(),
+        }
+    }
+
+    #[test]
+    fn when_mocked_method_then_returns_reference_to_field() {
+        Config::name.mock_safe(|config| {
+            MockResult::ReturnFromArgs((config,), Box::new(|(config,)| &config.alias))
+        });
+
+        assert_eq!("alias", config().name());
+    }
+
+    #[test]
+    fn when_mocked_with_modified_args_then_closure_receives_modified_args() {
+        pick.mock_safe(|values, index| {
+            MockResult::ReturnFromArgs(
+                (values, index + 1),
+                Box::new(|&(values, index)| &values[index]),
+            )
+        });
+        let values = vec!["a".to_string(), "b".to_string()];
+
+        assert_eq!("b", pick(&values, 0));
+    }
+
+    #[tokio::test]
+    async fn when_ready_mocked_then_returns_value_created_from_args() {
+        first_len.mock_ready(|values| {
+            MockResult::ReturnFromArgs((values,), Box::new(|(values,)| values.len()))
+        });
+
+        assert_eq!(2, first_len(vec!["abc".to_string(), "d".to_string()]).await);
+    }
+}
+
+mod mock_result_return_iter {
+    use super::*;
+    use tokio::stream::{Stream, StreamExt};
+
+    #[mockable(box_impl_trait)]
+    fn numbers() -> impl Iterator<Item = u32> {
+        0..3
+    }
+
+    #[mockable(box_impl_trait)]
+    fn send_items<'a>(items: &'a [u32]) -> impl Iterator<Item = &'a u32> + Send + 'a {
+        items.iter()
+    }
+
+    #[mockable(box_impl_trait)]
+    fn events() -> impl Stream<Item = u32> {
+        tokio::stream::iter(0..3)
+    }
+
+    #[mockable(box_impl_trait)]
+    fn send_events() -> impl Stream<Item = u32> + Send {
+        tokio::stream::iter(0..3)
+    }
+
+    #[test]
+    fn when_not_mocked_then_returns_real_iterator() {
+        assert_eq!(vec![0, 1, 2], numbers().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn when_mocked_then_returns_iterator_over_values() {
+        numbers.mock_safe(|| MockResult::return_iter(vec![5, 6]));
+
+        assert_eq!(vec![5, 6], numbers().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn when_mocked_send_with_borrowed_values_then_returns_iterator_over_them() {
+        static ITEMS: [u32; 2] = [5, 6];
+        send_items.mock_safe(|_| MockResult::return_iter(&ITEMS));
+
+        assert_eq!(vec![&5, &6], send_items(&[1]).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn when_stream_not_mocked_then_returns_real_stream() {
+        assert_eq!(vec![0, 1, 2], events().collect::<Vec<_>>().await);
+    }
+
+    #[tokio::test]
+    async fn when_stream_mocked_then_returns_stream_of_values() {
+        events.mock_safe(|| MockResult::return_stream(vec![5, 6]));
+        send_events.mock_safe(|| MockResult::return_stream(Some(7)));
+
+        assert_eq!(vec![5, 6], events().collect::<Vec<_>>().await);
+        assert_eq!(vec![7], send_events().collect::<Vec<_>>().await);
+    }
+
+    #[mockable(box_impl_trait)]
+    async fn subscribe(topic: &str) -> impl Stream<Item = String> {
+        let topic = topic.to_string();
+        let _ = tokio::task::yield_now().await;
+        if topic.is_empty() {
+            return tokio::stream::iter(vec!["empty".to_string()]);
+        }
+        tokio::stream::iter(vec![topic])
+    }
+
+    #[mockable(box_impl_trait)]
+    async fn send_subscribe() -> impl Stream<Item = u32> + Send {
+        tokio::stream::iter(0..3)
+    }
+
+    struct Client;
+
+    #[mockable(box_impl_trait)]
+    impl Client {
+        async fn subscribe(&self) -> impl Stream<Item = u32> {
+            tokio::stream::iter(0..3)
+        }
+    }
+
+    #[tokio::test]
+    async fn when_async_stream_not_mocked_then_returns_real_stream() {
+        assert_eq!(vec!["a"], subscribe("a").await.collect::<Vec<_>>().await);
+        assert_eq!(vec!["empty"], subscribe("").await.collect::<Vec<_>>().await);
+        assert_eq!(
+            vec![0, 1, 2],
+            Client.subscribe().await.collect::<Vec<_>>().await
+        );
+    }
+
+    #[tokio::test]
+    async fn when_async_stream_mocked_then_returns_stream_of_values() {
+        subscribe.mock_return_stream(vec!["b".to_string(), "c".to_string()]);
+        Client::subscribe.mock_return_stream(vec![5]);
+
+        assert_eq!(
+            vec!["b", "c"],
+            subscribe("a").await.collect::<Vec<_>>().await
+        );
+        assert_eq!(
+            vec!["b", "c"],
+            subscribe("a").await.collect::<Vec<_>>().await
+        );
+        assert_eq!(vec![5], Client.subscribe().await.collect::<Vec<_>>().await);
+    }
+
+    #[tokio::test]
+    async fn when_async_send_stream_mocked_ready_then_returns_stream_of_values() {
+        send_subscribe.mock_ready(|| MockResult::return_stream(vec![7, 8]));
+
+        assert_eq!(vec![7, 8], send_subscribe().await.collect::<Vec<_>>().await);
+    }
+}
+
+mod track_caller {
+    use super::*;
+    use std::cell::Cell;
+    use std::panic::{self, UnwindSafe};
+    use std::sync::Once;
+
+    thread_local! {
+        static PANIC_LINE: Cell<Option<u32>> = Cell::new(None);
+    }
+
+    fn panic_line(function: impl FnOnce() + UnwindSafe) -> Option<u32> {
+        static INSTALL_HOOK: Once = Once::new();
+        INSTALL_HOOK.call_once(|| {
+            let default_hook = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                PANIC_LINE.with(|line| line.set(info.location().map(|location| location.line())));
+                default_hook(info);
+            }));
+        });
+        PANIC_LINE.with(|line| line.set(None));
+        assert!(panic::catch_unwind(function).is_err());
+        PANIC_LINE.with(|line| line.get())
+    }
+
+    #[mockable]
+    #[track_caller]
+    fn checked(value: u32) -> u32 {
+        assert!(value > 0, "value is zero");
+        value
+    }
+
+    #[test]
+    fn when_not_mocked_then_real_panic_reports_caller_location() {
+        let expected_line = line!() + 2;
+        let line = panic_line(|| {
+            let _ = checked(0);
+        });
+
+        assert_eq!(Some(expected_line), line);
+    }
+
+    #[test]
+    fn when_mocked_with_panic_then_reports_caller_location() {
+        checked.mock_safe(|_| MockResult::Panic("mocked".to_string()));
+
+        let expected_line = line!() + 2;
+        let line = panic_line(|| {
+            let _ = checked(1);
+        });
+
+        assert_eq!(Some(expected_line), line);
+    }
+
+    #[test]
+    fn when_continue_mocked_then_real_panic_reports_caller_location() {
+        checked.mock_safe(|_| MockResult::Continue((0,)));
+
+        let expected_line = line!() + 2;
+        let line = panic_line(|| {
+            let _ = checked(1);
+        });
+
+        assert_eq!(Some(expected_line), line);
+    }
+}
+
+mod fn_pointers {
+    use super::*;
+
+    #[mockable]
+    fn double(value: u32) -> u32 {
+        value * 2
+    }
+
+    struct Struct;
+
+    #[mockable]
+    impl Struct {
+        fn method(&self, value: u32) -> u32 {
+            value + 1
+        }
+    }
+
+    fn apply(function: fn(u32) -> u32, value: u32) -> u32 {
+        function(value)
+    }
+
+    #[test]
+    fn when_not_mocked_then_pointer_runs_normally() {
+        assert_eq!(4, apply(double, 2));
+    }
+
+    #[test]
+    fn when_mocked_then_pointer_runs_mock() {
+        let pointer: fn(u32) -> u32 = double;
+        let method_pointer: fn(&Struct, u32) -> u32 = Struct::method;
+
+        double.mock_safe(|value| MockResult::Return(value * 10));
+        Struct::method.mock_safe(|_, value| MockResult::Return(value * 10));
+
+        assert_eq!(20, pointer(2));
+        assert_eq!(20, apply(double, 2));
+        assert_eq!(20, method_pointer(&Struct, 2));
+    }
+
+    #[test]
+    fn when_pointer_mocked_then_runs_normally() {
+        let pointer: fn(u32) -> u32 = double;
+
+        pointer.mock_safe(|value| MockResult::Return(value * 10));
+
+        assert_eq!(4, pointer(2));
+        assert_eq!(4, double(2));
+    }
+}
+
+mod mockable_statics {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{LazyLock, OnceLock};
+
+    #[mockable]
+    static LAZY: LazyLock<String> = LazyLock::new(|| "not mocked".to_string());
+
+    fn init() -> u32 {
+        1
+    }
+
+    #[mockable]
+    static LAZY_FN: std::sync::LazyLock<u32> = std::sync::LazyLock::new(init);
+
+    #[mockable]
+    static COUNTER: AtomicUsize = AtomicUsize::new(1);
+
+    #[mockable]
+    static ONCE: OnceLock<u32> = OnceLock::new();
+
+    #[test]
+    fn when_not_mocked_then_returns_real_value() {
+        assert_eq!("not mocked", *LAZY);
+        assert_eq!(1, *LAZY_FN);
+        assert_eq!(1, COUNTER.load(Ordering::SeqCst));
+        assert!(!LAZY.is_mocked());
+    }
+
+    #[test]
+    fn when_mocked_then_returns_mock_value() {
+        LAZY.mock_value("mocked".to_string());
+        LAZY_FN.mock_value(2);
+        COUNTER.mock_value(AtomicUsize::new(2));
+        ONCE.mock_value(OnceLock::from(2));
+
+        assert_eq!("mocked", *LAZY);
+        assert_eq!(6, LAZY.len());
+        assert_eq!(2, *LAZY_FN);
+        assert_eq!(2, COUNTER.load(Ordering::SeqCst));
+        assert_eq!(Some(&2), ONCE.get());
+        assert!(LAZY.is_mocked());
+        assert_eq!("not mocked", LAZY.real_value());
+    }
+
+    #[test]
+    fn when_mock_cleared_then_returns_real_value() {
+        LAZY.mock_value("mocked".to_string());
+        LAZY_FN.mock_value(2);
+
+        LAZY.clear_mock();
+        clear_all_mocks();
+
+        assert_eq!("not mocked", *LAZY);
+        assert_eq!(1, *LAZY_FN);
+    }
+
+    #[test]
+    fn when_mocked_then_other_threads_get_real_value() {
+        LAZY.mock_value("mocked".to_string());
+
+        let value = std::thread::spawn(|| LAZY.to_string()).join().unwrap();
+
+        assert_eq!("not mocked", value);
+    }
+}
+
+mod nested_fns {
+    use super::*;
+
+    #[mockable]
+    fn outer(value: u32) -> u32 {
+        #[mockable]
+        fn inner(value: u32) -> u32 {
+            value + 1
+        }
+
+        inner(value) * 10
+    }
+
+    #[mockable]
+    async fn outer_async(value: u32) -> u32 {
+        #[mockable]
+        fn inner(value: u32) -> u32 {
+            value + 1
+        }
+
+        inner(value) * 10
+    }
+
+    struct Struct;
+
+    #[mockable]
+    impl Struct {
+        fn method(&self, value: u32) -> u32 {
+            #[mockable]
+            fn inner(value: u32) -> u32 {
+                value + 1
+            }
+
+            inner(value) * 10
+        }
+    }
+
+    #[test]
+    fn when_not_mocked_then_runs_normally() {
+        assert_eq!(20, outer(1));
+        assert_eq!(20, Struct.method(1));
+    }
+
+    #[test]
+    fn when_nested_fn_mocked_by_name_then_runs_mock() {
+        unsafe {
+            mock_by_name("mocking::nested_fns::outer::inner", |_: u32| {
+                MockResult::Return(5u32)
+            });
+            mock_by_name("mocking::nested_fns::Struct::method::inner", |_: u32| {
+                MockResult::Return(6u32)
+            });
+        }
+
+        assert_eq!(50, outer(1));
+        assert_eq!(60, Struct.method(1));
+    }
+
+    #[tokio::test]
+    async fn when_nested_fn_of_async_fn_mocked_by_name_then_runs_mock() {
+        unsafe {
+            mock_by_name("mocking::nested_fns::outer_async::inner", |_: u32| {
+                MockResult::Return(5u32)
+            });
+        }
+
+        assert_eq!(50, outer_async(1).await);
+    }
+}
+
+mod struct_doubles {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Store {
+        items: Vec<String>,
+    }
+
+    #[mockable_struct(pub)]
+    impl Store {
+        pub fn new() -> Self {
+            Store { items: Vec::new() }
+        }
+
+        pub fn len(&self) -> usize {
+            self.items.len()
+        }
+
+        pub fn get(&self, index: usize) -> &str {
+            &self.items[index]
+        }
+
+        pub fn push(&mut self, item: impl Display) {
+            self.items.push(item.to_string());
+        }
+
+        pub fn parse<T: std::str::FromStr>(&self, index: usize) -> Option<T> {
+            self.items[index].parse().ok()
+        }
+
+        pub async fn load(&mut self) {
+            self.items.push("loaded".to_string());
+        }
+    }
+
+    #[test]
+    fn when_not_mocked_then_runs_normally() {
+        StoreDouble::new().run(|| {
+            let mut store = Store::new();
+            store.push(1);
+
+            assert_eq!(1, store.len());
+            assert_eq!("1", store.get(0));
+            assert_eq!(Some(1), store.parse::<u32>(0));
+        });
+    }
+
+    #[test]
+    fn when_mocked_with_values_then_returns_clones() {
+        StoreDouble::new()
+            .new_returns(Store {
+                items: vec!["a".to_string()],
+            })
+            .len_returns(5)
+            .parse_returns::<u32>(Some(7))
+            .run(|| {
+                let store = Store::new();
+
+                assert_eq!(vec!["a".to_string()], store.items);
+                assert_eq!(5, store.len());
+                assert_eq!(5, Store::new().len());
+                assert_eq!(Some(7), store.parse::<u32>(0));
+                assert_eq!(None, store.parse::<i32>(0));
+            });
+    }
+
+    #[test]
+    fn when_mocked_with_closures_then_runs_mocks() {
+        let mocked = "mocked".to_string();
+        StoreDouble::new()
+            .get_with(|_, _| MockResult::Return(&mocked))
+            .push_with(|store: &mut Store, item: u32| MockResult::Continue((store, item * 2)))
+            .run(|| {
+                let mut store = Store::new();
+                store.push(1u32);
+                store.push("a");
+
+                assert_eq!(vec!["2".to_string(), "a".to_string()], store.items);
+                assert_eq!("mocked", store.get(0));
+            });
+    }
+
+    #[test]
+    fn when_converted_into_context_then_mocks_more_fns() {
+        StoreDouble::new()
+            .len_returns(5)
+            .into_context()
+            .mock_safe(Store::get, |_, _| MockResult::Return("mocked"))
+            .run(|| {
+                let store = Store::new();
+
+                assert_eq!(5, store.len());
+                assert_eq!("mocked", store.get(0));
+            });
+    }
+
+    #[tokio::test]
+    async fn when_async_fn_mocked_in_context_then_runs_mock() {
+        let mut store = Store::new();
+        StoreDouble::new()
+            .len_returns(5)
+            .into_context()
+            .mock_safe(Store::load, |_| MockResult::Return(Box::pin(async {})))
+            .run_async(async {
+                store.load().await;
+
+                assert_eq!(5, store.len());
+            })
+            .await;
+
+        assert!(store.items.is_empty());
+    }
+
+    #[test]
+    fn when_strict_then_panics_on_unmocked_calls() {
+        let result = std::panic::catch_unwind(|| {
+            StoreDouble::new()
+                .len_returns(5)
+                .strict()
+                .run(|| Store { items: Vec::new() }.get(0).to_string())
+        });
+
+        assert!(result.is_err());
+    }
+}
+
+mod doctest_harness {
+    use super::*;
+
+    #[mockable]
+    fn get() -> u32 {
+        0
+    }
+
+    #[test]
+    fn when_run_then_clears_mocks_before_and_after_example() {
+        get.mock_safe(|| MockResult::Return(1));
+
+        let result = mocktopus::doctest::run(|| {
+            assert_eq!(0, get());
+            get.mock_safe(|| MockResult::Return(2));
+            get()
+        });
+
+        assert_eq!(2, result);
+        assert_eq!(0, get());
+    }
+
+    #[test]
+    #[should_panic]
+    fn when_expectation_unmet_then_panics() {
+        mocktopus::doctest::run(|| get.expect_calls(1));
+    }
+}
+
+mod pending_then {
+    use super::*;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+
+    #[mockable]
+    async fn fetch(url: &str) -> String {
+        format!("fetched {}", url)
+    }
+
+    #[test]
+    fn when_polled_then_pending_given_number_of_times() {
+        let mut context = Context::from_waker(Waker::noop());
+        let mut future = PendingThen::new(2, "ready");
+
+        assert_eq!(Poll::Pending, Pin::new(&mut future).poll(&mut context));
+        assert_eq!(1, future.pending_polls());
+        assert_eq!(Poll::Pending, Pin::new(&mut future).poll(&mut context));
+        assert_eq!(
+            Poll::Ready("ready"),
+            Pin::new(&mut future).poll(&mut context)
+        );
+    }
+
+    #[test]
+    fn when_returned_by_mock_then_mocked_fn_is_pending() {
+        fetch.mock_async(|_| PendingThen::new(1, "mocked".to_string()));
+        let mut context = Context::from_waker(Waker::noop());
+        let mut future = fetch("a");
+
+        assert_eq!(Poll::Pending, future.as_mut().poll(&mut context));
+        assert_eq!(
+            Poll::Ready("mocked".to_string()),
+            future.as_mut().poll(&mut context)
+        );
+    }
+
+    #[tokio::test]
+    async fn when_awaited_then_resolves() {
+        fetch.mock_async(|_| PendingThen::new(3, "mocked".to_string()));
+
+        assert_eq!("mocked", fetch("a").await);
+    }
+
+    #[tokio::test]
+    async fn when_raced_then_loses_to_ready_future() {
+        fetch.mock_async(|_| PendingThen::new(2, "mocked".to_string()));
+
+        let result = tokio::select! {
+            body = fetch("a") => body,
+            _ = async {} => "cached".to_string(),
+        };
+
+        assert_eq!("cached", result);
+    }
+}
+
+mod future_observer {
+    use super::*;
+    use std::future::Future;
+    use std::task::{Context, Waker};
+    use std::time::Duration;
+
+    #[mockable]
+    async fn fetch(url: &str) -> String {
+        format!("fetched {}", url)
+    }
+
+    fn mock_fetch(observer: &FutureObserver, pending_polls: usize) {
+        let observer = observer.clone();
+        fetch.mock_async(move |_| {
+            observer.observe(PendingThen::new(pending_polls, "mocked".to_string()))
+        });
+    }
+
+    #[tokio::test]
+    async fn when_awaited_then_records_completion() {
+        let observer = FutureObserver::new();
+        mock_fetch(&observer, 2);
+
+        assert_eq!("mocked", fetch("a").await);
+
+        assert_eq!(1, observer.completed_count());
+        assert_eq!(0, observer.cancelled_count());
+        assert_eq!(0, observer.in_flight_count());
+    }
+
+    #[tokio::test]
+    async fn when_losing_select_then_records_cancellation() {
+        let observer = FutureObserver::new();
+        mock_fetch(&observer, 2);
+
+        tokio::select! {
+            _ = fetch("a") => panic!("mock should be pending"),
+            _ = async {} => (),
+        };
+
+        assert_eq!(0, observer.completed_count());
+        assert_eq!(1, observer.cancelled_count());
+    }
+
+    #[tokio::test]
+    async fn when_timed_out_then_records_cancellation() {
+        let observer = FutureObserver::new();
+        let mock_observer = observer.clone();
+        fetch.mock_async(move |_| mock_observer.observe(std::future::pending()));
+
+        let result = tokio::time::timeout(Duration::from_millis(1), fetch("a")).await;
+
+        assert!(result.is_err());
+        assert_eq!(1, observer.cancelled_count());
+    }
+
+    #[tokio::test]
+    async fn when_not_dropped_yet_then_in_flight() {
+        let observer = FutureObserver::new();
+        mock_fetch(&observer, 0);
+
+        let first = fetch("a");
+        let second = fetch("a");
+        assert_eq!(2, observer.in_flight_count());
+
+        first.await;
+        drop(second);
+
+        assert_eq!(1, observer.completed_count());
+        assert_eq!(1, observer.cancelled_count());
+        assert_eq!(0, observer.in_flight_count());
+    }
+
+    #[tokio::test]
+    async fn when_awaited_then_counts_polls_until_ready() {
+        let observer = FutureObserver::new();
+        mock_fetch(&observer, 2);
+
+        fetch("a").await;
+        fetch("a").await;
+
+        assert_eq!(6, observer.poll_count());
+    }
+
+    #[tokio::test]
+    async fn when_dropped_without_polling_then_counts_no_polls() {
+        let observer = FutureObserver::new();
+        mock_fetch(&observer, 2);
+
+        drop(fetch("a"));
+
+        assert_eq!(0, observer.poll_count());
+        assert_eq!(1, observer.cancelled_count());
+    }
+
+    #[test]
+    fn when_polled_manually_then_future_counts_own_polls() {
+        let observer = FutureObserver::new();
+        let mut first = Box::pin(observer.observe(PendingThen::new(5, ())));
+        let mut second = Box::pin(observer.observe(PendingThen::new(5, ())));
+        let mut context = Context::from_waker(Waker::noop());
+
+        for _ in 0..3 {
+            assert!(first.as_mut().poll(&mut context).is_pending());
+        }
+        assert!(second.as_mut().poll(&mut context).is_pending());
+
+        assert_eq!(3, first.poll_count());
+        assert_eq!(1, second.poll_count());
+        assert_eq!(4, observer.poll_count());
+    }
+}
+
+#[cfg(feature = "tokio")]
+mod spawning_tasks_with_mocks {
+    use super::*;
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn when_task_spawned_then_it_sees_shared_mocks() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        let task = mocktopus::mocking::spawn(async {
+            tokio::task::yield_now().await;
+            add(3, 4)
+        });
+
+        assert_eq!(12, task.await.unwrap());
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn when_task_spawned_by_spawned_task_then_it_sees_shared_mocks() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        let task = mocktopus::mocking::spawn(async {
+            mocktopus::mocking::spawn(async { add(3, 4) })
+                .await
+                .unwrap()
+        });
+
+        assert_eq!(12, task.await.unwrap());
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn when_blocking_fn_spawned_then_it_sees_shared_mocks_only_inside() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        let result = mocktopus::mocking::spawn_blocking(|| add(3, 4))
+            .await
+            .unwrap();
+        let unmocked = tokio::task::spawn_blocking(|| add(3, 4)).await.unwrap();
+
+        assert_eq!(12, result);
+        assert_eq!(7, unmocked);
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn when_task_spawned_with_tokio_then_it_runs_normally() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        let result = tokio::spawn(async { add(3, 4) }).await.unwrap();
+
+        assert_eq!(7, result);
+    }
+}
+
+#[cfg(feature = "async-std")]
+mod spawning_async_std_tasks_with_mocks {
+    use super::*;
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[test]
+    fn when_task_spawned_then_it_sees_shared_mocks() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        let task = mocktopus::mocking::spawn_async_std(async {
+            async_std::task::yield_now().await;
+            add(3, 4)
+        });
+
+        assert_eq!(12, async_std::task::block_on(task));
+    }
+
+    #[test]
+    fn when_blocking_fn_spawned_then_it_sees_shared_mocks_only_inside() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        let result = mocktopus::mocking::spawn_blocking_async_std(|| add(3, 4));
+        let unmocked = async_std::task::spawn_blocking(|| add(3, 4));
+
+        assert_eq!(12, async_std::task::block_on(result));
+        assert_eq!(7, async_std::task::block_on(unmocked));
+    }
+}
+
+#[cfg(feature = "smol")]
+mod spawning_smol_tasks_with_mocks {
+    use super::*;
+
+    #[mockable]
+    fn add(a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    #[test]
+    fn when_task_spawned_then_it_sees_shared_mocks() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        let task = mocktopus::mocking::spawn_smol(async {
+            smol::future::yield_now().await;
+            add(3, 4)
+        });
+
+        assert_eq!(12, smol::block_on(task));
+    }
+
+    #[test]
+    fn when_blocking_fn_unblocked_then_it_sees_shared_mocks_only_inside() {
+        add.mock_shared(|a, b| MockResult::Return(a * b));
+
+        let result = mocktopus::mocking::unblock_smol(|| add(3, 4));
+        let unmocked = smol::unblock(|| add(3, 4));
+
+        assert_eq!(12, smol::block_on(result));
+        assert_eq!(7, smol::block_on(unmocked));
+    }
+}
+
+mod async_ret_macros {
+    use super::*;
+    use mocktopus::{async_err, async_ok, async_ret};
+
+    #[mockable]
+    async fn get_string(arg: &str) -> String {
+        format!("not mocked {}", arg)
+    }
+
+    #[mockable]
+    async fn fetch(url: &str) -> Result<String, String> {
+        Ok(format!("fetched {}", url))
+    }
+
+    trait Trait {
+        async fn method(&self) -> u32;
+    }
+
+    struct Struct;
+
+    #[mockable]
+    impl Trait for Struct {
+        async fn method(&self) -> u32 {
+            0
+        }
+    }
+
+    #[tokio::test]
+    async fn when_ret_then_returns_value() {
+        get_string.mock_safe(|arg| {
+            let arg = arg.to_string();
+            async_ret!(format!("mocked {}", arg))
+        });
+        Struct::method.mock_safe(|_| async_ret!(1));
+
+        assert_eq!("mocked a", get_string("a").await);
+        assert_eq!(1, Struct.method().await);
+    }
+
+    #[tokio::test]
+    async fn when_ok_then_returns_ok() {
+        fetch.mock_safe(|_| async_ok!("mocked".to_string()));
+
+        assert_eq!(Ok("mocked".to_string()), fetch("a").await);
+    }
+
+    #[tokio::test]
+    async fn when_err_then_returns_err() {
+        fetch.mock_safe(|_| async_err!("timeout".to_string()));
+
+        assert_eq!(Err("timeout".to_string()), fetch("a").await);
+    }
+}
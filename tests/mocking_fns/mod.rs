@@ -1,6 +1,12 @@
 use super::*;
 
+mod when_fn_const_generic;
 mod when_fn_generic;
 mod when_fn_generic_async;
+mod when_fn_higher_ranked;
+mod when_fn_impl_trait_arg;
+mod when_fn_never_type;
+mod when_fn_precise_capturing;
 mod when_fn_regular;
 mod when_fn_regular_async;
+mod when_fn_where_clause;
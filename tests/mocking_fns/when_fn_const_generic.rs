@@ -0,0 +1,56 @@
+use super::*;
+
+#[mockable]
+fn function<const N: usize>(values: [u8; N]) -> u32 {
+    values.iter().map(|&value| value as u32).sum()
+}
+
+#[mockable]
+fn function_mixed<T: Into<u32> + Copy, const N: usize>(values: [T; N]) -> u32 {
+    values.iter().map(|&value| value.into()).sum()
+}
+
+#[mockable]
+async fn function_async<const N: usize>(values: [u8; N]) -> u32 {
+    values.iter().map(|&value| value as u32).sum()
+}
+
+#[test]
+fn and_not_mocked_then_runs_normally() {
+    assert_eq!(3, function([1, 2]));
+    assert_eq!(6, function([1, 2, 3]));
+    assert_eq!(3, function_mixed([1u8, 2]));
+}
+
+#[test]
+fn and_continue_mocked_then_runs_with_modified_args_for_mocked_const_only() {
+    function::<2>.mock_safe(|a| MockResult::Continue(([a[0] + 1, a[1] + 1],)));
+
+    assert_eq!(5, function([1, 2]));
+    assert_eq!(6, function([1, 2, 3]));
+}
+
+#[test]
+fn and_return_mocked_then_returns_mocking_result_for_mocked_const_only() {
+    function::<2>.mock_safe(|_| MockResult::Return(0));
+
+    assert_eq!(0, function([1, 2]));
+    assert_eq!(6, function([1, 2, 3]));
+}
+
+#[test]
+fn and_mixed_mocked_then_returns_mocking_result_for_mocked_type_and_const_only() {
+    function_mixed::<u8, 2>.mock_safe(|_| MockResult::Return(0));
+
+    assert_eq!(0, function_mixed([1u8, 2]));
+    assert_eq!(6, function_mixed([1u8, 2, 3]));
+    assert_eq!(3, function_mixed([1u16, 2]));
+}
+
+#[tokio::test]
+async fn and_async_mocked_then_returns_mocking_result_for_mocked_const_only() {
+    function_async::<2>.mock_safe(|_| MockResult::Return(Box::pin(async { 0 })));
+
+    assert_eq!(0, function_async([1, 2]).await);
+    assert_eq!(6, function_async([1, 2, 3]).await);
+}
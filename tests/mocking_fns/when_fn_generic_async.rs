@@ -32,3 +32,19 @@ async fn and_return_mocked_then_returns_mocking_result_for_mocked_type_only() {
     assert_eq!("mocked true 2.5", function(true, 2.5f32).await);
     assert_eq!("true abc", function(true, "abc").await);
 }
+
+#[tokio::test]
+async fn and_async_mocked_then_returns_mocking_result_for_mocked_type_only() {
+    function::<f32>.mock_async(|a, b| async move { format!("mocked {} {}", a, b) });
+
+    assert_eq!("mocked true 2.5", function(true, 2.5f32).await);
+    assert_eq!("true abc", function(true, "abc").await);
+}
+
+#[tokio::test]
+async fn and_ready_mocked_then_returns_mocking_result_for_mocked_type_only() {
+    function::<f32>.mock_ready(|a, b| MockResult::Return(format!("mocked {} {}", a, b)));
+
+    assert_eq!("mocked true 2.5", function(true, 2.5f32).await);
+    assert_eq!("true abc", function(true, "abc").await);
+}
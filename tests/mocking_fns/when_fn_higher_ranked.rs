@@ -0,0 +1,69 @@
+use super::*;
+
+type Identity = fn(&str) -> &str;
+
+fn identity(value: &str) -> &str {
+    value
+}
+
+#[mockable]
+fn bound<F: for<'a> Fn(&'a str) -> &'a str>(map: F) -> usize {
+    map("abc").len()
+}
+
+#[mockable]
+fn impl_trait_arg(map: impl for<'a> Fn(&'a str) -> &'a str) -> usize {
+    map("abc").len()
+}
+
+#[mockable]
+fn trait_object(map: &dyn for<'a> Fn(&'a str) -> &'a str) -> usize {
+    map("abc").len()
+}
+
+#[mockable]
+fn ref_bound<T>(values: T) -> usize
+where
+    for<'a> &'a T: IntoIterator,
+{
+    (&values).into_iter().count()
+}
+
+#[mockable]
+unsafe fn fn_pointer(map: for<'a> fn(&'a str) -> &'a str) -> usize {
+    map("abc").len()
+}
+
+#[mockable(box_impl_trait)]
+fn returned() -> impl for<'a> Fn(&'a str) -> &'a str {
+    |value| value
+}
+
+#[test]
+fn and_not_mocked_then_runs_normally() {
+    assert_eq!(3, bound(identity));
+    assert_eq!(3, impl_trait_arg(identity));
+    assert_eq!(3, trait_object(&identity));
+    assert_eq!(2, ref_bound(vec![1, 2]));
+    assert_eq!(3, unsafe { fn_pointer(identity) });
+    assert_eq!("abc", returned()("abc"));
+}
+
+#[test]
+fn and_mocked_then_returns_mocking_result() {
+    bound::<Identity>.mock_safe(|_| MockResult::Return(1));
+    impl_trait_arg::<Identity>.mock_safe(|_| MockResult::Return(2));
+    trait_object.mock_safe(|_| MockResult::Return(3));
+    ref_bound::<Vec<u8>>.mock_safe(|_| MockResult::Return(4));
+    unsafe {
+        mock_unsafe_fn(fn_pointer, |_: Identity| MockResult::Return(5usize));
+    }
+    returned.mock_safe(|| MockResult::Return(Box::new(|_| "mocked")));
+
+    assert_eq!(1, bound(identity as Identity));
+    assert_eq!(2, impl_trait_arg(identity as Identity));
+    assert_eq!(3, trait_object(&identity));
+    assert_eq!(4, ref_bound(vec![1u8, 2]));
+    assert_eq!(5, unsafe { fn_pointer(identity) });
+    assert_eq!("mocked", returned()("abc"));
+}
@@ -0,0 +1,75 @@
+use super::*;
+
+#[mockable]
+fn function(arg: bool, fn_generic: impl Display) -> String {
+    format!("{} {}", arg, fn_generic)
+}
+
+#[mockable]
+fn function_nested(values: impl Iterator<Item = impl Display>) -> String {
+    values.map(|value| value.to_string()).collect()
+}
+
+struct Struct;
+
+#[mockable]
+impl Struct {
+    fn method(&self, fn_generic: &impl Display) -> String {
+        format!("method {}", fn_generic)
+    }
+}
+
+trait Trait {
+    fn trait_method(&self, fn_generic: impl Display) -> String;
+}
+
+#[mockable]
+impl Trait for Struct {
+    fn trait_method(&self, fn_generic: impl Display) -> String {
+        format!("trait method {}", fn_generic)
+    }
+}
+
+#[test]
+fn and_not_mocked_then_runs_normally() {
+    assert_eq!("true 2.5", function(true, 2.5f32));
+    assert_eq!("12", function_nested(vec![1, 2].into_iter()));
+    assert_eq!("method abc", Struct.method(&"abc"));
+}
+
+#[test]
+fn and_continue_mocked_then_runs_with_modified_args_for_mocked_type_only() {
+    function::<f32>.mock_safe(|a, b| MockResult::Continue((!a, b + 1.)));
+
+    assert_eq!("false 3.5", function(true, 2.5f32));
+    assert_eq!("true abc", function(true, "abc"));
+}
+
+#[test]
+fn and_return_mocked_then_returns_mocking_result_for_mocked_type_only() {
+    function::<f32>.mock_safe(|a, b| MockResult::Return(format!("mocked {} {}", a, b)));
+
+    assert_eq!("mocked true 2.5", function(true, 2.5f32));
+    assert_eq!("true abc", function(true, "abc"));
+}
+
+#[test]
+fn and_nested_mocked_then_type_params_are_ordered_like_impl_keywords() {
+    function_nested::<std::vec::IntoIter<u8>, u8>
+        .mock_safe(|_| MockResult::Return("mocked".to_string()));
+
+    assert_eq!("mocked", function_nested(vec![1u8, 2].into_iter()));
+    assert_eq!("12", function_nested(vec![1u16, 2].into_iter()));
+}
+
+#[test]
+fn and_method_mocked_then_returns_mocking_result() {
+    Struct::method::<&str>.mock_safe(|_, b| MockResult::Return(format!("mocked {}", b)));
+
+    assert_eq!("mocked abc", Struct.method(&"abc"));
+}
+
+#[test]
+fn and_trait_impl_then_runs_normally() {
+    assert_eq!("trait method abc", Struct.trait_method("abc"));
+}
@@ -0,0 +1,81 @@
+use super::*;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+#[mockable]
+fn fail(message: &str) -> ! {
+    panic!("real {}", message)
+}
+
+#[mockable]
+unsafe fn fail_unsafe(message: &str) -> ! {
+    panic!("real {}", message)
+}
+
+#[mockable]
+extern "C-unwind" fn fail_extern() -> ! {
+    panic!("real")
+}
+
+struct Struct;
+
+#[mockable]
+impl Struct {
+    fn fail(&self, message: &str) -> ! {
+        panic!("real {}", message)
+    }
+
+    async fn fail_async(&self) -> ! {
+        panic!("real")
+    }
+}
+
+fn panic_message(function: impl FnOnce()) -> String {
+    let payload = catch_unwind(AssertUnwindSafe(function)).expect_err("function returned");
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast::<&str>()
+            .expect("not a message")
+            .to_string(),
+    }
+}
+
+#[test]
+fn and_not_mocked_then_runs_normally() {
+    assert_eq!("real abc", panic_message(|| fail("abc")));
+    assert_eq!("real abc", panic_message(|| unsafe { fail_unsafe("abc") }));
+    assert_eq!("real abc", panic_message(|| Struct.fail("abc")));
+}
+
+#[test]
+fn and_continue_mocked_then_runs_with_modified_args() {
+    fail.mock_safe(|_| MockResult::Continue(("mocked",)));
+    unsafe {
+        mock_unsafe_fn(fail_unsafe, |_: &str| {
+            MockResult::<_, Never>::Continue(("mocked",))
+        });
+    }
+    Struct::fail.mock_safe(|s, _| MockResult::Continue((s, "mocked")));
+
+    assert_eq!("real mocked", panic_message(|| fail("abc")));
+    assert_eq!(
+        "real mocked",
+        panic_message(|| unsafe { fail_unsafe("abc") })
+    );
+    assert_eq!("real mocked", panic_message(|| Struct.fail("abc")));
+}
+
+#[test]
+fn and_panic_mocked_then_panics_with_mock_message() {
+    fail.mock_safe(|_| MockResult::Panic("mocked".to_string()));
+    unsafe {
+        mock_unsafe_fn(fail_extern, || {
+            MockResult::<_, Never>::Panic("mocked".to_string())
+        });
+    }
+    Struct::fail_async.mock_safe(|_| MockResult::Panic("mocked".to_string()));
+
+    assert!(panic_message(|| fail("abc")).ends_with("panicked: mocked"));
+    assert!(panic_message(|| fail_extern()).ends_with("panicked: mocked"));
+    assert!(panic_message(|| drop(Struct.fail_async())).ends_with("panicked: mocked"));
+}
@@ -0,0 +1,45 @@
+use super::*;
+
+#[mockable]
+fn function<'a, 'b>(text: &'a str, _ignored: &'b str) -> impl Iterator<Item = char> + use<'a> {
+    text.chars()
+}
+
+#[mockable(box_impl_trait)]
+fn boxed<'a>(text: &'a str) -> impl Iterator<Item = char> + use<'a> + 'a {
+    text.chars()
+}
+
+trait Trait {
+    fn trait_method(&self) -> impl Iterator<Item = u32> + use<Self>;
+}
+
+struct Struct;
+
+#[mockable]
+impl Trait for Struct {
+    fn trait_method(&self) -> impl Iterator<Item = u32> + use<> {
+        0..3
+    }
+}
+
+#[test]
+fn and_not_mocked_then_runs_normally() {
+    assert_eq!("ab", function("ab", "c").collect::<String>());
+    assert_eq!("ab", boxed("ab").collect::<String>());
+    assert_eq!(3, Struct.trait_method().count());
+}
+
+#[test]
+fn and_continue_mocked_then_runs_with_modified_args() {
+    function.mock_safe(|_, ignored| MockResult::Continue(("mocked", ignored)));
+
+    assert_eq!("mocked", function("ab", "c").collect::<String>());
+}
+
+#[test]
+fn and_boxed_return_mocked_then_returns_mocking_result() {
+    boxed.mock_safe(|_| MockResult::Return(Box::new("mocked".chars())));
+
+    assert_eq!("mocked", boxed("ab").collect::<String>());
+}
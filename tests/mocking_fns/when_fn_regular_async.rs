@@ -27,3 +27,43 @@ async fn and_return_mocked_then_returns_mocking_result() {
 
     assert_eq!("mocked true", function(true).await);
 }
+
+#[tokio::test]
+async fn and_async_mocked_then_returns_mocking_result() {
+    function.mock_async(|a| async move { format!("mocked {}", a) });
+
+    assert_eq!("mocked true", function(true).await);
+}
+
+#[tokio::test]
+async fn and_ready_mocked_then_returns_mocking_result() {
+    function.mock_ready(|a| MockResult::Return(format!("mocked {}", a)));
+
+    assert_eq!("mocked true", function(true).await);
+}
+
+#[tokio::test]
+async fn and_ready_continue_mocked_then_runs_with_modified_args() {
+    function.mock_ready(|a| MockResult::Continue((!a,)));
+
+    assert_eq!("false", function(true).await);
+}
+
+#[mockable]
+async fn function_result(arg: bool) -> Result<String, String> {
+    Ok(format!("{}", arg))
+}
+
+#[tokio::test]
+async fn and_ok_mocked_then_returns_ok() {
+    function_result.mock_return_ok("mocked".to_string());
+
+    assert_eq!(Ok("mocked".to_string()), function_result(true).await);
+}
+
+#[tokio::test]
+async fn and_err_mocked_then_returns_err() {
+    function_result.mock_return_err("mocked".to_string());
+
+    assert_eq!(Err("mocked".to_string()), function_result(true).await);
+}
@@ -0,0 +1,68 @@
+use super::*;
+
+#[mockable]
+fn unsized_arg<T>(value: &T) -> String
+where
+    T: ?Sized + Display,
+{
+    value.to_string()
+}
+
+#[mockable]
+fn higher_ranked<F>(map: F) -> usize
+where
+    F: for<'a> Fn(&'a str) -> &'a str,
+{
+    map("abc").len()
+}
+
+#[mockable]
+fn assoc_type_bound<I>(values: I) -> u32
+where
+    I: IntoIterator,
+    I::Item: Into<u32>,
+{
+    values.into_iter().map(Into::into).sum()
+}
+
+#[mockable]
+fn lifetime_bound<'a, 'b: 'a, T>(first: &'a T, second: &'b T) -> &'a T
+where
+    T: ?Sized,
+{
+    let _ = second;
+    first
+}
+
+#[test]
+fn and_not_mocked_then_runs_normally() {
+    assert_eq!("abc", unsized_arg("abc"));
+    assert_eq!(3, higher_ranked(|s| s));
+    assert_eq!(3, assoc_type_bound(vec![1u8, 2]));
+    assert_eq!("abc", lifetime_bound("abc", "def"));
+}
+
+#[test]
+fn and_unsized_mocked_then_returns_mocking_result() {
+    unsized_arg::<str>.mock_safe(|a| MockResult::Return(format!("mocked {}", a)));
+
+    assert_eq!("mocked abc", unsized_arg("abc"));
+    assert_eq!("1", unsized_arg(&1));
+}
+
+#[test]
+fn and_assoc_type_bound_mocked_then_returns_mocking_result_for_mocked_type_only() {
+    assoc_type_bound::<Vec<u8>>.mock_safe(|_| MockResult::Return(0));
+
+    assert_eq!(0, assoc_type_bound(vec![1u8, 2]));
+    assert_eq!(3, assoc_type_bound(vec![1u16, 2]));
+}
+
+#[test]
+fn and_lifetime_bound_mocked_then_returns_mocking_result() {
+    unsafe {
+        lifetime_bound::<str>.mock_raw(|_, b| MockResult::Return(b));
+    }
+
+    assert_eq!("def", lifetime_bound("abc", "def"));
+}
@@ -1,6 +1,10 @@
 use super::*;
 
+mod when_enum_regular_method_regular;
+mod when_struct_const_generic_method_const_generic;
 mod when_struct_generic_method_generic;
 mod when_struct_generic_method_regular;
+mod when_struct_regular_method_arbitrary_self;
 mod when_struct_regular_method_generic;
 mod when_struct_regular_method_regular;
+mod when_union_regular_method_regular;
@@ -0,0 +1,57 @@
+use super::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Idle,
+    Running(u32),
+    Done { code: i32 },
+}
+
+#[mockable]
+impl State {
+    fn next(self) -> Self {
+        match self {
+            Self::Idle => Self::Running(0),
+            Self::Running(step) if step >= 2 => Self::Done { code: 0 },
+            Self::Running(step) => Self::Running(step + 1),
+            done @ Self::Done { .. } => done,
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        matches!(*self, State::Done { .. })
+    }
+
+    fn advance(&mut self) {
+        *self = self.next();
+    }
+}
+
+#[test]
+fn and_not_mocked_then_runs_normally() {
+    let mut state = State::Running(1);
+    state.advance();
+
+    assert_eq!(State::Running(0), State::Idle.next());
+    assert_eq!(State::Running(2), state);
+    assert!(!state.is_done());
+}
+
+#[test]
+fn and_mocked_then_returns_mocking_result() {
+    State::next.mock_safe(|_| MockResult::Return(State::Done { code: 1 }));
+    State::is_done.mock_safe(|_| MockResult::Return(true));
+    let mut state = State::Idle;
+    state.advance();
+
+    assert_eq!(State::Done { code: 1 }, State::Idle.next());
+    assert_eq!(State::Done { code: 1 }, state);
+    assert!(State::Idle.is_done());
+}
+
+#[test]
+fn and_continue_mocked_then_runs_with_modified_self() {
+    State::next.mock_safe(|_| MockResult::Continue((State::Running(2),)));
+
+    assert_eq!(State::Done { code: 0 }, State::Idle.next());
+}
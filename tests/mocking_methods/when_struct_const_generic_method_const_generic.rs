@@ -0,0 +1,37 @@
+use super::*;
+
+struct Struct<const M: usize>;
+
+#[mockable]
+impl<const M: usize> Struct<M> {
+    fn static_method<const N: usize>() -> usize {
+        M * N
+    }
+
+    fn ref_method<const N: usize>(&self, arg: usize) -> usize {
+        M * N + arg
+    }
+}
+
+#[test]
+fn and_not_mocked_then_runs_normally() {
+    assert_eq!(6, Struct::<2>::static_method::<3>());
+    assert_eq!(7, Struct::<2>.ref_method::<3>(1));
+}
+
+#[test]
+fn and_static_mocked_then_returns_mocking_result_for_mocked_consts_only() {
+    Struct::<2>::static_method::<3>.mock_safe(|| MockResult::Return(0));
+
+    assert_eq!(0, Struct::<2>::static_method::<3>());
+    assert_eq!(8, Struct::<2>::static_method::<4>());
+    assert_eq!(9, Struct::<3>::static_method::<3>());
+}
+
+#[test]
+fn and_ref_mocked_then_returns_mocking_result_for_mocked_consts_only() {
+    Struct::<2>::ref_method::<3>.mock_safe(|_, b| MockResult::Return(b));
+
+    assert_eq!(1, Struct::<2>.ref_method::<3>(1));
+    assert_eq!(9, Struct::<2>.ref_method::<4>(1));
+}
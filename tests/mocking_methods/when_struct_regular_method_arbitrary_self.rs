@@ -0,0 +1,68 @@
+use super::*;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+
+struct Struct(u8);
+
+#[mockable]
+impl Struct {
+    fn arc_method(self: Arc<Self>, arg: bool) -> String {
+        format!("{} {}", self.0, arg)
+    }
+
+    fn rc_method(self: Rc<Self>, arg: bool) -> String {
+        format!("{} {}", self.0, arg)
+    }
+
+    fn box_method(self: Box<Self>, arg: bool) -> String {
+        format!("{} {}", self.0, arg)
+    }
+
+    fn pin_method(self: Pin<&mut Self>, arg: bool) -> String {
+        format!("{} {}", self.0, arg)
+    }
+}
+
+trait Trait {
+    fn trait_arc_method(self: Arc<Self>) -> String;
+}
+
+#[mockable]
+impl Trait for Struct {
+    fn trait_arc_method(self: Arc<Self>) -> String {
+        format!("trait {}", self.0)
+    }
+}
+
+#[test]
+fn and_not_mocked_then_runs_normally() {
+    assert_eq!("2 true", Arc::new(Struct(2)).arc_method(true));
+    assert_eq!("2 true", Rc::new(Struct(2)).rc_method(true));
+    assert_eq!("2 true", Box::new(Struct(2)).box_method(true));
+    assert_eq!("2 true", Pin::new(&mut Struct(2)).pin_method(true));
+    assert_eq!("trait 2", Arc::new(Struct(2)).trait_arc_method());
+}
+
+#[test]
+fn and_continue_mocked_then_runs_with_modified_args() {
+    Struct::arc_method.mock_safe(|_, b| MockResult::Continue((Arc::new(Struct(3)), !b)));
+    Struct::rc_method.mock_safe(|_, b| MockResult::Continue((Rc::new(Struct(3)), !b)));
+    Struct::box_method.mock_safe(|_, b| MockResult::Continue((Box::new(Struct(3)), !b)));
+
+    assert_eq!("3 false", Arc::new(Struct(2)).arc_method(true));
+    assert_eq!("3 false", Rc::new(Struct(2)).rc_method(true));
+    assert_eq!("3 false", Box::new(Struct(2)).box_method(true));
+}
+
+#[test]
+fn and_return_mocked_then_returns_mocking_result() {
+    Struct::arc_method.mock_safe(|a, b| MockResult::Return(format!("mocked {} {}", a.0, b)));
+    Struct::pin_method.mock_safe(|a, b| MockResult::Return(format!("mocked {} {}", a.0, b)));
+    <Struct as Trait>::trait_arc_method
+        .mock_safe(|a| MockResult::Return(format!("mocked {}", a.0)));
+
+    assert_eq!("mocked 2 true", Arc::new(Struct(2)).arc_method(true));
+    assert_eq!("mocked 2 true", Pin::new(&mut Struct(2)).pin_method(true));
+    assert_eq!("mocked 2", Arc::new(Struct(2)).trait_arc_method());
+}
@@ -0,0 +1,37 @@
+use super::*;
+
+union Bits {
+    int: u32,
+    float: f32,
+}
+
+#[mockable]
+impl Bits {
+    fn int(&self) -> u32 {
+        unsafe { self.int }
+    }
+
+    unsafe fn float(&self) -> f32 {
+        self.float
+    }
+}
+
+#[test]
+fn and_not_mocked_then_runs_normally() {
+    let bits = Bits { float: 1.0 };
+
+    assert_eq!(0x3f80_0000, bits.int());
+    assert_eq!(1.0, unsafe { bits.float() });
+}
+
+#[test]
+fn and_mocked_then_returns_mocking_result() {
+    Bits::int.mock_safe(|_| MockResult::Return(1));
+    unsafe {
+        mock_unsafe_fn(Bits::float, |_: &Bits| MockResult::Return(2.0f32));
+    }
+    let bits = Bits { int: 0 };
+
+    assert_eq!(1, bits.int());
+    assert_eq!(2.0, unsafe { bits.float() });
+}
@@ -1,7 +1,15 @@
 use super::*;
 
+mod when_enum_generic_method_regular_async;
 mod when_struct_complex_method_regular_async;
+mod when_struct_generic_method_assoc_items_async;
 mod when_struct_generic_method_generic_async;
 mod when_struct_generic_method_regular_async;
+mod when_struct_generic_method_where_clause_async;
+mod when_struct_regular_method_arbitrary_self_async;
 mod when_struct_regular_method_generic_async;
+mod when_struct_regular_method_higher_ranked_async;
+mod when_struct_regular_method_local_async;
 mod when_struct_regular_method_regular_async;
+mod when_struct_regular_method_returns_box_future;
+mod when_trait_impl_with_async_trait;
@@ -0,0 +1,43 @@
+use super::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State<T> {
+    Idle,
+    Running(T),
+    Done { code: i32 },
+}
+
+#[mockable]
+impl<T: Copy + Send + Sync> State<T> {
+    async fn start(self, value: T) -> Self {
+        match self {
+            Self::Idle => Self::Running(value),
+            Self::Running(_) => Self::Done { code: 0 },
+            done @ Self::Done { .. } => done,
+        }
+    }
+
+    async fn code(&self) -> Option<i32> {
+        match *self {
+            Self::Done { code } => Some(code),
+            _ => None,
+        }
+    }
+}
+
+#[tokio::test]
+async fn and_not_mocked_then_runs_normally() {
+    assert_eq!(State::Running(1), State::Idle.start(1).await);
+    assert_eq!(State::Done { code: 0 }, State::Running(1).start(2).await);
+    assert_eq!(Some(3), State::<u8>::Done { code: 3 }.code().await);
+}
+
+#[tokio::test]
+async fn and_mocked_then_returns_mocking_result() {
+    State::<u8>::start.mock_safe(|_, _| MockResult::Return(Box::pin(async { State::Idle })));
+    State::<u8>::code.mock_safe(|_| MockResult::Return(Box::pin(async { Some(1) })));
+
+    assert_eq!(State::Idle, State::Idle.start(1u8).await);
+    assert_eq!(Some(1), State::<u8>::Idle.code().await);
+    assert_eq!(State::Running(1u16), State::Idle.start(1u16).await);
+}
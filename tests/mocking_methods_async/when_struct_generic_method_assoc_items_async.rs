@@ -0,0 +1,41 @@
+use super::*;
+
+struct Struct<T>(T);
+
+#[mockable]
+impl<T: Into<u32> + Copy + Send + Sync> Struct<T> {
+    const OFFSET: u32 = 10;
+
+    fn offset() -> u32 {
+        Self::OFFSET
+    }
+
+    async fn ref_method(&self) -> u32 {
+        self.0.into() + Self::OFFSET + Self::offset()
+    }
+
+    async fn new_method(value: T) -> Self {
+        let created: Self = Struct(value);
+        created
+    }
+}
+
+#[tokio::test]
+async fn and_not_mocked_then_runs_normally() {
+    assert_eq!(21, Struct(1u8).ref_method().await);
+    assert_eq!(1, Struct::new_method(1u8).await.0);
+}
+
+#[tokio::test]
+async fn and_assoc_fn_mocked_then_calls_from_async_method_run_mock() {
+    Struct::<u8>::offset.mock_safe(|| MockResult::Return(0));
+
+    assert_eq!(11, Struct(1u8).ref_method().await);
+}
+
+#[tokio::test]
+async fn and_return_mocked_then_returns_mocking_result() {
+    Struct::<u8>::new_method.mock_safe(|_| MockResult::Return(Box::pin(async { Struct(2) })));
+
+    assert_eq!(2, Struct::new_method(1u8).await.0);
+}
@@ -0,0 +1,47 @@
+use super::*;
+
+struct Struct<T>(T);
+
+#[mockable]
+impl<T> Struct<T>
+where
+    T: Clone + Into<u32> + Send + Sync,
+{
+    async fn ref_method<U>(&self, arg: U) -> u32
+    where
+        U: Into<u32> + Send,
+        Self: Sync,
+    {
+        self.0.clone().into() + arg.into()
+    }
+
+    async fn val_method(self, other: Self) -> Self
+    where
+        Self: Sized,
+    {
+        let _ = other;
+        self
+    }
+}
+
+#[tokio::test]
+async fn and_not_mocked_then_runs_normally() {
+    assert_eq!(3, Struct(1u8).ref_method(2u8).await);
+    assert_eq!(1, Struct(1u8).val_method(Struct(2)).await.0);
+}
+
+#[tokio::test]
+async fn and_return_mocked_then_returns_mocking_result_for_mocked_type_only() {
+    Struct::<u8>::ref_method::<u8>
+        .mock_safe(|_, b| MockResult::Return(Box::pin(async move { b.into() })));
+
+    assert_eq!(2, Struct(1u8).ref_method(2u8).await);
+    assert_eq!(3, Struct(1u16).ref_method(2u8).await);
+}
+
+#[tokio::test]
+async fn and_self_typed_mocked_then_returns_mocking_result() {
+    Struct::<u8>::val_method.mock_safe(|_, b| MockResult::Return(Box::pin(async move { b })));
+
+    assert_eq!(2, Struct(1u8).val_method(Struct(2)).await.0);
+}
@@ -0,0 +1,48 @@
+use super::*;
+use std::pin::Pin;
+use std::sync::Arc;
+
+struct Struct(u8);
+
+#[mockable]
+impl Struct {
+    async fn arc_method(self: Arc<Self>, arg: bool) -> String {
+        format!("{} {}", self.0, arg)
+    }
+
+    async fn pin_method(self: Pin<&mut Self>, arg: bool) -> String {
+        format!("{} {}", self.0, arg)
+    }
+}
+
+#[tokio::test]
+async fn and_not_mocked_then_runs_normally() {
+    assert_eq!("2 true", Arc::new(Struct(2)).arc_method(true).await);
+    assert_eq!("2 true", Pin::new(&mut Struct(2)).pin_method(true).await);
+}
+
+#[tokio::test]
+async fn and_continue_mocked_then_runs_with_modified_args() {
+    Struct::arc_method.mock_safe(|_, b| MockResult::Continue((Arc::new(Struct(3)), !b)));
+
+    assert_eq!("3 false", Arc::new(Struct(2)).arc_method(true).await);
+}
+
+#[tokio::test]
+async fn and_return_mocked_then_returns_mocking_result() {
+    Struct::arc_method.mock_safe(|a, b| {
+        MockResult::Return(Box::pin(async move { format!("mocked {} {}", a.0, b) }))
+    });
+    unsafe {
+        Struct::pin_method.mock_raw(|a, b| {
+            let value = a.0;
+            MockResult::Return(Box::pin(async move { format!("mocked {} {}", value, b) }))
+        });
+    }
+
+    assert_eq!("mocked 2 true", Arc::new(Struct(2)).arc_method(true).await);
+    assert_eq!(
+        "mocked 2 true",
+        Pin::new(&mut Struct(2)).pin_method(true).await
+    );
+}
@@ -0,0 +1,45 @@
+use super::*;
+
+type Identity = fn(&str) -> &str;
+
+fn identity(value: &str) -> &str {
+    value
+}
+
+struct Struct;
+
+#[mockable]
+impl Struct {
+    async fn bound<F>(&self, map: F) -> usize
+    where
+        F: for<'a> Fn(&'a str) -> &'a str + Send,
+    {
+        map("abc").len()
+    }
+
+    async fn trait_object(&self, map: &(dyn for<'a> Fn(&'a str) -> &'a str + Sync)) -> usize {
+        map("abc").len()
+    }
+
+    async fn elided(&self, map: Box<dyn Fn(&str) -> &str + Send>) -> usize {
+        map("abc").len()
+    }
+}
+
+#[tokio::test]
+async fn and_not_mocked_then_runs_normally() {
+    assert_eq!(3, Struct.bound(identity).await);
+    assert_eq!(3, Struct.trait_object(&identity).await);
+    assert_eq!(3, Struct.elided(Box::new(identity)).await);
+}
+
+#[tokio::test]
+async fn and_mocked_then_returns_mocking_result() {
+    Struct::bound::<Identity>.mock_safe(|_, _| MockResult::Return(Box::pin(async { 1 })));
+    Struct::trait_object.mock_safe(|_, _| MockResult::Return(Box::pin(async { 2 })));
+    Struct::elided.mock_safe(|_, _| MockResult::Return(Box::pin(async { 3 })));
+
+    assert_eq!(1, Struct.bound(identity as Identity).await);
+    assert_eq!(2, Struct.trait_object(&identity).await);
+    assert_eq!(3, Struct.elided(Box::new(identity)).await);
+}
@@ -0,0 +1,104 @@
+use super::*;
+use std::rc::Rc;
+use tokio::task::LocalSet;
+
+struct Struct(Rc<u8>);
+
+#[mockable(local)]
+impl Struct {
+    async fn static_method(arg: Rc<bool>) -> String {
+        let _ = tokio::task::yield_now().await;
+        format!("{}", arg)
+    }
+
+    async fn ref_method(&self, arg: bool) -> Rc<String> {
+        let value = self.0.clone();
+        let _ = tokio::task::yield_now().await;
+        Rc::new(format!("{} {}", value, arg))
+    }
+}
+
+mod and_async_method_is_static {
+    use super::*;
+
+    #[tokio::test]
+    async fn and_not_mocked_then_runs_normally() {
+        assert_eq!("true", Struct::static_method(Rc::new(true)).await);
+    }
+
+    #[tokio::test]
+    async fn and_async_mocked_then_returns_mocking_result() {
+        Struct::static_method.mock_async(|a| {
+            let a = *a;
+            async move { format!("mocked {}", a) }
+        });
+
+        assert_eq!("mocked true", Struct::static_method(Rc::new(true)).await);
+    }
+
+    #[tokio::test]
+    async fn and_async_local_mocked_then_returns_mocking_result() {
+        Struct::static_method.mock_async_local(|a| async move {
+            let _ = tokio::task::yield_now().await;
+            format!("mocked {}", a)
+        });
+
+        assert_eq!("mocked true", Struct::static_method(Rc::new(true)).await);
+    }
+
+    #[tokio::test]
+    async fn and_ready_local_mocked_then_returns_mocking_result() {
+        Struct::static_method.mock_ready_local(|a| MockResult::Return(format!("mocked {}", a)));
+
+        assert_eq!("mocked true", Struct::static_method(Rc::new(true)).await);
+    }
+
+    #[tokio::test]
+    async fn and_ready_local_continued_then_runs_normally() {
+        Struct::static_method.mock_ready_local(|a| MockResult::Continue((a,)));
+
+        assert_eq!("true", Struct::static_method(Rc::new(true)).await);
+    }
+}
+
+mod and_async_method_is_ref_method {
+    use super::*;
+
+    #[tokio::test]
+    async fn and_not_mocked_then_runs_in_local_set() {
+        let local_set = LocalSet::new();
+
+        let result = local_set
+            .run_until(Struct(Rc::new(2)).ref_method(true))
+            .await;
+
+        assert_eq!("2 true", *result);
+    }
+
+    #[tokio::test]
+    async fn and_async_local_mocked_then_mock_runs_in_local_task() {
+        let mocked = Rc::new("mocked".to_string());
+        Struct::ref_method.mock_async_local(move |s, _| {
+            let mocked = mocked.clone();
+            let value = s.0.clone();
+            async move {
+                let _ = tokio::task::yield_now().await;
+                Rc::new(format!("{} {}", mocked, value))
+            }
+        });
+        let local_set = LocalSet::new();
+
+        let task = local_set.spawn_local(async { Struct(Rc::new(2)).ref_method(true).await });
+        let result = local_set.run_until(task).await.unwrap();
+
+        assert_eq!("mocked 2", *result);
+    }
+
+    #[tokio::test]
+    async fn and_ready_local_mocked_then_returns_mocking_result() {
+        Struct::ref_method
+            .mock_ready_local(|_, a| MockResult::Return(Rc::new(format!("mocked {}", a))));
+
+        assert_eq!("mocked true", *Struct(Rc::new(2)).ref_method(true).await);
+    }
+}
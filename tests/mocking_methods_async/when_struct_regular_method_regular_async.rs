@@ -48,6 +48,13 @@ mod and_async_method_is_static {
 
         assert_eq!("mocked true", Struct::static_method(true).await);
     }
+
+    #[tokio::test]
+    async fn and_async_mocked_then_returns_mocking_result() {
+        Struct::static_method.mock_async(|a| async move { format!("mocked {}", a) });
+
+        assert_eq!("mocked true", Struct::static_method(true).await);
+    }
 }
 
 mod and_method_is_ref_method {
@@ -83,6 +90,24 @@ mod and_method_is_ref_method {
         assert_eq!("mocked 2 true", struct_2.ref_method(true).await);
         assert_eq!(2, struct_2.0);
     }
+
+    #[tokio::test]
+    async fn and_async_mocked_then_returns_mocking_result() {
+        let struct_2 = Struct(2);
+        Struct::ref_method.mock_async(|a, b| async move { format!("mocked {} {}", a.0, b) });
+
+        assert_eq!("mocked 2 true", struct_2.ref_method(true).await);
+        assert_eq!(2, struct_2.0);
+    }
+
+    #[tokio::test]
+    async fn and_ready_mocked_then_returns_mocking_result() {
+        let struct_2 = Struct(2);
+        Struct::ref_method.mock_ready(|a, b| MockResult::Return(format!("mocked {} {}", a.0, b)));
+
+        assert_eq!("mocked 2 true", struct_2.ref_method(true).await);
+        assert_eq!(2, struct_2.0);
+    }
 }
 
 mod and_async_method_is_ref_mut_method {
@@ -150,4 +175,11 @@ mod and_async_method_is_val_method {
 
         assert_eq!("mocked 2 true", Struct(2).val_method(true).await);
     }
+
+    #[tokio::test]
+    async fn and_async_mocked_then_returns_mocking_result() {
+        Struct::val_method.mock_async(|a, b| async move { format!("mocked {} {}", a.0, b) });
+
+        assert_eq!("mocked 2 true", Struct(2).val_method(true).await);
+    }
 }
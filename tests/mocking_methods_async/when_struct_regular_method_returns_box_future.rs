@@ -0,0 +1,122 @@
+use super::*;
+use futures_core::future::{BoxFuture, LocalBoxFuture};
+use std::rc::Rc;
+
+struct Struct(u8);
+
+#[mockable]
+impl Struct {
+    fn static_method(arg: bool) -> BoxFuture<'static, String> {
+        Box::pin(async move { format!("{}", arg) })
+    }
+
+    fn ref_method(&self, arg: bool) -> BoxFuture<'_, String> {
+        Box::pin(async move { format!("{} {}", self.0, arg) })
+    }
+
+    fn local_method(&self, arg: Rc<bool>) -> LocalBoxFuture<'_, String> {
+        Box::pin(async move { format!("{} {}", self.0, arg) })
+    }
+
+    fn result_method(&self, arg: bool) -> BoxFuture<'_, Result<u8, String>> {
+        Box::pin(async move {
+            match arg {
+                true => Ok(self.0),
+                false => Err("error".to_string()),
+            }
+        })
+    }
+}
+
+mod and_method_is_static {
+    use super::*;
+
+    #[tokio::test]
+    async fn and_not_mocked_then_runs_normally() {
+        assert_eq!("true", Struct::static_method(true).await);
+    }
+
+    #[tokio::test]
+    async fn and_async_mocked_then_returns_mocking_result() {
+        Struct::static_method.mock_async(|a| async move { format!("mocked {}", a) });
+
+        assert_eq!("mocked true", Struct::static_method(true).await);
+    }
+
+    #[tokio::test]
+    async fn and_ready_mocked_then_returns_mocking_result() {
+        Struct::static_method.mock_ready(|a| MockResult::Return(format!("mocked {}", a)));
+
+        assert_eq!("mocked true", Struct::static_method(true).await);
+    }
+}
+
+mod and_method_is_ref_method {
+    use super::*;
+
+    #[tokio::test]
+    async fn and_not_mocked_then_runs_normally() {
+        assert_eq!("2 true", Struct(2).ref_method(true).await);
+    }
+
+    #[tokio::test]
+    async fn and_continue_mocked_then_runs_with_modified_args() {
+        Struct::ref_method.mock_ready(|s, a| MockResult::Continue((s, !a)));
+
+        assert_eq!("2 false", Struct(2).ref_method(true).await);
+    }
+
+    #[tokio::test]
+    async fn and_ready_mocked_then_returns_mocking_result() {
+        Struct::ref_method.mock_ready(|_, a| MockResult::Return(format!("mocked {}", a)));
+
+        assert_eq!("mocked true", Struct(2).ref_method(true).await);
+    }
+}
+
+mod and_method_returns_local_box_future {
+    use super::*;
+
+    #[tokio::test]
+    async fn and_not_mocked_then_runs_normally() {
+        assert_eq!("2 true", Struct(2).local_method(Rc::new(true)).await);
+    }
+
+    #[tokio::test]
+    async fn and_async_mocked_then_returns_mocking_result() {
+        Struct::local_method.mock_async(|_, a| {
+            let a = *a;
+            async move { format!("mocked {}", a) }
+        });
+
+        assert_eq!("mocked true", Struct(2).local_method(Rc::new(true)).await);
+    }
+
+    #[tokio::test]
+    async fn and_async_local_mocked_then_returns_mocking_result() {
+        Struct::local_method.mock_async_local(|_, a| async move { format!("mocked {}", a) });
+
+        assert_eq!("mocked true", Struct(2).local_method(Rc::new(true)).await);
+    }
+}
+
+mod and_method_returns_result {
+    use super::*;
+
+    #[tokio::test]
+    async fn and_ok_mocked_then_returns_ok() {
+        Struct::result_method.mock_return_ok(5);
+
+        assert_eq!(Ok(5), Struct(2).result_method(false).await);
+    }
+
+    #[tokio::test]
+    async fn and_err_mocked_then_returns_err() {
+        Struct::result_method.mock_return_err("mocked".to_string());
+
+        assert_eq!(
+            Err("mocked".to_string()),
+            Struct(2).result_method(true).await
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use super::*;
+use async_trait::async_trait;
+use std::rc::Rc;
+
+#[async_trait]
+trait Trait {
+    async fn ref_method(&self, arg: u8) -> String;
+
+    async fn generic_method<T: Display + Send>(&self, arg: T) -> String;
+}
+
+#[async_trait(?Send)]
+trait LocalTrait {
+    async fn local_method(&self, arg: Rc<u8>) -> String;
+}
+
+#[mockable]
+#[async_trait]
+trait DefaultTrait: Sync {
+    async fn default_method(&self, arg: u8) -> String {
+        format!("default {}", arg)
+    }
+}
+
+struct MockableFirst(u8);
+
+#[mockable]
+#[async_trait]
+impl Trait for MockableFirst {
+    async fn ref_method(&self, arg: u8) -> String {
+        format!("{} {}", self.0, arg)
+    }
+
+    async fn generic_method<T: Display + Send>(&self, arg: T) -> String {
+        format!("{} {}", self.0, arg)
+    }
+}
+
+#[mockable]
+#[async_trait(?Send)]
+impl LocalTrait for MockableFirst {
+    async fn local_method(&self, arg: Rc<u8>) -> String {
+        format!("{} {}", self.0, arg)
+    }
+}
+
+impl DefaultTrait for MockableFirst {}
+
+struct AsyncTraitFirst(u8);
+
+#[async_trait]
+#[mockable]
+impl Trait for AsyncTraitFirst {
+    async fn ref_method(&self, arg: u8) -> String {
+        format!("{} {}", self.0, arg)
+    }
+
+    async fn generic_method<T: Display + Send>(&self, arg: T) -> String {
+        format!("{} {}", self.0, arg)
+    }
+}
+
+#[async_trait(?Send)]
+#[mockable]
+impl LocalTrait for AsyncTraitFirst {
+    async fn local_method(&self, arg: Rc<u8>) -> String {
+        format!("{} {}", self.0, arg)
+    }
+}
+
+#[tokio::test]
+async fn and_not_mocked_then_runs_normally() {
+    assert_eq!("1 2", MockableFirst(1).ref_method(2).await);
+    assert_eq!("1 2", MockableFirst(1).generic_method(2).await);
+    assert_eq!("1 2", MockableFirst(1).local_method(Rc::new(2)).await);
+    assert_eq!("default 2", MockableFirst(1).default_method(2).await);
+    assert_eq!("1 2", AsyncTraitFirst(1).ref_method(2).await);
+    assert_eq!("1 2", AsyncTraitFirst(1).generic_method(2).await);
+    assert_eq!("1 2", AsyncTraitFirst(1).local_method(Rc::new(2)).await);
+}
+
+#[tokio::test]
+async fn and_mockable_first_mocked_then_returns_mocking_result() {
+    MockableFirst::ref_method
+        .mock_safe(|_, b| MockResult::Return(Box::pin(async move { format!("mocked {}", b) })));
+    MockableFirst::generic_method::<u8>
+        .mock_safe(|_, b| MockResult::Return(Box::pin(async move { format!("mocked {}", b) })));
+    MockableFirst::local_method
+        .mock_safe(|_, b| MockResult::Return(Box::pin(async move { format!("mocked {}", b) })));
+    MockableFirst::default_method
+        .mock_safe(|_, b| MockResult::Return(Box::pin(async move { format!("mocked {}", b) })));
+
+    assert_eq!("mocked 2", MockableFirst(1).ref_method(2).await);
+    assert_eq!("mocked 2", MockableFirst(1).generic_method(2u8).await);
+    assert_eq!("1 2", MockableFirst(1).generic_method(2u16).await);
+    assert_eq!("mocked 2", MockableFirst(1).local_method(Rc::new(2)).await);
+    assert_eq!("mocked 2", MockableFirst(1).default_method(2).await);
+}
+
+#[tokio::test]
+async fn and_async_trait_first_mocked_then_returns_mocking_result() {
+    AsyncTraitFirst::ref_method
+        .mock_safe(|_, b| MockResult::Return(Box::pin(async move { format!("mocked {}", b) })));
+    AsyncTraitFirst::generic_method::<u8>
+        .mock_safe(|_, b| MockResult::Return(Box::pin(async move { format!("mocked {}", b) })));
+    AsyncTraitFirst::local_method
+        .mock_safe(|_, b| MockResult::Return(Box::pin(async move { format!("mocked {}", b) })));
+
+    assert_eq!("mocked 2", AsyncTraitFirst(1).ref_method(2).await);
+    assert_eq!("mocked 2", AsyncTraitFirst(1).generic_method(2u8).await);
+    assert_eq!("1 2", AsyncTraitFirst(1).generic_method(2u16).await);
+    assert_eq!(
+        "mocked 2",
+        AsyncTraitFirst(1).local_method(Rc::new(2)).await
+    );
+}
@@ -37,3 +37,125 @@ mod mocking_default_impl_of_trait_of_struct {
         assert_eq!("not mocked", Struct2::method());
     }
 }
+
+mod mocking_default_impl_of_trait_for_all_implementors {
+    use super::*;
+
+    #[mockable]
+    trait Trait {
+        fn method(arg: u32) -> &'static str {
+            let _ = arg;
+            "not mocked"
+        }
+
+        fn self_method(&self) -> &'static str {
+            "not mocked"
+        }
+    }
+
+    #[mockable]
+    trait GenericTrait<T> {
+        fn generic_method(arg: T) -> T {
+            arg
+        }
+    }
+
+    struct Struct1;
+
+    impl Trait for Struct1 {}
+
+    impl GenericTrait<u32> for Struct1 {}
+
+    impl GenericTrait<u64> for Struct1 {}
+
+    struct Struct2;
+
+    #[mockable]
+    impl Trait for Struct2 {
+        fn method(arg: u32) -> &'static str {
+            let _ = arg;
+            "not mocked override"
+        }
+    }
+
+    impl GenericTrait<u32> for Struct2 {}
+
+    fn mock_method() {
+        unsafe {
+            mock_trait_method_by_name(concat!(module_path!(), "::Trait::method"), |_: u32| {
+                MockResult::Return("mocked")
+            });
+        }
+    }
+
+    #[test]
+    fn mocks_default_impls_and_mockable_impls() {
+        mock_method();
+
+        assert_eq!("mocked", Struct1::method(1));
+        assert_eq!("mocked", Struct2::method(1));
+    }
+
+    #[test]
+    fn when_implementor_mocked_separately_then_runs_its_mock() {
+        mock_method();
+        Struct1::method.mock_safe(|_| MockResult::Return("mocked separately"));
+
+        assert_eq!("mocked separately", Struct1::method(1));
+        assert_eq!("mocked", Struct2::method(1));
+    }
+
+    #[test]
+    fn when_called_then_shares_mock_state_between_implementors() {
+        let mut calls = 0;
+        unsafe {
+            mock_trait_method_by_name(concat!(module_path!(), "::Trait::method"), move |_: u32| {
+                calls += 1;
+                MockResult::Return(if calls == 1 { "first" } else { "next" })
+            });
+        }
+
+        assert_eq!("first", Struct1::method(1));
+        assert_eq!("next", Struct2::method(1));
+        assert_eq!("next", Struct1::method(1));
+    }
+
+    #[test]
+    fn when_cleared_then_runs_normally() {
+        mock_method();
+        Struct1::method(1);
+
+        clear_all_mocks();
+
+        assert_eq!("not mocked", Struct1::method(1));
+        assert_eq!("not mocked override", Struct2::method(1));
+    }
+
+    #[test]
+    fn mocks_generic_trait_for_mocked_type_only() {
+        unsafe {
+            mock_trait_method_by_name(
+                concat!(module_path!(), "::GenericTrait<u32>::generic_method"),
+                |_: u32| MockResult::Return(0u32),
+            );
+        }
+
+        assert_eq!(0, <Struct1 as GenericTrait<u32>>::generic_method(1));
+        assert_eq!(0, <Struct2 as GenericTrait<u32>>::generic_method(1));
+        assert_eq!(1, <Struct1 as GenericTrait<u64>>::generic_method(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "has signature")]
+    fn when_signature_depends_on_implementor_then_panics() {
+        unsafe {
+            mock_trait_method_by_name(
+                concat!(module_path!(), "::Trait::self_method"),
+                |_: &Struct1| MockResult::Return("mocked"),
+            );
+        }
+
+        assert_eq!("mocked", Struct1.self_method());
+        Struct2.self_method();
+    }
+}
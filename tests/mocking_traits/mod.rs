@@ -63,3 +63,217 @@ mod mocking_impls_of_traits_generic_over_generic_refs {
         assert_eq!("mocked", <Struct as Trait<&u32>>::method());
     }
 }
+
+mod mocking_impls_of_traits_with_associated_items {
+    use super::*;
+
+    struct Struct(u32);
+
+    trait Trait {
+        type Output;
+        const OFFSET: u32;
+
+        fn method(&self) -> Self::Output;
+    }
+
+    #[mockable]
+    impl Trait for Struct {
+        type Output = u32;
+        const OFFSET: u32 = 1;
+
+        fn method(&self) -> Self::Output {
+            self.0 + Self::OFFSET
+        }
+    }
+
+    #[test]
+    fn keeps_associated_items() {
+        assert_eq!(1, <Struct as Trait>::OFFSET);
+        assert_eq!(3, Struct(2).method());
+    }
+
+    #[test]
+    fn mocks_successfully() {
+        Struct::method.mock_safe(|_| MockResult::Return(0));
+
+        assert_eq!(0, Struct(2).method());
+    }
+}
+
+mod mocking_blanket_impls_of_traits {
+    use super::*;
+
+    trait Trait {
+        fn static_method() -> &'static str;
+        fn ref_method(&self) -> String;
+        fn generic_method<U: Display>(&self, arg: U) -> String;
+    }
+
+    #[mockable]
+    impl<T> Trait for T
+    where
+        T: Display + ?Sized,
+    {
+        fn static_method() -> &'static str {
+            "not mocked"
+        }
+
+        fn ref_method(&self) -> String {
+            format!("{}", self)
+        }
+
+        fn generic_method<U: Display>(&self, arg: U) -> String {
+            format!("{} {}", self, arg)
+        }
+    }
+
+    trait SliceTrait {
+        fn method(&self) -> usize;
+    }
+
+    #[mockable]
+    impl<T> SliceTrait for [T] {
+        fn method(&self) -> usize {
+            self.len()
+        }
+    }
+
+    #[test]
+    fn when_not_mocked_then_runs_normally() {
+        assert_eq!("not mocked", <u32 as Trait>::static_method());
+        assert_eq!("1", 1u32.ref_method());
+        assert_eq!("a b", "a".generic_method('b'));
+        assert_eq!(2, [1u8, 2][..].method());
+    }
+
+    #[test]
+    fn when_mocked_then_runs_mock_for_mocked_type_only() {
+        <u32 as Trait>::static_method.mock_safe(|| MockResult::Return("mocked"));
+        <str as Trait>::ref_method.mock_safe(|_| MockResult::Return("mocked".to_string()));
+        <[u8] as SliceTrait>::method.mock_safe(|_| MockResult::Return(0));
+
+        assert_eq!("mocked", <u32 as Trait>::static_method());
+        assert_eq!("not mocked", <u8 as Trait>::static_method());
+        assert_eq!("mocked", "a".ref_method());
+        assert_eq!("a", "a".to_string().ref_method());
+        assert_eq!(0, [1u8, 2][..].method());
+        assert_eq!(2, [1u16, 2][..].method());
+    }
+
+    #[test]
+    fn when_generic_method_mocked_then_runs_mock_for_mocked_types_only() {
+        <u32 as Trait>::generic_method::<char>
+            .mock_safe(|_, arg| MockResult::Return(format!("mocked {}", arg)));
+
+        assert_eq!("mocked b", 1u32.generic_method('b'));
+        assert_eq!("1 b", 1u32.generic_method("b"));
+        assert_eq!("1 b", 1u8.generic_method('b'));
+    }
+
+    #[test]
+    fn when_mocked_in_context_then_runs_mock_for_mocked_type_only() {
+        MockContext::new()
+            .mock_safe(<u32 as Trait>::ref_method, |_| {
+                MockResult::Return("mocked".to_string())
+            })
+            .run(|| {
+                assert_eq!("mocked", 1u32.ref_method());
+                assert_eq!("1", 1u64.ref_method());
+            });
+
+        assert_eq!("1", 1u32.ref_method());
+    }
+}
+
+mod mocking_async_fns_of_trait_impls {
+    use super::*;
+    use std::future::Future;
+    use std::task::{Context, Poll, Waker};
+
+    trait Trait {
+        async fn method(&self, text: &str) -> usize;
+        async fn generic_method<T: Display + Send>(&self, arg: T) -> String;
+        async fn static_method() -> &'static str;
+    }
+
+    struct Struct(usize);
+
+    #[mockable]
+    impl Trait for Struct {
+        async fn method(&self, text: &str) -> usize {
+            if text.is_empty() {
+                return self.0;
+            }
+            self.0 + text.len()
+        }
+
+        async fn generic_method<T: Display + Send>(&self, arg: T) -> String {
+            format!("{} {}", self.0, arg)
+        }
+
+        async fn static_method() -> &'static str {
+            "not mocked"
+        }
+    }
+
+    fn assert_send<T: Send>(value: T) -> T {
+        value
+    }
+
+    #[tokio::test]
+    async fn when_not_mocked_then_runs_normally() {
+        let text = "abc".to_string();
+
+        assert_eq!(4, assert_send(Struct(1).method(&text)).await);
+        assert_eq!(1, Struct(1).method("").await);
+        assert_eq!("1 a", Struct(1).generic_method('a').await);
+        assert_eq!("not mocked", Struct::static_method().await);
+    }
+
+    #[tokio::test]
+    async fn when_mocked_async_then_returns_mock() {
+        <Struct as Trait>::method.mock_async(|_, text| {
+            let len = text.len();
+            async move { len * 10 }
+        });
+        Struct::static_method.mock_async(|| async { "mocked" });
+
+        assert_eq!(30, Struct(1).method("abc").await);
+        assert_eq!("mocked", Struct::static_method().await);
+    }
+
+    #[tokio::test]
+    async fn when_generic_method_mocked_then_runs_mock_for_mocked_type_only() {
+        <Struct as Trait>::generic_method::<char>
+            .mock_ready(|_, arg| MockResult::Return(format!("mocked {}", arg)));
+
+        assert_eq!("mocked a", Struct(1).generic_method('a').await);
+        assert_eq!("1 a", Struct(1).generic_method("a").await);
+    }
+
+    #[tokio::test]
+    async fn when_mock_continues_then_runs_body_with_new_args() {
+        Struct::method.mock_safe(|this, _| MockResult::Continue((this, "de")));
+
+        assert_eq!(3, Struct(1).method("abc").await);
+    }
+
+    #[test]
+    fn when_mocked_then_mock_runs_on_call_not_on_poll() {
+        Struct::method.mock_ready(|_, _| MockResult::Panic("mocked".to_string()));
+
+        let result = std::panic::catch_unwind(|| Struct(1).method("abc"));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn when_mocked_with_pending_future_then_is_pending() {
+        Struct::static_method.mock_async(|| PendingThen::new(1, "mocked"));
+        let mut context = Context::from_waker(Waker::noop());
+        let mut future = Box::pin(Struct::static_method());
+
+        assert_eq!(Poll::Pending, future.as_mut().poll(&mut context));
+        assert_eq!(Poll::Ready("mocked"), future.as_mut().poll(&mut context));
+    }
+}
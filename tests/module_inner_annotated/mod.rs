@@ -0,0 +1,5 @@
+#![mockable]
+
+pub fn function() -> &'static str {
+    "not mocked"
+}
@@ -0,0 +1,3 @@
+pub fn included_function() -> &'static str {
+    "not mocked"
+}
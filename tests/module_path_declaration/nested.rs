@@ -0,0 +1,5 @@
+#![mocktopus::macros::mockable]
+
+pub fn function() -> &'static str {
+    "not mocked"
+}
@@ -0,0 +1,7 @@
+pub mod nested;
+
+include!("included.rs");
+
+pub fn function() -> &'static str {
+    "not mocked"
+}